@@ -0,0 +1,83 @@
+#![feature(test)]
+
+extern crate mithril;
+extern crate test;
+
+use mithril::randomx::hardware::{with_aes_mode, AesMode};
+use mithril::randomx::hash::{fill_aes_1rx4_u64, hash_aes_1rx4, hash_and_fill_aes_1rx4};
+use mithril::randomx::m128::m128i;
+use test::Bencher;
+
+//2MiB, the scratchpad of RandomX
+const SCRATCHPAD_WORDS: usize = 2 * 1024 * 1024 / 8;
+
+#[allow(overflowing_literals)]
+fn seed() -> [m128i; 4] {
+    [
+        m128i::from_i32(0x31903876, 0xbb7a2914, 0xb370f616, 0xd6f7e4f3),
+        m128i::from_i32(0xb5a8ef67, 0x749809c8, 0xf349884a, 0x05c9f5ef),
+        m128i::from_i32(0xa9a93ab0, 0x22e46d0a, 0x1a1fe305, 0xb42708c0),
+        m128i::from_i32(0x68247034, 0xed99ee84, 0x438f563a, 0x138612ff),
+    ]
+}
+
+fn bench_fill(b: &mut Bencher, mode: AesMode) {
+    with_aes_mode(mode, || {
+        let seed = seed();
+        let mut scratchpad = vec![0; SCRATCHPAD_WORDS];
+        b.iter(|| fill_aes_1rx4_u64(&seed, &mut scratchpad));
+    });
+}
+
+fn bench_hash_and_fill(b: &mut Bencher, mode: AesMode) {
+    with_aes_mode(mode, || {
+        let seed = seed();
+        let mut scratchpad = vec![0; SCRATCHPAD_WORDS];
+        fill_aes_1rx4_u64(&seed, &mut scratchpad);
+        b.iter(|| hash_and_fill_aes_1rx4(&seed, &mut scratchpad));
+    });
+}
+
+fn bench_hash(b: &mut Bencher, mode: AesMode) {
+    with_aes_mode(mode, || {
+        let mut scratchpad = vec![0; SCRATCHPAD_WORDS];
+        fill_aes_1rx4_u64(&seed(), &mut scratchpad);
+        b.iter(|| hash_aes_1rx4(&scratchpad));
+    });
+}
+
+//Hardware uses AES-NI if the cpu has no VAES
+#[bench]
+fn bench_fill_scratchpad_vaes(b: &mut Bencher) {
+    bench_fill(b, AesMode::Hardware);
+}
+
+#[bench]
+fn bench_fill_scratchpad_aesni(b: &mut Bencher) {
+    bench_fill(b, AesMode::Aesni);
+}
+
+#[bench]
+fn bench_fill_scratchpad_software(b: &mut Bencher) {
+    bench_fill(b, AesMode::Software);
+}
+
+#[bench]
+fn bench_hash_scratchpad_vaes(b: &mut Bencher) {
+    bench_hash(b, AesMode::Hardware);
+}
+
+#[bench]
+fn bench_hash_scratchpad_aesni(b: &mut Bencher) {
+    bench_hash(b, AesMode::Aesni);
+}
+
+#[bench]
+fn bench_hash_and_fill_scratchpad_vaes(b: &mut Bencher) {
+    bench_hash_and_fill(b, AesMode::Hardware);
+}
+
+#[bench]
+fn bench_hash_and_fill_scratchpad_aesni(b: &mut Bencher) {
+    bench_hash_and_fill(b, AesMode::Aesni);
+}
@@ -0,0 +1,37 @@
+#![feature(test)]
+
+extern crate lazy_static;
+extern crate mithril;
+extern crate test;
+
+use lazy_static::lazy_static;
+use mithril::randomx::hardware::DatasetInit;
+use mithril::randomx::memory::{init_dataset, DatasetItem, SeedMemory};
+use test::Bencher;
+
+const ITEMS: usize = 4096;
+
+lazy_static! {
+    static ref SEED_MEM: SeedMemory = SeedMemory::new_initialised(b"test key 000");
+}
+
+//executors that are not available fall back to the next one (see dataset_init_executor)
+fn bench_init(b: &mut Bencher, executor: DatasetInit) {
+    let mut dataset = vec![DatasetItem([0; 8]); ITEMS];
+    b.iter(|| init_dataset(&SEED_MEM, &mut dataset, 1, executor));
+}
+
+#[bench]
+fn bench_dataset_init_compiled(b: &mut Bencher) {
+    bench_init(b, DatasetInit::Compiled);
+}
+
+#[bench]
+fn bench_dataset_init_avx2(b: &mut Bencher) {
+    bench_init(b, DatasetInit::Avx2);
+}
+
+#[bench]
+fn bench_dataset_init_interpreted(b: &mut Bencher) {
+    bench_init(b, DatasetInit::Interpreted);
+}
@@ -8,18 +8,27 @@ extern crate mithril;
 
 use self::crossbeam_channel::{select, unbounded, Receiver};
 use mithril::bandit_tools;
+use mithril::byte_string;
 use mithril::metric;
 use mithril::mithril_config;
-use mithril::randomx::memory::VmMemoryAllocator;
+use mithril::randomx::asm;
+use mithril::randomx::conformance;
+use mithril::randomx::hardware;
+use mithril::randomx::memory::{self, VmMemory, VmMemoryAllocator};
+use mithril::randomx::params::{RandomXVariant, RX_0};
+use mithril::randomx::vm::VmMode;
 use mithril::stratum::{StratumAction, StratumClient};
 use mithril::timer;
 use mithril::worker::worker_pool;
 use mithril::worker::worker_pool::WorkerPool;
+use std::env;
 use std::io;
 use std::io::Error;
 use std::path::Path;
+use std::process;
+use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use bandit::MultiArmedBandit;
 
@@ -33,11 +42,46 @@ enum MainLoopExit {
 fn main() {
     env_logger::init();
 
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("disasm") {
+        if let Err(err) = disasm(&args[1..]) {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+        return;
+    }
+
     //Read config
     let cwd_path = &format!("{}{}", "./", mithril_config::CONFIG_FILE_NAME);
     let config =
         mithril_config::read_config(Path::new(cwd_path), mithril_config::CONFIG_FILE_NAME).unwrap();
 
+    let aes = config.hardware_conf.aes;
+    hardware::set_aes_mode(aes);
+    let forced_aes = aes == hardware::AesMode::Hardware || aes == hardware::AesMode::Aesni;
+    if forced_aes && !hardware::has_hardware_aes() {
+        warn!(
+            "aes mode {} needs AES-NI, which the cpu does not have, falling back to software AES",
+            aes
+        );
+    }
+    info!(
+        "using {} AES (mode {}, cpu has AES-NI: {})",
+        if hardware::use_hardware_aes() {
+            "hardware"
+        } else {
+            "software"
+        },
+        config.hardware_conf.aes,
+        hardware::has_hardware_aes(),
+    );
+
+    if config.worker_conf.self_test {
+        self_test(config.worker_conf.randomx_mode);
+    } else {
+        warn!("self-test disabled");
+    }
+
     if config.donation_conf.percentage > 0.0 {
         print_donation_hint(config.donation_conf.percentage);
     }
@@ -52,7 +96,12 @@ fn main() {
 
     let timer_rcvr = timer::setup(&config.worker_conf, &config.donation_conf);
     let mut donation_hashing = false;
-    let mut vm_memory_allocator = VmMemoryAllocator::initial();
+    let mut vm_memory_allocator = VmMemoryAllocator::initial(
+        config.hardware_conf.clone(),
+        config.dataset_cache_conf.clone(),
+        config.worker_conf.randomx_variant.params(),
+    );
+    vm_memory_allocator.shared_dataset_conf = config.shared_dataset_conf.clone();
 
     loop {
         //Stratum start
@@ -84,6 +133,14 @@ fn main() {
         let (metric_sndr, metric_rcvr) = unbounded();
         let metric = metric::start(config.metric_conf.clone(), metric_rcvr);
 
+        //the donation pool mines Monero
+        vm_memory_allocator.params = if donation_hashing {
+            RX_0
+        } else {
+            config.worker_conf.randomx_variant.params()
+        };
+        let num_threads = admit_worker_threads(num_threads, &vm_memory_allocator);
+
         //worker pool start
         let mut pool = worker_pool::start(
             num_threads,
@@ -91,6 +148,8 @@ fn main() {
             config.metric_conf.resolution,
             &metric_sndr.clone(),
             vm_memory_allocator,
+            config.worker_conf.randomx_mode,
+            config.worker_conf.verify_shares,
         );
 
         let term_result =
@@ -133,6 +192,51 @@ fn main() {
     }
 }
 
+/// Exits if the RandomX implementation computes a wrong hash on this machine,
+/// the pool would reject all shares anyway.
+fn self_test(vm_mode: VmMode) {
+    let start = Instant::now();
+    let report = conformance::self_test(vm_mode);
+    if !report.is_ok() {
+        for mismatch in &report.mismatches {
+            error!("self-test: {}", mismatch);
+        }
+        eprintln!(
+            "self-test failed, not mining: {}\n\
+             The RandomX hashes computed on this machine are wrong. Check for unstable overclocking,\n\
+             try aes = \"software\" in [hardware] or randomx_mode = \"interpreted\" in [worker].\n\
+             The test can be disabled with self_test = false in [worker].",
+            report
+        );
+        process::exit(1);
+    }
+    info!(
+        "self-test passed ({} checks in {}ms)",
+        report.checks,
+        start.elapsed().as_millis()
+    );
+}
+
+//refuses thread counts whose scratchpads do not fit into memory (besides the
+//memory of the first seed if it is not allocated yet)
+fn admit_worker_threads(requested: u64, vm_memory_allocator: &VmMemoryAllocator) -> u64 {
+    let reserved = if vm_memory_allocator.vm_memory_seed.is_empty() {
+        vm_memory_allocator.memory_size()
+    } else {
+        0
+    };
+    let available = hardware::available_memory().map(|a| a.saturating_sub(reserved));
+    let admitted = memory::admit_worker_threads(requested, &vm_memory_allocator.params, available);
+    if admitted < requested {
+        warn!(
+            "the scratchpads of {} worker threads do not fit into the available memory, \
+             using {} threads",
+            requested, admitted
+        );
+    }
+    admitted
+}
+
 fn await_timeout() {
     thread::sleep(Duration::from_secs(60))
 }
@@ -156,6 +260,7 @@ fn start_main_event_loop(
     stratum_rcvr: &Receiver<StratumAction>,
     timer_rcvr: &Receiver<timer::TickAction>,
 ) -> io::Result<MainLoopExit> {
+    let built_memory_rcvr = pool.vm_memory_allocator.built_memory().clone();
     loop {
         select! {
             recv(stratum_rcvr) -> stratum_msg => {
@@ -163,8 +268,8 @@ fn start_main_event_loop(
                     return Err(io::Error::new(io::ErrorKind::ConnectionAborted, "received error"));
                 }
                 match stratum_msg.unwrap() {
-                    StratumAction::Job{miner_id, seed_hash, blob, job_id, target} => {
-                        pool.job_change(&miner_id, &seed_hash, &blob, &job_id, &target);
+                    StratumAction::Job{miner_id, seed_hash, next_seed_hash, blob, job_id, target} => {
+                        pool.job_change(&miner_id, &seed_hash, next_seed_hash.as_deref(), &blob, &job_id, &target);
                     },
                     StratumAction::Error{err} => {
                         error!("Received stratum error: {}", err);
@@ -193,6 +298,10 @@ fn start_main_event_loop(
                     }
                 }
             },
+            recv(built_memory_rcvr) -> built => {
+                //the allocator keeps a sender, so the channel is never disconnected here
+                pool.memory_built(built.expect("built memory"));
+            },
             recv(client_err_rcvr) -> client_err_msg => {
                 return Err(io::Error::new(io::ErrorKind::Other, format!("error received {:?}", client_err_msg)));
             }
@@ -200,6 +309,41 @@ fn start_main_event_loop(
     }
 }
 
+const DISASM_USAGE: &str =
+    "usage: mithril disasm <seed-hex> [<input-hex>] [--variant <rx/0|rx/wow|...>]";
+
+/// Prints the programs generated for hashing an input with the key `seed`.
+fn disasm(args: &[String]) -> Result<(), String> {
+    let mut hex_args = Vec::new();
+    let mut variant = RandomXVariant::Rx0;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--variant" {
+            let name = args.next().ok_or(DISASM_USAGE)?;
+            variant = name
+                .parse()
+                .map_err(|_| format!("unknown variant {}", name))?;
+        } else {
+            hex_args.push(parse_hex(arg)?);
+        }
+    }
+    if hex_args.is_empty() || hex_args.len() > 2 {
+        return Err(DISASM_USAGE.to_string());
+    }
+    let input = hex_args.get(1).cloned().unwrap_or_default();
+
+    let mem = Arc::new(VmMemory::light_with_params(&hex_args[0], &variant.params()));
+    print!("{}", asm::disassemble(&asm::generate_programs(mem, &input)));
+    Ok(())
+}
+
+fn parse_hex(hex: &str) -> Result<Vec<u8>, String> {
+    if !hex.len().is_multiple_of(2) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("{} is not a hex string", hex));
+    }
+    Ok(byte_string::string_to_u8_array(hex))
+}
+
 fn print_donation_hint(percentage: f64) {
     println!("-------------------------------------------------------------------");
     println!("Donation Hashing enabled with {}%.", percentage);
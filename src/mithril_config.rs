@@ -1,12 +1,16 @@
 extern crate config;
 
+use bandit_tools;
 use metric::MetricConfig;
+use randomx::dataset_cache::DatasetCacheConfig;
+use randomx::hardware::HardwareConfig;
+use randomx::shared_dataset::SharedDatasetConfig;
 use stratum::stratum_data::PoolConfig;
 use worker::worker_pool::WorkerConfig;
 
 use self::config::{Config, ConfigError, File};
 use std;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 pub const CONFIG_FILE_NAME: &str = "config.toml";
 
@@ -17,6 +21,9 @@ pub struct MithrilConfig {
     pub worker_conf: WorkerConfig,
     pub metric_conf: MetricConfig,
     pub donation_conf: DonationConfig,
+    pub hardware_conf: HardwareConfig,
+    pub dataset_cache_conf: DatasetCacheConfig,
+    pub shared_dataset_conf: SharedDatasetConfig,
 }
 
 #[derive(Clone)]
@@ -31,12 +38,74 @@ pub fn read_config(conf_file: &Path, filename: &str) -> Result<MithrilConfig, co
     let worker_conf = worker_config(&config)?;
     let metric_conf = metric_config(&config)?;
     let donation_conf = donation_config(&config)?;
+    let hardware_conf = hardware_config(&config)?;
+    let dataset_cache_conf = dataset_cache_config(&config)?;
+    let shared_dataset_conf = shared_dataset_config(&config)?;
 
     Ok(MithrilConfig {
         pool_conf,
         worker_conf,
         metric_conf,
         donation_conf,
+        hardware_conf,
+        dataset_cache_conf,
+        shared_dataset_conf,
+    })
+}
+
+fn dataset_cache_config(conf: &Config) -> Result<DatasetCacheConfig, ConfigError> {
+    let enabled = conf.get_bool("dataset_cache.enabled")?;
+    let max_seeds = get_u64_no_zero(conf, "dataset_cache.max_seeds")?;
+    let mut dir = bandit_tools::mithril_folder();
+    dir.push("dataset_cache");
+    Ok(DatasetCacheConfig {
+        enabled,
+        max_seeds: max_seeds as usize,
+        dir,
+    })
+}
+
+fn shared_dataset_config(conf: &Config) -> Result<SharedDatasetConfig, ConfigError> {
+    let enabled = conf.get_bool("shared_dataset.enabled")?;
+    let max_seeds = get_u64_no_zero(conf, "shared_dataset.max_seeds")?;
+    let dir = conf.get_string("shared_dataset.dir")?;
+    Ok(SharedDatasetConfig {
+        enabled,
+        max_seeds: max_seeds as usize,
+        dir: PathBuf::from(dir),
+    })
+}
+
+fn hardware_config(conf: &Config) -> Result<HardwareConfig, ConfigError> {
+    let huge_pages = conf.get_bool("hardware.huge_pages")?;
+    let huge_pages_1g = conf.get_bool("hardware.huge_pages_1g")?;
+    let aes = conf.get_string("hardware.aes")?.parse().map_err(|_| {
+        ConfigError::Message("aes has to be auto, hardware, aesni or software".to_string())
+    })?;
+    let memory_mode = conf
+        .get_string("hardware.memory_mode")?
+        .parse()
+        .map_err(|_| {
+            ConfigError::Message("memory_mode has to be auto, light, hybrid or full".to_string())
+        })?;
+    let hybrid_cache_mib = get_u64_no_zero(conf, "hardware.hybrid_cache_mib")? as usize;
+    let numa = conf.get_bool("hardware.numa")?;
+    let dataset_init = conf
+        .get_string("hardware.dataset_init")?
+        .parse()
+        .map_err(|_| {
+            ConfigError::Message(
+                "dataset_init has to be auto, compiled, avx2 or interpreted".to_string(),
+            )
+        })?;
+    Ok(HardwareConfig {
+        huge_pages,
+        huge_pages_1g,
+        aes,
+        memory_mode,
+        hybrid_cache_mib,
+        numa,
+        dataset_init,
     })
 }
 
@@ -75,11 +144,35 @@ fn worker_config(conf: &Config) -> Result<WorkerConfig, ConfigError> {
 
     let auto_tune_log = conf.get_string("worker.auto_tune_log")?;
 
+    let randomx_mode = conf
+        .get_string("worker.randomx_mode")?
+        .parse()
+        .map_err(|_| {
+            ConfigError::Message("randomx_mode has to be interpreted or compiled".to_string())
+        })?;
+
+    let randomx_variant = conf
+        .get_string("worker.randomx_variant")?
+        .parse()
+        .map_err(|_| {
+            ConfigError::Message(
+                "randomx_variant has to be one of rx/0, rx/wow, rx/arq, rx/loki, rx/sfx, rx/keva"
+                    .to_string(),
+            )
+        })?;
+
+    let self_test = conf.get_bool("worker.self_test")?;
+    let verify_shares = conf.get_bool("worker.verify_shares")?;
+
     Ok(WorkerConfig {
         num_threads: num_threads as u64,
         auto_tune,
         auto_tune_interval_minutes: auto_tune_interval_minutes as u64,
         auto_tune_log,
+        randomx_mode,
+        randomx_variant,
+        self_test,
+        verify_shares,
     })
 }
 
@@ -0,0 +1,425 @@
+extern crate argon2;
+
+//In-tree Argon2d (v1.3) block filling for the seed memory. The first two blocks of
+//each lane are still computed by the argon2 crate (`argon2::core::initialize`), all
+//other blocks are filled here with a scalar or an AVX2 implementation of the block function.
+//
+//Every block of a lane depends on the block before it, so a single lane (rx/0 has
+//only one) cannot be filled by more than one thread. Variants with more lanes fill
+//the lanes of a slice in parallel.
+
+use self::argon2::block::Block;
+use std::thread;
+
+#[cfg(all(target_arch = "x86_64", not(feature = "portable")))]
+use std::arch::x86_64::{
+    __m256i, _mm256_add_epi64, _mm256_loadu_si256, _mm256_mul_epu32, _mm256_permute2x128_si256,
+    _mm256_permute4x64_epi64, _mm256_setr_epi8, _mm256_setzero_si256, _mm256_shuffle_epi32,
+    _mm256_shuffle_epi8, _mm256_srli_epi64, _mm256_storeu_si256, _mm256_xor_si256,
+};
+
+use super::params::RandomXParams;
+
+const SYNC_POINTS: usize = 4;
+const QWORDS_IN_BLOCK: usize = 128;
+
+/// The implementation of the Argon2 block function.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockFiller {
+    Scalar,
+    /// needs a CPU with AVX2, check with `BlockFiller::avx2_available`
+    Avx2,
+}
+
+impl BlockFiller {
+    /// The fastest implementation supported by this CPU.
+    pub fn detect() -> BlockFiller {
+        if BlockFiller::avx2_available() {
+            BlockFiller::Avx2
+        } else {
+            BlockFiller::Scalar
+        }
+    }
+
+    #[cfg(all(target_arch = "x86_64", not(feature = "portable")))]
+    pub fn avx2_available() -> bool {
+        is_x86_feature_detected!("avx2")
+    }
+
+    #[cfg(any(not(target_arch = "x86_64"), feature = "portable"))]
+    pub fn avx2_available() -> bool {
+        false
+    }
+
+    fn fill_fn(self) -> FillBlockFn {
+        match self {
+            BlockFiller::Scalar => fill_block_scalar,
+            BlockFiller::Avx2 => avx2_fill_fn(),
+        }
+    }
+}
+
+//(prev, ref, next, with_xor)
+type FillBlockFn = unsafe fn(&Block, &Block, &mut Block, bool);
+
+#[cfg(all(target_arch = "x86_64", not(feature = "portable")))]
+fn avx2_fill_fn() -> FillBlockFn {
+    assert!(BlockFiller::avx2_available(), "AVX2 not supported");
+    fill_block_avx2
+}
+
+#[cfg(any(not(target_arch = "x86_64"), feature = "portable"))]
+fn avx2_fill_fn() -> FillBlockFn {
+    panic!("AVX2 not supported")
+}
+
+#[derive(Clone, Copy)]
+struct Geometry {
+    lanes: usize,
+    lane_length: usize,
+    segment_length: usize,
+}
+
+//The blocks shared by the lane threads. Each thread only writes the blocks of its
+//own segment and Argon2 only references blocks that are not written in the same slice
+//(other lanes) or that were already written by the thread itself (own lane).
+struct SharedBlocks {
+    ptr: *mut Block,
+    len: usize,
+}
+
+unsafe impl Sync for SharedBlocks {}
+
+impl SharedBlocks {
+    unsafe fn get(&self, ix: usize) -> &Block {
+        debug_assert!(ix < self.len);
+        &*self.ptr.add(ix)
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn get_mut(&self, ix: usize) -> &mut Block {
+        debug_assert!(ix < self.len);
+        &mut *self.ptr.add(ix)
+    }
+}
+
+/// Fills all blocks of an Argon2d memory with `argon_iterations` passes. The first
+/// two blocks of each lane must already be initialised.
+pub fn fill_memory_blocks(blocks: &mut [Block], params: &RandomXParams, filler: BlockFiller) {
+    let lanes = params.argon_lanes as usize;
+    let lane_length = params.argon_memory as usize / lanes;
+    assert_eq!(blocks.len(), lanes * lane_length);
+    let geometry = Geometry {
+        lanes,
+        lane_length,
+        segment_length: lane_length / SYNC_POINTS,
+    };
+    let fill = filler.fill_fn();
+    let shared = SharedBlocks {
+        ptr: blocks.as_mut_ptr(),
+        len: blocks.len(),
+    };
+
+    for pass in 0..params.argon_iterations as usize {
+        for slice in 0..SYNC_POINTS {
+            if lanes == 1 {
+                fill_segment(&shared, geometry, fill, pass, 0, slice);
+                continue;
+            }
+            thread::scope(|scope| {
+                for lane in 0..lanes {
+                    let shared = &shared;
+                    scope.spawn(move || fill_segment(shared, geometry, fill, pass, lane, slice));
+                }
+            });
+        }
+    }
+}
+
+fn fill_segment(
+    blocks: &SharedBlocks,
+    geometry: Geometry,
+    fill: FillBlockFn,
+    pass: usize,
+    lane: usize,
+    slice: usize,
+) {
+    let first_slice = pass == 0 && slice == 0;
+    let start_index = if first_slice { 2 } else { 0 };
+    let lane_offset = lane * geometry.lane_length;
+
+    for index in start_index..geometry.segment_length {
+        let curr = lane_offset + slice * geometry.segment_length + index;
+        let prev = if slice == 0 && index == 0 {
+            //the last block of the lane
+            lane_offset + geometry.lane_length - 1
+        } else {
+            curr - 1
+        };
+
+        unsafe {
+            let pseudo_rand = blocks.get(prev)[0];
+            let ref_lane = if first_slice {
+                lane
+            } else {
+                ((pseudo_rand >> 32) % geometry.lanes as u64) as usize
+            };
+            let ref_index = index_alpha(
+                geometry,
+                pass,
+                slice,
+                index,
+                pseudo_rand as u32,
+                ref_lane == lane,
+            );
+            let reference = ref_lane * geometry.lane_length + ref_index;
+            fill(
+                blocks.get(prev),
+                blocks.get(reference),
+                blocks.get_mut(curr),
+                pass > 0,
+            );
+        }
+    }
+}
+
+//index of the reference block within its lane
+fn index_alpha(
+    geometry: Geometry,
+    pass: usize,
+    slice: usize,
+    index: usize,
+    pseudo_rand: u32,
+    same_lane: bool,
+) -> usize {
+    let segment_length = geometry.segment_length;
+    let reference_area_size = if pass == 0 {
+        if slice == 0 {
+            index - 1
+        } else if same_lane {
+            slice * segment_length + index - 1
+        } else if index == 0 {
+            slice * segment_length - 1
+        } else {
+            slice * segment_length
+        }
+    } else if same_lane {
+        geometry.lane_length - segment_length + index - 1
+    } else if index == 0 {
+        geometry.lane_length - segment_length - 1
+    } else {
+        geometry.lane_length - segment_length
+    } as u64;
+
+    let mut relative_position = u64::from(pseudo_rand);
+    relative_position = (relative_position * relative_position) >> 32;
+    relative_position = reference_area_size - 1 - ((reference_area_size * relative_position) >> 32);
+
+    let start_position = if pass != 0 && slice != SYNC_POINTS - 1 {
+        (slice + 1) * segment_length
+    } else {
+        0
+    };
+    (start_position + relative_position as usize) % geometry.lane_length
+}
+
+fn words(block: &Block) -> &[u64; QWORDS_IN_BLOCK] {
+    //as_u8 is a view of the [u64; 128] the block consists of
+    unsafe { &*(block.as_u8().as_ptr() as *const [u64; QWORDS_IN_BLOCK]) }
+}
+
+fn words_mut(block: &mut Block) -> &mut [u64; QWORDS_IN_BLOCK] {
+    unsafe { &mut *(block.as_u8_mut().as_mut_ptr() as *mut [u64; QWORDS_IN_BLOCK]) }
+}
+
+//scalar
+
+unsafe fn fill_block_scalar(prev: &Block, reference: &Block, next: &mut Block, with_xor: bool) {
+    let prev = words(prev);
+    let reference = words(reference);
+    let next = words_mut(next);
+
+    let mut r = [0u64; QWORDS_IN_BLOCK];
+    for i in 0..QWORDS_IN_BLOCK {
+        r[i] = prev[i] ^ reference[i];
+    }
+    let mut tmp = r;
+    if with_xor {
+        for i in 0..QWORDS_IN_BLOCK {
+            tmp[i] ^= next[i];
+        }
+    }
+
+    //rows of 16 words: (0..16), (16..32), ..., (112..128)
+    for i in 0..8 {
+        let mut v = [0u64; 16];
+        v.copy_from_slice(&r[16 * i..16 * i + 16]);
+        round(&mut v);
+        r[16 * i..16 * i + 16].copy_from_slice(&v);
+    }
+
+    //columns of pairs: (0,1,16,17,...,112,113), ..., (14,15,30,31,...,126,127)
+    for i in 0..8 {
+        let mut v = [0u64; 16];
+        for j in 0..8 {
+            v[2 * j] = r[2 * i + 16 * j];
+            v[2 * j + 1] = r[2 * i + 16 * j + 1];
+        }
+        round(&mut v);
+        for j in 0..8 {
+            r[2 * i + 16 * j] = v[2 * j];
+            r[2 * i + 16 * j + 1] = v[2 * j + 1];
+        }
+    }
+
+    for i in 0..QWORDS_IN_BLOCK {
+        next[i] = tmp[i] ^ r[i];
+    }
+}
+
+#[inline(always)]
+fn round(v: &mut [u64; 16]) {
+    g(v, 0, 4, 8, 12);
+    g(v, 1, 5, 9, 13);
+    g(v, 2, 6, 10, 14);
+    g(v, 3, 7, 11, 15);
+    g(v, 0, 5, 10, 15);
+    g(v, 1, 6, 11, 12);
+    g(v, 2, 7, 8, 13);
+    g(v, 3, 4, 9, 14);
+}
+
+#[inline(always)]
+fn g(v: &mut [u64; 16], a: usize, b: usize, c: usize, d: usize) {
+    v[a] = bla_mka(v[a], v[b]);
+    v[d] = (v[d] ^ v[a]).rotate_right(32);
+    v[c] = bla_mka(v[c], v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(24);
+    v[a] = bla_mka(v[a], v[b]);
+    v[d] = (v[d] ^ v[a]).rotate_right(16);
+    v[c] = bla_mka(v[c], v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(63);
+}
+
+#[inline(always)]
+fn bla_mka(x: u64, y: u64) -> u64 {
+    let xy = (x & 0xFFFF_FFFF) * (y & 0xFFFF_FFFF);
+    x.wrapping_add(y).wrapping_add(xy.wrapping_add(xy))
+}
+
+//AVX2, each vector holds 4 consecutive words of the block
+
+#[cfg(all(target_arch = "x86_64", not(feature = "portable")))]
+#[target_feature(enable = "avx2")]
+unsafe fn fill_block_avx2(prev: &Block, reference: &Block, next: &mut Block, with_xor: bool) {
+    let prev = words(prev).as_ptr() as *const __m256i;
+    let reference = words(reference).as_ptr() as *const __m256i;
+    let next = words_mut(next).as_mut_ptr() as *mut __m256i;
+
+    let mut r = [_mm256_setzero_si256(); 32];
+    let mut tmp = r;
+    for i in 0..32 {
+        r[i] = _mm256_xor_si256(
+            _mm256_loadu_si256(prev.add(i)),
+            _mm256_loadu_si256(reference.add(i)),
+        );
+        tmp[i] = if with_xor {
+            _mm256_xor_si256(r[i], _mm256_loadu_si256(next.add(i)))
+        } else {
+            r[i]
+        };
+    }
+
+    //rows: words 16i..16i+16 are the vectors 4i..4i+4
+    for i in 0..8 {
+        let (mut a, mut b, mut c, mut d) = (r[4 * i], r[4 * i + 1], r[4 * i + 2], r[4 * i + 3]);
+        round_avx2(&mut a, &mut b, &mut c, &mut d);
+        r[4 * i] = a;
+        r[4 * i + 1] = b;
+        r[4 * i + 2] = c;
+        r[4 * i + 3] = d;
+    }
+
+    //columns: the column of words 2i, 2i + 1 consists of the low (i even) or high (i odd)
+    //halves of the vectors i / 2 + 4k, so two columns are processed together
+    for j in 0..4 {
+        let mut v = [r[0]; 8];
+        for k in 0..4 {
+            let lo = r[j + 8 * k];
+            let hi = r[j + 8 * k + 4];
+            v[k] = _mm256_permute2x128_si256(lo, hi, 0x20);
+            v[k + 4] = _mm256_permute2x128_si256(lo, hi, 0x31);
+        }
+        let (first, second) = v.split_at_mut(4);
+        round_avx2_slice(first);
+        round_avx2_slice(second);
+        for k in 0..4 {
+            r[j + 8 * k] = _mm256_permute2x128_si256(v[k], v[k + 4], 0x20);
+            r[j + 8 * k + 4] = _mm256_permute2x128_si256(v[k], v[k + 4], 0x31);
+        }
+    }
+
+    for i in 0..32 {
+        _mm256_storeu_si256(next.add(i), _mm256_xor_si256(tmp[i], r[i]));
+    }
+}
+
+#[cfg(all(target_arch = "x86_64", not(feature = "portable")))]
+#[target_feature(enable = "avx2")]
+#[inline]
+unsafe fn round_avx2_slice(v: &mut [__m256i]) {
+    let (mut a, mut b, mut c, mut d) = (v[0], v[1], v[2], v[3]);
+    round_avx2(&mut a, &mut b, &mut c, &mut d);
+    v[0] = a;
+    v[1] = b;
+    v[2] = c;
+    v[3] = d;
+}
+
+//the round on 16 words with a = (v0..v3), b = (v4..v7), c = (v8..v11), d = (v12..v15),
+//the diagonal step rotates b, c and d so that the lanes line up
+#[cfg(all(target_arch = "x86_64", not(feature = "portable")))]
+#[target_feature(enable = "avx2")]
+#[inline]
+unsafe fn round_avx2(a: &mut __m256i, b: &mut __m256i, c: &mut __m256i, d: &mut __m256i) {
+    g_avx2(a, b, c, d);
+    *b = _mm256_permute4x64_epi64(*b, 0x39);
+    *c = _mm256_permute4x64_epi64(*c, 0x4e);
+    *d = _mm256_permute4x64_epi64(*d, 0x93);
+    g_avx2(a, b, c, d);
+    *b = _mm256_permute4x64_epi64(*b, 0x93);
+    *c = _mm256_permute4x64_epi64(*c, 0x4e);
+    *d = _mm256_permute4x64_epi64(*d, 0x39);
+}
+
+#[cfg(all(target_arch = "x86_64", not(feature = "portable")))]
+#[target_feature(enable = "avx2")]
+#[inline]
+unsafe fn g_avx2(a: &mut __m256i, b: &mut __m256i, c: &mut __m256i, d: &mut __m256i) {
+    let rot24 = _mm256_setr_epi8(
+        3, 4, 5, 6, 7, 0, 1, 2, 11, 12, 13, 14, 15, 8, 9, 10, 3, 4, 5, 6, 7, 0, 1, 2, 11, 12, 13,
+        14, 15, 8, 9, 10,
+    );
+    let rot16 = _mm256_setr_epi8(
+        2, 3, 4, 5, 6, 7, 0, 1, 10, 11, 12, 13, 14, 15, 8, 9, 2, 3, 4, 5, 6, 7, 0, 1, 10, 11, 12,
+        13, 14, 15, 8, 9,
+    );
+
+    *a = bla_mka_avx2(*a, *b);
+    *d = _mm256_shuffle_epi32(_mm256_xor_si256(*d, *a), 0xb1);
+    *c = bla_mka_avx2(*c, *d);
+    *b = _mm256_shuffle_epi8(_mm256_xor_si256(*b, *c), rot24);
+    *a = bla_mka_avx2(*a, *b);
+    *d = _mm256_shuffle_epi8(_mm256_xor_si256(*d, *a), rot16);
+    *c = bla_mka_avx2(*c, *d);
+    let x = _mm256_xor_si256(*b, *c);
+    *b = _mm256_xor_si256(_mm256_srli_epi64(x, 63), _mm256_add_epi64(x, x));
+}
+
+#[cfg(all(target_arch = "x86_64", not(feature = "portable")))]
+#[target_feature(enable = "avx2")]
+#[inline]
+unsafe fn bla_mka_avx2(x: __m256i, y: __m256i) -> __m256i {
+    let xy = _mm256_mul_epu32(x, y);
+    _mm256_add_epi64(_mm256_add_epi64(x, y), _mm256_add_epi64(xy, xy))
+}
@@ -0,0 +1,295 @@
+//Disassembler and assembler for RandomX programs in the assembly syntax of the
+//reference implementation (the `Display` format of `Program`), e.g.
+//
+//  ; program 0
+//  IADD_RS r0, r3, SHFT 2
+//  IMUL_M r1, L1[r4-1033394175]
+//  CBRANCH r2, -1224145731, COND 3
+//
+//Everything after a `;` is a comment.
+
+use std::str::FromStr;
+use std::sync::Arc;
+
+use super::memory::VmMemory;
+use super::program::{
+    track_register_usage, Decoder, Instr, Opcode, Program, MAX_FLOAT_REG, MAX_PROGRAM_SIZE, MAX_REG,
+};
+use super::trace::{TracePos, Tracer};
+use super::vm::{new_vm, Vm};
+
+/// Generates the programs that are run for hashing `input` with the key of `mem`
+/// (`program_count` programs of the variant of the memory).
+pub fn generate_programs(mem: Arc<VmMemory>, input: &[u8]) -> Vec<Program> {
+    let mut vm = new_vm(mem);
+    let mut recorder = ProgramRecorder {
+        programs: Vec::new(),
+    };
+    vm.calculate_hash_traced(input, &mut recorder);
+    recorder.programs
+}
+
+//records the program in the first iteration of each program
+struct ProgramRecorder {
+    programs: Vec<Program>,
+}
+
+impl Tracer for ProgramRecorder {
+    fn iteration(&mut self, vm: &Vm, pos: TracePos, _scratchpad: [u64; 2], _dataset: u64) {
+        if pos.iteration == 0 {
+            self.programs.push((*vm.prog).clone());
+        }
+    }
+}
+
+/// The programs as text, each one preceded by a `; program <n>` comment.
+pub fn disassemble(programs: &[Program]) -> String {
+    let mut text = String::new();
+    for (i, program) in programs.iter().enumerate() {
+        text.push_str(&format!("; program {}\n{}", i, program));
+    }
+    text
+}
+
+/// Parses a program, the scratchpad levels are translated to the masks of the
+/// variant of `decoder`. The CBRANCH targets are computed like in the decoder,
+/// the entropy of the program is 0.
+pub fn assemble(text: &str, decoder: &Decoder) -> Result<Program, String> {
+    let mut program = Program::default();
+    for (line_ix, line) in text.lines().enumerate() {
+        let code = line.split(';').next().unwrap_or("").trim();
+        if code.is_empty() {
+            continue;
+        }
+        if program.size == MAX_PROGRAM_SIZE {
+            return Err(format!("more than {} instructions", MAX_PROGRAM_SIZE));
+        }
+        let mut instr = parse_instr(code, decoder)
+            .map_err(|e| format!("line {}: {} ({})", line_ix + 1, e, code))?;
+        track_register_usage(&mut instr, program.size as i32, &mut program.register_usage);
+        program.program[program.size] = instr;
+        program.size += 1;
+    }
+    Ok(program)
+}
+
+/// Parses a rx/0 program.
+impl FromStr for Program {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Program, String> {
+        assemble(text, &Decoder::default())
+    }
+}
+
+fn parse_instr(code: &str, decoder: &Decoder) -> Result<Instr, String> {
+    let mut parts = code.splitn(2, char::is_whitespace);
+    let mnemonic = parts.next().unwrap_or("");
+    let op = Opcode::from_str(mnemonic).map_err(|_| "unknown instruction".to_string())?;
+    let operands: Vec<&str> = parts
+        .next()
+        .unwrap_or("")
+        .split(',')
+        .map(str::trim)
+        .filter(|o| !o.is_empty())
+        .collect();
+    let operand = |i: usize| -> Result<&str, String> {
+        operands
+            .get(i)
+            .cloned()
+            .ok_or_else(|| "missing operand".to_string())
+    };
+
+    let expected_operands = match op {
+        Opcode::INEG_R | Opcode::FSWAP_R | Opcode::FSCAL_R | Opcode::FSQRT_R => 1,
+        Opcode::CBRANCH => 3,
+        Opcode::IADD_RS => {
+            if operands.len() == 4 {
+                4
+            } else {
+                3
+            }
+        }
+        Opcode::NOP => operands.len().min(1),
+        _ => 2,
+    };
+    if operands.len() != expected_operands {
+        return Err(format!("expected {} operands", expected_operands));
+    }
+
+    let instr = match op {
+        Opcode::IADD_RS => {
+            let instr = Instr::new(op, reg(operand(0)?, 'r')?, reg(operand(1)?, 'r')?)
+                .with_mode(keyword(operand(expected_operands - 1)?, "SHFT", 3)?);
+            if expected_operands == 4 {
+                instr.with_imm(imm(operand(2)?)?)
+            } else {
+                instr
+            }
+        }
+        Opcode::IADD_M
+        | Opcode::ISUB_M
+        | Opcode::IMUL_M
+        | Opcode::IMULH_M
+        | Opcode::ISMULH_M
+        | Opcode::IXOR_M => mem_instr(op, reg(operand(0)?, 'r')?, operand(1)?, decoder)?,
+        Opcode::ISUB_R | Opcode::IMUL_R | Opcode::IXOR_R => {
+            reg_or_imm_instr(op, reg(operand(0)?, 'r')?, operand(1)?, None)?
+        }
+        Opcode::IROR_R | Opcode::IROL_R => {
+            reg_or_imm_instr(op, reg(operand(0)?, 'r')?, operand(1)?, Some(63))?
+        }
+        Opcode::IMULH_R | Opcode::ISMULH_R | Opcode::ISWAP_R => {
+            Instr::new(op, reg(operand(0)?, 'r')?, reg(operand(1)?, 'r')?)
+        }
+        Opcode::IMUL_RCP => {
+            let divisor = u32::from_str(operand(1)?).map_err(|_| "invalid divisor".to_string())?;
+            Instr::new(op, reg(operand(0)?, 'r')?, 0).with_imm(divisor as i32)
+        }
+        Opcode::INEG_R => Instr::new(op, reg(operand(0)?, 'r')?, 0),
+        Opcode::FSWAP_R => {
+            let dst = operand(0)?;
+            if dst.starts_with('e') {
+                Instr::new(op, reg(dst, 'e')? + MAX_FLOAT_REG, 0)
+            } else {
+                Instr::new(op, reg(dst, 'f')?, 0)
+            }
+        }
+        Opcode::FADD_R | Opcode::FSUB_R => {
+            Instr::new(op, reg(operand(0)?, 'f')?, reg(operand(1)?, 'a')?)
+        }
+        Opcode::FMUL_R => Instr::new(op, reg(operand(0)?, 'e')?, reg(operand(1)?, 'a')?),
+        Opcode::FADD_M | Opcode::FSUB_M => {
+            mem_instr(op, reg(operand(0)?, 'f')?, operand(1)?, decoder)?
+        }
+        Opcode::FDIV_M => mem_instr(op, reg(operand(0)?, 'e')?, operand(1)?, decoder)?,
+        Opcode::FSCAL_R => Instr::new(op, reg(operand(0)?, 'f')?, 0),
+        Opcode::FSQRT_R => Instr::new(op, reg(operand(0)?, 'e')?, 0),
+        Opcode::CBRANCH => Instr::new(op, reg(operand(0)?, 'r')?, 0)
+            .with_mode(keyword(operand(2)?, "COND", 15)?)
+            .with_imm(imm(operand(1)?)?),
+        Opcode::CFROUND => {
+            Instr::new(op, 0, reg(operand(0)?, 'r')?).with_imm(bounded_imm(operand(1)?, 63)?)
+        }
+        Opcode::ISTORE => {
+            let src = reg(operand(1)?, 'r')?;
+            let (level, addr) = mem(operand(0)?)?;
+            match addr {
+                MemAddr::Reg(dst, offset) => {
+                    decoder.with_mem(Instr::new(op, dst, src).with_imm(offset), level)
+                }
+                MemAddr::Imm(_) => return Err("ISTORE needs an address register".to_string()),
+            }
+        }
+        Opcode::NOP => match operands.first() {
+            Some(o) => Instr::new(op, 0, 0).with_imm(imm(o)?),
+            None => Instr::new(op, 0, 0),
+        },
+    };
+    Ok(instr)
+}
+
+//memory operand: L<level>[r<reg><+|-><imm>] or L3[<imm>]
+enum MemAddr {
+    Reg(usize, i32),
+    Imm(i32),
+}
+
+fn mem_instr(op: Opcode, dst: usize, operand: &str, decoder: &Decoder) -> Result<Instr, String> {
+    let (level, addr) = mem(operand)?;
+    let instr = match addr {
+        MemAddr::Reg(src, offset) => Instr::new(op, dst, src).with_imm(offset),
+        MemAddr::Imm(addr) => {
+            let float_op = op == Opcode::FADD_M || op == Opcode::FSUB_M || op == Opcode::FDIV_M;
+            if level != 3 || float_op {
+                return Err("only integer instructions read from L3[imm]".to_string());
+            }
+            Instr::new(op, dst, 0).with_src_imm(addr)
+        }
+    };
+    let instr = decoder.with_mem(instr, level);
+    if instr.src_imm && instr.imm as u32 & !instr.mem_mask != 0 {
+        return Err("address outside of the scratchpad".to_string());
+    }
+    Ok(instr)
+}
+
+fn mem(operand: &str) -> Result<(u8, MemAddr), String> {
+    let invalid = || "invalid memory operand".to_string();
+    if !operand.starts_with('L') || !operand.ends_with(']') {
+        return Err(invalid());
+    }
+    let open = operand.find('[').ok_or_else(invalid)?;
+    let level = u8::from_str(&operand[1..open]).map_err(|_| invalid())?;
+    if !(1..=3).contains(&level) {
+        return Err(invalid());
+    }
+    let addr = &operand[open + 1..operand.len() - 1];
+    if addr.starts_with('r') {
+        let sign = addr.find(['+', '-']).ok_or_else(invalid)?;
+        let src = reg(&addr[..sign], 'r')?;
+        let offset = imm(addr[sign..].trim_start_matches('+'))?;
+        Ok((level, MemAddr::Reg(src, offset)))
+    } else {
+        Ok((level, MemAddr::Imm(imm(addr)?)))
+    }
+}
+
+//the source is a register or an immediate (bounded by max_imm if given)
+fn reg_or_imm_instr(
+    op: Opcode,
+    dst: usize,
+    operand: &str,
+    max_imm: Option<i32>,
+) -> Result<Instr, String> {
+    if operand.starts_with('r') {
+        return Ok(Instr::new(op, dst, reg(operand, 'r')?));
+    }
+    let v = match max_imm {
+        Some(max) => bounded_imm(operand, max)?,
+        None => imm(operand)?,
+    };
+    Ok(Instr::new(op, dst, 0).with_src_imm(v))
+}
+
+fn reg(operand: &str, prefix: char) -> Result<usize, String> {
+    let count = if prefix == 'r' {
+        MAX_REG
+    } else {
+        MAX_FLOAT_REG
+    };
+    if operand.starts_with(prefix) {
+        if let Ok(ix) = usize::from_str(&operand[1..]) {
+            if ix < count {
+                return Ok(ix);
+            }
+        }
+    }
+    Err(format!(
+        "expected register {}0-{}{}",
+        prefix,
+        prefix,
+        count - 1
+    ))
+}
+
+fn imm(operand: &str) -> Result<i32, String> {
+    i32::from_str(operand).map_err(|_| "invalid immediate".to_string())
+}
+
+fn bounded_imm(operand: &str, max: i32) -> Result<i32, String> {
+    let v = imm(operand)?;
+    if !(0..=max).contains(&v) {
+        return Err(format!("immediate has to be 0-{}", max));
+    }
+    Ok(v)
+}
+
+//<keyword> <n> with n <= max, e.g. COND 3
+fn keyword(operand: &str, keyword: &str, max: u8) -> Result<u8, String> {
+    let v = operand
+        .strip_prefix(keyword)
+        .and_then(|v| u8::from_str(v.trim()).ok())
+        .filter(|v| *v <= max);
+    v.ok_or_else(|| format!("expected {} 0-{}", keyword, max))
+}
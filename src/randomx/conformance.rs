@@ -0,0 +1,397 @@
+extern crate serde_json;
+
+//Runs a subset of the RandomX reference vectors (from the tests of the reference
+//implementation, stored in `conformance.json`) against this implementation. The vectors
+//check the building blocks (reciprocal, AES generators, cache, dataset) and complete
+//hashes, so a new backend can be validated with one call, e.g.
+//
+//  let report = conformance::run(&conformance::reference_vectors(), RandomXMode::Light, VmMode::Compiled);
+//  assert!(report.is_ok(), "{}", report);
+//
+//The AesHash1R, SuperscalarHash and per-instruction vectors are not in the file yet.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use super::super::byte_string::{string_to_u8_array, u8_array_to_string};
+use super::common::randomx_reciprocal;
+use super::hash::{fill_aes_1rx4_u64, gen_program_aes_4rx4};
+use super::hasher::{RandomX, RandomXMode};
+use super::m128::m128i;
+use super::memory::{init_dataset_item, init_dataset_item_interpreted, VmMemory};
+use super::params::RandomXVariant;
+use super::vm::{new_vm_with_mode, VmMode};
+
+const REFERENCE_VECTORS: &str = include_str!("conformance.json");
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Vectors {
+    /// incremented on every change of the vectors
+    pub version: u32,
+    /// the variant all vectors are for, e.g. "rx/0"
+    pub variant: String,
+    pub reciprocals: Vec<ReciprocalVector>,
+    pub aes: Vec<AesVector>,
+    pub cache: Vec<CacheVector>,
+    pub dataset: Vec<DatasetVector>,
+    pub hashes: Vec<HashVector>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ReciprocalVector {
+    pub name: String,
+    pub divisor: u64,
+    pub reciprocal: u64,
+}
+
+/// Output of an AES generator ("1rx4" or "4rx4") for a 64 byte state.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AesVector {
+    pub name: String,
+    pub generator: String,
+    pub state: String,
+    pub outputs: Vec<AesOutput>,
+}
+
+/// The expected output bytes at a byte offset.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AesOutput {
+    pub offset: usize,
+    pub bytes: String,
+}
+
+/// 64 bit words of the initialised cache (seed memory) of a key.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CacheVector {
+    pub name: String,
+    pub key: String,
+    pub words: Vec<CacheWord>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CacheWord {
+    pub index: usize,
+    pub value: String,
+}
+
+/// The first 64 bit word of dataset items of a key.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DatasetVector {
+    pub name: String,
+    pub key: String,
+    pub items: Vec<DatasetWord>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DatasetWord {
+    pub item: u64,
+    pub value: String,
+}
+
+/// Key, input and hash are hex encoded.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HashVector {
+    pub name: String,
+    pub key: String,
+    pub input: String,
+    pub hash: String,
+}
+
+/// A check that failed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mismatch {
+    /// the name of the vector
+    pub vector: String,
+    /// what was checked, e.g. "item 10000000 (interpreted)"
+    pub check: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: {}: expected {}, got {}",
+            self.vector, self.check, self.expected, self.actual
+        )
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    /// the number of checks run
+    pub checks: usize,
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+
+    fn check(&mut self, vector: &str, check: String, expected: &str, actual: String) {
+        self.checks += 1;
+        if !expected.eq_ignore_ascii_case(&actual) {
+            self.mismatches.push(Mismatch {
+                vector: vector.to_string(),
+                check,
+                expected: expected.to_string(),
+                actual,
+            });
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} checks failed",
+            self.mismatches.len(),
+            self.checks
+        )?;
+        for mismatch in &self.mismatches {
+            write!(f, "\n{}", mismatch)?;
+        }
+        Ok(())
+    }
+}
+
+/// The vectors shipped with mithril (`conformance.json`).
+pub fn reference_vectors() -> Vectors {
+    parse_vectors(REFERENCE_VECTORS).expect("reference vectors")
+}
+
+pub fn parse_vectors(json: &str) -> serde_json::Result<Vectors> {
+    serde_json::from_str(json)
+}
+
+/// Runs all vectors with the given modes. In full mode the dataset items are read
+/// from the initialised dataset, in light mode they are computed with the compiled
+/// and the interpreted superscalar programs. AES is checked with the currently
+/// selected implementation (see `hardware::set_aes_mode`).
+pub fn run(vectors: &Vectors, mode: RandomXMode, vm_mode: VmMode) -> Report {
+    let mut report = Report::default();
+    let variant = match RandomXVariant::from_str(&vectors.variant) {
+        Ok(variant) => variant,
+        Err(_) => {
+            report.check(
+                "variant",
+                "known variant".to_string(),
+                "rx/0|rx/wow|rx/arq|rx/loki|rx/sfx|rx/keva",
+                vectors.variant.clone(),
+            );
+            return report;
+        }
+    };
+
+    for v in &vectors.reciprocals {
+        report.check(
+            &v.name,
+            format!("divisor {}", v.divisor),
+            &v.reciprocal.to_string(),
+            randomx_reciprocal(v.divisor).to_string(),
+        );
+    }
+    for v in &vectors.aes {
+        run_aes(v, &mut report);
+    }
+
+    //the memory is initialised once per key, only one is alive at a time
+    let mut keys = Vec::new();
+    let mut seen = HashSet::new();
+    let all_keys = vectors.cache.iter().map(|v| &v.key);
+    let all_keys = all_keys.chain(vectors.dataset.iter().map(|v| &v.key));
+    for key in all_keys.chain(vectors.hashes.iter().map(|v| &v.key)) {
+        if seen.insert(key.to_lowercase()) {
+            keys.push(key);
+        }
+    }
+    for key in keys {
+        let mut randomx =
+            RandomX::new_with_params(&string_to_u8_array(key), mode, vm_mode, &variant.params());
+        for v in vectors
+            .cache
+            .iter()
+            .filter(|v| v.key.eq_ignore_ascii_case(key))
+        {
+            run_cache(v, &randomx, &mut report);
+        }
+        for v in vectors
+            .dataset
+            .iter()
+            .filter(|v| v.key.eq_ignore_ascii_case(key))
+        {
+            run_dataset(v, &randomx, &mut report);
+        }
+        for v in vectors
+            .hashes
+            .iter()
+            .filter(|v| v.key.eq_ignore_ascii_case(key))
+        {
+            let hash = randomx.hash(&string_to_u8_array(&v.input));
+            report.check(
+                &v.name,
+                format!("{} hash ({} vm)", mode, vm_mode),
+                &v.hash,
+                u8_array_to_string(&hash),
+            );
+        }
+    }
+    report
+}
+
+/// The startup check of the miner: the reciprocal and AES vectors and the hashes of
+/// the first key of the reference vectors in light mode. The hashes are computed with
+/// the pipelined vm functions the workers use.
+pub fn self_test(vm_mode: VmMode) -> Report {
+    let vectors = reference_vectors();
+    let key = vectors.hashes[0].key.clone();
+    let hashes: Vec<HashVector> = vectors
+        .hashes
+        .iter()
+        .filter(|v| v.key == key)
+        .cloned()
+        .collect();
+    let building_blocks = Vectors {
+        cache: Vec::new(),
+        dataset: Vec::new(),
+        hashes: Vec::new(),
+        ..vectors
+    };
+    let mut report = run(&building_blocks, RandomXMode::Light, vm_mode);
+
+    let mem = Arc::new(VmMemory::light(&string_to_u8_array(&key)));
+    let mut vm = new_vm_with_mode(mem, vm_mode);
+    vm.calculate_hash_first(&string_to_u8_array(&hashes[0].input));
+    for (i, v) in hashes.iter().enumerate() {
+        let hash = match hashes.get(i + 1) {
+            Some(next) => vm.calculate_hash_next(&string_to_u8_array(&next.input)),
+            None => vm.calculate_hash_last(),
+        };
+        report.check(
+            &v.name,
+            format!("pipelined light hash ({} vm)", vm_mode),
+            &v.hash,
+            u8_array_to_string(hash.as_bytes()),
+        );
+    }
+    report
+}
+
+fn run_aes(v: &AesVector, report: &mut Report) {
+    let state = string_to_u8_array(&v.state);
+    if state.len() != 64 {
+        report.check(
+            &v.name,
+            "state".to_string(),
+            "64 bytes",
+            format!("{} bytes", state.len()),
+        );
+        return;
+    }
+    let input = [
+        m128i::from_u8(&state[0..16]),
+        m128i::from_u8(&state[16..32]),
+        m128i::from_u8(&state[32..48]),
+        m128i::from_u8(&state[48..64]),
+    ];
+    //both generators produce 64 bytes per round
+    let len = v
+        .outputs
+        .iter()
+        .map(|o| o.offset + o.bytes.len() / 2)
+        .max()
+        .unwrap_or(0);
+    let len = len.div_ceil(64) * 64;
+
+    let output = match v.generator.as_str() {
+        "1rx4" => {
+            let mut words = vec![0u64; len / 8];
+            fill_aes_1rx4_u64(&input, &mut words);
+            words.iter().flat_map(|w| w.to_le_bytes()).collect()
+        }
+        "4rx4" => gen_program_aes_4rx4(&input, len / 16)
+            .iter()
+            .flat_map(|m| {
+                let (h, l) = m.as_i64();
+                let mut bytes = (l as u64).to_le_bytes().to_vec();
+                bytes.extend_from_slice(&(h as u64).to_le_bytes());
+                bytes
+            })
+            .collect::<Vec<u8>>(),
+        _ => {
+            report.check(
+                &v.name,
+                "generator".to_string(),
+                "1rx4|4rx4",
+                v.generator.clone(),
+            );
+            return;
+        }
+    };
+    for o in &v.outputs {
+        let actual = &output[o.offset..o.offset + o.bytes.len() / 2];
+        report.check(
+            &v.name,
+            format!("bytes at offset {}", o.offset),
+            &o.bytes,
+            u8_array_to_string(actual),
+        );
+    }
+}
+
+fn run_cache(v: &CacheVector, randomx: &RandomX, report: &mut Report) {
+    let blocks = &randomx.memory().seed_memory.blocks;
+    for w in &v.words {
+        let actual = blocks
+            .get(w.index / 128)
+            .map(|block| format!("{:016x}", block[w.index % 128]));
+        report.check(
+            &v.name,
+            format!("word {}", w.index),
+            &w.value,
+            actual.unwrap_or_else(|| "no such word".to_string()),
+        );
+    }
+}
+
+fn run_dataset(v: &DatasetVector, randomx: &RandomX, report: &mut Report) {
+    let mem = randomx.memory();
+    for w in &v.items {
+        if w.item >= mem.seed_memory.params.dataset_item_count() as u64 {
+            report.check(
+                &v.name,
+                format!("item {}", w.item),
+                &w.value,
+                "no such item".to_string(),
+            );
+        } else if mem.cache {
+            let item = &mem.dataset_memory[w.item as usize];
+            report.check(
+                &v.name,
+                format!("item {}", w.item),
+                &w.value,
+                format!("{:016x}", item.0[0]),
+            );
+        } else {
+            let item = init_dataset_item(&mem.seed_memory, w.item);
+            report.check(
+                &v.name,
+                format!("item {}", w.item),
+                &w.value,
+                format!("{:016x}", item[0]),
+            );
+            let item = init_dataset_item_interpreted(&mem.seed_memory, w.item);
+            report.check(
+                &v.name,
+                format!("item {} (interpreted)", w.item),
+                &w.value,
+                format!("{:016x}", item[0]),
+            );
+        }
+    }
+}
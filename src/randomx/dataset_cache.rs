@@ -0,0 +1,263 @@
+extern crate argon2;
+extern crate blake2b_simd;
+
+//On-disk cache for the seed memory and the dataset of a seed. One file per seed
+//and variant: a header page (magic, version, seed, item count, checksum) followed
+//by the seed memory blocks and the dataset items. The dataset is mapped back in directly.
+
+use self::argon2::block::Block;
+use self::blake2b_simd::Params;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use super::super::byte_string;
+use super::hardware::{HardwareConfig, PageBuffer};
+use super::memory::{new_argon_memory, DatasetItem, SeedMemory, VmMemory};
+use super::params::RandomXParams;
+
+const MAGIC: &[u8; 8] = b"MTHRLDS\0";
+const VERSION: u32 = 1;
+//the dataset starts page aligned after the header
+const HEADER_SIZE: usize = 4096;
+const MAX_SEED_LEN: usize = 64;
+const CHECKSUM_LEN: usize = 32;
+const CHECKSUM_OFFSET: usize = 16 + MAX_SEED_LEN + 8;
+const BLOCK_SIZE: usize = 1024;
+const FILE_EXTENSION: &str = "dataset";
+const IO_BUFFER_SIZE: usize = 1 << 20;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DatasetCacheConfig {
+    /// store the dataset on disk and reload it for a known seed
+    pub enabled: bool,
+    /// number of seeds kept on disk, the least recently used are deleted
+    pub max_seeds: usize,
+    pub dir: PathBuf,
+}
+
+impl Default for DatasetCacheConfig {
+    fn default() -> DatasetCacheConfig {
+        DatasetCacheConfig {
+            enabled: false,
+            max_seeds: 2,
+            dir: PathBuf::new(),
+        }
+    }
+}
+
+/// Path of the cache file for `key`. The argon salt distinguishes the
+/// variants, it differs for all of them.
+pub fn cache_file(conf: &DatasetCacheConfig, key: &[u8], params: &RandomXParams) -> PathBuf {
+    let mut path = conf.dir.clone();
+    path.push(format!(
+        "{}_{}.{}",
+        byte_string::u8_array_to_string(params.argon_salt),
+        byte_string::u8_array_to_string(key),
+        FILE_EXTENSION
+    ));
+    path
+}
+
+/// Loads the memory for `key` from the cache. Returns `Ok(None)` if there is
+/// no cache file for the key and an error if the file is invalid.
+pub fn load(
+    conf: &DatasetCacheConfig,
+    key: &[u8],
+    params: &RandomXParams,
+    hardware_conf: &HardwareConfig,
+) -> io::Result<Option<VmMemory>> {
+    load_file(
+        &cache_file(conf, key, params),
+        key,
+        params,
+        hardware_conf,
+        false,
+    )
+}
+
+/// Loads the memory for `key` from the cache file at `path`. With `shared` the
+/// dataset is mapped read-only and shared with other processes mapping the file,
+/// otherwise it is mapped privately.
+pub fn load_file(
+    path: &Path,
+    key: &[u8],
+    params: &RandomXParams,
+    hardware_conf: &HardwareConfig,
+    shared: bool,
+) -> io::Result<Option<VmMemory>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    let mut reader = BufReader::with_capacity(IO_BUFFER_SIZE, &file);
+    let mut header = [0; HEADER_SIZE];
+    reader.read_exact(&mut header)?;
+    let (item_count, checksum) = parse_header(&header, key)?;
+    //the vm reads every item of the dataset
+    if item_count != params.dataset_item_count() {
+        return Err(invalid_data(
+            "cache file has the wrong number of dataset items",
+        ));
+    }
+
+    let seed_memory_size = params.argon_memory as usize * BLOCK_SIZE;
+    let expected_len = item_count
+        .checked_mul(64)
+        .and_then(|size| size.checked_add(HEADER_SIZE + seed_memory_size))
+        .ok_or_else(|| invalid_data("cache file has the wrong size"))?;
+    if file.metadata()?.len() != expected_len as u64 {
+        return Err(invalid_data("cache file has the wrong size"));
+    }
+
+    let mut blocks = new_argon_memory(params).blocks;
+    for block in blocks.iter_mut() {
+        reader.read_exact(block.as_u8_mut())?;
+    }
+    let dataset_offset = (HEADER_SIZE + seed_memory_size) as u64;
+    let dataset: PageBuffer<DatasetItem> = if shared {
+        PageBuffer::map_file_shared(&file, dataset_offset, item_count)?
+    } else {
+        PageBuffer::map_file(&file, dataset_offset, item_count)?
+    };
+
+    if compute_checksum(key, &blocks, &dataset) != checksum {
+        return Err(invalid_data("cache file checksum mismatch"));
+    }
+
+    //marks the seed as recently used for the eviction
+    let _ = file.set_modified(SystemTime::now());
+
+    Ok(Some(VmMemory {
+        seed_memory: Arc::new(SeedMemory::from_blocks(key, blocks, params)),
+        dataset_memory: dataset,
+        cache: true,
+        item_cache: None,
+        hardware_conf: hardware_conf.clone(),
+    }))
+}
+
+/// Writes the memory for `key` to the cache and deletes the least
+/// recently used seeds if there are more than `max_seeds` in the cache.
+pub fn store(conf: &DatasetCacheConfig, key: &[u8], mem: &VmMemory) -> io::Result<()> {
+    fs::create_dir_all(&conf.dir)?;
+    store_file(&cache_file(conf, key, &mem.seed_memory.params), key, mem)?;
+    evict(conf)
+}
+
+/// Writes the memory for `key` to the cache file at `path`. The file is written
+/// under a temporary name first, so readers never see a partial file.
+pub fn store_file(path: &Path, key: &[u8], mem: &VmMemory) -> io::Result<()> {
+    if key.len() > MAX_SEED_LEN {
+        return Err(invalid_data("seed too long for the cache"));
+    }
+    let tmp_path = path.with_extension("tmp");
+    let dataset = dataset_bytes(&mem.dataset_memory);
+    let checksum = compute_checksum(key, &mem.seed_memory.blocks, &mem.dataset_memory);
+    {
+        let mut writer = BufWriter::with_capacity(IO_BUFFER_SIZE, File::create(&tmp_path)?);
+        writer.write_all(&header(key, mem.dataset_memory.len(), &checksum))?;
+        for block in mem.seed_memory.blocks.iter() {
+            writer.write_all(block.as_u8())?;
+        }
+        writer.write_all(dataset)?;
+        writer.into_inner()?.sync_all()?;
+    }
+    fs::rename(&tmp_path, path)
+}
+
+fn header(key: &[u8], item_count: usize, checksum: &[u8; CHECKSUM_LEN]) -> Vec<u8> {
+    let mut header = vec![0; HEADER_SIZE];
+    header[0..8].copy_from_slice(MAGIC);
+    header[8..12].copy_from_slice(&VERSION.to_le_bytes());
+    header[12..16].copy_from_slice(&(key.len() as u32).to_le_bytes());
+    header[16..16 + key.len()].copy_from_slice(key);
+    let count_offset = 16 + MAX_SEED_LEN;
+    header[count_offset..count_offset + 8].copy_from_slice(&(item_count as u64).to_le_bytes());
+    header[CHECKSUM_OFFSET..CHECKSUM_OFFSET + CHECKSUM_LEN].copy_from_slice(checksum);
+    header
+}
+
+//returns the item count and the checksum
+fn parse_header(header: &[u8], key: &[u8]) -> io::Result<(usize, [u8; CHECKSUM_LEN])> {
+    if &header[0..8] != MAGIC {
+        return Err(invalid_data("not a dataset cache file"));
+    }
+    if read_u32(&header[8..12]) != VERSION {
+        return Err(invalid_data("unsupported cache file version"));
+    }
+    let key_len = read_u32(&header[12..16]) as usize;
+    if key_len > MAX_SEED_LEN || &header[16..16 + key_len] != key {
+        return Err(invalid_data("cache file is for another seed"));
+    }
+    let count_offset = 16 + MAX_SEED_LEN;
+    let mut count = [0; 8];
+    count.copy_from_slice(&header[count_offset..count_offset + 8]);
+    let mut checksum = [0; CHECKSUM_LEN];
+    checksum.copy_from_slice(&header[CHECKSUM_OFFSET..CHECKSUM_OFFSET + CHECKSUM_LEN]);
+    Ok((u64::from_le_bytes(count) as usize, checksum))
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    let mut b = [0; 4];
+    b.copy_from_slice(bytes);
+    u32::from_le_bytes(b)
+}
+
+fn compute_checksum(
+    key: &[u8],
+    blocks: &[Block],
+    dataset: &PageBuffer<DatasetItem>,
+) -> [u8; CHECKSUM_LEN] {
+    let mut state = Params::new().hash_length(CHECKSUM_LEN).to_state();
+    state.update(key);
+    for block in blocks {
+        state.update(block.as_u8());
+    }
+    state.update(dataset_bytes(dataset));
+    let mut checksum = [0; CHECKSUM_LEN];
+    checksum.copy_from_slice(state.finalize().as_bytes());
+    checksum
+}
+
+fn dataset_bytes(dataset: &PageBuffer<DatasetItem>) -> &[u8] {
+    unsafe {
+        std::slice::from_raw_parts(
+            dataset.as_ptr() as *const u8,
+            std::mem::size_of_val::<[DatasetItem]>(dataset),
+        )
+    }
+}
+
+/// Deletes the least recently used cache files beyond `max_seeds`.
+pub fn evict(conf: &DatasetCacheConfig) -> io::Result<()> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(&conf.dir)? {
+        let path = entry?.path();
+        if is_cache_file(&path) {
+            let modified = fs::metadata(&path)?.modified()?;
+            files.push((modified, path));
+        }
+    }
+    files.sort();
+    while files.len() > conf.max_seeds {
+        let (_, path) = files.remove(0);
+        info!("removing cached dataset {}", path.display());
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+fn is_cache_file(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext == FILE_EXTENSION)
+        .unwrap_or(false)
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
@@ -0,0 +1,477 @@
+extern crate libc;
+
+use std::fs::File;
+use std::io;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
+#[cfg(unix)]
+use std::ptr;
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Mutex;
+use strum::{Display, EnumString};
+
+const HUGE_PAGE_SIZE_2M: usize = 2 * 1024 * 1024;
+const HUGE_PAGE_SIZE_1G: usize = 1024 * 1024 * 1024;
+#[cfg(not(unix))]
+const PAGE_SIZE: usize = 4096;
+#[cfg(target_os = "linux")]
+const THP_ENABLED_PATH: &str = "/sys/kernel/mm/transparent_hugepage/enabled";
+
+//the AesMode selected with set_aes_mode, stored as u8
+static AES_MODE: AtomicU8 = AtomicU8::new(AesMode::Auto as u8);
+//held by with_aes_mode, so the callers that change the mode run one at a time
+static AES_MODE_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HardwareConfig {
+    /// try to back the dataset and scratchpads with huge pages
+    pub huge_pages: bool,
+    /// also try 1GiB pages for allocations that are at least that big
+    pub huge_pages_1g: bool,
+    /// which AES implementation to use
+    pub aes: AesMode,
+    /// how much of the dataset is kept in memory
+    pub memory_mode: MemoryMode,
+    /// size of the dataset item cache in hybrid mode
+    pub hybrid_cache_mib: usize,
+    /// replicate the dataset on each NUMA node and pin the workers to the nodes
+    pub numa: bool,
+    /// how the items of the full dataset are computed
+    pub dataset_init: DatasetInit,
+}
+
+impl Default for HardwareConfig {
+    fn default() -> HardwareConfig {
+        HardwareConfig {
+            huge_pages: true,
+            huge_pages_1g: false,
+            aes: AesMode::Auto,
+            memory_mode: MemoryMode::Full,
+            hybrid_cache_mib: 512,
+            numa: true,
+            dataset_init: DatasetInit::Auto,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumString, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum MemoryMode {
+    /// selects the mode from the available memory when mining starts
+    Auto,
+    /// only the seed memory, every dataset item is computed when it is read
+    Light,
+    /// the seed memory and a bounded cache of computed dataset items
+    Hybrid,
+    /// the seed memory and the complete dataset
+    Full,
+}
+
+/// How the superscalar programs are run when the full dataset is initialised. If
+/// the executor is not available, the next one in this order is used.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumString, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum DatasetInit {
+    /// the fastest available executor (compiled)
+    Auto,
+    /// the programs compiled to native code
+    Compiled,
+    /// four items at once in AVX2 lanes
+    Avx2,
+    /// one item at a time in the interpreter
+    Interpreted,
+}
+
+/// The memory available for new allocations without swapping in bytes, `None`
+/// if it is unknown.
+#[cfg(target_os = "linux")]
+pub fn available_memory() -> Option<u64> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    parse_mem_available(&meminfo)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn available_memory() -> Option<u64> {
+    None
+}
+
+/// Parses the `MemAvailable` line of /proc/meminfo into bytes.
+pub fn parse_mem_available(meminfo: &str) -> Option<u64> {
+    let line = meminfo.lines().find(|l| l.starts_with("MemAvailable:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumString, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum AesMode {
+    /// use hardware AES if the CPU supports it
+    Auto = 0,
+    /// AES-NI, with VAES if the CPU supports it
+    Hardware = 1,
+    Software = 2,
+    /// AES-NI without VAES
+    Aesni = 3,
+}
+
+/// Overrides the AES implementation for the whole process.
+pub fn set_aes_mode(mode: AesMode) {
+    AES_MODE.store(mode as u8, Ordering::Relaxed);
+}
+
+/// Runs `f` with the AES implementation of `mode` and restores the previous mode
+/// afterwards (also if `f` panics). Concurrent calls wait for each other, e.g. tests
+/// comparing the implementations, so `f` must not call `with_aes_mode` again.
+pub fn with_aes_mode<R, F: FnOnce() -> R>(mode: AesMode, f: F) -> R {
+    struct RestoreMode(u8);
+    impl Drop for RestoreMode {
+        fn drop(&mut self) {
+            AES_MODE.store(self.0, Ordering::Relaxed);
+        }
+    }
+
+    let _lock = AES_MODE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let _restore = RestoreMode(AES_MODE.load(Ordering::Relaxed));
+    set_aes_mode(mode);
+    f()
+}
+
+/// Returns whether the AES rounds should be computed with AES-NI. A forced
+/// hardware mode falls back to software AES on a CPU without AES-NI.
+pub fn use_hardware_aes() -> bool {
+    match AES_MODE.load(Ordering::Relaxed) {
+        1 | 3 => has_hardware_aes(),
+        2 => false,
+        _ => has_hardware_aes(),
+    }
+}
+
+/// Returns whether the AES rounds should be computed with VAES, two states
+/// per instruction.
+pub fn use_vaes() -> bool {
+    match AES_MODE.load(Ordering::Relaxed) {
+        0 | 1 => use_hardware_aes() && has_vaes(),
+        _ => false,
+    }
+}
+
+#[cfg(all(target_arch = "x86_64", not(feature = "portable")))]
+pub fn has_hardware_aes() -> bool {
+    is_x86_feature_detected!("aes")
+}
+
+#[cfg(all(target_arch = "x86_64", not(feature = "portable")))]
+pub fn has_vaes() -> bool {
+    is_x86_feature_detected!("vaes") && is_x86_feature_detected!("avx2")
+}
+
+#[cfg(all(target_arch = "x86_64", not(feature = "portable")))]
+pub fn has_avx2() -> bool {
+    is_x86_feature_detected!("avx2")
+}
+
+//the portable m128 backend always uses software AES
+#[cfg(any(not(target_arch = "x86_64"), feature = "portable"))]
+pub fn has_hardware_aes() -> bool {
+    false
+}
+
+#[cfg(any(not(target_arch = "x86_64"), feature = "portable"))]
+pub fn has_vaes() -> bool {
+    false
+}
+
+#[cfg(any(not(target_arch = "x86_64"), feature = "portable"))]
+pub fn has_avx2() -> bool {
+    false
+}
+
+/// The kind of pages an allocation is actually backed with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Display)]
+pub enum PageKind {
+    #[strum(serialize = "1GiB huge")]
+    Huge1G,
+    #[strum(serialize = "2MiB huge")]
+    Huge2M,
+    #[strum(serialize = "transparent huge")]
+    Transparent,
+    #[strum(serialize = "normal")]
+    Normal,
+    #[strum(serialize = "file mapped")]
+    File,
+    #[strum(serialize = "shared file mapped")]
+    SharedFile,
+}
+
+/// A zero initialised buffer allocated directly from the OS via mmap (on other
+/// platforms than unix page aligned from the heap).
+/// Only use it for types where all zero bytes is a valid value.
+pub struct PageBuffer<T: Copy> {
+    ptr: *mut T,
+    len: usize,
+    map_len: usize,
+    kind: PageKind,
+    _marker: PhantomData<T>,
+}
+
+//the buffer exclusively owns its mapping, like a Vec
+unsafe impl<T: Copy + Send> Send for PageBuffer<T> {}
+unsafe impl<T: Copy + Sync> Sync for PageBuffer<T> {}
+
+impl<T: Copy> PageBuffer<T> {
+    /// Allocates `len` zeroed elements. Huge pages are tried first (if enabled
+    /// in the config), then transparent huge pages and finally normal pages.
+    pub fn zeroed(len: usize, conf: &HardwareConfig) -> PageBuffer<T> {
+        PageBuffer::try_zeroed(len, conf).expect("mmap of normal pages failed")
+    }
+
+    /// Like `zeroed`, but returns an error if not even normal pages can be mapped
+    /// (e.g. not enough memory with strict overcommit).
+    pub fn try_zeroed(len: usize, conf: &HardwareConfig) -> io::Result<PageBuffer<T>> {
+        let size = len * std::mem::size_of::<T>();
+        if size == 0 {
+            return Ok(PageBuffer {
+                ptr: NonNull::dangling().as_ptr(),
+                len,
+                map_len: 0,
+                kind: PageKind::Normal,
+                _marker: PhantomData,
+            });
+        }
+        let (ptr, map_len, kind) = map_pages(size, conf)?;
+        Ok(PageBuffer {
+            ptr: ptr as *mut T,
+            len,
+            map_len,
+            kind,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Maps `len` elements of `file`, starting at byte `offset` (must be a multiple
+    /// of the page size). The mapping is private, writes are not visible in the file.
+    #[cfg(unix)]
+    pub fn map_file(file: &File, offset: u64, len: usize) -> io::Result<PageBuffer<T>> {
+        let map_len = len * std::mem::size_of::<T>();
+        if map_len == 0 {
+            return Ok(PageBuffer::zeroed(0, &HardwareConfig::default()));
+        }
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                map_len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                offset as libc::off_t,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(PageBuffer {
+            ptr: ptr as *mut T,
+            len,
+            map_len,
+            kind: PageKind::File,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Maps `len` elements of `file` read-only and shared with all processes that
+    /// map the file. The buffer must not be written, writes crash the process.
+    #[cfg(unix)]
+    pub fn map_file_shared(file: &File, offset: u64, len: usize) -> io::Result<PageBuffer<T>> {
+        let map_len = len * std::mem::size_of::<T>();
+        if map_len == 0 {
+            return Ok(PageBuffer::zeroed(0, &HardwareConfig::default()));
+        }
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                map_len,
+                libc::PROT_READ,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                offset as libc::off_t,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(PageBuffer {
+            ptr: ptr as *mut T,
+            len,
+            map_len,
+            kind: PageKind::SharedFile,
+            _marker: PhantomData,
+        })
+    }
+
+    //without mmap the file is read into the buffer
+    #[cfg(not(unix))]
+    pub fn map_file(file: &File, offset: u64, len: usize) -> io::Result<PageBuffer<T>> {
+        use std::io::{Read, Seek, SeekFrom};
+
+        let mut buffer = PageBuffer::try_zeroed(len, &HardwareConfig::default())?;
+        let size = len * std::mem::size_of::<T>();
+        let bytes = unsafe { std::slice::from_raw_parts_mut(buffer.ptr as *mut u8, size) };
+        let mut file = file;
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(bytes)?;
+        buffer.kind = PageKind::File;
+        Ok(buffer)
+    }
+
+    //the buffer is a private copy, it is not shared with other processes
+    #[cfg(not(unix))]
+    pub fn map_file_shared(file: &File, offset: u64, len: usize) -> io::Result<PageBuffer<T>> {
+        PageBuffer::map_file(file, offset, len)
+    }
+
+    pub fn kind(&self) -> PageKind {
+        self.kind
+    }
+}
+
+impl<T: Copy> Deref for PageBuffer<T> {
+    type Target = [T];
+    fn deref(&self) -> &[T] {
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl<T: Copy> DerefMut for PageBuffer<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr, self.len) }
+    }
+}
+
+impl<T: Copy> Drop for PageBuffer<T> {
+    fn drop(&mut self) {
+        if self.map_len == 0 {
+            return;
+        }
+        free_pages(self.ptr as *mut u8, self.map_len);
+    }
+}
+
+fn map_pages(size: usize, conf: &HardwareConfig) -> io::Result<(*mut u8, usize, PageKind)> {
+    if conf.huge_pages {
+        if conf.huge_pages_1g && size >= HUGE_PAGE_SIZE_1G {
+            let map_len = round_up(size, HUGE_PAGE_SIZE_1G);
+            if let Some(ptr) = mmap_huge(map_len, HUGE_PAGE_SIZE_1G) {
+                return Ok((ptr, map_len, PageKind::Huge1G));
+            }
+        }
+        let map_len = round_up(size, HUGE_PAGE_SIZE_2M);
+        if let Some(ptr) = mmap_huge(map_len, HUGE_PAGE_SIZE_2M) {
+            return Ok((ptr, map_len, PageKind::Huge2M));
+        }
+    }
+
+    let map_len = if conf.huge_pages {
+        round_up(size, HUGE_PAGE_SIZE_2M)
+    } else {
+        size
+    };
+    let ptr = alloc_pages(map_len)?;
+    if conf.huge_pages && madvise_huge(ptr, map_len) {
+        return Ok((ptr, map_len, PageKind::Transparent));
+    }
+    Ok((ptr, map_len, PageKind::Normal))
+}
+
+#[cfg(unix)]
+fn alloc_pages(len: usize) -> io::Result<*mut u8> {
+    mmap(len, 0).ok_or_else(io::Error::last_os_error)
+}
+
+//without mmap the buffer is page aligned from the heap
+#[cfg(not(unix))]
+fn alloc_pages(len: usize) -> io::Result<*mut u8> {
+    let layout = std::alloc::Layout::from_size_align(len, PAGE_SIZE)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let ptr = unsafe { std::alloc::alloc_zeroed(layout) };
+    if ptr.is_null() {
+        return Err(io::Error::new(
+            io::ErrorKind::OutOfMemory,
+            "page allocation failed",
+        ));
+    }
+    Ok(ptr)
+}
+
+#[cfg(unix)]
+fn free_pages(ptr: *mut u8, len: usize) {
+    unsafe {
+        libc::munmap(ptr as *mut libc::c_void, len);
+    }
+}
+
+#[cfg(not(unix))]
+fn free_pages(ptr: *mut u8, len: usize) {
+    unsafe {
+        std::alloc::dealloc(
+            ptr,
+            std::alloc::Layout::from_size_align_unchecked(len, PAGE_SIZE),
+        )
+    }
+}
+
+#[cfg(unix)]
+fn mmap(len: usize, extra_flags: libc::c_int) -> Option<*mut u8> {
+    let ptr = unsafe {
+        libc::mmap(
+            ptr::null_mut(),
+            len,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | extra_flags,
+            -1,
+            0,
+        )
+    };
+    if ptr == libc::MAP_FAILED {
+        return None;
+    }
+    Some(ptr as *mut u8)
+}
+
+#[cfg(target_os = "linux")]
+fn mmap_huge(len: usize, page_size: usize) -> Option<*mut u8> {
+    let size_flag = if page_size == HUGE_PAGE_SIZE_1G {
+        libc::MAP_HUGE_1GB
+    } else {
+        libc::MAP_HUGE_2MB
+    };
+    mmap(len, libc::MAP_HUGETLB | size_flag)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn mmap_huge(_len: usize, _page_size: usize) -> Option<*mut u8> {
+    None
+}
+
+//madvise also succeeds if transparent huge pages are disabled system wide
+#[cfg(target_os = "linux")]
+fn madvise_huge(ptr: *mut u8, len: usize) -> bool {
+    let thp_disabled = std::fs::read_to_string(THP_ENABLED_PATH)
+        .map(|enabled| enabled.contains("[never]"))
+        .unwrap_or(true);
+    if thp_disabled {
+        return false;
+    }
+    unsafe { libc::madvise(ptr as *mut libc::c_void, len, libc::MADV_HUGEPAGE) == 0 }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn madvise_huge(_ptr: *mut u8, _len: usize) -> bool {
+    false
+}
+
+fn round_up(size: usize, page_size: usize) -> usize {
+    size.div_ceil(page_size) * page_size
+}
@@ -1,4 +1,36 @@
+use super::hardware::use_hardware_aes;
 use super::m128::m128i;
+use super::soft_aes;
+#[cfg(all(target_arch = "x86_64", not(feature = "portable")))]
+use super::{hardware::use_vaes, vaes};
+
+//one AES round, either with AES-NI or the software tables
+trait AesRound {
+    fn enc(state: m128i, key: m128i) -> m128i;
+    fn dec(state: m128i, key: m128i) -> m128i;
+}
+
+struct HardAes;
+
+impl AesRound for HardAes {
+    fn enc(state: m128i, key: m128i) -> m128i {
+        state.aesenc(key)
+    }
+    fn dec(state: m128i, key: m128i) -> m128i {
+        state.aesdec(key)
+    }
+}
+
+struct SoftAes;
+
+impl AesRound for SoftAes {
+    fn enc(state: m128i, key: m128i) -> m128i {
+        soft_aes::aesenc(state, key)
+    }
+    fn dec(state: m128i, key: m128i) -> m128i {
+        soft_aes::aesdec(state, key)
+    }
+}
 
 #[allow(overflowing_literals)]
 fn keys_1rx4() -> (m128i, m128i, m128i, m128i) {
@@ -10,14 +42,35 @@ fn keys_1rx4() -> (m128i, m128i, m128i, m128i) {
     )
 }
 
-#[allow(overflowing_literals)]
 pub fn hash_aes_1rx4(input: &[u64]) -> [m128i; 4] {
+    #[cfg(all(target_arch = "x86_64", not(feature = "portable")))]
+    {
+        if use_vaes() {
+            let states = unsafe { vaes::hash_aes_1rx4(hash_states_1rx4(), input) };
+            return finish_hash_1rx4::<HardAes>(states);
+        }
+    }
+    if use_hardware_aes() {
+        hash_aes_1rx4_with::<HardAes>(input)
+    } else {
+        hash_aes_1rx4_with::<SoftAes>(input)
+    }
+}
+
+#[allow(overflowing_literals)]
+fn hash_states_1rx4() -> [m128i; 4] {
+    [
+        m128i::from_i32(0xd7983aad, 0xcc82db47, 0x9fa856de, 0x92b52c0d),
+        m128i::from_i32(0xace78057, 0xf59e125a, 0x15c7b798, 0x338d996e),
+        m128i::from_i32(0xe8a07ce4, 0x5079506b, 0xae62c7d0, 0x6a770017),
+        m128i::from_i32(0x7e994948, 0x79a10005, 0x07ad828d, 0x630a240c),
+    ]
+}
+
+fn hash_aes_1rx4_with<A: AesRound>(input: &[u64]) -> [m128i; 4] {
     debug_assert!(input.len() % 64 == 0);
 
-    let mut state0 = m128i::from_i32(0xd7983aad, 0xcc82db47, 0x9fa856de, 0x92b52c0d);
-    let mut state1 = m128i::from_i32(0xace78057, 0xf59e125a, 0x15c7b798, 0x338d996e);
-    let mut state2 = m128i::from_i32(0xe8a07ce4, 0x5079506b, 0xae62c7d0, 0x6a770017);
-    let mut state3 = m128i::from_i32(0x7e994948, 0x79a10005, 0x07ad828d, 0x630a240c);
+    let [mut state0, mut state1, mut state2, mut state3] = hash_states_1rx4();
 
     let mut i = 0;
     while i < input.len() {
@@ -26,31 +79,52 @@ pub fn hash_aes_1rx4(input: &[u64]) -> [m128i; 4] {
         let in2 = m128i::from_u64(input[i + 5], input[i + 4]);
         let in3 = m128i::from_u64(input[i + 7], input[i + 6]);
 
-        state0 = state0.aesenc(in0);
-        state1 = state1.aesdec(in1);
-        state2 = state2.aesenc(in2);
-        state3 = state3.aesdec(in3);
+        state0 = A::enc(state0, in0);
+        state1 = A::dec(state1, in1);
+        state2 = A::enc(state2, in2);
+        state3 = A::dec(state3, in3);
 
         i += 8;
     }
 
+    finish_hash_1rx4::<A>([state0, state1, state2, state3])
+}
+
+#[allow(overflowing_literals)]
+fn finish_hash_1rx4<A: AesRound>(states: [m128i; 4]) -> [m128i; 4] {
+    let [mut state0, mut state1, mut state2, mut state3] = states;
+
     let x_key_0 = m128i::from_i32(0x06890201, 0x90dc56bf, 0x8b24949f, 0xf6fa8389);
     let x_key_1 = m128i::from_i32(0xed18f99b, 0xee1043c6, 0x51f4e03c, 0x61b263d1);
 
-    state0 = state0.aesenc(x_key_0);
-    state1 = state1.aesdec(x_key_0);
-    state2 = state2.aesenc(x_key_0);
-    state3 = state3.aesdec(x_key_0);
+    state0 = A::enc(state0, x_key_0);
+    state1 = A::dec(state1, x_key_0);
+    state2 = A::enc(state2, x_key_0);
+    state3 = A::dec(state3, x_key_0);
 
-    state0 = state0.aesenc(x_key_1);
-    state1 = state1.aesdec(x_key_1);
-    state2 = state2.aesenc(x_key_1);
-    state3 = state3.aesdec(x_key_1);
+    state0 = A::enc(state0, x_key_1);
+    state1 = A::dec(state1, x_key_1);
+    state2 = A::enc(state2, x_key_1);
+    state3 = A::dec(state3, x_key_1);
 
     [state0, state1, state2, state3]
 }
 
-pub fn fill_aes_1rx4_u64(input: &[m128i; 4], into: &mut Vec<u64>) -> [m128i; 4] {
+pub fn fill_aes_1rx4_u64(input: &[m128i; 4], into: &mut [u64]) -> [m128i; 4] {
+    #[cfg(all(target_arch = "x86_64", not(feature = "portable")))]
+    {
+        if use_vaes() {
+            return unsafe { vaes::fill_aes_1rx4_u64(input, keys_1rx4(), into) };
+        }
+    }
+    if use_hardware_aes() {
+        fill_aes_1rx4_u64_with::<HardAes>(input, into)
+    } else {
+        fill_aes_1rx4_u64_with::<SoftAes>(input, into)
+    }
+}
+
+fn fill_aes_1rx4_u64_with<A: AesRound>(input: &[m128i; 4], into: &mut [u64]) -> [m128i; 4] {
     let (key0, key1, key2, key3) = keys_1rx4();
     let mut state0 = input[0];
     let mut state1 = input[1];
@@ -59,10 +133,10 @@ pub fn fill_aes_1rx4_u64(input: &[m128i; 4], into: &mut Vec<u64>) -> [m128i; 4]
 
     let mut out_ix = 0;
     while out_ix < into.len() {
-        state0 = state0.aesdec(key0);
-        state1 = state1.aesenc(key1);
-        state2 = state2.aesdec(key2);
-        state3 = state3.aesenc(key3);
+        state0 = A::dec(state0, key0);
+        state1 = A::enc(state1, key1);
+        state2 = A::dec(state2, key2);
+        state3 = A::enc(state3, key3);
         let (s0_1, s0_0) = state0.as_i64();
         let (s1_1, s1_0) = state1.as_i64();
         let (s2_1, s2_0) = state2.as_i64();
@@ -81,7 +155,77 @@ pub fn fill_aes_1rx4_u64(input: &[m128i; 4], into: &mut Vec<u64>) -> [m128i; 4]
     [state0, state1, state2, state3]
 }
 
-fn fill_aes_1rx4_m128i(input: &[m128i; 4], into: &mut Vec<m128i>) -> [m128i; 4] {
+/// Hashes `scratchpad` like `hash_aes_1rx4` and refills it like `fill_aes_1rx4_u64`
+/// with `fill_seed` in the same pass. Returns the hash and the new fill state.
+pub fn hash_and_fill_aes_1rx4(
+    fill_seed: &[m128i; 4],
+    scratchpad: &mut [u64],
+) -> ([m128i; 4], [m128i; 4]) {
+    #[cfg(all(target_arch = "x86_64", not(feature = "portable")))]
+    {
+        if use_vaes() {
+            let (hash, fill) = unsafe {
+                vaes::hash_and_fill_aes_1rx4(hash_states_1rx4(), fill_seed, keys_1rx4(), scratchpad)
+            };
+            return (finish_hash_1rx4::<HardAes>(hash), fill);
+        }
+    }
+    if use_hardware_aes() {
+        hash_and_fill_aes_1rx4_with::<HardAes>(fill_seed, scratchpad)
+    } else {
+        hash_and_fill_aes_1rx4_with::<SoftAes>(fill_seed, scratchpad)
+    }
+}
+
+fn hash_and_fill_aes_1rx4_with<A: AesRound>(
+    fill_seed: &[m128i; 4],
+    scratchpad: &mut [u64],
+) -> ([m128i; 4], [m128i; 4]) {
+    debug_assert!(scratchpad.len() % 64 == 0);
+
+    let (key0, key1, key2, key3) = keys_1rx4();
+    let [mut hash0, mut hash1, mut hash2, mut hash3] = hash_states_1rx4();
+    let [mut fill0, mut fill1, mut fill2, mut fill3] = *fill_seed;
+
+    let mut i = 0;
+    while i < scratchpad.len() {
+        let in0 = m128i::from_u64(scratchpad[i + 1], scratchpad[i]);
+        let in1 = m128i::from_u64(scratchpad[i + 3], scratchpad[i + 2]);
+        let in2 = m128i::from_u64(scratchpad[i + 5], scratchpad[i + 4]);
+        let in3 = m128i::from_u64(scratchpad[i + 7], scratchpad[i + 6]);
+
+        hash0 = A::enc(hash0, in0);
+        hash1 = A::dec(hash1, in1);
+        hash2 = A::enc(hash2, in2);
+        hash3 = A::dec(hash3, in3);
+
+        fill0 = A::dec(fill0, key0);
+        fill1 = A::enc(fill1, key1);
+        fill2 = A::dec(fill2, key2);
+        fill3 = A::enc(fill3, key3);
+        let (f0_1, f0_0) = fill0.as_i64();
+        let (f1_1, f1_0) = fill1.as_i64();
+        let (f2_1, f2_0) = fill2.as_i64();
+        let (f3_1, f3_0) = fill3.as_i64();
+        scratchpad[i] = f0_0 as u64;
+        scratchpad[i + 1] = f0_1 as u64;
+        scratchpad[i + 2] = f1_0 as u64;
+        scratchpad[i + 3] = f1_1 as u64;
+        scratchpad[i + 4] = f2_0 as u64;
+        scratchpad[i + 5] = f2_1 as u64;
+        scratchpad[i + 6] = f3_0 as u64;
+        scratchpad[i + 7] = f3_1 as u64;
+
+        i += 8;
+    }
+
+    (
+        finish_hash_1rx4::<A>([hash0, hash1, hash2, hash3]),
+        [fill0, fill1, fill2, fill3],
+    )
+}
+
+fn fill_aes_1rx4_m128i<A: AesRound>(input: &[m128i; 4], into: &mut [m128i]) -> [m128i; 4] {
     let (key0, key1, key2, key3) = keys_1rx4();
     let mut state0 = input[0];
     let mut state1 = input[1];
@@ -89,10 +233,10 @@ fn fill_aes_1rx4_m128i(input: &[m128i; 4], into: &mut Vec<m128i>) -> [m128i; 4]
     let mut state3 = input[3];
     let mut out_ix = 0;
     while out_ix < into.len() {
-        state0 = state0.aesdec(key0);
-        state1 = state1.aesenc(key1);
-        state2 = state2.aesdec(key2);
-        state3 = state3.aesenc(key3);
+        state0 = A::dec(state0, key0);
+        state1 = A::enc(state1, key1);
+        state2 = A::dec(state2, key2);
+        state3 = A::enc(state3, key3);
         into[out_ix] = state0;
         into[out_ix + 1] = state1;
         into[out_ix + 2] = state2;
@@ -107,22 +251,46 @@ pub fn gen_program_aes_1rx4(input: &[m128i; 4], output_size: usize) -> (Vec<m128
     debug_assert!(output_size % 4 == 0);
 
     let mut result: Vec<m128i> = vec![m128i::zero(); output_size];
-    let new_seed = fill_aes_1rx4_m128i(input, &mut result);
+    let new_seed = if use_hardware_aes() {
+        fill_aes_1rx4_m128i::<HardAes>(input, &mut result)
+    } else {
+        fill_aes_1rx4_m128i::<SoftAes>(input, &mut result)
+    };
     (result, new_seed)
 }
 
-#[allow(overflowing_literals)]
 pub fn gen_program_aes_4rx4(input: &[m128i; 4], output_size: usize) -> Vec<m128i> {
+    #[cfg(all(target_arch = "x86_64", not(feature = "portable")))]
+    {
+        if use_vaes() {
+            return unsafe { vaes::gen_program_aes_4rx4(input, &keys_4rx4(), output_size) };
+        }
+    }
+    if use_hardware_aes() {
+        gen_program_aes_4rx4_with::<HardAes>(input, output_size)
+    } else {
+        gen_program_aes_4rx4_with::<SoftAes>(input, output_size)
+    }
+}
+
+#[allow(overflowing_literals)]
+fn keys_4rx4() -> [m128i; 8] {
+    [
+        m128i::from_i32(0x99e5d23f, 0x2f546d2b, 0xd1833ddb, 0x6421aadd),
+        m128i::from_i32(0xa5dfcde5, 0x06f79d53, 0xb6913f55, 0xb20e3450),
+        m128i::from_i32(0x171c02bf, 0x0aa4679f, 0x515e7baf, 0x5c3ed904),
+        m128i::from_i32(0xd8ded291, 0xcd673785, 0xe78f5d08, 0x85623763),
+        m128i::from_i32(0x229effb4, 0x3d518b6d, 0xe3d6a7a6, 0xb5826f73),
+        m128i::from_i32(0xb272b7d2, 0xe9024d4e, 0x9c10b3d9, 0xc7566bf3),
+        m128i::from_i32(0xf63befa7, 0x2ba9660a, 0xf765a38b, 0xf273c9e7),
+        m128i::from_i32(0xc0b0762d, 0x0c06d1fd, 0x915839de, 0x7a7cd609),
+    ]
+}
+
+fn gen_program_aes_4rx4_with<A: AesRound>(input: &[m128i; 4], output_size: usize) -> Vec<m128i> {
     debug_assert!(output_size % 4 == 0);
     let mut result = Vec::with_capacity(output_size);
-    let key0 = m128i::from_i32(0x99e5d23f, 0x2f546d2b, 0xd1833ddb, 0x6421aadd);
-    let key1 = m128i::from_i32(0xa5dfcde5, 0x06f79d53, 0xb6913f55, 0xb20e3450);
-    let key2 = m128i::from_i32(0x171c02bf, 0x0aa4679f, 0x515e7baf, 0x5c3ed904);
-    let key3 = m128i::from_i32(0xd8ded291, 0xcd673785, 0xe78f5d08, 0x85623763);
-    let key4 = m128i::from_i32(0x229effb4, 0x3d518b6d, 0xe3d6a7a6, 0xb5826f73);
-    let key5 = m128i::from_i32(0xb272b7d2, 0xe9024d4e, 0x9c10b3d9, 0xc7566bf3);
-    let key6 = m128i::from_i32(0xf63befa7, 0x2ba9660a, 0xf765a38b, 0xf273c9e7);
-    let key7 = m128i::from_i32(0xc0b0762d, 0x0c06d1fd, 0x915839de, 0x7a7cd609);
+    let [key0, key1, key2, key3, key4, key5, key6, key7] = keys_4rx4();
 
     let mut state0 = input[0];
     let mut state1 = input[1];
@@ -131,23 +299,23 @@ pub fn gen_program_aes_4rx4(input: &[m128i; 4], output_size: usize) -> Vec<m128i
 
     let mut out_ix = 0;
     while out_ix < output_size {
-        state0 = state0.aesdec(key0);
-        state1 = state1.aesenc(key0);
-        state2 = state2.aesdec(key4);
-        state3 = state3.aesenc(key4);
-        state0 = state0.aesdec(key1);
-        state1 = state1.aesenc(key1);
-        state2 = state2.aesdec(key5);
-        state3 = state3.aesenc(key5);
-
-        state0 = state0.aesdec(key2);
-        state1 = state1.aesenc(key2);
-        state2 = state2.aesdec(key6);
-        state3 = state3.aesenc(key6);
-        state0 = state0.aesdec(key3);
-        state1 = state1.aesenc(key3);
-        state2 = state2.aesdec(key7);
-        state3 = state3.aesenc(key7);
+        state0 = A::dec(state0, key0);
+        state1 = A::enc(state1, key0);
+        state2 = A::dec(state2, key4);
+        state3 = A::enc(state3, key4);
+        state0 = A::dec(state0, key1);
+        state1 = A::enc(state1, key1);
+        state2 = A::dec(state2, key5);
+        state3 = A::enc(state3, key5);
+
+        state0 = A::dec(state0, key2);
+        state1 = A::enc(state1, key2);
+        state2 = A::dec(state2, key6);
+        state3 = A::enc(state3, key6);
+        state0 = A::dec(state0, key3);
+        state1 = A::enc(state1, key3);
+        state2 = A::dec(state2, key7);
+        state3 = A::enc(state3, key7);
 
         result.push(state0);
         result.push(state1);
@@ -0,0 +1,112 @@
+extern crate num_cpus;
+
+use super::hardware::HardwareConfig;
+use super::memory::VmMemory;
+use super::params::{RandomXParams, RX_0};
+use super::trace::Tracer;
+use super::vm::{new_vm_with_mode, Vm, VmMode};
+use std::sync::Arc;
+use strum::{Display, EnumString};
+
+pub const HASH_SIZE: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum RandomXMode {
+    /// only the 256MiB cache, dataset items are computed on the fly (slow hashing)
+    Light,
+    /// the complete 2GiB dataset is initialised up front (fast hashing)
+    Full,
+}
+
+/// Computes RandomX hashes for one key.
+///
+/// The cache/dataset is shared (via `Arc`) between all hashers created with
+/// `clone`, each hasher has its own scratchpad and registers. Create one hasher
+/// per thread.
+pub struct RandomX {
+    mode: RandomXMode,
+    vm_mode: VmMode,
+    vm: Vm,
+}
+
+impl RandomX {
+    pub fn new(key: &[u8], mode: RandomXMode) -> RandomX {
+        RandomX::new_with_vm_mode(key, mode, VmMode::Interpreted)
+    }
+
+    /// Like `new`, but runs the programs in the given vm mode. Falls back to
+    /// interpreted mode if compiled mode is not available on this platform.
+    pub fn new_with_vm_mode(key: &[u8], mode: RandomXMode, vm_mode: VmMode) -> RandomX {
+        RandomX::new_with_params(key, mode, vm_mode, &RX_0)
+    }
+
+    /// Creates a hasher for the RandomX variant described by `params`
+    /// (e.g. `RandomXVariant::Wow.params()`).
+    pub fn new_with_params(
+        key: &[u8],
+        mode: RandomXMode,
+        vm_mode: VmMode,
+        params: &RandomXParams,
+    ) -> RandomX {
+        let mem = match mode {
+            RandomXMode::Light => VmMemory::light_with_params(key, params),
+            RandomXMode::Full => {
+                VmMemory::full_with_config(key, params, num_cpus::get(), &HardwareConfig::default())
+            }
+        };
+        RandomX::from_memory(Arc::new(mem), vm_mode)
+    }
+
+    /// Creates a hasher on an already initialised memory.
+    pub fn from_memory(mem: Arc<VmMemory>, vm_mode: VmMode) -> RandomX {
+        let mode = if mem.cache {
+            RandomXMode::Full
+        } else {
+            RandomXMode::Light
+        };
+        RandomX {
+            mode,
+            vm_mode,
+            vm: new_vm_with_mode(mem, vm_mode),
+        }
+    }
+
+    pub fn mode(&self) -> RandomXMode {
+        self.mode
+    }
+
+    pub fn memory(&self) -> &Arc<VmMemory> {
+        &self.vm.mem
+    }
+
+    pub fn hash(&mut self, input: &[u8]) -> [u8; HASH_SIZE] {
+        let mut result = [0; HASH_SIZE];
+        result.copy_from_slice(self.vm.calculate_hash(input).as_bytes());
+        result
+    }
+
+    /// Like `hash`, but calls the hooks of `tracer` while running the programs
+    /// (e.g. a `trace::JsonTracer` to record the vm state after each instruction).
+    pub fn hash_traced<T: Tracer>(&mut self, input: &[u8], tracer: &mut T) -> [u8; HASH_SIZE] {
+        let mut result = [0; HASH_SIZE];
+        result.copy_from_slice(self.vm.calculate_hash_traced(input, tracer).as_bytes());
+        result
+    }
+
+    /// Returns true if `expected` is the RandomX hash of `input`.
+    pub fn verify(&mut self, input: &[u8], expected: &[u8; HASH_SIZE]) -> bool {
+        self.hash(input) == *expected
+    }
+}
+
+/// Creates a new hasher that shares the cache/dataset with this one.
+impl Clone for RandomX {
+    fn clone(&self) -> RandomX {
+        RandomX {
+            mode: self.mode,
+            vm_mode: self.vm_mode,
+            vm: new_vm_with_mode(self.vm.mem.clone(), self.vm_mode),
+        }
+    }
+}
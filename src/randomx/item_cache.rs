@@ -0,0 +1,139 @@
+//A bounded cache of computed dataset items for the hybrid memory mode, for machines
+//that have more RAM than the light mode needs but not enough for the full dataset.
+//
+//The cache is direct mapped: an item can only be stored in the slot `item % slots`
+//and replaces the item stored there. The items are read pseudo-randomly, so the hit
+//rate is about the cached share of the dataset (e.g. 25% for 512MiB with rx/0).
+//
+//All workers share the cache. A slot is written under a seqlock: the writer marks the
+//tag of the slot as busy, writes the item and publishes the tag. A reader only uses
+//an item if the tag matches before and after reading it. Writers do not wait for a
+//busy slot, the item is just not cached then.
+
+use std::io;
+use std::sync::atomic::{fence, AtomicU64, Ordering};
+
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::{_mm_prefetch, _MM_HINT_NTA};
+
+const EMPTY: u64 = 0;
+const BUSY: u64 = u64::MAX;
+const ITEM_WORDS: usize = 8;
+//the 64 byte item and the tag
+const SLOT_SIZE: usize = ITEM_WORDS * 8 + 8;
+
+pub struct ItemCache {
+    //item number + 1 of the item in the slot, EMPTY or BUSY
+    tags: Box<[AtomicU64]>,
+    items: Box<[AtomicU64]>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl ItemCache {
+    /// Creates a cache that uses about `size` bytes (at least one item).
+    pub fn with_size(size: usize) -> ItemCache {
+        ItemCache::new((size / SLOT_SIZE).max(1))
+    }
+
+    /// Creates a cache for `slots` items.
+    pub fn new(slots: usize) -> ItemCache {
+        ItemCache::try_new(slots).expect("item cache allocation")
+    }
+
+    /// Like `with_size`, but returns an error if the memory can not be allocated.
+    pub fn try_with_size(size: usize) -> io::Result<ItemCache> {
+        ItemCache::try_new((size / SLOT_SIZE).max(1))
+    }
+
+    fn try_new(slots: usize) -> io::Result<ItemCache> {
+        Ok(ItemCache {
+            tags: try_alloc_zeroed(slots)?,
+            items: try_alloc_zeroed(slots * ITEM_WORDS)?,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        })
+    }
+
+    pub fn slots(&self) -> usize {
+        self.tags.len()
+    }
+
+    /// The number of `get` calls that found the item.
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// The number of `get` calls that did not find the item.
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    pub fn get(&self, item_num: u64) -> Option<[u64; 8]> {
+        let slot = self.slot(item_num);
+        let tag = &self.tags[slot];
+        let expected = item_num + 1;
+        if tag.load(Ordering::Acquire) == expected {
+            let mut item = [0; 8];
+            for (i, word) in item.iter_mut().enumerate() {
+                *word = self.items[slot * ITEM_WORDS + i].load(Ordering::Relaxed);
+            }
+            //the item was not replaced while it was read
+            fence(Ordering::Acquire);
+            if tag.load(Ordering::Relaxed) == expected {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return Some(item);
+            }
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        None
+    }
+
+    /// Stores the item, replacing the item in its slot.
+    pub fn insert(&self, item_num: u64, item: &[u64; 8]) {
+        let slot = self.slot(item_num);
+        let tag = &self.tags[slot];
+        let current = tag.load(Ordering::Relaxed);
+        if current == BUSY
+            || tag
+                .compare_exchange(current, BUSY, Ordering::Acquire, Ordering::Relaxed)
+                .is_err()
+        {
+            return;
+        }
+        fence(Ordering::Release);
+        for (i, word) in item.iter().enumerate() {
+            self.items[slot * ITEM_WORDS + i].store(*word, Ordering::Relaxed);
+        }
+        tag.store(item_num + 1, Ordering::Release);
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    pub fn prefetch(&self, item_num: u64) {
+        let slot = self.slot(item_num);
+        unsafe {
+            _mm_prefetch(self.tags[slot].as_ptr() as *const i8, _MM_HINT_NTA);
+            _mm_prefetch(
+                self.items[slot * ITEM_WORDS].as_ptr() as *const i8,
+                _MM_HINT_NTA,
+            );
+        }
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    pub fn prefetch(&self, _item_num: u64) {}
+
+    fn slot(&self, item_num: u64) -> usize {
+        (item_num % self.tags.len() as u64) as usize
+    }
+}
+
+//all slots start EMPTY (0)
+fn try_alloc_zeroed(len: usize) -> io::Result<Box<[AtomicU64]>> {
+    let mut words = Vec::new();
+    words
+        .try_reserve_exact(len)
+        .map_err(|e| io::Error::new(io::ErrorKind::OutOfMemory, e))?;
+    words.extend((0..len).map(|_| AtomicU64::new(EMPTY)));
+    Ok(words.into_boxed_slice())
+}
@@ -0,0 +1,899 @@
+extern crate libc;
+
+use super::common::randomx_reciprocal;
+use super::m128::MXCSR_DEFAULT;
+#[cfg(all(target_arch = "x86_64", feature = "portable"))]
+use super::m128::{get_rounding_mode, set_rounding_mode};
+use super::memory::{
+    DatasetItem, SeedMemory, SUPERSCALAR_ADD_1, SUPERSCALAR_ADD_2, SUPERSCALAR_ADD_3,
+    SUPERSCALAR_ADD_4, SUPERSCALAR_ADD_5, SUPERSCALAR_ADD_6, SUPERSCALAR_ADD_7, SUPERSCALAR_MUL_0,
+};
+use super::params::RandomXParams;
+use super::program::{Instr, Opcode, Program};
+use super::superscalar::{ScInstr, ScOpcode, ScProgram};
+use super::vm::{Register, CONDITION_MASK, CONDITION_OFFSET};
+#[cfg(all(target_arch = "x86_64", feature = "portable"))]
+use std::arch::x86_64::{_mm_getcsr, _mm_setcsr};
+use std::marker::PhantomData;
+use std::ptr;
+
+/// Size of the executable buffer of one compiler. A compiled program
+/// is around 10KiB, the buffer leaves plenty of room for that.
+const CODE_BUFFER_SIZE: usize = 64 * 1024;
+
+//register mapping (same as the reference implementation):
+//r0-r7 => r8-r15, f0-f3 => xmm0-xmm3, e0-e3 => xmm4-xmm7, a0-a3 => xmm8-xmm11
+const RAX: u8 = 0;
+const RCX: u8 = 1;
+const RDX: u8 = 2;
+const RBX: u8 = 3;
+const RBP: u8 = 5;
+const RSI: u8 = 6;
+const RDI: u8 = 7;
+const R_BASE: u8 = 8;
+const F_BASE: u8 = 0;
+const E_BASE: u8 = 4;
+const A_BASE: u8 = 8;
+const XMM_TMP: u8 = 12;
+const XMM_MANTISSA_MASK: u8 = 13;
+const XMM_EXPONENT_MASK: u8 = 14;
+const XMM_SCALE_MASK: u8 = 15;
+
+//offsets into the #[repr(C)] Register struct
+const REG_R_OFFSET: i32 = 0;
+const REG_F_OFFSET: i32 = 64;
+const REG_E_OFFSET: i32 = 128;
+const REG_A_OFFSET: i32 = 192;
+
+const SCALE_MASK: u64 = 0x80F0000000000000;
+
+/// Constants the compiled code needs besides the registers. The layout
+/// matches the xmm register layout (low lane first).
+#[repr(C, align(16))]
+pub struct JitConstants {
+    pub e_mask: [u64; 2],
+    pub mantissa_mask: [u64; 2],
+    pub scale_mask: [u64; 2],
+}
+
+impl JitConstants {
+    pub fn new(e_mask: [u64; 2], mantissa_mask: u64) -> JitConstants {
+        JitConstants {
+            e_mask,
+            mantissa_mask: [mantissa_mask, mantissa_mask],
+            scale_mask: [SCALE_MASK, SCALE_MASK],
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+type JitFn = unsafe extern "sysv64" fn(*mut Register, *mut u64, *const JitConstants);
+//never called, JitCompiler::new fails on other architectures
+#[cfg(not(target_arch = "x86_64"))]
+type JitFn = unsafe extern "C" fn(*mut Register, *mut u64, *const JitConstants);
+
+//(seed memory, first output item, first item number, end item number)
+#[cfg(target_arch = "x86_64")]
+type DatasetInitFn = unsafe extern "sysv64" fn(*const u64, *mut DatasetItem, u64, u64);
+#[cfg(not(target_arch = "x86_64"))]
+type DatasetInitFn = unsafe extern "C" fn(*const u64, *mut DatasetItem, u64, u64);
+
+/// A program compiled to native code. It is only valid as long as the
+/// compiler that produced it does not compile another program.
+pub struct CompiledProgram<'a> {
+    code: JitFn,
+    //largest scratchpad address mask of the program
+    mem_mask: u32,
+    _compiler: PhantomData<&'a mut JitCompiler>,
+}
+
+impl CompiledProgram<'_> {
+    /// Executes the program once (one iteration) on the given registers.
+    pub fn execute(&self, reg: &mut Register, scratchpad: &mut [u64], consts: &JitConstants) {
+        debug_assert!(scratchpad.len() as u64 * 8 > u64::from(self.mem_mask));
+        load_rounding_mode();
+        unsafe { (self.code)(reg, scratchpad.as_mut_ptr(), consts) }
+        store_rounding_mode();
+    }
+}
+
+//The compiled code keeps the rounding mode in MXCSR. The portable m128 backend
+//does not, so the mode has to be moved in and out of the compiled code.
+#[cfg(all(target_arch = "x86_64", feature = "portable"))]
+fn load_rounding_mode() {
+    unsafe { _mm_setcsr(MXCSR_DEFAULT | (get_rounding_mode() << 13)) }
+}
+
+#[cfg(all(target_arch = "x86_64", feature = "portable"))]
+fn store_rounding_mode() {
+    unsafe {
+        set_rounding_mode((_mm_getcsr() >> 13) & 3);
+        //the portable float ops rely on round to nearest
+        _mm_setcsr(MXCSR_DEFAULT);
+    }
+}
+
+#[cfg(not(all(target_arch = "x86_64", feature = "portable")))]
+fn load_rounding_mode() {}
+
+#[cfg(not(all(target_arch = "x86_64", feature = "portable")))]
+fn store_rounding_mode() {}
+
+/// Translates RandomX programs into x86-64 machine code. Each compiler owns
+/// one executable buffer that is reused for every compiled program.
+pub struct JitCompiler {
+    code: *mut u8,
+    asm: Assembler,
+}
+
+//the compiler exclusively owns its code buffer, which is only modified through &mut self
+unsafe impl Send for JitCompiler {}
+unsafe impl Sync for JitCompiler {}
+
+impl JitCompiler {
+    /// Returns `None` if compiled mode is not supported on this platform
+    /// or the executable memory could not be allocated.
+    pub fn new() -> Option<JitCompiler> {
+        if !cfg!(all(target_arch = "x86_64", unix)) {
+            return None;
+        }
+        let code = alloc_code_buffer(CODE_BUFFER_SIZE)?;
+        Some(JitCompiler {
+            code,
+            asm: Assembler::new(),
+        })
+    }
+
+    pub fn compile(&mut self, prog: &Program) -> CompiledProgram<'_> {
+        self.asm.clear();
+        emit_program(&mut self.asm, prog);
+        assert!(self.asm.code.len() <= CODE_BUFFER_SIZE);
+
+        unsafe {
+            protect_code_buffer(self.code, CODE_BUFFER_SIZE, false);
+            ptr::copy_nonoverlapping(self.asm.code.as_ptr(), self.code, self.asm.code.len());
+            protect_code_buffer(self.code, CODE_BUFFER_SIZE, true);
+        }
+
+        CompiledProgram {
+            code: unsafe { std::mem::transmute::<*mut u8, JitFn>(self.code) },
+            mem_mask: prog.instrs().iter().map(|i| i.mem_mask).max().unwrap_or(0),
+            _compiler: PhantomData,
+        }
+    }
+}
+
+impl Drop for JitCompiler {
+    fn drop(&mut self) {
+        free_code_buffer(self.code, CODE_BUFFER_SIZE);
+    }
+}
+
+/// The superscalar programs of one seed compiled to native code, including the
+/// mixing with the seed memory. The code is never modified after compilation,
+/// so it can be shared between all threads initialising the dataset.
+pub struct CompiledSuperscalar {
+    code: *mut u8,
+    len: usize,
+}
+
+//the code is read only after compile
+unsafe impl Send for CompiledSuperscalar {}
+unsafe impl Sync for CompiledSuperscalar {}
+
+impl CompiledSuperscalar {
+    /// Returns `None` if compiling is not supported on this platform
+    /// or the executable memory could not be allocated.
+    pub fn compile(
+        programs: &[ScProgram<'_>],
+        params: &RandomXParams,
+    ) -> Option<CompiledSuperscalar> {
+        if !cfg!(all(target_arch = "x86_64", unix)) {
+            return None;
+        }
+        let mut asm = Assembler::new();
+        emit_dataset_init(&mut asm, programs, params.cache_line_mask());
+
+        let len = asm.code.len();
+        let code = alloc_code_buffer(len)?;
+        unsafe {
+            ptr::copy_nonoverlapping(asm.code.as_ptr(), code, len);
+            protect_code_buffer(code, len, true);
+        }
+        Some(CompiledSuperscalar { code, len })
+    }
+
+    /// Computes the dataset items `start_item..start_item + items.len()` into `items`.
+    pub fn init_dataset_items(
+        &self,
+        seed_mem: &SeedMemory,
+        start_item: u64,
+        items: &mut [DatasetItem],
+    ) {
+        //the compiled code reads the blocks as one contiguous array of u64
+        assert_eq!(seed_mem.blocks.len(), seed_mem.params.argon_memory as usize);
+        assert_eq!(std::mem::size_of_val(&seed_mem.blocks[0]), 1024);
+        if items.is_empty() {
+            return;
+        }
+        let end_item = start_item + items.len() as u64;
+        unsafe {
+            let code = std::mem::transmute::<*mut u8, DatasetInitFn>(self.code);
+            code(
+                seed_mem.blocks.as_ptr() as *const u64,
+                items.as_mut_ptr(),
+                start_item,
+                end_item,
+            );
+        }
+    }
+}
+
+impl Drop for CompiledSuperscalar {
+    fn drop(&mut self) {
+        free_code_buffer(self.code, self.len);
+    }
+}
+
+#[cfg(unix)]
+fn alloc_code_buffer(len: usize) -> Option<*mut u8> {
+    let ptr = unsafe {
+        libc::mmap(
+            ptr::null_mut(),
+            len,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+            -1,
+            0,
+        )
+    };
+    if ptr == libc::MAP_FAILED {
+        return None;
+    }
+    Some(ptr as *mut u8)
+}
+
+#[cfg(not(unix))]
+fn alloc_code_buffer(_len: usize) -> Option<*mut u8> {
+    None
+}
+
+#[cfg(unix)]
+unsafe fn protect_code_buffer(code: *mut u8, len: usize, executable: bool) {
+    let prot = if executable {
+        libc::PROT_READ | libc::PROT_EXEC
+    } else {
+        libc::PROT_READ | libc::PROT_WRITE
+    };
+    let result = libc::mprotect(code as *mut libc::c_void, len, prot);
+    assert_eq!(result, 0, "mprotect of jit code buffer failed");
+}
+
+#[cfg(not(unix))]
+unsafe fn protect_code_buffer(_code: *mut u8, _len: usize, _executable: bool) {}
+
+#[cfg(unix)]
+fn free_code_buffer(code: *mut u8, len: usize) {
+    unsafe {
+        libc::munmap(code as *mut libc::c_void, len);
+    }
+}
+
+#[cfg(not(unix))]
+fn free_code_buffer(_code: *mut u8, _len: usize) {}
+
+fn emit_program(asm: &mut Assembler, prog: &Program) {
+    emit_prologue(asm);
+
+    let mut instr_offsets = Vec::with_capacity(prog.size);
+    for instr in prog.instrs() {
+        instr_offsets.push(asm.code.len());
+        emit_instr(asm, instr, &instr_offsets);
+    }
+
+    emit_epilogue(asm);
+}
+
+fn emit_prologue(asm: &mut Assembler) {
+    for reg in 12..16 {
+        asm.push(reg);
+    }
+    asm.sub_rsp(8); //slot for ldmxcsr
+
+    for i in 0..8 {
+        asm.mov_r_m(R_BASE + i, RDI, REG_R_OFFSET + 8 * i as i32);
+    }
+    for i in 0..4 {
+        asm.movupd_x_m(F_BASE + i, RDI, REG_F_OFFSET + 16 * i as i32);
+        asm.movupd_x_m(E_BASE + i, RDI, REG_E_OFFSET + 16 * i as i32);
+        asm.movupd_x_m(A_BASE + i, RDI, REG_A_OFFSET + 16 * i as i32);
+    }
+    asm.movupd_x_m(XMM_EXPONENT_MASK, RDX, 0);
+    asm.movupd_x_m(XMM_MANTISSA_MASK, RDX, 16);
+    asm.movupd_x_m(XMM_SCALE_MASK, RDX, 32);
+}
+
+fn emit_epilogue(asm: &mut Assembler) {
+    for i in 0..8 {
+        asm.mov_m_r(RDI, REG_R_OFFSET + 8 * i as i32, R_BASE + i);
+    }
+    for i in 0..4 {
+        asm.movupd_m_x(RDI, REG_F_OFFSET + 16 * i as i32, F_BASE + i);
+        asm.movupd_m_x(RDI, REG_E_OFFSET + 16 * i as i32, E_BASE + i);
+    }
+    asm.add_rsp(8);
+    for reg in (12..16).rev() {
+        asm.pop(reg);
+    }
+    asm.ret();
+}
+
+fn emit_instr(asm: &mut Assembler, instr: &Instr, instr_offsets: &[usize]) {
+    match instr.op {
+        Opcode::NOP => {}
+        Opcode::IADD_RS => {
+            let dst = r(instr.dst);
+            asm.alu_rr(MOV, RAX, r(instr.src));
+            if instr.modi > 0 {
+                asm.shift_ri(SHL, RAX, instr.modi);
+            }
+            asm.alu_rr(ADD, dst, RAX);
+            if instr.imm64 != 0 {
+                asm.alu_ri(ADD_EXT, dst, instr.imm);
+            }
+        }
+        Opcode::IADD_M => emit_mem_op(asm, instr, ADD_M),
+        Opcode::ISUB_M => emit_mem_op(asm, instr, SUB_M),
+        Opcode::IXOR_M => emit_mem_op(asm, instr, XOR_M),
+        Opcode::IMUL_M => {
+            emit_src_address(asm, instr);
+            asm.imul_r_sp(r(instr.dst));
+        }
+        Opcode::ISUB_R => emit_reg_or_imm_op(asm, instr, SUB, SUB_EXT),
+        Opcode::IXOR_R => emit_reg_or_imm_op(asm, instr, XOR, XOR_EXT),
+        Opcode::IMUL_R => {
+            let dst = r(instr.dst);
+            if instr.src_imm {
+                asm.imul_rri(dst, instr.imm);
+            } else {
+                asm.imul_rr(dst, r(instr.src));
+            }
+        }
+        Opcode::IMULH_R | Opcode::ISMULH_R => {
+            let dst = r(instr.dst);
+            let ext = if instr.op == Opcode::IMULH_R {
+                MUL_EXT
+            } else {
+                IMUL1_EXT
+            };
+            asm.alu_rr(MOV, RAX, dst);
+            asm.unary_r(ext, r(instr.src));
+            asm.alu_rr(MOV, dst, RDX);
+        }
+        Opcode::IMULH_M | Opcode::ISMULH_M => {
+            let dst = r(instr.dst);
+            let ext = if instr.op == Opcode::IMULH_M {
+                MUL_EXT
+            } else {
+                IMUL1_EXT
+            };
+            emit_src_address(asm, instr);
+            asm.alu_rr(MOV, RAX, dst);
+            asm.unary_sp(ext);
+            asm.alu_rr(MOV, dst, RDX);
+        }
+        Opcode::IMUL_RCP => {
+            //imm64 is the reciprocal, 0 if the instruction is a nop
+            if instr.imm64 != 0 {
+                asm.mov_ri64(RAX, instr.imm64);
+                asm.imul_rr(r(instr.dst), RAX);
+            }
+        }
+        Opcode::INEG_R => asm.unary_r(NEG_EXT, r(instr.dst)),
+        Opcode::IROR_R | Opcode::IROL_R => {
+            let dst = r(instr.dst);
+            let ext = if instr.op == Opcode::IROR_R { ROR } else { ROL };
+            if instr.src_imm {
+                asm.shift_ri(ext, dst, (instr.imm & 63) as u8);
+            } else {
+                asm.alu_rr(MOV, RCX, r(instr.src));
+                asm.shift_rcl(ext, dst);
+            }
+        }
+        Opcode::ISWAP_R => asm.alu_rr(XCHG, r(instr.dst), r(instr.src)),
+        Opcode::FSWAP_R => {
+            //dst 0-3 are the f registers, 4-7 the e registers (E_BASE == F_BASE + 4)
+            let dst = F_BASE + instr.dst;
+            asm.shufpd(dst, dst, 1);
+        }
+        Opcode::FADD_R => asm.sse_rr(ADDPD, f(instr.dst), a(instr.src)),
+        Opcode::FSUB_R => asm.sse_rr(SUBPD, f(instr.dst), a(instr.src)),
+        Opcode::FADD_M | Opcode::FSUB_M => {
+            emit_src_address(asm, instr);
+            asm.cvtdq2pd_sp(XMM_TMP);
+            let op = if instr.op == Opcode::FADD_M {
+                ADDPD
+            } else {
+                SUBPD
+            };
+            asm.sse_rr(op, f(instr.dst), XMM_TMP);
+        }
+        Opcode::FSCAL_R => asm.sse_rr(XORPD, f(instr.dst), XMM_SCALE_MASK),
+        Opcode::FMUL_R => asm.sse_rr(MULPD, e(instr.dst), a(instr.src)),
+        Opcode::FDIV_M => {
+            emit_src_address(asm, instr);
+            asm.cvtdq2pd_sp(XMM_TMP);
+            asm.sse_rr(ANDPD, XMM_TMP, XMM_MANTISSA_MASK);
+            asm.sse_rr(ORPD, XMM_TMP, XMM_EXPONENT_MASK);
+            asm.sse_rr(DIVPD, e(instr.dst), XMM_TMP);
+        }
+        Opcode::FSQRT_R => {
+            let dst = e(instr.dst);
+            asm.sse_rr(SQRTPD, dst, dst);
+        }
+        Opcode::CBRANCH => {
+            let dst = r(instr.dst);
+            let shift = u64::from(instr.modi) + CONDITION_OFFSET;
+            let imm = instr.imm64;
+            if imm as i64 == (imm as i32) as i64 {
+                asm.alu_ri(ADD_EXT, dst, imm as i32);
+            } else {
+                asm.mov_ri64(RAX, imm);
+                asm.alu_rr(ADD, dst, RAX);
+            }
+            asm.test_ri(dst, (CONDITION_MASK << shift) as i32);
+            let target = (instr.target + 1) as usize;
+            asm.jz(instr_offsets[target]);
+        }
+        Opcode::CFROUND => {
+            asm.alu_rr(MOV, RAX, r(instr.src));
+            asm.shift_ri(ROR, RAX, (instr.imm & 63) as u8);
+            asm.set_rounding_mode_from_eax();
+        }
+        Opcode::ISTORE => {
+            asm.mov_ecx_r32(r(instr.dst));
+            asm.add_ecx(instr.imm);
+            asm.and_ecx(instr.mem_mask);
+            asm.mov_sp_r(r(instr.src));
+        }
+    }
+}
+
+/// Loads the scratchpad byte offset of the source operand into rcx.
+fn emit_src_address(asm: &mut Assembler, instr: &Instr) {
+    if instr.src_imm {
+        asm.mov_ecx_imm((instr.imm as u32) & instr.mem_mask);
+    } else {
+        asm.mov_ecx_r32(r(instr.src));
+        asm.add_ecx(instr.imm);
+        asm.and_ecx(instr.mem_mask);
+    }
+}
+
+fn emit_mem_op(asm: &mut Assembler, instr: &Instr, op: u8) {
+    emit_src_address(asm, instr);
+    asm.op_r_sp(op, r(instr.dst));
+}
+
+fn emit_reg_or_imm_op(asm: &mut Assembler, instr: &Instr, op: u8, ext: u8) {
+    let dst = r(instr.dst);
+    if instr.src_imm {
+        asm.alu_ri(ext, dst, instr.imm);
+    } else {
+        asm.alu_rr(op, dst, r(instr.src));
+    }
+}
+
+//Register usage of the dataset init code:
+//rdi seed memory, rsi output item, rbx item number, rbp end item number,
+//rcx seed memory offset of the current cache line, r8-r15 the item registers
+fn emit_dataset_init(asm: &mut Assembler, programs: &[ScProgram<'_>], cache_line_mask: u64) {
+    for reg in [RBX, RBP, 12, 13, 14, 15] {
+        asm.push(reg);
+    }
+    asm.alu_rr(MOV, RBX, RDX);
+    asm.alu_rr(MOV, RBP, RCX);
+
+    let loop_start = asm.code.len();
+    asm.alu_rr(MOV, r(0), RBX);
+    asm.alu_ri(ADD_EXT, r(0), 1);
+    asm.mov_ri64(RAX, SUPERSCALAR_MUL_0);
+    asm.imul_rr(r(0), RAX);
+    let adds = [
+        SUPERSCALAR_ADD_1,
+        SUPERSCALAR_ADD_2,
+        SUPERSCALAR_ADD_3,
+        SUPERSCALAR_ADD_4,
+        SUPERSCALAR_ADD_5,
+        SUPERSCALAR_ADD_6,
+        SUPERSCALAR_ADD_7,
+    ];
+    for (i, add) in adds.iter().enumerate() {
+        asm.mov_ri64(RAX, *add);
+        asm.alu_rr(MOV, r(i as u8 + 1), r(0));
+        asm.alu_rr(XOR, r(i as u8 + 1), RAX);
+    }
+
+    asm.alu_rr(MOV, RCX, RBX);
+    for prog in programs {
+        asm.and_ecx(cache_line_mask as u32);
+        asm.shift_ri(SHL, RCX, 6);
+        asm.prefetchnta_cache();
+        for instr in &prog.prog {
+            emit_sc_instr(asm, instr);
+        }
+        for i in 0..8 {
+            asm.xor_r_cache(r(i), 8 * i);
+        }
+        asm.alu_rr(MOV, RCX, r(prog.address_reg as u8));
+    }
+
+    for i in 0..8 {
+        asm.mov_m_r(RSI, 8 * i as i32, r(i));
+    }
+    asm.alu_ri(ADD_EXT, RSI, 64);
+    asm.alu_ri(ADD_EXT, RBX, 1);
+    asm.alu_rr(CMP, RBX, RBP);
+    asm.jb(loop_start);
+
+    for reg in [15, 14, 13, 12, RBP, RBX] {
+        asm.pop(reg);
+    }
+    asm.ret();
+}
+
+fn emit_sc_instr(asm: &mut Assembler, instr: &ScInstr<'_>) {
+    let dst = r(instr.dst as u8);
+    match instr.info.op {
+        ScOpcode::ISUB_R => asm.alu_rr(SUB, dst, r(instr.src as u8)),
+        ScOpcode::IXOR_R => asm.alu_rr(XOR, dst, r(instr.src as u8)),
+        ScOpcode::IADD_RS => {
+            asm.alu_rr(MOV, RAX, r(instr.src as u8));
+            asm.shift_ri(SHL, RAX, instr.mod_shift() as u8);
+            asm.alu_rr(ADD, dst, RAX);
+        }
+        ScOpcode::IMUL_R => asm.imul_rr(dst, r(instr.src as u8)),
+        ScOpcode::IROR_C => asm.shift_ri(ROR, dst, (instr.imm32 & 63) as u8),
+        ScOpcode::IADD_C7 | ScOpcode::IADD_C8 | ScOpcode::IADD_C9 => {
+            asm.alu_ri(ADD_EXT, dst, instr.imm32 as i32)
+        }
+        ScOpcode::IXOR_C7 | ScOpcode::IXOR_C8 | ScOpcode::IXOR_C9 => {
+            asm.alu_ri(XOR_EXT, dst, instr.imm32 as i32)
+        }
+        ScOpcode::IMULH_R | ScOpcode::ISMULH_R => {
+            let ext = if instr.info.op == ScOpcode::IMULH_R {
+                MUL_EXT
+            } else {
+                IMUL1_EXT
+            };
+            asm.alu_rr(MOV, RAX, dst);
+            asm.unary_r(ext, r(instr.src as u8));
+            asm.alu_rr(MOV, dst, RDX);
+        }
+        ScOpcode::IMUL_RCP => {
+            asm.mov_ri64(RAX, randomx_reciprocal(u64::from(instr.imm32)));
+            asm.imul_rr(dst, RAX);
+        }
+        ScOpcode::INVALID | ScOpcode::COUNT => panic!("invalid opcode {} here", instr.info.op),
+    }
+}
+
+fn r(i: u8) -> u8 {
+    R_BASE + i
+}
+
+fn f(i: u8) -> u8 {
+    F_BASE + i
+}
+
+fn e(i: u8) -> u8 {
+    E_BASE + i
+}
+
+fn a(i: u8) -> u8 {
+    A_BASE + i
+}
+
+//opcodes (reg/mem forms)
+const ADD: u8 = 0x01;
+const SUB: u8 = 0x29;
+const XOR: u8 = 0x31;
+const MOV: u8 = 0x89;
+const XCHG: u8 = 0x87;
+const CMP: u8 = 0x39;
+const ADD_M: u8 = 0x03;
+const SUB_M: u8 = 0x2B;
+const XOR_M: u8 = 0x33;
+//opcode extensions of the 0x81 (imm32) group
+const ADD_EXT: u8 = 0;
+const SUB_EXT: u8 = 5;
+const XOR_EXT: u8 = 6;
+//opcode extensions of the 0xF7 group
+const TEST_EXT: u8 = 0;
+const NEG_EXT: u8 = 3;
+const MUL_EXT: u8 = 4;
+const IMUL1_EXT: u8 = 5;
+//opcode extensions of the shift group
+const ROL: u8 = 0;
+const ROR: u8 = 1;
+const SHL: u8 = 4;
+//SSE2 packed double opcodes (0x66 0x0F xx)
+const SQRTPD: u8 = 0x51;
+const ANDPD: u8 = 0x54;
+const ORPD: u8 = 0x56;
+const XORPD: u8 = 0x57;
+const ADDPD: u8 = 0x58;
+const MULPD: u8 = 0x59;
+const SUBPD: u8 = 0x5C;
+const DIVPD: u8 = 0x5E;
+
+//modrm/sib for the [rsi + rcx] scratchpad operand
+const MODRM_SIB: u8 = 0b100;
+const SIB_RSI_RCX: u8 = (RCX << 3) | RSI;
+//sib for the [rdi + rcx] seed memory operand
+const SIB_RDI_RCX: u8 = (RCX << 3) | RDI;
+//condition codes of the 0x0F 0x8x jumps
+const JB: u8 = 0x82;
+const JZ: u8 = 0x84;
+
+/// Minimal x86-64 encoder, only knows the instruction forms needed by the
+/// RandomX programs.
+struct Assembler {
+    code: Vec<u8>,
+}
+
+impl Assembler {
+    fn new() -> Assembler {
+        Assembler {
+            code: Vec::with_capacity(CODE_BUFFER_SIZE),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.code.clear();
+    }
+
+    fn emit(&mut self, bytes: &[u8]) {
+        self.code.extend_from_slice(bytes);
+    }
+
+    fn emit_i32(&mut self, v: i32) {
+        self.emit(&v.to_le_bytes());
+    }
+
+    fn rex(&mut self, w: bool, reg: u8, base: u8) {
+        let rex = 0x40 | ((w as u8) << 3) | ((reg >> 3) << 2) | (base >> 3);
+        if rex != 0x40 {
+            self.code.push(rex);
+        }
+    }
+
+    fn modrm_rr(&mut self, reg: u8, rm: u8) {
+        self.code.push(0xC0 | ((reg & 7) << 3) | (rm & 7));
+    }
+
+    /// [base + disp32], base must not be rsp/r12
+    fn modrm_disp32(&mut self, reg: u8, base: u8, disp: i32) {
+        debug_assert!(base & 7 != 4);
+        self.code.push(0x80 | ((reg & 7) << 3) | (base & 7));
+        self.emit_i32(disp);
+    }
+
+    fn modrm_sp(&mut self, reg: u8) {
+        self.code.push(((reg & 7) << 3) | MODRM_SIB);
+        self.code.push(SIB_RSI_RCX);
+    }
+
+    fn push(&mut self, reg: u8) {
+        self.rex(false, 0, reg);
+        self.code.push(0x50 + (reg & 7));
+    }
+
+    fn pop(&mut self, reg: u8) {
+        self.rex(false, 0, reg);
+        self.code.push(0x58 + (reg & 7));
+    }
+
+    fn sub_rsp(&mut self, v: u8) {
+        self.emit(&[0x48, 0x83, 0xEC, v]);
+    }
+
+    fn add_rsp(&mut self, v: u8) {
+        self.emit(&[0x48, 0x83, 0xC4, v]);
+    }
+
+    fn ret(&mut self) {
+        self.code.push(0xC3);
+    }
+
+    /// `op dst, src` for the r/m64, r64 forms (add, sub, xor, mov, xchg)
+    fn alu_rr(&mut self, op: u8, dst: u8, src: u8) {
+        self.rex(true, src, dst);
+        self.code.push(op);
+        self.modrm_rr(src, dst);
+    }
+
+    /// `op dst, imm32` (sign extended)
+    fn alu_ri(&mut self, ext: u8, dst: u8, imm: i32) {
+        self.rex(true, 0, dst);
+        self.code.push(0x81);
+        self.modrm_rr(ext, dst);
+        self.emit_i32(imm);
+    }
+
+    fn test_ri(&mut self, dst: u8, imm: i32) {
+        self.rex(true, 0, dst);
+        self.code.push(0xF7);
+        self.modrm_rr(TEST_EXT, dst);
+        self.emit_i32(imm);
+    }
+
+    fn unary_r(&mut self, ext: u8, dst: u8) {
+        self.rex(true, 0, dst);
+        self.code.push(0xF7);
+        self.modrm_rr(ext, dst);
+    }
+
+    fn unary_sp(&mut self, ext: u8) {
+        self.code.push(0x48);
+        self.code.push(0xF7);
+        self.modrm_sp(ext);
+    }
+
+    fn imul_rr(&mut self, dst: u8, src: u8) {
+        self.rex(true, dst, src);
+        self.emit(&[0x0F, 0xAF]);
+        self.modrm_rr(dst, src);
+    }
+
+    fn imul_rri(&mut self, dst: u8, imm: i32) {
+        self.rex(true, dst, dst);
+        self.code.push(0x69);
+        self.modrm_rr(dst, dst);
+        self.emit_i32(imm);
+    }
+
+    fn imul_r_sp(&mut self, dst: u8) {
+        self.rex(true, dst, 0);
+        self.emit(&[0x0F, 0xAF]);
+        self.modrm_sp(dst);
+    }
+
+    /// `op dst, [rsi + rcx]` (add, sub, xor)
+    fn op_r_sp(&mut self, op: u8, dst: u8) {
+        self.rex(true, dst, 0);
+        self.code.push(op);
+        self.modrm_sp(dst);
+    }
+
+    /// `mov [rsi + rcx], src`
+    fn mov_sp_r(&mut self, src: u8) {
+        self.rex(true, src, 0);
+        self.code.push(MOV);
+        self.modrm_sp(src);
+    }
+
+    fn shift_ri(&mut self, ext: u8, dst: u8, imm: u8) {
+        self.rex(true, 0, dst);
+        self.code.push(0xC1);
+        self.modrm_rr(ext, dst);
+        self.code.push(imm);
+    }
+
+    fn shift_rcl(&mut self, ext: u8, dst: u8) {
+        self.rex(true, 0, dst);
+        self.code.push(0xD3);
+        self.modrm_rr(ext, dst);
+    }
+
+    fn mov_ri64(&mut self, dst: u8, imm: u64) {
+        self.rex(true, 0, dst);
+        self.code.push(0xB8 + (dst & 7));
+        self.emit(&imm.to_le_bytes());
+    }
+
+    fn mov_r_m(&mut self, dst: u8, base: u8, disp: i32) {
+        self.rex(true, dst, base);
+        self.code.push(0x8B);
+        self.modrm_disp32(dst, base, disp);
+    }
+
+    fn mov_m_r(&mut self, base: u8, disp: i32, src: u8) {
+        self.rex(true, src, base);
+        self.code.push(MOV);
+        self.modrm_disp32(src, base, disp);
+    }
+
+    fn mov_ecx_r32(&mut self, src: u8) {
+        self.rex(false, src, RCX);
+        self.code.push(MOV);
+        self.modrm_rr(src, RCX);
+    }
+
+    fn mov_ecx_imm(&mut self, imm: u32) {
+        self.code.push(0xB9);
+        self.emit(&imm.to_le_bytes());
+    }
+
+    fn add_ecx(&mut self, imm: i32) {
+        self.emit(&[0x81, 0xC1]);
+        self.emit_i32(imm);
+    }
+
+    fn and_ecx(&mut self, imm: u32) {
+        self.emit(&[0x81, 0xE1]);
+        self.emit(&imm.to_le_bytes());
+    }
+
+    /// Takes the rounding mode from the lower two bits of eax and loads it into MXCSR.
+    fn set_rounding_mode_from_eax(&mut self) {
+        self.emit(&[0x83, 0xE0, 0x03]); //and eax, 3
+        self.emit(&[0xC1, 0xE0, 0x0D]); //shl eax, 13
+        self.code.push(0x0D); //or eax, MXCSR_DEFAULT
+        self.emit(&MXCSR_DEFAULT.to_le_bytes());
+        self.emit(&[0x89, 0x04, 0x24]); //mov [rsp], eax
+        self.emit(&[0x0F, 0xAE, 0x14, 0x24]); //ldmxcsr [rsp]
+    }
+
+    fn jz(&mut self, target: usize) {
+        self.jcc(JZ, target);
+    }
+
+    fn jb(&mut self, target: usize) {
+        self.jcc(JB, target);
+    }
+
+    fn jcc(&mut self, cc: u8, target: usize) {
+        let rel = target as i64 - (self.code.len() as i64 + 6);
+        self.emit(&[0x0F, cc]);
+        self.emit_i32(rel as i32);
+    }
+
+    /// `xor dst, [rdi + rcx + disp]`
+    fn xor_r_cache(&mut self, dst: u8, disp: u8) {
+        self.rex(true, dst, 0);
+        self.code.push(XOR_M);
+        self.code.push(0x40 | ((dst & 7) << 3) | MODRM_SIB);
+        self.code.push(SIB_RDI_RCX);
+        self.code.push(disp);
+    }
+
+    /// `prefetchnta [rdi + rcx]`
+    fn prefetchnta_cache(&mut self) {
+        self.emit(&[0x0F, 0x18, MODRM_SIB, SIB_RDI_RCX]);
+    }
+
+    fn sse_rr(&mut self, op: u8, dst: u8, src: u8) {
+        self.code.push(0x66);
+        self.rex(false, dst, src);
+        self.emit(&[0x0F, op]);
+        self.modrm_rr(dst, src);
+    }
+
+    fn shufpd(&mut self, dst: u8, src: u8, imm: u8) {
+        self.sse_rr(0xC6, dst, src);
+        self.code.push(imm);
+    }
+
+    /// `cvtdq2pd dst, qword [rsi + rcx]`
+    fn cvtdq2pd_sp(&mut self, dst: u8) {
+        self.code.push(0xF3);
+        self.rex(false, dst, 0);
+        self.emit(&[0x0F, 0xE6]);
+        self.modrm_sp(dst);
+    }
+
+    fn movupd_x_m(&mut self, dst: u8, base: u8, disp: i32) {
+        self.code.push(0x66);
+        self.rex(false, dst, base);
+        self.emit(&[0x0F, 0x10]);
+        self.modrm_disp32(dst, base, disp);
+    }
+
+    fn movupd_m_x(&mut self, base: u8, disp: i32, src: u8) {
+        self.code.push(0x66);
+        self.rex(false, src, base);
+        self.emit(&[0x0F, 0x11]);
+        self.modrm_disp32(src, base, disp);
+    }
+}
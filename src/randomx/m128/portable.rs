@@ -0,0 +1,263 @@
+use std::cell::Cell;
+
+use super::super::soft_aes;
+use super::{ROUND_DOWN, ROUND_TO_NEAREST, ROUND_TO_ZERO, ROUND_UP};
+
+//Directed rounding is emulated: each operation is computed with the default
+//round to nearest and then corrected by one ulp, depending on the sign of the
+//exact rounding error (computed with error-free transformations).
+
+thread_local! {
+    static ROUNDING_MODE: Cell<u32> = Cell::new(ROUND_TO_NEAREST);
+}
+
+pub fn set_rounding_mode(mode: u32) {
+    ROUNDING_MODE.with(|m| m.set(mode & 3));
+}
+
+pub fn get_rounding_mode() -> u32 {
+    ROUNDING_MODE.with(|m| m.get())
+}
+
+#[allow(nonstandard_style)]
+#[derive(Copy, Clone)]
+pub struct m128i(pub [u64; 2]);
+
+impl m128i {
+    pub fn from_i32(i3: i32, i2: i32, i1: i32, i0: i32) -> m128i {
+        let u0 = u64::from(i0 as u32) | u64::from(i1 as u32) << 32;
+        let u1 = u64::from(i2 as u32) | u64::from(i3 as u32) << 32;
+        m128i([u0, u1])
+    }
+    pub fn from_u64(u1: u64, u0: u64) -> m128i {
+        m128i([u0, u1])
+    }
+    pub fn aesdec(&self, key: m128i) -> m128i {
+        soft_aes::aesdec(*self, key)
+    }
+    pub fn aesenc(&self, key: m128i) -> m128i {
+        soft_aes::aesenc(*self, key)
+    }
+    pub fn as_i64(&self) -> (i64, i64) {
+        (self.0[1] as i64, self.0[0] as i64)
+    }
+
+    //_mm_cvtepi32_pd
+    pub fn lower_to_m128d(&self) -> m128d {
+        let i0 = self.0[0] as i32;
+        let i1 = (self.0[0] >> 32) as i32;
+        m128d::from_f64(f64::from(i1), f64::from(i0))
+    }
+}
+
+impl PartialEq for m128i {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+//==== m128d
+
+//stores the bits of the two lanes, low lane first
+#[allow(nonstandard_style)]
+#[derive(Copy, Clone)]
+pub struct m128d(pub [u64; 2]);
+
+impl m128d {
+    pub fn from_f64(h: f64, l: f64) -> m128d {
+        m128d([l.to_bits(), h.to_bits()])
+    }
+    pub fn as_f64(&self) -> (f64, f64) {
+        (f64::from_bits(self.0[1]), f64::from_bits(self.0[0]))
+    }
+
+    //_mm_shuffle_pd(a, b, 1)
+    pub fn shuffle_1(&self, other: &m128d) -> m128d {
+        m128d([self.0[1], other.0[0]])
+    }
+
+    //_mm_sqrt_pd
+    pub fn sqrt(&self) -> m128d {
+        self.map(|a| {
+            let s = a.sqrt();
+            if !s.is_finite() || s == 0.0 {
+                return s;
+            }
+            round(s, -s.mul_add(s, -a))
+        })
+    }
+
+    fn map<F: Fn(f64) -> f64>(&self, f: F) -> m128d {
+        let (h, l) = self.as_f64();
+        m128d::from_f64(f(h), f(l))
+    }
+
+    fn zip<F: Fn(f64, f64) -> f64>(&self, other: &m128d, f: F) -> m128d {
+        let (h1, l1) = self.as_f64();
+        let (h2, l2) = other.as_f64();
+        m128d::from_f64(f(h1, h2), f(l1, l2))
+    }
+}
+
+impl PartialEq for m128d {
+    fn eq(&self, other: &Self) -> bool {
+        let (h1, l1) = self.as_f64();
+        let (h2, l2) = other.as_f64();
+        h1 == h2 && l1 == l2
+    }
+}
+
+impl std::ops::Add for m128d {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        self.zip(&other, add)
+    }
+}
+
+impl std::ops::Sub for m128d {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self.zip(&other, |a, b| add(a, -b))
+    }
+}
+
+impl std::ops::BitXor for m128d {
+    type Output = Self;
+
+    fn bitxor(self, rhs: Self) -> Self::Output {
+        m128d([self.0[0] ^ rhs.0[0], self.0[1] ^ rhs.0[1]])
+    }
+}
+
+impl std::ops::BitAnd for m128d {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self::Output {
+        m128d([self.0[0] & rhs.0[0], self.0[1] & rhs.0[1]])
+    }
+}
+
+impl std::ops::BitOr for m128d {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        m128d([self.0[0] | rhs.0[0], self.0[1] | rhs.0[1]])
+    }
+}
+
+impl std::ops::Mul for m128d {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        self.zip(&rhs, mul)
+    }
+}
+
+impl std::ops::Div for m128d {
+    type Output = Self;
+
+    fn div(self, rhs: Self) -> Self {
+        self.zip(&rhs, div)
+    }
+}
+
+fn add(a: f64, b: f64) -> f64 {
+    let s = a + b;
+    let mode = get_rounding_mode();
+    if mode == ROUND_TO_NEAREST {
+        return s;
+    }
+    if s.is_infinite() && a.is_finite() && b.is_finite() {
+        return overflow(s, mode);
+    }
+    if !s.is_finite() {
+        return s;
+    }
+    if s == 0.0 {
+        //an exact zero sum is -0 when rounding down, unless both operands are +0
+        if mode == ROUND_DOWN && (a.is_sign_negative() || b.is_sign_negative()) {
+            return -0.0;
+        }
+        return s;
+    }
+    //TwoSum
+    let b_virtual = s - a;
+    let a_virtual = s - b_virtual;
+    let err = (a - a_virtual) + (b - b_virtual);
+    round(s, err)
+}
+
+fn mul(a: f64, b: f64) -> f64 {
+    let p = a * b;
+    let mode = get_rounding_mode();
+    if mode == ROUND_TO_NEAREST {
+        return p;
+    }
+    if p.is_infinite() && a.is_finite() && b.is_finite() {
+        return overflow(p, mode);
+    }
+    if !p.is_finite() || p == 0.0 {
+        return p;
+    }
+    round(p, a.mul_add(b, -p))
+}
+
+fn div(a: f64, b: f64) -> f64 {
+    let q = a / b;
+    let mode = get_rounding_mode();
+    if mode == ROUND_TO_NEAREST {
+        return q;
+    }
+    if q.is_infinite() && a.is_finite() && b.is_finite() && b != 0.0 {
+        return overflow(q, mode);
+    }
+    if !q.is_finite() || q == 0.0 {
+        return q;
+    }
+    //a = q * b + r, the exact quotient is q + r / b
+    let r = (-q).mul_add(b, a);
+    round(q, if b > 0.0 { r } else { -r })
+}
+
+//corrects the round to nearest result `r` of an operation by one ulp according to
+//the current rounding mode, `err` has the sign of (exact result - r)
+fn round(r: f64, err: f64) -> f64 {
+    if err == 0.0 {
+        return r;
+    }
+    match get_rounding_mode() {
+        ROUND_DOWN if err < 0.0 => next_down(r),
+        ROUND_UP if err > 0.0 => next_up(r),
+        ROUND_TO_ZERO if err < 0.0 && r > 0.0 => next_down(r),
+        ROUND_TO_ZERO if err > 0.0 && r < 0.0 => next_up(r),
+        _ => r,
+    }
+}
+
+//result for an operation that overflowed to infinity with round to nearest
+fn overflow(inf: f64, mode: u32) -> f64 {
+    match mode {
+        ROUND_DOWN if inf > 0.0 => f64::MAX,
+        ROUND_UP if inf < 0.0 => f64::MIN,
+        ROUND_TO_ZERO => f64::MAX.copysign(inf),
+        _ => inf,
+    }
+}
+
+fn next_up(x: f64) -> f64 {
+    if x == 0.0 {
+        return f64::from_bits(1);
+    }
+    let bits = x.to_bits();
+    if x > 0.0 {
+        f64::from_bits(bits + 1)
+    } else {
+        f64::from_bits(bits - 1)
+    }
+}
+
+fn next_down(x: f64) -> f64 {
+    -next_up(-x)
+}
@@ -1,43 +1,64 @@
 extern crate argon2;
+extern crate crossbeam_channel;
+extern crate num_cpus;
 
 
-use std::sync::{Arc, RwLock};
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
 use std::time::Instant;
+#[cfg(target_arch = "x86_64")]
 use std::arch::x86_64::{
     _mm_prefetch,
     _MM_HINT_NTA
 };
 
 use self::argon2::block::Block;
+use self::crossbeam_channel::{unbounded, Receiver, Sender};
 
 use super::super::byte_string;
+use super::argon2d::{self, BlockFiller};
+use super::dataset_cache::{self, DatasetCacheConfig};
+use super::hardware::{self, DatasetInit, HardwareConfig, MemoryMode, PageBuffer};
+use super::item_cache::ItemCache;
+use super::jit::CompiledSuperscalar;
+use super::numa::{self, NumaTopology};
+use super::params::{RandomXParams, RX_0};
+use super::shared_dataset::{self, SharedDatasetConfig};
 use super::superscalar::{Blake2Generator, ScProgram};
-
-const RANDOMX_ARGON_LANES: u32 = 1;
-const RANDOMX_ARGON_MEMORY: u32 = 262144;
-const RANDOMX_ARGON_SALT: &[u8; 8] = b"RandomX\x03";
-const RANDOMX_ARGON_ITERATIONS: u32 = 3;
-const RANDOMX_CACHE_ACCESSES: usize = 8;
+#[cfg(all(target_arch = "x86_64", not(feature = "portable")))]
+use super::superscalar_avx2;
 
 const ARGON2_SYNC_POINTS: u32 = 4;
 const ARGON_BLOCK_SIZE: u32 = 1024;
 
 pub const CACHE_LINE_SIZE: u64 = 64;
-pub const DATASET_ITEM_COUNT: usize = (2147483648 + 33554368) / 64; //34.078.719
-
-const SUPERSCALAR_MUL_0: u64 = 6364136223846793005;
-const SUPERSCALAR_ADD_1: u64 = 9298411001130361340;
-const SUPERSCALAR_ADD_2: u64 = 12065312585734608966;
-const SUPERSCALAR_ADD_3: u64 = 9306329213124626780;
-const SUPERSCALAR_ADD_4: u64 = 5281919268842080866;
-const SUPERSCALAR_ADD_5: u64 = 10536153434571861004;
-const SUPERSCALAR_ADD_6: u64 = 3398623926847679864;
-const SUPERSCALAR_ADD_7: u64 = 9549104520008361294;
-
-//256MiB, always used, named randomx_cache in the reference implementation
+
+//memory kept free for the OS and the scratchpads when the memory mode is selected
+const MEMORY_HEADROOM: u64 = 256 * 1024 * 1024;
+
+//number of items a thread initialises before reporting progress
+const DATASET_INIT_BATCH_SIZE: usize = 1 << 16;
+
+pub const SUPERSCALAR_MUL_0: u64 = 6364136223846793005;
+pub const SUPERSCALAR_ADD_1: u64 = 9298411001130361340;
+pub const SUPERSCALAR_ADD_2: u64 = 12065312585734608966;
+pub const SUPERSCALAR_ADD_3: u64 = 9306329213124626780;
+pub const SUPERSCALAR_ADD_4: u64 = 5281919268842080866;
+pub const SUPERSCALAR_ADD_5: u64 = 10536153434571861004;
+pub const SUPERSCALAR_ADD_6: u64 = 3398623926847679864;
+pub const SUPERSCALAR_ADD_7: u64 = 9549104520008361294;
+
+//256MiB (for rx/0), always used, named randomx_cache in the reference implementation
 pub struct SeedMemory {
     pub blocks: Box<[Block]>,
     pub programs: Vec<ScProgram<'static>>,
+    /// the programs compiled to native code, `None` if not supported on this platform
+    pub compiled: Option<CompiledSuperscalar>,
+    /// the parameters of the variant the memory was initialised for
+    pub params: RandomXParams,
 }
 
 impl SeedMemory {
@@ -45,62 +66,102 @@ impl SeedMemory {
         SeedMemory {
             blocks: Box::new([]),
             programs: Vec::with_capacity(0),
+            compiled: None,
+            params: RX_0,
         }
     }
 
-    /// Creates a new initialised seed memory.
+    /// Creates a new initialised seed memory for rx/0.
     pub fn new_initialised(key: &[u8]) -> SeedMemory {
-        let mut mem = argon2::memory::Memory::new(RANDOMX_ARGON_LANES, RANDOMX_ARGON_MEMORY);
-        let context = &create_argon_context(key);
-        argon2::core::initialize(context, &mut mem);
-        argon2::core::fill_memory_blocks(context, &mut mem);
+        SeedMemory::new_initialised_with_params(key, &RX_0)
+    }
+
+    pub fn new_initialised_with_params(key: &[u8], params: &RandomXParams) -> SeedMemory {
+        let init_start = Instant::now();
+        let filler = BlockFiller::detect();
+        let mut mem = new_argon_memory(params);
+        argon2::core::initialize(&create_argon_context(key, params), &mut mem);
+        argon2d::fill_memory_blocks(&mut mem.blocks, params, filler);
+        info!(
+            "seed memory init took {}ms ({:?} block filler)",
+            init_start.elapsed().as_millis(),
+            filler
+        );
+        SeedMemory::from_blocks(key, mem.blocks, params)
+    }
 
-        let mut programs = Vec::with_capacity(RANDOMX_CACHE_ACCESSES);
+    /// Creates a seed memory from already initialised blocks (e.g. loaded from disk).
+    pub fn from_blocks(key: &[u8], blocks: Box<[Block]>, params: &RandomXParams) -> SeedMemory {
+        let mut programs = Vec::with_capacity(params.cache_accesses);
         let mut gen = Blake2Generator::new(key, 0);
-        for _ in 0..RANDOMX_CACHE_ACCESSES {
+        for _ in 0..params.cache_accesses {
             programs.push(ScProgram::generate(&mut gen));
         }
 
+        let compiled = CompiledSuperscalar::compile(&programs, params);
         SeedMemory {
-            blocks: mem.blocks,
+            blocks,
             programs,
+            compiled,
+            params: *params,
         }
     }
 }
 
-fn create_argon_context(key: &[u8]) -> argon2::context::Context {
-    let segment_length = RANDOMX_ARGON_MEMORY / (RANDOMX_ARGON_LANES * ARGON2_SYNC_POINTS);
+/// Allocates the (zeroed) Argon2 blocks of a seed memory.
+pub fn new_argon_memory(params: &RandomXParams) -> argon2::memory::Memory {
+    argon2::memory::Memory::new(params.argon_lanes, params.argon_memory / params.argon_lanes)
+}
+
+fn create_argon_context<'a>(key: &'a [u8], params: &RandomXParams) -> argon2::context::Context<'a> {
+    let segment_length = params.argon_memory / (params.argon_lanes * ARGON2_SYNC_POINTS);
     let config = argon2::config::Config {
         ad: &[],
         hash_length: 0,
-        lanes: RANDOMX_ARGON_LANES,
-        mem_cost: RANDOMX_ARGON_MEMORY,
+        lanes: params.argon_lanes,
+        mem_cost: params.argon_memory,
         secret: &[],
         thread_mode: argon2::ThreadMode::from_threads(1),
-        time_cost: RANDOMX_ARGON_ITERATIONS,
+        time_cost: params.argon_iterations,
         variant: argon2::Variant::Argon2d,
         version: argon2::Version::Version13,
     };
     argon2::context::Context {
         config,
-        memory_blocks: RANDOMX_ARGON_MEMORY,
+        memory_blocks: params.argon_memory,
         pwd: key,
-        salt: RANDOMX_ARGON_SALT,
+        salt: params.argon_salt,
         lane_length: segment_length * ARGON2_SYNC_POINTS,
         segment_length,
     }
 }
 
 fn mix_block_value(seed_mem: &SeedMemory, reg_value: u64, r: usize) -> u64 {
-    let mask = (((RANDOMX_ARGON_MEMORY * ARGON_BLOCK_SIZE) as u64) / CACHE_LINE_SIZE) - 1;
-    let byte_offset = ((reg_value & mask) * CACHE_LINE_SIZE) + (8 * r as u64);
+    let cache_line = reg_value & seed_mem.params.cache_line_mask();
+    let byte_offset = (cache_line * CACHE_LINE_SIZE) + (8 * r as u64);
 
     let block_ix = byte_offset / ARGON_BLOCK_SIZE as u64;
     let block_v_ix = (byte_offset - (block_ix * ARGON_BLOCK_SIZE as u64)) / 8;
     seed_mem.blocks[block_ix as usize][block_v_ix as usize]
 }
 
+/// One 64 byte item of the dataset, aligned to a cache line.
+#[repr(C, align(64))]
+#[derive(Clone, Copy)]
+pub struct DatasetItem(pub [u64; 8]);
+
 pub fn init_dataset_item(seed_mem: &SeedMemory, item_num: u64) -> [u64; 8] {
+    if let Some(compiled) = &seed_mem.compiled {
+        let mut item = [DatasetItem([0; 8])];
+        compiled.init_dataset_items(seed_mem, item_num, &mut item);
+        return item[0].0;
+    }
+    init_dataset_item_interpreted(seed_mem, item_num)
+}
+
+/// Same as `init_dataset_item`, but always runs the superscalar programs
+/// in the interpreter.
+pub fn init_dataset_item_interpreted(seed_mem: &SeedMemory, item_num: u64) -> [u64; 8] {
     let mut ds = [0; 8];
 
     let mut reg_value = item_num;
@@ -125,108 +186,715 @@ pub fn init_dataset_item(seed_mem: &SeedMemory, item_num: u64) -> [u64; 8] {
     ds
 }
 
+/// Computes the items `start_item..start_item + items.len()` like `init_dataset_item`,
+/// but runs each superscalar program on four items at once in AVX2 lanes. Without
+/// AVX2 the items are computed by the interpreter one at a time.
+pub fn init_dataset_items_batched(
+    seed_mem: &SeedMemory,
+    start_item: u64,
+    items: &mut [DatasetItem],
+) {
+    #[cfg(all(target_arch = "x86_64", not(feature = "portable")))]
+    {
+        if hardware::has_avx2() {
+            unsafe { superscalar_avx2::init_dataset_items(seed_mem, start_item, items) };
+            return;
+        }
+    }
+    init_dataset_items_interpreted(seed_mem, start_item, items);
+}
+
+fn init_dataset_items_interpreted(
+    seed_mem: &SeedMemory,
+    start_item: u64,
+    items: &mut [DatasetItem],
+) {
+    for (i, item) in items.iter_mut().enumerate() {
+        *item = DatasetItem(init_dataset_item_interpreted(
+            seed_mem,
+            start_item + i as u64,
+        ));
+    }
+}
+
+/// The executor `init_dataset` uses for `requested`: the compiled programs need
+/// `seed_mem.compiled` and the AVX2 lanes need AVX2, otherwise the next executor of
+/// compiled, AVX2 and interpreted is used. Never returns `DatasetInit::Auto`.
+pub fn dataset_init_executor(seed_mem: &SeedMemory, requested: DatasetInit) -> DatasetInit {
+    match requested {
+        DatasetInit::Auto | DatasetInit::Compiled if seed_mem.compiled.is_some() => {
+            DatasetInit::Compiled
+        }
+        DatasetInit::Interpreted => DatasetInit::Interpreted,
+        _ if hardware::has_avx2() => DatasetInit::Avx2,
+        _ => DatasetInit::Interpreted,
+    }
+}
+
+/// Initialises all dataset items with the executor selected for `requested` (see
+/// `dataset_init_executor`), split evenly across `num_threads` threads.
+pub fn init_dataset(
+    seed_mem: &SeedMemory,
+    dataset: &mut [DatasetItem],
+    num_threads: usize,
+    requested: DatasetInit,
+) {
+    let init_start = Instant::now();
+    let executor = dataset_init_executor(seed_mem, requested);
+    if requested != DatasetInit::Auto && executor != requested {
+        warn!(
+            "dataset init {} not available, using {}",
+            requested, executor
+        );
+    }
+    let num_threads = num_threads.max(1);
+    let chunk_size = dataset.len().div_ceil(num_threads);
+    let item_count = dataset.len();
+    let items_done = AtomicUsize::new(0);
+
+    thread::scope(|scope| {
+        for (chunk_ix, chunk) in dataset.chunks_mut(chunk_size).enumerate() {
+            let items_done = &items_done;
+            scope.spawn(move || {
+                let start_item = chunk_ix * chunk_size;
+                init_dataset_chunk(
+                    seed_mem, executor, start_item, chunk, items_done, item_count,
+                );
+            });
+        }
+    });
+
+    info!(
+        "dataset init took {}ms with {} threads ({})",
+        init_start.elapsed().as_millis(),
+        num_threads,
+        executor,
+    );
+}
+
+fn init_dataset_chunk(
+    seed_mem: &SeedMemory,
+    executor: DatasetInit,
+    start_item: usize,
+    chunk: &mut [DatasetItem],
+    items_done: &AtomicUsize,
+    item_count: usize,
+) {
+    for (batch_ix, batch) in chunk.chunks_mut(DATASET_INIT_BATCH_SIZE).enumerate() {
+        let batch_start = start_item + batch_ix * DATASET_INIT_BATCH_SIZE;
+        match (executor, &seed_mem.compiled) {
+            (DatasetInit::Compiled, Some(compiled)) => {
+                compiled.init_dataset_items(seed_mem, batch_start as u64, batch)
+            }
+            (DatasetInit::Avx2, _) => {
+                init_dataset_items_batched(seed_mem, batch_start as u64, batch)
+            }
+            _ => init_dataset_items_interpreted(seed_mem, batch_start as u64, batch),
+        }
+
+        let before = items_done.fetch_add(batch.len(), Ordering::Relaxed);
+        let after = before + batch.len();
+        let percent_before = before * 100 / item_count;
+        let percent_after = after * 100 / item_count;
+        if percent_after / 10 != percent_before / 10 {
+            info!("dataset init {}% done", percent_after / 10 * 10);
+        }
+    }
+}
+
+/// The memory of a seed, sent by the background thread of the
+/// `VmMemoryAllocator` once it is initialised.
+pub struct BuiltMemory {
+    pub seed: String,
+    pub memory: VmMemory,
+    /// dataset replicas for the NUMA nodes after the first one
+    pub replicas: Vec<VmMemory>,
+}
+
+/// Provides the memory for the seed of a job. The memory of a new seed is
+/// built on a background thread while the current memory keeps serving the
+/// jobs of the old seed. Only one memory is built at a time, a seed requested
+/// meanwhile is built afterwards.
 #[derive(Clone)]
 pub struct VmMemoryAllocator {
     pub vm_memory_seed: String,
     pub vm_memory: Arc<VmMemory>,
+    /// the replicas of `vm_memory` for the NUMA nodes after the first one
+    pub vm_memory_replicas: Vec<Arc<VmMemory>>,
+    /// a built memory that was not requested by a job yet (e.g. for the next seed)
+    pub next_vm_memory: Option<(String, Arc<VmMemory>)>,
+    next_vm_memory_replicas: Vec<Arc<VmMemory>>,
+    pub hardware_conf: HardwareConfig,
+    pub dataset_cache_conf: DatasetCacheConfig,
+    /// disabled by default
+    pub shared_dataset_conf: SharedDatasetConfig,
+    /// a single node unless NUMA is enabled and the machine has several nodes
+    pub numa: NumaTopology,
+    pub params: RandomXParams,
+    //seed and params of the memory that is currently built
+    building: Option<(String, RandomXParams)>,
+    queued_seed: Option<String>,
+    built_sndr: Sender<BuiltMemory>,
+    built_rcvr: Receiver<BuiltMemory>,
 }
 
 impl VmMemoryAllocator {
-    pub fn initial() -> VmMemoryAllocator {
+    pub fn initial(
+        hardware_conf: HardwareConfig,
+        dataset_cache_conf: DatasetCacheConfig,
+        params: RandomXParams,
+    ) -> VmMemoryAllocator {
+        let mut hardware_conf = hardware_conf;
+        //resolved once, so the mode does not change with every seed
+        if hardware_conf.memory_mode == MemoryMode::Auto {
+            let available = hardware::available_memory();
+            hardware_conf.memory_mode = select_memory_mode(&hardware_conf, &params, available);
+            info!(
+                "memory mode {} selected ({} MiB available)",
+                hardware_conf.memory_mode,
+                available.map_or("unknown".to_string(), |a| (a >> 20).to_string())
+            );
+        }
+        let numa = if hardware_conf.numa {
+            numa::detect(Path::new(numa::SYSFS_ROOT))
+        } else {
+            NumaTopology::single_node()
+        };
+        if numa.is_numa() {
+            info!(
+                "{} NUMA nodes found, building a dataset per node",
+                numa.nodes.len()
+            );
+        }
+        let (built_sndr, built_rcvr) = unbounded();
         VmMemoryAllocator {
             vm_memory_seed: "".to_string(),
             vm_memory: Arc::new(VmMemory::no_memory()),
+            vm_memory_replicas: Vec::new(),
+            next_vm_memory: None,
+            next_vm_memory_replicas: Vec::new(),
+            hardware_conf,
+            dataset_cache_conf,
+            shared_dataset_conf: SharedDatasetConfig::default(),
+            numa,
+            params,
+            building: None,
+            queued_seed: None,
+            built_sndr,
+            built_rcvr,
+        }
+    }
+
+    /// Returns the memory for `seed` if it is ready. Otherwise the memory is built
+    /// in the background and `None` is returned. The result has to be passed to
+    /// `memory_built` once it is received from `built_memory`.
+    pub fn memory_for(&mut self, seed: &str) -> Option<Arc<VmMemory>> {
+        if self.is_current(seed) {
+            return Some(self.vm_memory.clone());
+        }
+        if self.is_next(seed) {
+            let (next_seed, next_memory) = self.next_vm_memory.take().expect("next memory");
+            info!("switching to memory of seed_hash: {}", next_seed);
+            self.vm_memory = next_memory;
+            self.vm_memory_replicas = std::mem::take(&mut self.next_vm_memory_replicas);
+            self.vm_memory_seed = next_seed;
+            return Some(self.vm_memory.clone());
+        }
+        self.prepare(seed);
+        None
+    }
+
+    /// Starts building the memory for `seed` in the background, e.g. as soon as the
+    /// pool announces the next seed. Does nothing if the memory is ready or building.
+    pub fn prepare(&mut self, seed: &str) {
+        if self.is_current(seed) || self.is_next(seed) {
+            return;
+        }
+        let already_building = match self.building {
+            Some((ref building_seed, params)) => building_seed == seed && params == self.params,
+            None => false,
+        };
+        if already_building {
+            return;
+        }
+        if self.building.is_some() {
+            self.queued_seed = Some(seed.to_string());
+        } else {
+            self.start_build(seed.to_string());
         }
     }
 
+    /// Receiver for the memories built in the background.
+    pub fn built_memory(&self) -> &Receiver<BuiltMemory> {
+        &self.built_rcvr
+    }
+
+    /// Takes over a memory received from `built_memory`. It is used by the
+    /// next call of `memory_for` with its seed.
+    pub fn memory_built(&mut self, built: BuiltMemory) {
+        self.building = None;
+        if built.memory.seed_memory.params == self.params {
+            self.next_vm_memory = Some((built.seed, Arc::new(built.memory)));
+            self.next_vm_memory_replicas = built.replicas.into_iter().map(Arc::new).collect();
+        }
+        if let Some(seed) = self.queued_seed.take() {
+            self.prepare(&seed);
+        }
+    }
+
+    /// The replica of the current memory for NUMA node `node` (index into the nodes
+    /// of `numa`). Nodes without a replica use the memory of the first node.
+    pub fn memory_for_node(&self, node: usize) -> Arc<VmMemory> {
+        match node
+            .checked_sub(1)
+            .and_then(|i| self.vm_memory_replicas.get(i))
+        {
+            Some(replica) => replica.clone(),
+            None => self.vm_memory.clone(),
+        }
+    }
+
+    /// Blocks until the memory for `seed` is ready and makes it the current memory.
     pub fn reallocate(&mut self, seed: String) {
-        if seed != self.vm_memory_seed {
-            let mem_init_start = Instant::now();
-            self.vm_memory = Arc::new(VmMemory::full(&byte_string::string_to_u8_array(&seed)));
-            self.vm_memory_seed = seed;
-            info!(
-                "memory init took {}ms with seed_hash: {}",
-                mem_init_start.elapsed().as_millis(),
-                self.vm_memory_seed,
+        while self.memory_for(&seed).is_none() {
+            let built = self.built_rcvr.recv().expect("built memory");
+            self.memory_built(built);
+        }
+    }
+
+    /// The bytes the memory of a seed needs in the selected memory mode.
+    pub fn memory_size(&self) -> u64 {
+        let seed_size = self.params.argon_memory as u64 * 1024;
+        match self.hardware_conf.memory_mode {
+            MemoryMode::Light => seed_size,
+            MemoryMode::Hybrid => {
+                seed_size + self.hardware_conf.hybrid_cache_mib as u64 * 1024 * 1024
+            }
+            MemoryMode::Auto | MemoryMode::Full => {
+                //the replicas share the seed memory, each NUMA node has its own dataset
+                seed_size + dataset_size(&self.params) * self.numa.nodes.len() as u64
+            }
+        }
+    }
+
+    fn is_current(&self, seed: &str) -> bool {
+        seed == self.vm_memory_seed && self.vm_memory.seed_memory.params == self.params
+    }
+
+    fn is_next(&self, seed: &str) -> bool {
+        match self.next_vm_memory {
+            Some((ref next_seed, ref next_memory)) => {
+                next_seed == seed && next_memory.seed_memory.params == self.params
+            }
+            None => false,
+        }
+    }
+
+    fn start_build(&mut self, seed: String) {
+        info!(
+            "initialising memory for seed_hash {} in the background",
+            seed
+        );
+        self.building = Some((seed.clone(), self.params));
+        let params = self.params;
+        let hardware_conf = self.hardware_conf.clone();
+        let dataset_cache_conf = self.dataset_cache_conf.clone();
+        let shared_dataset_conf = self.shared_dataset_conf.clone();
+        let numa = self.numa.clone();
+        let built_sndr = self.built_sndr.clone();
+        thread::Builder::new()
+            .name("memory init".to_string())
+            .spawn(move || {
+                let mem_init_start = Instant::now();
+                let key = byte_string::string_to_u8_array(&seed);
+                //the dataset pages are allocated on the node of the threads that touch them first
+                if numa.is_numa() {
+                    pin_to_node(&numa, 0);
+                }
+                let memory = load_or_init(
+                    &key,
+                    &params,
+                    &hardware_conf,
+                    &dataset_cache_conf,
+                    &shared_dataset_conf,
+                );
+                info!(
+                    "memory init took {}ms with seed_hash: {}",
+                    mem_init_start.elapsed().as_millis(),
+                    seed,
+                );
+                let replicas = if numa.is_numa() && memory.cache {
+                    build_replicas(&memory, &numa)
+                } else {
+                    Vec::new()
+                };
+                //the allocator may be gone already (e.g. on shutdown)
+                let _ = built_sndr.send(BuiltMemory {
+                    seed,
+                    memory,
+                    replicas,
+                });
+            })
+            .expect("memory init thread");
+    }
+}
+
+//copies the dataset to the other NUMA nodes, the copying threads run on the node, so
+//the pages of a replica are local to it. If a replica can not be allocated the
+//workers of the remaining nodes use the dataset of the first node.
+fn build_replicas(memory: &VmMemory, numa: &NumaTopology) -> Vec<VmMemory> {
+    let mut replicas = Vec::with_capacity(numa.nodes.len() - 1);
+    for node in 1..numa.nodes.len() {
+        pin_to_node(numa, node);
+        let replica_start = Instant::now();
+        match memory.replica(numa.nodes[node].cpus.len()) {
+            Ok(replica) => {
+                info!(
+                    "dataset replicated to NUMA node {} in {}ms",
+                    numa.nodes[node].id,
+                    replica_start.elapsed().as_millis()
+                );
+                replicas.push(replica);
+            }
+            Err(e) => {
+                error!(
+                    "allocating the dataset for NUMA node {} failed: {}, the workers of \
+                     this and the following nodes use the dataset of node {}",
+                    numa.nodes[node].id, e, numa.nodes[0].id
+                );
+                break;
+            }
+        }
+    }
+    replicas
+}
+
+fn pin_to_node(numa: &NumaTopology, node: usize) {
+    if let Err(e) = numa::pin_current_thread(&numa.nodes[node].cpus) {
+        warn!("pinning to NUMA node {} failed: {}", numa.nodes[node].id, e);
+    }
+}
+
+/// Selects the memory mode for `MemoryMode::Auto` from the `available` memory in
+/// bytes: full if the dataset fits, hybrid if the item cache fits and light otherwise.
+/// Full is selected if the available memory is unknown.
+pub fn select_memory_mode(
+    hardware_conf: &HardwareConfig,
+    params: &RandomXParams,
+    available: Option<u64>,
+) -> MemoryMode {
+    if hardware_conf.memory_mode != MemoryMode::Auto {
+        return hardware_conf.memory_mode;
+    }
+    let available = match available {
+        Some(available) => available,
+        None => return MemoryMode::Full,
+    };
+    let seed_size = params.argon_memory as u64 * 1024;
+    let dataset_size = dataset_size(params);
+    let hybrid_size = hardware_conf.hybrid_cache_mib as u64 * 1024 * 1024;
+    if available >= seed_size + dataset_size + MEMORY_HEADROOM {
+        MemoryMode::Full
+    } else if available >= seed_size + hybrid_size + MEMORY_HEADROOM {
+        MemoryMode::Hybrid
+    } else {
+        MemoryMode::Light
+    }
+}
+
+/// The number of worker threads (at most `requested`, at least one) whose
+/// scratchpads fit into the `available` memory in bytes. Returns `requested`
+/// if the available memory is unknown.
+pub fn admit_worker_threads(requested: u64, params: &RandomXParams, available: Option<u64>) -> u64 {
+    let available = match available {
+        Some(available) => available.saturating_sub(MEMORY_HEADROOM),
+        None => return requested,
+    };
+    let fitting = available / params.scratchpad_l3;
+    requested.min(fitting).max(1)
+}
+
+//builds the memory for the memory mode, in full mode the dataset is shared with
+//other processes (if enabled), errors of the sharing are only logged, the memory is
+//then built for this process alone
+fn load_or_init(
+    key: &[u8],
+    params: &RandomXParams,
+    hardware_conf: &HardwareConfig,
+    dataset_cache_conf: &DatasetCacheConfig,
+    shared_dataset_conf: &SharedDatasetConfig,
+) -> VmMemory {
+    match hardware_conf.memory_mode {
+        MemoryMode::Light => return VmMemory::light_with_params(key, params),
+        MemoryMode::Hybrid => return hybrid_or_light(key, params, hardware_conf),
+        MemoryMode::Auto | MemoryMode::Full => {}
+    }
+    if shared_dataset_conf.enabled {
+        let shared =
+            shared_dataset::load_or_build(shared_dataset_conf, key, params, hardware_conf, || {
+                load_or_init_full(key, params, hardware_conf, dataset_cache_conf)
+            });
+        match shared {
+            Ok(mem) => return mem,
+            Err(e) => warn!("sharing the dataset failed: {}", e),
+        }
+    }
+    load_or_init_full(key, params, hardware_conf, dataset_cache_conf)
+}
+
+//tries the on-disk dataset cache first (if enabled), errors of the cache are only
+//logged, the dataset is then initialised from scratch
+fn load_or_init_full(
+    key: &[u8],
+    params: &RandomXParams,
+    hardware_conf: &HardwareConfig,
+    dataset_cache_conf: &DatasetCacheConfig,
+) -> VmMemory {
+    if !dataset_cache_conf.enabled {
+        return full_or_fallback(key, params, hardware_conf);
+    }
+
+    match dataset_cache::load(dataset_cache_conf, key, params, hardware_conf) {
+        Ok(Some(mem)) => {
+            info!("dataset loaded from cache");
+            return mem;
+        }
+        Ok(None) => info!("no cached dataset found"),
+        Err(e) => warn!("loading cached dataset failed: {}", e),
+    }
+
+    let mem = full_or_fallback(key, params, hardware_conf);
+    if mem.cache {
+        if let Err(e) = dataset_cache::store(dataset_cache_conf, key, &mem) {
+            warn!("storing dataset in cache failed: {}", e);
+        }
+    }
+    mem
+}
+
+//the dataset is allocated on demand, if the OS refuses the allocation (too little
+//memory, strict overcommit) the memory is built in hybrid or light mode instead
+fn full_or_fallback(
+    key: &[u8],
+    params: &RandomXParams,
+    hardware_conf: &HardwareConfig,
+) -> VmMemory {
+    match VmMemory::try_full_with_config(key, params, num_cpus::get(), hardware_conf) {
+        Ok(mem) => mem,
+        Err(e) => {
+            error!(
+                "allocating the dataset ({} MiB) failed: {}, full mode is not available",
+                dataset_size(params) >> 20,
+                e
+            );
+            hybrid_or_light(key, params, hardware_conf)
+        }
+    }
+}
+
+fn hybrid_or_light(key: &[u8], params: &RandomXParams, hardware_conf: &HardwareConfig) -> VmMemory {
+    let cache_size = hardware_conf.hybrid_cache_mib * 1024 * 1024;
+    match VmMemory::try_hybrid_with_config(key, params, cache_size, hardware_conf) {
+        Ok(mem) => {
+            warn!(
+                "using hybrid mode with a {} MiB dataset item cache",
+                hardware_conf.hybrid_cache_mib
             );
+            mem
+        }
+        Err(e) => {
+            error!(
+                "allocating the dataset item cache ({} MiB) failed: {}, using light mode",
+                hardware_conf.hybrid_cache_mib, e
+            );
+            VmMemory::light_with_params(key, params)
         }
     }
 }
 
+fn dataset_size(params: &RandomXParams) -> u64 {
+    params.dataset_base_size + params.dataset_extra_size
+}
+
 pub struct VmMemory {
-    pub seed_memory: SeedMemory,
-    pub dataset_memory: RwLock<Vec<Option<[u64; 8]>>>,
+    /// shared with the NUMA replicas of the memory
+    pub seed_memory: Arc<SeedMemory>,
+    pub dataset_memory: PageBuffer<DatasetItem>,
     pub cache: bool,
+    /// computed dataset items in hybrid mode (without a full dataset)
+    pub item_cache: Option<ItemCache>,
+    pub hardware_conf: HardwareConfig,
 }
 
 impl VmMemory {
     //only useful for testing
     pub fn no_memory() -> VmMemory {
         VmMemory {
-            seed_memory: SeedMemory::no_memory(),
+            seed_memory: Arc::new(SeedMemory::no_memory()),
             cache: false,
-            dataset_memory: RwLock::new(Vec::with_capacity(0)),
+            dataset_memory: PageBuffer::zeroed(0, &HardwareConfig::default()),
+            item_cache: None,
+            hardware_conf: HardwareConfig::default(),
         }
     }
 
     pub fn light(key: &[u8]) -> VmMemory {
+        VmMemory::light_with_params(key, &RX_0)
+    }
+
+    pub fn light_with_params(key: &[u8], params: &RandomXParams) -> VmMemory {
         VmMemory {
-            seed_memory: SeedMemory::new_initialised(key),
+            seed_memory: Arc::new(SeedMemory::new_initialised_with_params(key, params)),
             cache: false,
-            dataset_memory: RwLock::new(Vec::with_capacity(0)),
+            dataset_memory: PageBuffer::zeroed(0, &HardwareConfig::default()),
+            item_cache: None,
+            hardware_conf: HardwareConfig::default(),
         }
     }
+
+    /// Creates a memory that caches up to `cache_size` bytes of computed dataset
+    /// items, for machines without enough memory for the complete dataset.
+    pub fn hybrid_with_config(
+        key: &[u8],
+        params: &RandomXParams,
+        cache_size: usize,
+        hardware_conf: &HardwareConfig,
+    ) -> VmMemory {
+        VmMemory::try_hybrid_with_config(key, params, cache_size, hardware_conf)
+            .expect("item cache allocation")
+    }
+
+    /// Like `hybrid_with_config`, but returns an error if the item cache can not
+    /// be allocated.
+    pub fn try_hybrid_with_config(
+        key: &[u8],
+        params: &RandomXParams,
+        cache_size: usize,
+        hardware_conf: &HardwareConfig,
+    ) -> io::Result<VmMemory> {
+        let item_cache = ItemCache::try_with_size(cache_size)?;
+        info!(
+            "dataset item cache allocated with {} items",
+            item_cache.slots()
+        );
+        Ok(VmMemory {
+            seed_memory: Arc::new(SeedMemory::new_initialised_with_params(key, params)),
+            cache: false,
+            dataset_memory: PageBuffer::zeroed(0, hardware_conf),
+            item_cache: Some(item_cache),
+            hardware_conf: hardware_conf.clone(),
+        })
+    }
+
+    /// Creates a memory with the complete dataset. The dataset is
+    /// initialised up front using all available cores.
     pub fn full(key: &[u8]) -> VmMemory {
-        let seed_mem = SeedMemory::new_initialised(key);
-        let mem = vec![None; DATASET_ITEM_COUNT];
-        VmMemory {
-            seed_memory: seed_mem,
+        VmMemory::full_with_config(key, &RX_0, num_cpus::get(), &HardwareConfig::default())
+    }
+
+    pub fn full_with_config(
+        key: &[u8],
+        params: &RandomXParams,
+        num_threads: usize,
+        hardware_conf: &HardwareConfig,
+    ) -> VmMemory {
+        VmMemory::try_full_with_config(key, params, num_threads, hardware_conf)
+            .expect("dataset allocation")
+    }
+
+    /// Like `full_with_config`, but returns an error if the dataset can not be
+    /// allocated. The dataset is allocated before the seed memory is initialised,
+    /// so a failure is reported right away.
+    pub fn try_full_with_config(
+        key: &[u8],
+        params: &RandomXParams,
+        num_threads: usize,
+        hardware_conf: &HardwareConfig,
+    ) -> io::Result<VmMemory> {
+        let mut mem = PageBuffer::try_zeroed(params.dataset_item_count(), hardware_conf)?;
+        info!("dataset memory allocated with {} pages", mem.kind());
+        let seed_mem = SeedMemory::new_initialised_with_params(key, params);
+        init_dataset(&seed_mem, &mut mem, num_threads, hardware_conf.dataset_init);
+        Ok(VmMemory {
+            seed_memory: Arc::new(seed_mem),
             cache: true,
-            dataset_memory: RwLock::new(mem),
+            dataset_memory: mem,
+            item_cache: None,
+            hardware_conf: hardware_conf.clone(),
+        })
+    }
+
+    /// Copies the dataset of a full memory with `num_threads` threads, the seed memory
+    /// is shared. The pages of the copy are allocated on the NUMA node of the calling
+    /// thread (threads spawned by it inherit its cpus).
+    pub fn replica(&self, num_threads: usize) -> io::Result<VmMemory> {
+        let mut dataset: PageBuffer<DatasetItem> =
+            PageBuffer::try_zeroed(self.dataset_memory.len(), &self.hardware_conf)?;
+        if !dataset.is_empty() {
+            let chunk_size = dataset.len().div_ceil(num_threads.max(1));
+            thread::scope(|scope| {
+                let sources = self.dataset_memory.chunks(chunk_size);
+                for (chunk, source) in dataset.chunks_mut(chunk_size).zip(sources) {
+                    scope.spawn(move || chunk.copy_from_slice(source));
+                }
+            });
         }
+        Ok(VmMemory {
+            seed_memory: self.seed_memory.clone(),
+            dataset_memory: dataset,
+            cache: self.cache,
+            item_cache: None,
+            hardware_conf: self.hardware_conf.clone(),
+        })
     }
 
     pub fn dataset_prefetch(&self, offset: u64) {
         let item_num = offset / CACHE_LINE_SIZE;
         if self.cache {
-            let mem = self.dataset_memory.read().unwrap();
-            let rl_cached = &mem[item_num as usize];
-            if let Some(rl) = rl_cached {
-                unsafe{
-                    let raw : *const i8 = std::mem::transmute(rl);
-                    _mm_prefetch(raw, _MM_HINT_NTA);
-                }
-            }
+            let item = &self.dataset_memory[item_num as usize];
+            prefetch(item);
+        } else if let Some(ref item_cache) = self.item_cache {
+            item_cache.prefetch(item_num);
         }
     }
 
-    pub fn dataset_read(&self, offset: u64, reg: &mut [u64; 8]) {
+    /// Xors the dataset item at `offset` into `reg`. Returns true if the item was
+    /// read from the dataset or the item cache, false if it was computed.
+    pub fn dataset_read(&self, offset: u64, reg: &mut [u64; 8]) -> bool {
         let item_num = offset / CACHE_LINE_SIZE;
 
         if self.cache {
-            {
-                let mem = self.dataset_memory.read().unwrap();
-                let rl_cached = &mem[item_num as usize];
-                if let Some(rl) = rl_cached {
-                    for i in 0..8 {
-                        reg[i] ^= rl[i];
-                    }
-                    return;
-                }
-            }
-            {
-                let rl = init_dataset_item(&self.seed_memory, item_num);
-                let mut mem_mut = self.dataset_memory.write().unwrap();
-                mem_mut[item_num as usize] = Some(rl);
-                for i in 0..8 {
-                    reg[i] ^= rl[i];
-                }
+            let item = &self.dataset_memory[item_num as usize];
+            for (r, v) in reg.iter_mut().zip(item.0.iter()) {
+                *r ^= v;
             }
+            true
         } else {
-            let rl = init_dataset_item(&self.seed_memory, item_num);
+            let (rl, hit) = match self.item_cache {
+                Some(ref item_cache) => match item_cache.get(item_num) {
+                    Some(item) => (item, true),
+                    None => {
+                        let item = init_dataset_item(&self.seed_memory, item_num);
+                        item_cache.insert(item_num, &item);
+                        (item, false)
+                    }
+                },
+                None => (init_dataset_item(&self.seed_memory, item_num), false),
+            };
             for i in 0..8 {
                 reg[i] ^= rl[i];
             }
+            hit
         }
     }
 }
+
+#[cfg(target_arch = "x86_64")]
+fn prefetch(item: &DatasetItem) {
+    unsafe {
+        _mm_prefetch(item.0.as_ptr() as *const i8, _MM_HINT_NTA);
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn prefetch(_item: &DatasetItem) {}
@@ -1,7 +1,28 @@
+pub mod argon2d;
+pub mod asm;
 pub mod common;
+pub mod conformance;
+pub mod dataset_cache;
+pub mod hardware;
 pub mod hash;
+pub mod hasher;
+pub mod item_cache;
+pub mod jit;
 pub mod m128;
 pub mod memory;
+pub mod numa;
+pub mod params;
 pub mod program;
+pub mod shared_dataset;
+pub mod soft_aes;
+pub mod stats;
 pub mod superscalar;
+#[cfg(all(target_arch = "x86_64", not(feature = "portable")))]
+mod superscalar_avx2;
+pub mod trace;
+#[cfg(all(target_arch = "x86_64", not(feature = "portable")))]
+mod vaes;
 pub mod vm;
+
+pub use self::hasher::{RandomX, RandomXMode};
+pub use self::params::{RandomXParams, RandomXVariant};
@@ -0,0 +1,134 @@
+extern crate libc;
+extern crate num_cpus;
+
+//NUMA support for multi-socket machines: the layout of the nodes is read from sysfs
+//(<root>/devices/system/node/node<N>/cpulist), the workers are pinned to the cpus of
+//a node and read the dataset replica of their node instead of the dataset in the
+//memory of another socket. The sysfs root is a parameter, so a layout can be mocked
+//with a directory tree.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const SYSFS_ROOT: &str = "/sys";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NumaNode {
+    /// the node number of the OS
+    pub id: usize,
+    pub cpus: Vec<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NumaTopology {
+    pub nodes: Vec<NumaNode>,
+}
+
+impl NumaTopology {
+    /// A single node with all cpus, used without NUMA support.
+    pub fn single_node() -> NumaTopology {
+        NumaTopology {
+            nodes: vec![NumaNode {
+                id: 0,
+                cpus: (0..num_cpus::get()).collect(),
+            }],
+        }
+    }
+
+    pub fn is_numa(&self) -> bool {
+        self.nodes.len() > 1
+    }
+
+    /// The node (index into `nodes`) and cpu for worker `worker`. The workers are
+    /// spread evenly over the nodes.
+    pub fn worker_placement(&self, worker: usize) -> (usize, usize) {
+        let node = worker % self.nodes.len();
+        let cpus = &self.nodes[node].cpus;
+        (node, cpus[(worker / self.nodes.len()) % cpus.len()])
+    }
+}
+
+/// Reads the NUMA nodes from `<sysfs_root>/devices/system/node`. Returns a single
+/// node with all cpus if the layout can not be read (e.g. a kernel without NUMA).
+pub fn detect(sysfs_root: &Path) -> NumaTopology {
+    match read_nodes(sysfs_root) {
+        Ok(ref nodes) if nodes.is_empty() => NumaTopology::single_node(),
+        Ok(nodes) => NumaTopology { nodes },
+        Err(e) => {
+            warn!("reading the NUMA nodes failed: {}", e);
+            NumaTopology::single_node()
+        }
+    }
+}
+
+fn read_nodes(sysfs_root: &Path) -> io::Result<Vec<NumaNode>> {
+    let node_dir = sysfs_root.join("devices/system/node");
+    if !node_dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut nodes = Vec::new();
+    for entry in fs::read_dir(&node_dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let id = match name.strip_prefix("node").map(str::parse::<usize>) {
+            Some(Ok(id)) => id,
+            _ => continue,
+        };
+        let cpulist = fs::read_to_string(entry.path().join("cpulist"))?;
+        let cpus = parse_cpu_list(&cpulist).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid cpulist of {}: {}", name, cpulist.trim()),
+            )
+        })?;
+        //memory only nodes get no workers
+        if !cpus.is_empty() {
+            nodes.push(NumaNode { id, cpus });
+        }
+    }
+    nodes.sort_by_key(|node| node.id);
+    Ok(nodes)
+}
+
+/// Parses a cpu list of the kernel, e.g. "0-3,8-11".
+pub fn parse_cpu_list(list: &str) -> Option<Vec<usize>> {
+    let mut cpus = Vec::new();
+    for range in list.trim().split(',').filter(|r| !r.is_empty()) {
+        let mut bounds = range.splitn(2, '-');
+        let first: usize = bounds.next()?.parse().ok()?;
+        let last: usize = match bounds.next() {
+            Some(last) => last.parse().ok()?,
+            None => first,
+        };
+        if last < first {
+            return None;
+        }
+        cpus.extend(first..=last);
+    }
+    Some(cpus)
+}
+
+/// Restricts the current thread to `cpus`. Threads spawned afterwards inherit
+/// the restriction, memory they touch first is allocated on the node of the cpus.
+#[cfg(target_os = "linux")]
+pub fn pin_current_thread(cpus: &[usize]) -> io::Result<()> {
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        for cpu in cpus {
+            libc::CPU_SET(*cpu, &mut set);
+        }
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn pin_current_thread(_cpus: &[usize]) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "thread pinning is not supported on this platform",
+    ))
+}
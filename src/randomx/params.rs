@@ -0,0 +1,326 @@
+use super::program::MAX_PROGRAM_SIZE;
+use strum::{Display, EnumString};
+
+/// How often each instruction appears in a program, out of 256.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InstrFrequencies {
+    pub iadd_rs: u8,
+    pub iadd_m: u8,
+    pub isub_r: u8,
+    pub isub_m: u8,
+    pub imul_r: u8,
+    pub imul_m: u8,
+    pub imulh_r: u8,
+    pub imulh_m: u8,
+    pub ismulh_r: u8,
+    pub ismulh_m: u8,
+    pub imul_rcp: u8,
+    pub ineg_r: u8,
+    pub ixor_r: u8,
+    pub ixor_m: u8,
+    pub iror_r: u8,
+    pub irol_r: u8,
+    pub iswap_r: u8,
+    pub fswap_r: u8,
+    pub fadd_r: u8,
+    pub fadd_m: u8,
+    pub fsub_r: u8,
+    pub fsub_m: u8,
+    pub fscal_r: u8,
+    pub fmul_r: u8,
+    pub fdiv_m: u8,
+    pub fsqrt_r: u8,
+    pub cbranch: u8,
+    pub cfround: u8,
+    pub istore: u8,
+    pub nop: u8,
+}
+
+impl InstrFrequencies {
+    /// The frequencies in opcode order (the order of `program::Opcode`, NOP last).
+    pub fn as_array(&self) -> [u8; 30] {
+        [
+            self.iadd_rs,
+            self.iadd_m,
+            self.isub_r,
+            self.isub_m,
+            self.imul_r,
+            self.imul_m,
+            self.imulh_r,
+            self.imulh_m,
+            self.ismulh_r,
+            self.ismulh_m,
+            self.imul_rcp,
+            self.ineg_r,
+            self.ixor_r,
+            self.ixor_m,
+            self.iror_r,
+            self.irol_r,
+            self.iswap_r,
+            self.fswap_r,
+            self.fadd_r,
+            self.fadd_m,
+            self.fsub_r,
+            self.fsub_m,
+            self.fscal_r,
+            self.fmul_r,
+            self.fdiv_m,
+            self.fsqrt_r,
+            self.cbranch,
+            self.cfround,
+            self.istore,
+            self.nop,
+        ]
+    }
+}
+
+/// All parameters that differ between RandomX variants, named like the
+/// defines in the configuration.h of the reference implementation.
+/// Sizes are in bytes, `argon_memory` is in KiB (Argon2 blocks).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RandomXParams {
+    pub argon_memory: u32,
+    pub argon_iterations: u32,
+    pub argon_lanes: u32,
+    pub argon_salt: &'static [u8],
+    pub cache_accesses: usize,
+    pub dataset_base_size: u64,
+    pub dataset_extra_size: u64,
+    pub program_size: usize,
+    pub program_iterations: usize,
+    pub program_count: usize,
+    pub scratchpad_l1: u64,
+    pub scratchpad_l2: u64,
+    pub scratchpad_l3: u64,
+    pub frequencies: InstrFrequencies,
+}
+
+const DATASET_ITEM_SIZE: u64 = 64;
+
+impl RandomXParams {
+    pub const fn dataset_item_count(&self) -> usize {
+        ((self.dataset_base_size + self.dataset_extra_size) / DATASET_ITEM_SIZE) as usize
+    }
+
+    pub const fn dataset_extra_items(&self) -> u64 {
+        self.dataset_extra_size / DATASET_ITEM_SIZE
+    }
+
+    /// Number of 64 byte cache lines in the seed memory minus one.
+    pub const fn cache_line_mask(&self) -> u64 {
+        (self.argon_memory as u64 * 1024) / DATASET_ITEM_SIZE - 1
+    }
+
+    pub const fn scratchpad_l1_mask(&self) -> u64 {
+        self.scratchpad_l1 - 8
+    }
+
+    pub const fn scratchpad_l2_mask(&self) -> u64 {
+        self.scratchpad_l2 - 8
+    }
+
+    pub const fn scratchpad_l3_mask(&self) -> u64 {
+        self.scratchpad_l3 - 8
+    }
+
+    /// Size of the program data (entropy and instructions) in 16 byte blocks.
+    pub const fn program_blocks(&self) -> usize {
+        (128 + 8 * self.program_size) / 16
+    }
+
+    /// Checks the constraints the vm relies on, returns a description of the
+    /// first violated one.
+    pub fn validate(&self) -> Result<(), String> {
+        let sum: u32 = self
+            .frequencies
+            .as_array()
+            .iter()
+            .map(|f| u32::from(*f))
+            .sum();
+        if sum != 256 {
+            return Err(format!(
+                "instruction frequencies sum up to {}, not 256",
+                sum
+            ));
+        }
+        if self.program_size == 0
+            || self.program_size % 2 != 0
+            || self.program_size > MAX_PROGRAM_SIZE
+        {
+            return Err(format!(
+                "program_size has to be even, > 0 and <= {}",
+                MAX_PROGRAM_SIZE
+            ));
+        }
+        if !(self.scratchpad_l1.is_power_of_two()
+            && self.scratchpad_l2.is_power_of_two()
+            && self.scratchpad_l3.is_power_of_two())
+        {
+            return Err("scratchpad sizes have to be powers of 2".to_string());
+        }
+        if !(64 <= self.scratchpad_l1
+            && self.scratchpad_l1 <= self.scratchpad_l2
+            && self.scratchpad_l2 <= self.scratchpad_l3)
+        {
+            return Err("scratchpad sizes have to be 64 <= L1 <= L2 <= L3".to_string());
+        }
+        if !self.dataset_base_size.is_power_of_two() || self.dataset_extra_size % 64 != 0 {
+            return Err("invalid dataset size".to_string());
+        }
+        if !(self.argon_memory as u64 * 1024).is_power_of_two()
+            || self.argon_lanes == 0
+            || self.argon_memory % (4 * self.argon_lanes) != 0
+        {
+            return Err("invalid argon memory or lanes".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl Default for RandomXParams {
+    fn default() -> RandomXParams {
+        RX_0
+    }
+}
+
+const RX_0_FREQUENCIES: InstrFrequencies = InstrFrequencies {
+    iadd_rs: 16,
+    iadd_m: 7,
+    isub_r: 16,
+    isub_m: 7,
+    imul_r: 16,
+    imul_m: 4,
+    imulh_r: 4,
+    imulh_m: 1,
+    ismulh_r: 4,
+    ismulh_m: 1,
+    imul_rcp: 8,
+    ineg_r: 2,
+    ixor_r: 15,
+    ixor_m: 5,
+    iror_r: 8,
+    irol_r: 2,
+    iswap_r: 4,
+    fswap_r: 4,
+    fadd_r: 16,
+    fadd_m: 5,
+    fsub_r: 16,
+    fsub_m: 5,
+    fscal_r: 6,
+    fmul_r: 32,
+    fdiv_m: 4,
+    fsqrt_r: 6,
+    cbranch: 25,
+    cfround: 1,
+    istore: 16,
+    nop: 0,
+};
+
+/// Monero
+pub const RX_0: RandomXParams = RandomXParams {
+    argon_memory: 262144,
+    argon_iterations: 3,
+    argon_lanes: 1,
+    argon_salt: b"RandomX\x03",
+    cache_accesses: 8,
+    dataset_base_size: 2147483648,
+    dataset_extra_size: 33554368,
+    program_size: 256,
+    program_iterations: 2048,
+    program_count: 8,
+    scratchpad_l1: 16384,
+    scratchpad_l2: 262144,
+    scratchpad_l3: 2097152,
+    frequencies: RX_0_FREQUENCIES,
+};
+
+/// RandomWOW (Wownero)
+pub const RX_WOW: RandomXParams = RandomXParams {
+    argon_salt: b"RandomWOW\x01",
+    program_iterations: 1024,
+    program_count: 16,
+    scratchpad_l2: 131072,
+    scratchpad_l3: 1048576,
+    frequencies: InstrFrequencies {
+        iadd_rs: 25,
+        iror_r: 10,
+        irol_r: 0,
+        fswap_r: 8,
+        fadd_r: 20,
+        fsub_r: 20,
+        fmul_r: 20,
+        cbranch: 16,
+        ..RX_0_FREQUENCIES
+    },
+    ..RX_0
+};
+
+/// RandomARQ (ArQmA)
+pub const RX_ARQ: RandomXParams = RandomXParams {
+    argon_iterations: 1,
+    argon_salt: b"RandomARQ\x01",
+    program_iterations: 1024,
+    program_count: 4,
+    scratchpad_l2: 131072,
+    scratchpad_l3: 262144,
+    ..RX_0
+};
+
+/// RandomXL (Loki)
+pub const RX_LOKI: RandomXParams = RandomXParams {
+    argon_iterations: 4,
+    argon_lanes: 2,
+    argon_salt: b"RandomXL\x12",
+    program_size: 320,
+    program_count: 7,
+    frequencies: InstrFrequencies {
+        iadd_rs: 25,
+        cbranch: 16,
+        ..RX_0_FREQUENCIES
+    },
+    ..RX_0
+};
+
+/// RandomSFX (Safex)
+pub const RX_SFX: RandomXParams = RandomXParams {
+    argon_salt: b"RandomSFX\x01",
+    ..RX_0
+};
+
+/// RandomKV (Kevacoin)
+pub const RX_KEVA: RandomXParams = RandomXParams {
+    argon_salt: b"RandomKV\x01",
+    scratchpad_l2: 131072,
+    scratchpad_l3: 1048576,
+    ..RX_0
+};
+
+/// The built-in variants, named like the algorithms in the stratum protocol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, Display)]
+pub enum RandomXVariant {
+    #[strum(serialize = "rx/0")]
+    Rx0,
+    #[strum(serialize = "rx/wow")]
+    Wow,
+    #[strum(serialize = "rx/arq")]
+    Arq,
+    #[strum(serialize = "rx/loki")]
+    Loki,
+    #[strum(serialize = "rx/sfx")]
+    Sfx,
+    #[strum(serialize = "rx/keva")]
+    Keva,
+}
+
+impl RandomXVariant {
+    pub fn params(self) -> RandomXParams {
+        match self {
+            RandomXVariant::Rx0 => RX_0,
+            RandomXVariant::Wow => RX_WOW,
+            RandomXVariant::Arq => RX_ARQ,
+            RandomXVariant::Loki => RX_LOKI,
+            RandomXVariant::Sfx => RX_SFX,
+            RandomXVariant::Keva => RX_KEVA,
+        }
+    }
+}
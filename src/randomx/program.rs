@@ -1,679 +1,554 @@
+use super::common::{randomx_reciprocal, u64_from_i32_imm};
 use super::m128::m128i;
-use super::vm::{is_zero_or_power_of_2, Vm, SCRATCHPAD_L3_MASK};
+use super::params::{RandomXParams, RX_0};
+use super::vm::{
+    is_zero_or_power_of_2, Vm, CONDITION_OFFSET, SCRATCHPAD_L1_MASK, SCRATCHPAD_L2_MASK,
+};
 use std::fmt;
-use strum::Display;
+use strum::{Display, EnumString};
 
 pub const MAX_FLOAT_REG: usize = 4;
 pub const MAX_REG: usize = 8;
 pub const REG_NEEDS_DISPLACEMENT_IX: usize = 5;
-pub const REG_NEEDS_DISPLACEMENT: Store = Store::R(REG_NEEDS_DISPLACEMENT_IX);
+/// Largest program size of all supported variants (rx/0 uses 256 instructions).
+pub const MAX_PROGRAM_SIZE: usize = 512;
+const ENTROPY_SIZE: usize = 16;
 const STORE_L3_CONDITION: u8 = 14;
 
 #[allow(nonstandard_style)]
-#[derive(Display, Debug, PartialEq)]
+#[derive(Display, EnumString, Debug, PartialEq, Clone, Copy)]
 pub enum Opcode {
-    NOP = 0,
-    IADD_RS = 0x10,
-    IADD_M = 0x17,
-    ISUB_R = 0x27,
-    ISUB_M = 0x2e,
-    IMUL_R = 0x3e,
-    IMUL_M = 0x42,
-    IMULH_R = 0x46,
-    IMULH_M = 0x47,
-    ISMULH_R = 0x4b,
-    ISMULH_M = 0x4c,
-    IMUL_RCP = 0x54,
-    INEG_R = 0x56,
-    IXOR_R = 0x65,
-    IXOR_M = 0x6a,
-    IROR_R = 0x72,
-    IROL_R = 0x74,
-    ISWAP_R = 0x78,
-    FSWAP_R = 0x7c,
-    FADD_R = 0x8c,
-    FADD_M = 0x91,
-    FSUB_R = 0xa1,
-    FSUB_M = 0xa6,
-    FSCAL_R = 0xac,
-    FMUL_R = 0xcc,
-    FDIV_M = 0xd0,
-    FSQRT_R = 0xd6,
-    CBRANCH = 0xef,
-    CFROUND = 0xf0,
-    ISTORE = 0x100,
-}
-
-#[derive(Display, PartialEq)]
-pub enum Store {
-    NONE,
-    //registers
-    R(usize),
-    F(usize),
-    E(usize),
-    A(usize),
-    #[strum(serialize = "i")]
-    Imm, //non-register based Lx access
-    //Lx memory
-    L1(Box<Store>),
-    L2(Box<Store>),
-    L3(Box<Store>),
-}
-
-#[derive(PartialEq)]
-pub enum Mode {
-    None,
-    Cond(u8),
-    Shft(u8),
-}
-
-impl fmt::Display for Mode {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Mode::None => write!(f, "NONE"),
-            Mode::Cond(x) => write!(f, "COND {}", x),
-            Mode::Shft(x) => write!(f, "SHFT {}", x),
-        }
-    }
+    IADD_RS,
+    IADD_M,
+    ISUB_R,
+    ISUB_M,
+    IMUL_R,
+    IMUL_M,
+    IMULH_R,
+    IMULH_M,
+    ISMULH_R,
+    ISMULH_M,
+    IMUL_RCP,
+    INEG_R,
+    IXOR_R,
+    IXOR_M,
+    IROR_R,
+    IROL_R,
+    ISWAP_R,
+    FSWAP_R,
+    FADD_R,
+    FADD_M,
+    FSUB_R,
+    FSUB_M,
+    FSCAL_R,
+    FMUL_R,
+    FDIV_M,
+    FSQRT_R,
+    CBRANCH,
+    CFROUND,
+    ISTORE,
+    NOP,
 }
 
+//in the order of the frequencies in InstrFrequencies::as_array
+const OPCODES: [Opcode; 30] = [
+    Opcode::IADD_RS,
+    Opcode::IADD_M,
+    Opcode::ISUB_R,
+    Opcode::ISUB_M,
+    Opcode::IMUL_R,
+    Opcode::IMUL_M,
+    Opcode::IMULH_R,
+    Opcode::IMULH_M,
+    Opcode::ISMULH_R,
+    Opcode::ISMULH_M,
+    Opcode::IMUL_RCP,
+    Opcode::INEG_R,
+    Opcode::IXOR_R,
+    Opcode::IXOR_M,
+    Opcode::IROR_R,
+    Opcode::IROL_R,
+    Opcode::ISWAP_R,
+    Opcode::FSWAP_R,
+    Opcode::FADD_R,
+    Opcode::FADD_M,
+    Opcode::FSUB_R,
+    Opcode::FSUB_M,
+    Opcode::FSCAL_R,
+    Opcode::FMUL_R,
+    Opcode::FDIV_M,
+    Opcode::FSQRT_R,
+    Opcode::CBRANCH,
+    Opcode::CFROUND,
+    Opcode::ISTORE,
+    Opcode::NOP,
+];
+
+/// A decoded instruction. It is a fixed size value with the register indices,
+/// the scratchpad address mask and the 64 bit immediate operand precomputed
+/// during decoding, so executing it needs no further lookups.
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Instr {
     pub op: Opcode,
-    pub src: Store,
-    pub dst: Store,
-    pub imm: Option<i32>,
-    pub unsigned_imm: bool,
-    pub mode: Mode,
-    pub target: Option<i32>,
-    pub effect: fn(&mut Vm, &Instr),
+    /// destination register, for FSWAP_R 0-3 are the f and 4-7 the e registers
+    pub dst: u8,
+    /// source register, the address register for memory operands
+    pub src: u8,
+    /// shift of IADD_RS and condition of CBRANCH
+    pub modi: u8,
+    /// the source operand is the immediate (or L3[imm] for memory operands)
+    pub src_imm: bool,
+    /// the instruction CBRANCH jumps to (execution continues after it)
+    pub target: i16,
+    /// scratchpad level (1-3) of the memory operand, 0 if there is none
+    pub mem_level: u8,
+    /// scratchpad address mask of the memory operand, 0 if there is none
+    pub mem_mask: u32,
+    /// immediate as decoded, already masked where the instruction uses only some bits
+    pub imm: i32,
+    /// 64 bit operand derived from `imm`: sign extended, the reciprocal for
+    /// IMUL_RCP (0 if it is a nop) or the branch constant for CBRANCH
+    pub imm64: u64,
 }
 
-fn new_instr(
-    op: Opcode,
-    dst: Store,
-    src: Store,
-    imm: i32,
-    mode: Mode,
-    effect: fn(&mut Vm, &Instr),
-) -> Instr {
-    if src == dst {
-        return Instr {
+impl Instr {
+    pub fn new(op: Opcode, dst: usize, src: usize) -> Instr {
+        Instr {
             op,
-            dst,
-            src: Store::NONE,
-            imm: Some(imm),
-            unsigned_imm: false,
-            mode,
-            target: None,
-            effect,
-        };
+            dst: dst as u8,
+            src: src as u8,
+            modi: 0,
+            src_imm: false,
+            target: 0,
+            mem_level: 0,
+            mem_mask: 0,
+            imm: 0,
+            imm64: 0,
+        }
     }
-    Instr {
-        op,
-        dst,
-        src,
-        imm: None,
-        unsigned_imm: false,
-        mode,
-        target: None,
-        effect,
+
+    pub fn with_imm(mut self, imm: i32) -> Instr {
+        self.imm = imm;
+        self.imm64 = imm64(self.op, imm, self.modi);
+        self
     }
-}
 
-fn new_imm_instr(
-    op: Opcode,
-    dst: Store,
-    imm: i32,
-    mode: Mode,
-    effect: fn(&mut Vm, &Instr),
-) -> Instr {
-    Instr {
-        op,
-        dst,
-        src: Store::NONE,
-        imm: Some(imm),
-        unsigned_imm: false,
-        mode,
-        target: None,
-        effect,
+    /// Uses the immediate instead of the src register as source operand.
+    pub fn with_src_imm(mut self, imm: i32) -> Instr {
+        self.src_imm = true;
+        self.with_imm(imm)
     }
-}
 
-pub fn new_lcache_instr(
-    op: Opcode,
-    dst_reg: Store,
-    src: usize,
-    imm: i32,
-    modi: u8,
-    effect: fn(&mut Vm, &Instr),
-) -> Instr {
-    let src_reg = r_reg(src);
-    if src_reg == dst_reg {
-        return Instr {
-            op,
-            dst: dst_reg,
-            src: Store::L3(Box::new(Store::Imm)),
-            imm: Some(imm & (SCRATCHPAD_L3_MASK as i32)),
-            unsigned_imm: false,
-            mode: Mode::None,
-            target: None,
-            effect,
+    /// Sets the scratchpad address mask. The level is derived from the rx/0
+    /// masks, use `with_mem_level` for the masks of other variants.
+    pub fn with_mem_mask(mut self, mask: u64) -> Instr {
+        self.mem_mask = mask as u32;
+        self.mem_level = match mask {
+            SCRATCHPAD_L1_MASK => 1,
+            SCRATCHPAD_L2_MASK => 2,
+            _ => 3,
         };
+        self
     }
-    let lx = l12_cache(src, modi);
-    Instr {
-        op,
-        dst: dst_reg,
-        src: lx,
-        imm: Some(imm),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect,
+
+    pub fn with_mem_level(mut self, level: u8) -> Instr {
+        self.mem_level = level;
+        self
     }
-}
 
-impl Instr {
-    pub fn execute(&self, vm: &mut Vm) {
-        (self.effect)(vm, self);
+    pub fn with_mode(mut self, modi: u8) -> Instr {
+        self.modi = modi;
+        self.with_imm(self.imm)
     }
-}
 
-impl fmt::Display for Instr {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{} ", self.op)?;
-        match &self.dst {
-            Store::NONE => { /* do nothing */ }
-            Store::L1(reg) => write_l_access(f, self, reg, "L1")?,
-            Store::L2(reg) => write_l_access(f, self, reg, "L2")?,
-            Store::L3(reg) => write_l_access(f, self, reg, "L3")?,
-            Store::R(i) => write!(f, "r{}", i)?,
-            Store::F(i) => write!(f, "f{}", i)?,
-            Store::E(i) => write!(f, "e{}", i)?,
-            Store::A(i) => write!(f, "a{}", i)?,
-            _ => write!(f, "{}", self.dst)?,
+    pub fn with_target(mut self, target: i32) -> Instr {
+        self.target = target as i16;
+        self
+    }
+
+    pub fn execute(&self, vm: &mut Vm) {
+        match self.op {
+            Opcode::NOP => {}
+            Opcode::IADD_RS => vm.exec_iadd_rs(self),
+            Opcode::IADD_M => vm.exec_iadd_m(self),
+            Opcode::ISUB_R => vm.exec_isub_r(self),
+            Opcode::ISUB_M => vm.exec_isub_m(self),
+            Opcode::IMUL_R => vm.exec_imul_r(self),
+            Opcode::IMUL_M => vm.exec_imul_m(self),
+            Opcode::IMULH_R => vm.exec_imulh_r(self),
+            Opcode::IMULH_M => vm.exec_imulh_m(self),
+            Opcode::ISMULH_R => vm.exec_ismulh_r(self),
+            Opcode::ISMULH_M => vm.exec_ismulh_m(self),
+            Opcode::IMUL_RCP => vm.exec_imul_rcp(self),
+            Opcode::INEG_R => vm.exec_ineg_r(self),
+            Opcode::IXOR_R => vm.exec_ixor_r(self),
+            Opcode::IXOR_M => vm.exec_ixor_m(self),
+            Opcode::IROR_R => vm.exec_iror_r(self),
+            Opcode::IROL_R => vm.exec_irol_r(self),
+            Opcode::ISWAP_R => vm.exec_iswap_r(self),
+            Opcode::FSWAP_R => vm.exec_fswap_r(self),
+            Opcode::FADD_R => vm.exec_fadd_r(self),
+            Opcode::FADD_M => vm.exec_fadd_m(self),
+            Opcode::FSUB_R => vm.exec_fsub_r(self),
+            Opcode::FSUB_M => vm.exec_fsub_m(self),
+            Opcode::FSCAL_R => vm.exec_fscal_r(self),
+            Opcode::FMUL_R => vm.exec_fmul_r(self),
+            Opcode::FDIV_M => vm.exec_fdiv_m(self),
+            Opcode::FSQRT_R => vm.exec_fsqrt_r(self),
+            Opcode::CBRANCH => vm.exec_cbranch(self),
+            Opcode::CFROUND => vm.exec_cfround(self),
+            Opcode::ISTORE => vm.exec_istore(self),
         }
-        if self.dst != Store::NONE && self.src != Store::NONE {
-            write!(f, ", ")?;
+    }
+
+    fn write_src_r_or_imm(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.src_imm {
+            write!(f, "{}", self.imm)
+        } else {
+            write!(f, "r{}", self.src)
         }
-        match &self.src {
-            Store::NONE => { /* do nothing */ }
-            Store::L1(reg) => write_l_access(f, self, reg, "L1")?,
-            Store::L2(reg) => write_l_access(f, self, reg, "L2")?,
-            Store::L3(reg) => write_l_access(f, self, reg, "L3")?,
-            Store::R(i) => write!(f, "r{}", i)?,
-            Store::F(i) => write!(f, "f{}", i)?,
-            Store::E(i) => write!(f, "e{}", i)?,
-            Store::A(i) => write!(f, "a{}", i)?,
-            _ => write!(f, ", {}", self.src)?,
+    }
+
+    fn write_mem(&self, f: &mut fmt::Formatter<'_>, reg: u8) -> fmt::Result {
+        if self.src_imm {
+            write!(f, "L{}[{}]", self.mem_level, self.imm)
+        } else {
+            write!(f, "L{}[r{}{:+}]", self.mem_level, reg, self.imm)
         }
-        if self.imm.is_some() && !(is_l_cache(&self.dst) || is_l_cache(&self.src)) {
-            if self.unsigned_imm {
-                write!(f, ", {}", self.imm.unwrap() as u32)?
+    }
+}
+
+fn imm64(op: Opcode, imm: i32, modi: u8) -> u64 {
+    match op {
+        Opcode::IMUL_RCP => {
+            if is_zero_or_power_of_2(imm as u64) {
+                0
             } else {
-                write!(f, ", {}", self.imm.unwrap())?
+                randomx_reciprocal((imm as u64) & 0x00000000FFFFFFFF)
             }
         }
-        if self.mode != Mode::None {
-            write!(f, ", {}", self.mode)?;
+        Opcode::CBRANCH => {
+            let shift = u64::from(modi) + CONDITION_OFFSET;
+            let v = u64_from_i32_imm(imm) | 1 << shift;
+            v & !(1 << (shift - 1))
         }
-        Ok(())
+        _ => u64_from_i32_imm(imm),
     }
 }
 
-fn write_l_access(
-    f: &mut fmt::Formatter<'_>,
-    instr: &Instr,
-    reg: &Store,
-    lstore: &str,
-) -> fmt::Result {
-    if reg == &Store::Imm {
-        write!(f, "{}[{}]", lstore, instr.imm.unwrap())
-    } else {
-        write!(f, "{}[", lstore)?;
-        match reg {
-            Store::R(i) => write!(f, "r{}", i)?,
-            Store::F(i) => write!(f, "f{}", i)?,
-            Store::E(i) => write!(f, "e{}", i)?,
-            Store::A(i) => write!(f, "a{}", i)?,
-            _ => write!(f, "{}", reg)?,
+impl fmt::Display for Instr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ", self.op)?;
+        match self.op {
+            Opcode::NOP => write!(f, ", {}", self.imm),
+            Opcode::IADD_RS => {
+                write!(f, "r{}, r{}", self.dst, self.src)?;
+                if self.dst as usize == REG_NEEDS_DISPLACEMENT_IX {
+                    write!(f, ", {}", self.imm)?;
+                }
+                write!(f, ", SHFT {}", self.modi)
+            }
+            Opcode::IADD_M
+            | Opcode::ISUB_M
+            | Opcode::IMUL_M
+            | Opcode::IMULH_M
+            | Opcode::ISMULH_M
+            | Opcode::IXOR_M => {
+                write!(f, "r{}, ", self.dst)?;
+                self.write_mem(f, self.src)
+            }
+            Opcode::ISUB_R | Opcode::IMUL_R | Opcode::IXOR_R | Opcode::IROR_R | Opcode::IROL_R => {
+                write!(f, "r{}, ", self.dst)?;
+                self.write_src_r_or_imm(f)
+            }
+            Opcode::IMULH_R | Opcode::ISMULH_R | Opcode::ISWAP_R => {
+                write!(f, "r{}, r{}", self.dst, self.src)
+            }
+            Opcode::IMUL_RCP => write!(f, "r{}, {}", self.dst, self.imm as u32),
+            Opcode::INEG_R => write!(f, "r{}", self.dst),
+            Opcode::FSWAP_R => {
+                if (self.dst as usize) < MAX_FLOAT_REG {
+                    write!(f, "f{}", self.dst)
+                } else {
+                    write!(f, "e{}", self.dst as usize - MAX_FLOAT_REG)
+                }
+            }
+            Opcode::FADD_R | Opcode::FSUB_R => write!(f, "f{}, a{}", self.dst, self.src),
+            Opcode::FADD_M | Opcode::FSUB_M => {
+                write!(f, "f{}, ", self.dst)?;
+                self.write_mem(f, self.src)
+            }
+            Opcode::FSCAL_R => write!(f, "f{}", self.dst),
+            Opcode::FMUL_R => write!(f, "e{}, a{}", self.dst, self.src),
+            Opcode::FDIV_M => {
+                write!(f, "e{}, ", self.dst)?;
+                self.write_mem(f, self.src)
+            }
+            Opcode::FSQRT_R => write!(f, "e{}", self.dst),
+            Opcode::CBRANCH => write!(f, "r{}, {}, COND {}", self.dst, self.imm, self.modi),
+            Opcode::CFROUND => write!(f, "r{}, {}", self.src, self.imm),
+            Opcode::ISTORE => {
+                self.write_mem(f, self.dst)?;
+                write!(f, ", r{}", self.src)
+            }
         }
-        write!(f, "{:+}]", instr.imm.unwrap())
     }
 }
 
+#[derive(Clone)]
 pub struct Program {
-    pub entropy: Vec<u64>,
-    pub program: Vec<Instr>,
+    pub entropy: [u64; ENTROPY_SIZE],
+    /// the instructions, only the first `size` are part of the program
+    pub program: [Instr; MAX_PROGRAM_SIZE],
+    pub size: usize,
     pub register_usage: [i32; MAX_REG],
 }
 
+impl Default for Program {
+    fn default() -> Program {
+        Program {
+            entropy: [0; ENTROPY_SIZE],
+            program: [Instr::new(Opcode::NOP, 0, 0); MAX_PROGRAM_SIZE],
+            size: 0,
+            register_usage: [-1; MAX_REG],
+        }
+    }
+}
+
 impl Program {
+    /// Decodes a rx/0 program.
     pub fn from_bytes(bytes: Vec<m128i>) -> Program {
-        let mut entropy = Vec::with_capacity(16);
-        let mut program = Vec::with_capacity((bytes.len() - 8) * 2);
-        let mut register_usage = [-1; MAX_REG];
+        let mut program = Program::default();
+        program.decode(&bytes, &Decoder::new(&RX_0));
+        program
+    }
 
-        for byte in bytes.iter().take(8) {
+    /// Decodes `bytes` into this program, overwriting all instructions.
+    pub fn decode(&mut self, bytes: &[m128i], decoder: &Decoder) {
+        debug_assert!(bytes.len() == ENTROPY_SIZE / 2 + decoder.program_size / 2);
+        self.register_usage = [-1; MAX_REG];
+        self.size = decoder.program_size;
+
+        for (i, byte) in bytes.iter().take(8).enumerate() {
             let (e1, e0) = byte.as_i64();
-            entropy.push(e0 as u64);
-            entropy.push(e1 as u64);
+            self.entropy[2 * i] = e0 as u64;
+            self.entropy[2 * i + 1] = e1 as u64;
         }
 
         for (i, byte) in bytes.iter().enumerate().skip(8) {
             let (op2, op1) = byte.as_i64();
-            let instr1 = decode_instruction(op1, ((i - 8) * 2) as i32, &mut register_usage);
-            let instr2 = decode_instruction(op2, (((i - 8) * 2) + 1) as i32, &mut register_usage);
-            program.push(instr1);
-            program.push(instr2);
+            let ix = (i - 8) * 2;
+            self.program[ix] = decoder.decode_instruction(op1, ix as i32, &mut self.register_usage);
+            self.program[ix + 1] =
+                decoder.decode_instruction(op2, (ix + 1) as i32, &mut self.register_usage);
         }
+    }
 
-        Program {
-            entropy,
-            program,
-            register_usage,
-        }
+    pub fn instrs(&self) -> &[Instr] {
+        &self.program[..self.size]
     }
 }
 
 impl fmt::Display for Program {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        for instr in &self.program {
+        for instr in self.instrs() {
             writeln!(f, "{}", instr)?;
         }
         Ok(())
     }
 }
 
-#[allow(overflowing_literals)]
-pub fn decode_instruction(bytes: i64, i: i32, register_usage: &mut [i32; MAX_REG]) -> Instr {
-    let op = bytes & 0xFF;
-    let dst = ((bytes & 0xFF00) >> 8) as usize;
-    let src = ((bytes & 0xFF0000) >> 16) as usize;
-    let modi = ((bytes & 0xFF000000) >> 24) as u8;
-    let imm = ((bytes & 0xFFFFFFFF00000000) >> 32) as i32;
-    if op < Opcode::IADD_RS as i64 {
-        let dst_reg = r_reg(dst);
-        let imm_val;
-        if dst_reg == REG_NEEDS_DISPLACEMENT {
-            imm_val = Some(imm);
-        } else {
-            imm_val = None;
+/// Decodes instructions for the parameters of one RandomX variant: the opcode
+/// is looked up from the instruction frequencies, the scratchpad masks from
+/// the scratchpad sizes.
+#[derive(Clone)]
+pub struct Decoder {
+    opcodes: [Opcode; 256],
+    //indexed by the scratchpad level
+    masks: [u64; 4],
+    pub program_size: usize,
+}
+
+impl Decoder {
+    pub fn new(params: &RandomXParams) -> Decoder {
+        let mut opcodes = [Opcode::NOP; 256];
+        let mut ix = 0;
+        for (op, freq) in OPCODES.iter().zip(params.frequencies.as_array().iter()) {
+            for _ in 0..*freq {
+                assert!(ix < opcodes.len(), "instruction frequencies exceed 256");
+                opcodes[ix] = *op;
+                ix += 1;
+            }
         }
-        register_usage[dst % MAX_REG] = i;
-        return Instr {
-            op: Opcode::IADD_RS,
-            dst: dst_reg,
-            src: r_reg(src),
-            imm: imm_val,
-            unsigned_imm: false,
-            mode: mod_shft(modi),
-            target: None,
-            effect: Vm::exec_iadd_rs,
-        };
-    }
-    if op < Opcode::IADD_M as i64 {
-        register_usage[dst % MAX_REG] = i;
-        return new_lcache_instr(Opcode::IADD_M, r_reg(dst), src, imm, modi, Vm::exec_iadd_m);
-    }
-    if op < Opcode::ISUB_R as i64 {
-        register_usage[dst % MAX_REG] = i;
-        return new_instr(
-            Opcode::ISUB_R,
-            r_reg(dst),
-            r_reg(src),
-            imm,
-            Mode::None,
-            Vm::exec_isub_r,
-        );
-    }
-    if op < Opcode::ISUB_M as i64 {
-        register_usage[dst % MAX_REG] = i;
-        return new_lcache_instr(Opcode::ISUB_M, r_reg(dst), src, imm, modi, Vm::exec_isub_m);
-    }
-    if op < Opcode::IMUL_R as i64 {
-        register_usage[dst % MAX_REG] = i;
-        return new_instr(
-            Opcode::IMUL_R,
-            r_reg(dst),
-            r_reg(src),
-            imm,
-            Mode::None,
-            Vm::exec_imul_r,
+        assert_eq!(
+            ix,
+            opcodes.len(),
+            "instruction frequencies must sum up to 256"
         );
-    }
-    if op < Opcode::IMUL_M as i64 {
-        register_usage[dst % MAX_REG] = i;
-        return new_lcache_instr(Opcode::IMUL_M, r_reg(dst), src, imm, modi, Vm::exec_imul_m);
-    }
-    if op < Opcode::IMULH_R as i64 {
-        register_usage[dst % MAX_REG] = i;
-        return Instr {
-            op: Opcode::IMULH_R,
-            dst: r_reg(dst),
-            src: r_reg(src),
-            imm: None,
-            unsigned_imm: false,
-            mode: Mode::None,
-            target: None,
-            effect: Vm::exec_imulh_r,
-        };
-    }
-    if op < Opcode::IMULH_M as i64 {
-        register_usage[dst % MAX_REG] = i;
-        return new_lcache_instr(
-            Opcode::IMULH_M,
-            r_reg(dst),
-            src,
-            imm,
-            modi,
-            Vm::exec_imulh_m,
-        );
-    }
-    if op < Opcode::ISMULH_R as i64 {
-        register_usage[dst % MAX_REG] = i;
-        return Instr {
-            op: Opcode::ISMULH_R,
-            dst: r_reg(dst),
-            src: r_reg(src),
-            imm: None,
-            unsigned_imm: false,
-            mode: Mode::None,
-            target: None,
-            effect: Vm::exec_ismulh_r,
-        };
-    }
-    if op < Opcode::ISMULH_M as i64 {
-        register_usage[dst % MAX_REG] = i;
-        return new_lcache_instr(
-            Opcode::ISMULH_M,
-            r_reg(dst),
-            src,
-            imm,
-            modi,
-            Vm::exec_ismulh_m,
-        );
-    }
-    if op < Opcode::IMUL_RCP as i64 {
-        if !is_zero_or_power_of_2(imm as u64) {
-            register_usage[dst % MAX_REG] = i;
+        Decoder {
+            opcodes,
+            masks: [
+                0,
+                params.scratchpad_l1_mask(),
+                params.scratchpad_l2_mask(),
+                params.scratchpad_l3_mask(),
+            ],
+            program_size: params.program_size,
         }
-        let mut instr = new_imm_instr(
-            Opcode::IMUL_RCP,
-            r_reg(dst),
-            imm,
-            Mode::None,
-            Vm::exec_imul_rcp,
-        );
-        instr.unsigned_imm = true;
-        return instr;
-    }
-    if op < Opcode::INEG_R as i64 {
-        register_usage[dst % MAX_REG] = i;
-        return new_instr(
-            Opcode::INEG_R,
-            r_reg(dst),
-            Store::NONE,
-            imm,
-            Mode::None,
-            Vm::exec_ineg_r,
-        );
-    }
-    if op < Opcode::IXOR_R as i64 {
-        register_usage[dst % MAX_REG] = i;
-        return new_instr(
-            Opcode::IXOR_R,
-            r_reg(dst),
-            r_reg(src),
-            imm,
-            Mode::None,
-            Vm::exec_ixor_r,
-        );
-    }
-    if op < Opcode::IXOR_M as i64 {
-        register_usage[dst % MAX_REG] = i;
-        return new_lcache_instr(Opcode::IXOR_M, r_reg(dst), src, imm, modi, Vm::exec_ixor_m);
-    }
-    if op < Opcode::IROR_R as i64 {
-        register_usage[dst % MAX_REG] = i;
-        return new_instr(
-            Opcode::IROR_R,
-            r_reg(dst),
-            r_reg(src),
-            imm & 63,
-            Mode::None,
-            Vm::exec_iror_r,
-        );
     }
-    if op < Opcode::IROL_R as i64 {
-        register_usage[dst % MAX_REG] = i;
-        return new_instr(
-            Opcode::IROL_R,
-            r_reg(dst),
-            r_reg(src),
-            imm & 63,
-            Mode::None,
-            Vm::exec_irol_r,
-        );
-    }
-    if op < Opcode::ISWAP_R as i64 {
+
+    pub fn decode_instruction(
+        &self,
+        bytes: i64,
+        i: i32,
+        register_usage: &mut [i32; MAX_REG],
+    ) -> Instr {
+        let op = self.opcodes[(bytes & 0xFF) as usize];
+        let dst = ((bytes & 0xFF00) >> 8) as usize;
+        let src = ((bytes & 0xFF0000) >> 16) as usize;
+        let modi = ((bytes >> 24) & 0xFF) as u8;
+        let imm = (bytes >> 32) as i32;
         let dst_r = dst % MAX_REG;
         let src_r = src % MAX_REG;
-        if src_r != dst_r {
-            register_usage[dst_r] = i;
-            register_usage[src_r] = i;
-            return Instr {
-                op: Opcode::ISWAP_R,
-                dst: r_reg(dst),
-                src: r_reg(src),
-                imm: None,
-                unsigned_imm: false,
-                mode: Mode::None,
-                target: None,
-                effect: Vm::exec_iswap_r,
-            };
-        } else {
-            return new_instr(Opcode::NOP, Store::NONE, Store::NONE, imm, Mode::None, nop);
-        }
-    }
-    if op < Opcode::FSWAP_R as i64 {
-        let dst_ix = dst % MAX_REG;
-        if dst_ix >= MAX_FLOAT_REG {
-            return new_instr(
-                Opcode::FSWAP_R,
-                e_reg_ix(dst_ix % MAX_FLOAT_REG),
-                Store::NONE,
-                imm,
-                Mode::None,
-                Vm::exec_fswap_r,
-            );
-        } else {
-            return new_instr(
-                Opcode::FSWAP_R,
-                f_reg_ix(dst_ix % MAX_FLOAT_REG),
-                Store::NONE,
-                imm,
-                Mode::None,
-                Vm::exec_fswap_r,
-            );
-        }
-    }
-    if op < Opcode::FADD_R as i64 {
-        return new_instr(
-            Opcode::FADD_R,
-            f_reg(dst),
-            a_reg(src),
-            imm,
-            Mode::None,
-            Vm::exec_fadd_r,
-        );
-    }
-    if op < Opcode::FADD_M as i64 {
-        return new_lcache_instr(Opcode::FADD_M, f_reg(dst), src, imm, modi, Vm::exec_fadd_m);
-    }
-    if op < Opcode::FSUB_R as i64 {
-        return new_instr(
-            Opcode::FSUB_R,
-            f_reg(dst),
-            a_reg(src),
-            imm,
-            Mode::None,
-            Vm::exec_fsub_r,
-        );
-    }
-    if op < Opcode::FSUB_M as i64 {
-        return new_lcache_instr(Opcode::FSUB_M, f_reg(dst), src, imm, modi, Vm::exec_fsub_m);
-    }
-    if op < Opcode::FSCAL_R as i64 {
-        return new_instr(
-            Opcode::FSCAL_R,
-            f_reg(dst),
-            Store::NONE,
-            imm,
-            Mode::None,
-            Vm::exec_fscal_r,
-        );
-    }
-    if op < Opcode::FMUL_R as i64 {
-        return new_instr(
-            Opcode::FMUL_R,
-            e_reg(dst),
-            a_reg(src),
-            imm,
-            Mode::None,
-            Vm::exec_fmul_r,
-        );
-    }
-    if op < Opcode::FDIV_M as i64 {
-        return new_lcache_instr(Opcode::FDIV_M, e_reg(dst), src, imm, modi, Vm::exec_fdiv_m);
-    }
-    if op < Opcode::FSQRT_R as i64 {
-        return new_instr(
-            Opcode::FSQRT_R,
-            e_reg(dst),
-            Store::NONE,
-            imm,
-            Mode::None,
-            Vm::exec_fsqrt_r,
-        );
-    }
-    if op < Opcode::CBRANCH as i64 {
-        let target = register_usage[dst % MAX_REG];
-        for usage in register_usage.iter_mut().take(MAX_REG) {
-            *usage = i;
-        }
-        return Instr {
-            op: Opcode::CBRANCH,
-            dst: r_reg(dst),
-            src: Store::NONE,
-            imm: Some(imm),
-            unsigned_imm: false,
-            mode: mod_cond(modi),
-            target: Some(target),
-            effect: Vm::exec_cbranch,
-        };
-    }
-    if op < Opcode::CFROUND as i64 {
-        return Instr {
-            op: Opcode::CFROUND,
-            dst: Store::NONE,
-            src: r_reg(src),
-            imm: Some(imm & 63),
-            unsigned_imm: false,
-            mode: Mode::None,
-            target: None,
-            effect: Vm::exec_cfround,
-        };
-    }
-    if op < Opcode::ISTORE as i64 {
-        return Instr {
-            op: Opcode::ISTORE,
-            dst: l_cache(dst, modi),
-            src: r_reg(src),
-            imm: Some(imm),
-            unsigned_imm: false,
-            mode: Mode::None,
-            target: None,
-            effect: Vm::exec_istore,
+        let mut instr = match op {
+            Opcode::IADD_RS => {
+                let instr = Instr::new(Opcode::IADD_RS, dst_r, src_r).with_mode(mod_shft(modi));
+                if dst_r == REG_NEEDS_DISPLACEMENT_IX {
+                    instr.with_imm(imm)
+                } else {
+                    instr
+                }
+            }
+            Opcode::IADD_M
+            | Opcode::ISUB_M
+            | Opcode::IMUL_M
+            | Opcode::IMULH_M
+            | Opcode::ISMULH_M
+            | Opcode::IXOR_M => self.new_lcache_instr(op, dst_r, src, imm, modi),
+            Opcode::ISUB_R | Opcode::IMUL_R | Opcode::IXOR_R => new_instr(op, dst_r, src_r, imm),
+            Opcode::IMULH_R | Opcode::ISMULH_R => Instr::new(op, dst_r, src_r),
+            Opcode::IMUL_RCP => Instr::new(Opcode::IMUL_RCP, dst_r, 0).with_imm(imm),
+            Opcode::INEG_R => Instr::new(Opcode::INEG_R, dst_r, 0),
+            Opcode::IROR_R | Opcode::IROL_R => new_instr(op, dst_r, src_r, imm & 63),
+            Opcode::ISWAP_R => {
+                if src_r != dst_r {
+                    Instr::new(Opcode::ISWAP_R, dst_r, src_r)
+                } else {
+                    Instr::new(Opcode::NOP, 0, 0).with_imm(imm)
+                }
+            }
+            //0-3 are the f registers, 4-7 the e registers
+            Opcode::FSWAP_R => Instr::new(Opcode::FSWAP_R, dst_r, 0),
+            Opcode::FADD_R | Opcode::FSUB_R | Opcode::FMUL_R => {
+                Instr::new(op, dst % MAX_FLOAT_REG, src % MAX_FLOAT_REG)
+            }
+            Opcode::FADD_M | Opcode::FSUB_M | Opcode::FDIV_M => {
+                self.new_l12_instr(op, dst % MAX_FLOAT_REG, src, imm, modi)
+            }
+            Opcode::FSCAL_R | Opcode::FSQRT_R => Instr::new(op, dst % MAX_FLOAT_REG, 0),
+            Opcode::CBRANCH => Instr::new(Opcode::CBRANCH, dst_r, 0)
+                .with_mode(mod_cond_u8(modi))
+                .with_imm(imm),
+            Opcode::CFROUND => Instr::new(Opcode::CFROUND, 0, src_r).with_imm(imm & 63),
+            Opcode::ISTORE => {
+                let level = if mod_cond_u8(modi) >= STORE_L3_CONDITION {
+                    3
+                } else {
+                    l12_level(modi)
+                };
+                self.with_mem(
+                    Instr::new(Opcode::ISTORE, dst_r, src_r).with_imm(imm),
+                    level,
+                )
+            }
+            Opcode::NOP => Instr::new(Opcode::NOP, 0, 0).with_imm(imm),
         };
+        track_register_usage(&mut instr, i, register_usage);
+        instr
     }
-    new_instr(Opcode::NOP, Store::NONE, Store::NONE, imm, Mode::None, nop)
-}
 
-pub fn r_reg(dst: usize) -> Store {
-    match dst % MAX_REG {
-        0 => Store::R(0),
-        1 => Store::R(1),
-        2 => Store::R(2),
-        3 => Store::R(3),
-        4 => Store::R(4),
-        5 => Store::R(5),
-        6 => Store::R(6),
-        7 => Store::R(7),
-        _ => Store::R(0),
+    /// Sets the scratchpad level and the mask of that level for this variant.
+    pub fn with_mem(&self, instr: Instr, level: u8) -> Instr {
+        instr
+            .with_mem_mask(self.masks[level as usize])
+            .with_mem_level(level)
     }
-}
 
-pub fn a_reg(dst: usize) -> Store {
-    match dst % MAX_FLOAT_REG {
-        0 => Store::A(0),
-        1 => Store::A(1),
-        2 => Store::A(2),
-        3 => Store::A(3),
-        _ => Store::A(0),
+    //integer instruction with a L1/L2 memory source, or L3[imm] if src equals dst
+    fn new_lcache_instr(&self, op: Opcode, dst: usize, src: usize, imm: i32, modi: u8) -> Instr {
+        if src % MAX_REG == dst {
+            let instr = Instr::new(op, dst, 0).with_src_imm(imm & (self.masks[3] as i32));
+            return self.with_mem(instr, 3);
+        }
+        self.new_l12_instr(op, dst, src, imm, modi)
     }
-}
 
-pub fn e_reg(dst: usize) -> Store {
-    e_reg_ix(dst % MAX_FLOAT_REG)
-}
-
-fn e_reg_ix(ix: usize) -> Store {
-    match ix {
-        0 => Store::E(0),
-        1 => Store::E(1),
-        2 => Store::E(2),
-        3 => Store::E(3),
-        _ => Store::E(0),
+    //instruction with a L1/L2 memory source
+    fn new_l12_instr(&self, op: Opcode, dst: usize, src: usize, imm: i32, modi: u8) -> Instr {
+        let instr = Instr::new(op, dst, src % MAX_REG).with_imm(imm);
+        self.with_mem(instr, l12_level(modi))
     }
 }
 
-pub fn f_reg(dst: usize) -> Store {
-    f_reg_ix(dst % MAX_FLOAT_REG)
-}
-
-fn f_reg_ix(ix: usize) -> Store {
-    match ix {
-        0 => Store::F(0),
-        1 => Store::F(1),
-        2 => Store::F(2),
-        3 => Store::F(3),
-        _ => Store::F(0),
+impl Default for Decoder {
+    fn default() -> Decoder {
+        Decoder::new(&RX_0)
     }
 }
 
-fn l_cache(dst: usize, modi: u8) -> Store {
-    let reg = r_reg(dst);
-    let cond = mod_cond_u8(modi);
-    if cond < STORE_L3_CONDITION {
-        if mod_mem_u8(modi) == 0 {
-            return Store::L2(Box::new(reg));
+/// Records the instruction `i` as the last one that modified its integer registers
+/// and sets the target of a CBRANCH to the last modification of its register
+/// (`register_usage` starts with -1 for all registers).
+pub fn track_register_usage(instr: &mut Instr, i: i32, register_usage: &mut [i32; MAX_REG]) {
+    let dst = instr.dst as usize;
+    match instr.op {
+        Opcode::IADD_RS
+        | Opcode::IADD_M
+        | Opcode::ISUB_R
+        | Opcode::ISUB_M
+        | Opcode::IMUL_R
+        | Opcode::IMUL_M
+        | Opcode::IMULH_R
+        | Opcode::IMULH_M
+        | Opcode::ISMULH_R
+        | Opcode::ISMULH_M
+        | Opcode::INEG_R
+        | Opcode::IXOR_R
+        | Opcode::IXOR_M
+        | Opcode::IROR_R
+        | Opcode::IROL_R => register_usage[dst] = i,
+        Opcode::IMUL_RCP => {
+            if !is_zero_or_power_of_2(instr.imm as u64) {
+                register_usage[dst] = i;
+            }
         }
-        return Store::L1(Box::new(reg));
+        Opcode::ISWAP_R => {
+            register_usage[dst] = i;
+            register_usage[instr.src as usize] = i;
+        }
+        Opcode::CBRANCH => {
+            *instr = instr.with_target(register_usage[dst]);
+            for usage in register_usage.iter_mut() {
+                *usage = i;
+            }
+        }
+        _ => {}
     }
-    Store::L3(Box::new(reg))
 }
 
-fn l12_cache(src: usize, modi: u8) -> Store {
-    let reg = r_reg(src);
-    if mod_mem_u8(modi) == 0 {
-        return Store::L2(Box::new(reg));
+//register to register instruction, with the immediate as source if src equals dst
+fn new_instr(op: Opcode, dst: usize, src: usize, imm: i32) -> Instr {
+    if src == dst {
+        return Instr::new(op, dst, 0).with_src_imm(imm);
     }
-    Store::L1(Box::new(reg))
+    Instr::new(op, dst, src)
 }
 
-fn is_l_cache(store: &Store) -> bool {
-    matches!(store, Store::L1(_) | Store::L2(_) | Store::L3(_))
+fn l12_level(modi: u8) -> u8 {
+    if mod_mem_u8(modi) == 0 {
+        return 2;
+    }
+    1
 }
 
 fn mod_mem_u8(modi: u8) -> u8 {
@@ -684,12 +559,6 @@ fn mod_cond_u8(modi: u8) -> u8 {
     modi >> 4 //bits 4-7
 }
 
-fn mod_cond(modi: u8) -> Mode {
-    Mode::Cond(mod_cond_u8(modi))
-}
-
-fn mod_shft(modi: u8) -> Mode {
-    Mode::Shft((modi >> 2) % 4)
+fn mod_shft(modi: u8) -> u8 {
+    (modi >> 2) % 4
 }
-
-pub fn nop(_state: &mut Vm, _instr: &Instr) {}
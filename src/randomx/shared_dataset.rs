@@ -0,0 +1,138 @@
+extern crate libc;
+
+//Shares the dataset of a seed between several mithril processes on the same machine
+//(e.g. one per pool). The first process that needs a seed builds the memory and
+//publishes it as a file in a memory backed directory (default /dev/shm/mithril),
+//the other processes map the dataset of the file read-only, so the machine keeps one
+//copy of the dataset per seed. The file has the format of the dataset cache, a lock
+//file per seed makes sure only one process builds it.
+
+#[cfg(unix)]
+use std::fs::OpenOptions;
+use std::fs::{self, File};
+use std::io;
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use super::dataset_cache::{self, DatasetCacheConfig};
+use super::hardware::HardwareConfig;
+use super::memory::VmMemory;
+use super::params::RandomXParams;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SharedDatasetConfig {
+    /// share the dataset with other processes via `dir`
+    pub enabled: bool,
+    /// number of seeds kept in `dir`, the least recently used are deleted
+    pub max_seeds: usize,
+    /// a directory in memory (tmpfs), e.g. /dev/shm/mithril
+    pub dir: PathBuf,
+}
+
+impl Default for SharedDatasetConfig {
+    fn default() -> SharedDatasetConfig {
+        SharedDatasetConfig {
+            enabled: false,
+            max_seeds: 2,
+            dir: PathBuf::from("/dev/shm/mithril"),
+        }
+    }
+}
+
+impl SharedDatasetConfig {
+    fn store_conf(&self) -> DatasetCacheConfig {
+        DatasetCacheConfig {
+            enabled: self.enabled,
+            max_seeds: self.max_seeds,
+            dir: self.dir.clone(),
+        }
+    }
+}
+
+/// Maps the shared dataset for `key`. If no other process published it yet, the
+/// memory is built with `build` and published. Other processes that need the same
+/// seed meanwhile wait for it. Errors are only returned before `build` is called,
+/// a memory that can not be published is returned unshared.
+pub fn load_or_build<F>(
+    conf: &SharedDatasetConfig,
+    key: &[u8],
+    params: &RandomXParams,
+    hardware_conf: &HardwareConfig,
+    build: F,
+) -> io::Result<VmMemory>
+where
+    F: FnOnce() -> VmMemory,
+{
+    let store_conf = conf.store_conf();
+    fs::create_dir_all(&conf.dir)?;
+    let path = dataset_cache::cache_file(&store_conf, key, params);
+    //released when the file is closed at the end
+    let _lock = lock(&path.with_extension("lock"))?;
+
+    match dataset_cache::load_file(&path, key, params, hardware_conf, true) {
+        Ok(Some(mem)) => {
+            info!("shared dataset mapped from {}", path.display());
+            return Ok(mem);
+        }
+        Ok(None) => {}
+        Err(e) => warn!("shared dataset {} is invalid: {}", path.display(), e),
+    }
+
+    let mem = build();
+    //a fallback memory without dataset is not shared
+    if !mem.cache {
+        return Ok(mem);
+    }
+    if let Err(e) = dataset_cache::store_file(&path, key, &mem) {
+        warn!("publishing shared dataset failed: {}", e);
+        let _ = fs::remove_file(path.with_extension("tmp"));
+        return Ok(mem);
+    }
+    info!("shared dataset published to {}", path.display());
+    if let Err(e) = dataset_cache::evict(&store_conf) {
+        warn!("removing old shared datasets failed: {}", e);
+    }
+
+    //the private dataset is replaced by the mapping, so only the shared copy is kept
+    match dataset_cache::load_file(&path, key, params, hardware_conf, true) {
+        Ok(Some(shared)) => Ok(shared),
+        Ok(None) => Ok(mem),
+        Err(e) => {
+            warn!("mapping published shared dataset failed: {}", e);
+            Ok(mem)
+        }
+    }
+}
+
+//blocks until no other process holds the lock
+#[cfg(unix)]
+fn lock(path: &Path) -> io::Result<File> {
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)?;
+    if flock(&file, libc::LOCK_EX | libc::LOCK_NB).is_err() {
+        info!("waiting for another process to build the shared dataset");
+        flock(&file, libc::LOCK_EX)?;
+    }
+    Ok(file)
+}
+
+#[cfg(unix)]
+fn flock(file: &File, operation: libc::c_int) -> io::Result<()> {
+    if unsafe { libc::flock(file.as_raw_fd(), operation) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+//the memory is then built without sharing
+#[cfg(not(unix))]
+fn lock(_path: &Path) -> io::Result<File> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "sharing the dataset is not supported on this platform",
+    ))
+}
@@ -0,0 +1,124 @@
+//Table based software implementation of the AES-NI aesenc/aesdec round
+//instructions, used on CPUs without hardware AES.
+
+use super::m128::m128i;
+
+const SBOX: [u8; 256] = [
+    0x63, 0x7c, 0x77, 0x7b, 0xf2, 0x6b, 0x6f, 0xc5, 0x30, 0x01, 0x67, 0x2b, 0xfe, 0xd7, 0xab, 0x76,
+    0xca, 0x82, 0xc9, 0x7d, 0xfa, 0x59, 0x47, 0xf0, 0xad, 0xd4, 0xa2, 0xaf, 0x9c, 0xa4, 0x72, 0xc0,
+    0xb7, 0xfd, 0x93, 0x26, 0x36, 0x3f, 0xf7, 0xcc, 0x34, 0xa5, 0xe5, 0xf1, 0x71, 0xd8, 0x31, 0x15,
+    0x04, 0xc7, 0x23, 0xc3, 0x18, 0x96, 0x05, 0x9a, 0x07, 0x12, 0x80, 0xe2, 0xeb, 0x27, 0xb2, 0x75,
+    0x09, 0x83, 0x2c, 0x1a, 0x1b, 0x6e, 0x5a, 0xa0, 0x52, 0x3b, 0xd6, 0xb3, 0x29, 0xe3, 0x2f, 0x84,
+    0x53, 0xd1, 0x00, 0xed, 0x20, 0xfc, 0xb1, 0x5b, 0x6a, 0xcb, 0xbe, 0x39, 0x4a, 0x4c, 0x58, 0xcf,
+    0xd0, 0xef, 0xaa, 0xfb, 0x43, 0x4d, 0x33, 0x85, 0x45, 0xf9, 0x02, 0x7f, 0x50, 0x3c, 0x9f, 0xa8,
+    0x51, 0xa3, 0x40, 0x8f, 0x92, 0x9d, 0x38, 0xf5, 0xbc, 0xb6, 0xda, 0x21, 0x10, 0xff, 0xf3, 0xd2,
+    0xcd, 0x0c, 0x13, 0xec, 0x5f, 0x97, 0x44, 0x17, 0xc4, 0xa7, 0x7e, 0x3d, 0x64, 0x5d, 0x19, 0x73,
+    0x60, 0x81, 0x4f, 0xdc, 0x22, 0x2a, 0x90, 0x88, 0x46, 0xee, 0xb8, 0x14, 0xde, 0x5e, 0x0b, 0xdb,
+    0xe0, 0x32, 0x3a, 0x0a, 0x49, 0x06, 0x24, 0x5c, 0xc2, 0xd3, 0xac, 0x62, 0x91, 0x95, 0xe4, 0x79,
+    0xe7, 0xc8, 0x37, 0x6d, 0x8d, 0xd5, 0x4e, 0xa9, 0x6c, 0x56, 0xf4, 0xea, 0x65, 0x7a, 0xae, 0x08,
+    0xba, 0x78, 0x25, 0x2e, 0x1c, 0xa6, 0xb4, 0xc6, 0xe8, 0xdd, 0x74, 0x1f, 0x4b, 0xbd, 0x8b, 0x8a,
+    0x70, 0x3e, 0xb5, 0x66, 0x48, 0x03, 0xf6, 0x0e, 0x61, 0x35, 0x57, 0xb9, 0x86, 0xc1, 0x1d, 0x9e,
+    0xe1, 0xf8, 0x98, 0x11, 0x69, 0xd9, 0x8e, 0x94, 0x9b, 0x1e, 0x87, 0xe9, 0xce, 0x55, 0x28, 0xdf,
+    0x8c, 0xa1, 0x89, 0x0d, 0xbf, 0xe6, 0x42, 0x68, 0x41, 0x99, 0x2d, 0x0f, 0xb0, 0x54, 0xbb, 0x16,
+];
+
+//SubBytes + MixColumns for one byte of row 0, the other rows are rotations of it
+const ENC_TABLE: [u32; 256] = enc_table();
+//InvSubBytes + InvMixColumns for one byte of row 0
+const DEC_TABLE: [u32; 256] = dec_table();
+
+const fn gf_mul(a: u8, b: u8) -> u8 {
+    let mut a = a;
+    let mut b = b;
+    let mut p = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            p ^= a;
+        }
+        let carry = a & 0x80;
+        a <<= 1;
+        if carry != 0 {
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    p
+}
+
+const fn column(b0: u8, b1: u8, b2: u8, b3: u8) -> u32 {
+    (b0 as u32) | (b1 as u32) << 8 | (b2 as u32) << 16 | (b3 as u32) << 24
+}
+
+const fn enc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let s = SBOX[i];
+        table[i] = column(gf_mul(s, 2), s, s, gf_mul(s, 3));
+        i += 1;
+    }
+    table
+}
+
+const fn dec_table() -> [u32; 256] {
+    let mut inv_sbox = [0u8; 256];
+    let mut i = 0;
+    while i < 256 {
+        inv_sbox[SBOX[i] as usize] = i as u8;
+        i += 1;
+    }
+
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let s = inv_sbox[i];
+        table[i] = column(gf_mul(s, 14), gf_mul(s, 9), gf_mul(s, 13), gf_mul(s, 11));
+        i += 1;
+    }
+    table
+}
+
+fn to_columns(v: m128i) -> [u32; 4] {
+    let (hi, lo) = v.as_i64();
+    [lo as u32, (lo >> 32) as u32, hi as u32, (hi >> 32) as u32]
+}
+
+fn from_columns(c: [u32; 4]) -> m128i {
+    let lo = u64::from(c[0]) | u64::from(c[1]) << 32;
+    let hi = u64::from(c[2]) | u64::from(c[3]) << 32;
+    m128i::from_u64(hi, lo)
+}
+
+fn byte(col: u32, row: u32) -> usize {
+    ((col >> (8 * row)) & 0xff) as usize
+}
+
+/// Same result as `_mm_aesenc_si128`: ShiftRows, SubBytes, MixColumns, AddRoundKey.
+pub fn aesenc(state: m128i, key: m128i) -> m128i {
+    let s = to_columns(state);
+    let k = to_columns(key);
+    let mut out = [0; 4];
+    for (j, o) in out.iter_mut().enumerate() {
+        *o = ENC_TABLE[byte(s[j], 0)]
+            ^ ENC_TABLE[byte(s[(j + 1) % 4], 1)].rotate_left(8)
+            ^ ENC_TABLE[byte(s[(j + 2) % 4], 2)].rotate_left(16)
+            ^ ENC_TABLE[byte(s[(j + 3) % 4], 3)].rotate_left(24)
+            ^ k[j];
+    }
+    from_columns(out)
+}
+
+/// Same result as `_mm_aesdec_si128`: InvShiftRows, InvSubBytes, InvMixColumns, AddRoundKey.
+pub fn aesdec(state: m128i, key: m128i) -> m128i {
+    let s = to_columns(state);
+    let k = to_columns(key);
+    let mut out = [0; 4];
+    for (j, o) in out.iter_mut().enumerate() {
+        *o = DEC_TABLE[byte(s[j], 0)]
+            ^ DEC_TABLE[byte(s[(j + 3) % 4], 1)].rotate_left(8)
+            ^ DEC_TABLE[byte(s[(j + 2) % 4], 2)].rotate_left(16)
+            ^ DEC_TABLE[byte(s[(j + 1) % 4], 3)].rotate_left(24)
+            ^ k[j];
+    }
+    from_columns(out)
+}
@@ -0,0 +1,116 @@
+extern crate serde_json;
+
+//Execution statistics of the interpreter, collected with the tracer hooks of the
+//vm, e.g. to compare the executed instructions with the frequencies of the decoder:
+//
+//  let mut stats = VmStats::default();
+//  vm.calculate_hash_traced(input, &mut stats);
+//  println!("{}", stats.to_json());
+
+use std::collections::BTreeMap;
+
+use super::m128::ROUND_TO_NEAREST;
+use super::program::{Instr, Opcode};
+use super::trace::{TracePos, Tracer};
+use super::vm::Vm;
+
+/// Counts of the interpreted programs. Collecting the statistics interprets the
+/// programs even if the vm runs in compiled mode.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct VmStats {
+    pub programs: u64,
+    pub iterations: u64,
+    pub instructions: u64,
+    /// executions per opcode (by name)
+    pub opcodes: BTreeMap<String, u64>,
+    pub cbranch_taken: u64,
+    /// taken CBRANCHes of all executed CBRANCHes
+    pub cbranch_taken_ratio: f64,
+    /// CFROUNDs that set a different rounding mode
+    pub cfround_mode_changes: u64,
+    /// scratchpad reads and writes of the instructions per level
+    pub scratchpad_l1: u64,
+    pub scratchpad_l2: u64,
+    pub scratchpad_l3: u64,
+    /// dataset items read from the dataset (full mode) or the item cache (hybrid mode)
+    pub dataset_hits: u64,
+    /// dataset items computed from the cache (light mode and item cache misses)
+    pub dataset_misses: u64,
+    //the rounding mode after the last instruction
+    #[serde(skip)]
+    rounding_mode: u32,
+}
+
+impl VmStats {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("stats json")
+    }
+
+    pub fn opcode_count(&self, op: Opcode) -> u64 {
+        self.opcodes.get(&op.to_string()).cloned().unwrap_or(0)
+    }
+
+    /// The share of the executed instructions for each opcode.
+    pub fn frequencies(&self) -> BTreeMap<String, f64> {
+        self.opcodes
+            .iter()
+            .map(|(op, count)| (op.clone(), *count as f64 / self.instructions as f64))
+            .collect()
+    }
+}
+
+impl Tracer for VmStats {
+    fn traces_instructions(&self) -> bool {
+        true
+    }
+
+    fn instruction(&mut self, vm: &Vm, pos: TracePos, instr: &Instr, _address: Option<u64>) {
+        //the rounding mode is reset at the start of each hash
+        if pos.program == 0 && pos.iteration == 0 && pos.pc == Some(0) {
+            self.rounding_mode = ROUND_TO_NEAREST;
+        }
+        self.instructions += 1;
+        *self.opcodes.entry(instr.op.to_string()).or_insert(0) += 1;
+
+        match instr.op {
+            Opcode::CBRANCH => {
+                //a taken branch sets the pc to the target
+                if pos.pc != Some(vm.pc as usize) {
+                    self.cbranch_taken += 1;
+                }
+                let executed = self.opcode_count(Opcode::CBRANCH);
+                self.cbranch_taken_ratio = self.cbranch_taken as f64 / executed as f64;
+            }
+            Opcode::CFROUND => {
+                let mode = vm.get_rounding_mode();
+                if mode != self.rounding_mode {
+                    self.cfround_mode_changes += 1;
+                    self.rounding_mode = mode;
+                }
+            }
+            _ => {}
+        }
+
+        match instr.mem_level {
+            1 => self.scratchpad_l1 += 1,
+            2 => self.scratchpad_l2 += 1,
+            3 => self.scratchpad_l3 += 1,
+            _ => {}
+        }
+    }
+
+    fn iteration(&mut self, _vm: &Vm, pos: TracePos, _scratchpad: [u64; 2], _dataset: u64) {
+        if pos.iteration == 0 {
+            self.programs += 1;
+        }
+        self.iterations += 1;
+    }
+
+    fn dataset_item(&mut self, _vm: &Vm, _pos: TracePos, hit: bool) {
+        if hit {
+            self.dataset_hits += 1;
+        } else {
+            self.dataset_misses += 1;
+        }
+    }
+}
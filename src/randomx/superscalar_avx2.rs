@@ -0,0 +1,244 @@
+//Runs the superscalar programs of the dataset initialisation on four items at once:
+//register i of item n is lane n of the AVX2 register i. AVX2 has no 64 bit
+//multiplication, IMUL_R and IMUL_RCP are composed of 32 bit multiplications. IMULH_R
+//adds up the four 32x32 bit partial products and ISMULH_R corrects the unsigned high
+//product for negative factors. The items are identical to the items of the interpreter
+//(`memory::init_dataset_item_interpreted`).
+//
+//The functions require AVX2, the caller has to check for it (see `hardware::has_avx2`).
+
+use std::arch::x86_64::{
+    __m256i, _mm256_add_epi64, _mm256_and_si256, _mm256_cmpgt_epi64, _mm256_i64gather_epi64,
+    _mm256_loadu_si256, _mm256_mul_epu32, _mm256_or_si256, _mm256_set1_epi64x,
+    _mm256_setzero_si256, _mm256_sll_epi64, _mm256_slli_epi64, _mm256_srl_epi64, _mm256_srli_epi64,
+    _mm256_storeu_si256, _mm256_sub_epi64, _mm256_xor_si256, _mm_cvtsi64_si128,
+};
+
+use super::common::{randomx_reciprocal, u64_from_u32_imm};
+use super::memory::{
+    init_dataset_item_interpreted, DatasetItem, SeedMemory, SUPERSCALAR_ADD_1, SUPERSCALAR_ADD_2,
+    SUPERSCALAR_ADD_3, SUPERSCALAR_ADD_4, SUPERSCALAR_ADD_5, SUPERSCALAR_ADD_6, SUPERSCALAR_ADD_7,
+    SUPERSCALAR_MUL_0,
+};
+use super::superscalar::{ScOpcode, ScProgram};
+
+const LANES: usize = 4;
+
+//an instruction with the operand computed upfront
+#[derive(Clone, Copy)]
+struct BatchInstr {
+    op: ScOpcode,
+    dst: usize,
+    src: usize,
+    //the shift of IADD_RS, the rotation of IROR_C, the constant or the reciprocal
+    imm: u64,
+}
+
+struct BatchProgram {
+    instrs: Vec<BatchInstr>,
+    address_reg: usize,
+}
+
+impl BatchProgram {
+    fn new(prog: &ScProgram<'_>) -> BatchProgram {
+        let instrs = prog
+            .prog
+            .iter()
+            .map(|instr| {
+                let imm = match instr.info.op {
+                    ScOpcode::IADD_RS => instr.mod_shift(),
+                    ScOpcode::IROR_C => instr.imm32 as u64 % 64,
+                    ScOpcode::IADD_C7
+                    | ScOpcode::IADD_C8
+                    | ScOpcode::IADD_C9
+                    | ScOpcode::IXOR_C7
+                    | ScOpcode::IXOR_C8
+                    | ScOpcode::IXOR_C9 => u64_from_u32_imm(instr.imm32),
+                    ScOpcode::IMUL_RCP => randomx_reciprocal(instr.imm32 as u64),
+                    _ => 0,
+                };
+                BatchInstr {
+                    op: instr.info.op,
+                    dst: instr.dst as usize,
+                    src: instr.src as usize,
+                    imm,
+                }
+            })
+            .collect();
+        BatchProgram {
+            instrs,
+            address_reg: prog.address_reg,
+        }
+    }
+}
+
+/// Computes the dataset items `start_item..start_item + items.len()` into `items`,
+/// four items at a time. The remaining items are computed by the interpreter.
+#[target_feature(enable = "avx2")]
+pub unsafe fn init_dataset_items(
+    seed_mem: &SeedMemory,
+    start_item: u64,
+    items: &mut [DatasetItem],
+) {
+    //the cache lines are gathered from the blocks as one contiguous array of u64
+    assert_eq!(seed_mem.blocks.len(), seed_mem.params.argon_memory as usize);
+    assert_eq!(std::mem::size_of_val(&seed_mem.blocks[0]), 1024);
+
+    let programs: Vec<BatchProgram> = seed_mem.programs.iter().map(BatchProgram::new).collect();
+    let blocks = seed_mem.blocks.as_ptr() as *const i64;
+    let line_mask = _mm256_set1_epi64x(seed_mem.params.cache_line_mask() as i64);
+
+    let mut item_num = start_item;
+    let mut batches = items.chunks_exact_mut(LANES);
+    for batch in &mut batches {
+        init_batch(&programs, blocks, line_mask, item_num, batch);
+        item_num += LANES as u64;
+    }
+    for item in batches.into_remainder() {
+        *item = DatasetItem(init_dataset_item_interpreted(seed_mem, item_num));
+        item_num += 1;
+    }
+}
+
+#[target_feature(enable = "avx2")]
+unsafe fn init_batch(
+    programs: &[BatchProgram],
+    blocks: *const i64,
+    line_mask: __m256i,
+    first_item: u64,
+    batch: &mut [DatasetItem],
+) {
+    let mut item_nums = [0u64; LANES];
+    let mut reg0 = [0u64; LANES];
+    for (lane, (item_num, r0)) in item_nums.iter_mut().zip(reg0.iter_mut()).enumerate() {
+        *item_num = first_item + lane as u64;
+        *r0 = (*item_num + 1).wrapping_mul(SUPERSCALAR_MUL_0);
+    }
+
+    let r0 = load(&reg0);
+    let mut regs = [r0; 8];
+    let adds = [
+        SUPERSCALAR_ADD_1,
+        SUPERSCALAR_ADD_2,
+        SUPERSCALAR_ADD_3,
+        SUPERSCALAR_ADD_4,
+        SUPERSCALAR_ADD_5,
+        SUPERSCALAR_ADD_6,
+        SUPERSCALAR_ADD_7,
+    ];
+    for (reg, add) in regs[1..].iter_mut().zip(adds.iter()) {
+        *reg = _mm256_xor_si256(r0, _mm256_set1_epi64x(*add as i64));
+    }
+
+    let mut reg_value = load(&item_nums);
+    for prog in programs {
+        execute(prog, &mut regs);
+
+        //word index of the cache line in the blocks
+        let line = _mm256_slli_epi64::<3>(_mm256_and_si256(reg_value, line_mask));
+        for (r, reg) in regs.iter_mut().enumerate() {
+            let mix = _mm256_i64gather_epi64::<8>(blocks.add(r), line);
+            *reg = _mm256_xor_si256(*reg, mix);
+        }
+        reg_value = regs[prog.address_reg];
+    }
+
+    let mut lanes = [[0u64; LANES]; 8];
+    for (lane, reg) in lanes.iter_mut().zip(regs.iter()) {
+        _mm256_storeu_si256(lane.as_mut_ptr() as *mut __m256i, *reg);
+    }
+    for (n, item) in batch.iter_mut().enumerate() {
+        for (r, word) in item.0.iter_mut().enumerate() {
+            *word = lanes[r][n];
+        }
+    }
+}
+
+#[target_feature(enable = "avx2")]
+unsafe fn execute(prog: &BatchProgram, regs: &mut [__m256i; 8]) {
+    for instr in &prog.instrs {
+        let dst = regs[instr.dst];
+        let value = match instr.op {
+            ScOpcode::ISUB_R => _mm256_sub_epi64(dst, regs[instr.src]),
+            ScOpcode::IXOR_R => _mm256_xor_si256(dst, regs[instr.src]),
+            ScOpcode::IADD_RS => {
+                let shifted =
+                    _mm256_sll_epi64(regs[instr.src], _mm_cvtsi64_si128(instr.imm as i64));
+                _mm256_add_epi64(dst, shifted)
+            }
+            ScOpcode::IMUL_R => mul_epi64(dst, regs[instr.src]),
+            //a shift by 64 gives 0, so a rotation by 0 keeps the value
+            ScOpcode::IROR_C => _mm256_or_si256(
+                _mm256_srl_epi64(dst, _mm_cvtsi64_si128(instr.imm as i64)),
+                _mm256_sll_epi64(dst, _mm_cvtsi64_si128(64 - instr.imm as i64)),
+            ),
+            ScOpcode::IADD_C7 | ScOpcode::IADD_C8 | ScOpcode::IADD_C9 => {
+                _mm256_add_epi64(dst, _mm256_set1_epi64x(instr.imm as i64))
+            }
+            ScOpcode::IXOR_C7 | ScOpcode::IXOR_C8 | ScOpcode::IXOR_C9 => {
+                _mm256_xor_si256(dst, _mm256_set1_epi64x(instr.imm as i64))
+            }
+            ScOpcode::IMULH_R => mulh_epu64(dst, regs[instr.src]),
+            ScOpcode::ISMULH_R => mulh_epi64(dst, regs[instr.src]),
+            ScOpcode::IMUL_RCP => mul_epi64(dst, _mm256_set1_epi64x(instr.imm as i64)),
+            ScOpcode::COUNT => panic!("COUNT execution tried"),
+            ScOpcode::INVALID => panic!("INVALLID execution tried"),
+        };
+        regs[instr.dst] = value;
+    }
+}
+
+#[target_feature(enable = "avx2")]
+unsafe fn load(lanes: &[u64; LANES]) -> __m256i {
+    _mm256_loadu_si256(lanes.as_ptr() as *const __m256i)
+}
+
+//the low 64 bits of a * b
+#[target_feature(enable = "avx2")]
+unsafe fn mul_epi64(a: __m256i, b: __m256i) -> __m256i {
+    let lo_lo = _mm256_mul_epu32(a, b);
+    let cross = _mm256_add_epi64(
+        _mm256_mul_epu32(_mm256_srli_epi64::<32>(a), b),
+        _mm256_mul_epu32(a, _mm256_srli_epi64::<32>(b)),
+    );
+    _mm256_add_epi64(lo_lo, _mm256_slli_epi64::<32>(cross))
+}
+
+//the high 64 bits of the unsigned a * b
+#[target_feature(enable = "avx2")]
+unsafe fn mulh_epu64(a: __m256i, b: __m256i) -> __m256i {
+    let a_hi = _mm256_srli_epi64::<32>(a);
+    let b_hi = _mm256_srli_epi64::<32>(b);
+    let lo_lo = _mm256_mul_epu32(a, b);
+    let lo_hi = _mm256_mul_epu32(a, b_hi);
+    let hi_lo = _mm256_mul_epu32(a_hi, b);
+    let hi_hi = _mm256_mul_epu32(a_hi, b_hi);
+
+    //the carry of the middle 32 bits, at most 34 bits wide
+    let low_mask = _mm256_set1_epi64x(0xffff_ffff);
+    let mid = _mm256_add_epi64(
+        _mm256_add_epi64(
+            _mm256_srli_epi64::<32>(lo_lo),
+            _mm256_and_si256(lo_hi, low_mask),
+        ),
+        _mm256_and_si256(hi_lo, low_mask),
+    );
+    _mm256_add_epi64(
+        _mm256_add_epi64(hi_hi, _mm256_srli_epi64::<32>(lo_hi)),
+        _mm256_add_epi64(_mm256_srli_epi64::<32>(hi_lo), _mm256_srli_epi64::<32>(mid)),
+    )
+}
+
+//the high 64 bits of the signed a * b: the unsigned product of a negative factor
+//is too large by the other factor * 2^64
+#[target_feature(enable = "avx2")]
+unsafe fn mulh_epi64(a: __m256i, b: __m256i) -> __m256i {
+    let zero = _mm256_setzero_si256();
+    let a_neg = _mm256_cmpgt_epi64(zero, a);
+    let b_neg = _mm256_cmpgt_epi64(zero, b);
+    let hi = mulh_epu64(a, b);
+    _mm256_sub_epi64(
+        _mm256_sub_epi64(hi, _mm256_and_si256(a_neg, b)),
+        _mm256_and_si256(b_neg, a),
+    )
+}
@@ -0,0 +1,205 @@
+extern crate serde_json;
+
+//Step-by-step tracing of the RandomX vm, e.g. to find where a hash diverges from
+//the reference implementation. The vm is generic over the `Tracer`, the untraced
+//functions use `NoTracer` whose hooks compile to nothing.
+
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+use strum::{Display, EnumString};
+
+use super::m128::m128d;
+use super::program::{Instr, MAX_FLOAT_REG, MAX_REG};
+use super::vm::Vm;
+
+/// Where in the hash calculation a trace hook is called.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TracePos {
+    /// the program of the hash (0..program_count)
+    pub program: usize,
+    /// the iteration of the program (0..program_iterations)
+    pub iteration: usize,
+    /// the instruction, `None` for the end of an iteration
+    pub pc: Option<usize>,
+}
+
+/// Hooks called by the vm while it runs a program.
+pub trait Tracer {
+    /// Whether `instruction` is called. If true, the programs are interpreted
+    /// even if the vm runs in compiled mode.
+    fn traces_instructions(&self) -> bool {
+        false
+    }
+
+    /// Called after each executed instruction with the scratchpad byte address
+    /// the instruction read or wrote.
+    fn instruction(&mut self, _vm: &Vm, _pos: TracePos, _instr: &Instr, _address: Option<u64>) {}
+
+    /// Called at the end of each program iteration with the scratchpad byte addresses
+    /// of the integer and float registers and the dataset address read in the iteration.
+    fn iteration(&mut self, _vm: &Vm, _pos: TracePos, _scratchpad: [u64; 2], _dataset: u64) {}
+
+    /// Called before `iteration` with whether the dataset item of the iteration was
+    /// read from the dataset or the item cache (`hit`) or computed from the cache.
+    fn dataset_item(&mut self, _vm: &Vm, _pos: TracePos, _hit: bool) {}
+}
+
+/// Traces nothing.
+pub struct NoTracer;
+
+impl Tracer for NoTracer {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum TraceLevel {
+    /// a record after each instruction and each iteration
+    Instruction,
+    /// a record after each iteration
+    Iteration,
+}
+
+/// Restricts the records to some programs, iterations and instructions,
+/// `None` matches everything.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TraceFilter {
+    pub programs: Option<RangeInclusive<usize>>,
+    pub iterations: Option<RangeInclusive<usize>>,
+    pub pcs: Option<RangeInclusive<usize>>,
+}
+
+impl TraceFilter {
+    pub fn matches(&self, pos: &TracePos) -> bool {
+        in_range(&self.programs, pos.program)
+            && in_range(&self.iterations, pos.iteration)
+            && match pos.pc {
+                Some(pc) => in_range(&self.pcs, pc),
+                None => true,
+            }
+    }
+}
+
+fn in_range(range: &Option<RangeInclusive<usize>>, v: usize) -> bool {
+    range.as_ref().map(|r| r.contains(&v)).unwrap_or(true)
+}
+
+/// The vm state after an instruction or an iteration. Float registers are
+/// written as the bits of their (low, high) doubles.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TraceRecord {
+    pub program: usize,
+    pub iteration: usize,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub pc: Option<usize>,
+    /// the executed instruction in the disassembly format
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub instr: Option<String>,
+    pub r: [u64; MAX_REG],
+    pub f: [[u64; 2]; MAX_FLOAT_REG],
+    pub e: [[u64; 2]; MAX_FLOAT_REG],
+    pub a: [[u64; 2]; MAX_FLOAT_REG],
+    pub mx: usize,
+    pub ma: usize,
+    /// scratchpad byte addresses touched
+    pub scratchpad: Vec<u64>,
+    /// dataset byte address read (iteration records only)
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub dataset: Option<u64>,
+    pub rounding_mode: u32,
+}
+
+impl TraceRecord {
+    pub fn new(vm: &Vm, pos: TracePos) -> TraceRecord {
+        TraceRecord {
+            program: pos.program,
+            iteration: pos.iteration,
+            pc: pos.pc,
+            instr: None,
+            r: vm.reg.r,
+            f: float_bits(&vm.reg.f),
+            e: float_bits(&vm.reg.e),
+            a: float_bits(&vm.reg.a),
+            mx: vm.mem_reg.mx,
+            ma: vm.mem_reg.ma,
+            scratchpad: Vec::new(),
+            dataset: None,
+            rounding_mode: vm.get_rounding_mode(),
+        }
+    }
+}
+
+fn float_bits(regs: &[m128d; MAX_FLOAT_REG]) -> [[u64; 2]; MAX_FLOAT_REG] {
+    let mut bits = [[0; 2]; MAX_FLOAT_REG];
+    for (b, reg) in bits.iter_mut().zip(regs.iter()) {
+        let (h, l) = reg.as_u64();
+        *b = [l, h];
+    }
+    bits
+}
+
+/// Writes a `TraceRecord` as JSON line to the sink for every hook call
+/// that matches the filter.
+pub struct JsonTracer<W: Write> {
+    sink: W,
+    level: TraceLevel,
+    filter: TraceFilter,
+    //the first write error, the following records are dropped
+    error: Option<io::Error>,
+}
+
+impl<W: Write> JsonTracer<W> {
+    pub fn new(sink: W, level: TraceLevel, filter: TraceFilter) -> JsonTracer<W> {
+        JsonTracer {
+            sink,
+            level,
+            filter,
+            error: None,
+        }
+    }
+
+    /// Flushes the sink and returns it, or the first error writing to it.
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        self.sink.flush()?;
+        Ok(self.sink)
+    }
+
+    fn write(&mut self, record: &TraceRecord) {
+        if self.error.is_some() {
+            return;
+        }
+        let result = serde_json::to_writer(&mut self.sink, record)
+            .map_err(io::Error::from)
+            .and_then(|_| self.sink.write_all(b"\n"));
+        if let Err(e) = result {
+            self.error = Some(e);
+        }
+    }
+}
+
+impl<W: Write> Tracer for JsonTracer<W> {
+    fn traces_instructions(&self) -> bool {
+        self.level == TraceLevel::Instruction
+    }
+
+    fn instruction(&mut self, vm: &Vm, pos: TracePos, instr: &Instr, address: Option<u64>) {
+        if !self.filter.matches(&pos) {
+            return;
+        }
+        let mut record = TraceRecord::new(vm, pos);
+        record.instr = Some(instr.to_string());
+        record.scratchpad.extend(address);
+        self.write(&record);
+    }
+
+    fn iteration(&mut self, vm: &Vm, pos: TracePos, scratchpad: [u64; 2], dataset: u64) {
+        if !self.filter.matches(&pos) {
+            return;
+        }
+        let mut record = TraceRecord::new(vm, pos);
+        record.scratchpad.extend_from_slice(&scratchpad);
+        record.dataset = Some(dataset);
+        self.write(&record);
+    }
+}
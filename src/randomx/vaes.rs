@@ -0,0 +1,154 @@
+//The AES generators and the scratchpad hash with VAES: two of the four 128 bit states
+//are kept in one 256 bit register, so one instruction computes two AES rounds.
+//The 1Rx4 and 4Rx4 functions apply aesenc to two of the states and aesdec to the
+//other two. The register pairs the two states that use the same instruction
+//(A = state 0 and 2, B = state 1 and 3). 512 bit registers would need all four
+//states to use the same instruction, so they do not help here.
+//
+//The functions require VAES and AVX2, the caller has to check for them
+//(see `hardware::use_vaes`).
+
+use std::arch::x86_64::{
+    __m256i, _mm256_aesdec_epi128, _mm256_aesenc_epi128, _mm256_castsi256_si128,
+    _mm256_extracti128_si256, _mm256_loadu_si256, _mm256_permute2x128_si256, _mm256_set_m128i,
+    _mm256_storeu_si256,
+};
+
+use super::m128::m128i;
+
+#[target_feature(enable = "avx2,vaes")]
+unsafe fn pair(lo: m128i, hi: m128i) -> __m256i {
+    _mm256_set_m128i(hi.0, lo.0)
+}
+
+#[target_feature(enable = "avx2,vaes")]
+unsafe fn unpair(v: __m256i) -> (m128i, m128i) {
+    (
+        m128i(_mm256_castsi256_si128(v)),
+        m128i(_mm256_extracti128_si256::<1>(v)),
+    )
+}
+
+//the 8 words of a 64 byte chunk as (state 0, state 2) and (state 1, state 3)
+#[target_feature(enable = "avx2,vaes")]
+unsafe fn load_chunk(chunk: &[u64]) -> (__m256i, __m256i) {
+    debug_assert_eq!(chunk.len(), 8);
+    let lo = _mm256_loadu_si256(chunk.as_ptr() as *const __m256i);
+    let hi = _mm256_loadu_si256(chunk[4..].as_ptr() as *const __m256i);
+    (
+        _mm256_permute2x128_si256::<0x20>(lo, hi),
+        _mm256_permute2x128_si256::<0x31>(lo, hi),
+    )
+}
+
+#[target_feature(enable = "avx2,vaes")]
+unsafe fn store_chunk(a: __m256i, b: __m256i, chunk: &mut [u64]) {
+    debug_assert_eq!(chunk.len(), 8);
+    _mm256_storeu_si256(
+        chunk.as_mut_ptr() as *mut __m256i,
+        _mm256_permute2x128_si256::<0x20>(a, b),
+    );
+    _mm256_storeu_si256(
+        chunk[4..].as_mut_ptr() as *mut __m256i,
+        _mm256_permute2x128_si256::<0x31>(a, b),
+    );
+}
+
+/// `hash::fill_aes_1rx4_u64` with VAES.
+#[target_feature(enable = "avx2,vaes")]
+pub unsafe fn fill_aes_1rx4_u64(
+    input: &[m128i; 4],
+    keys: (m128i, m128i, m128i, m128i),
+    into: &mut [u64],
+) -> [m128i; 4] {
+    let (key0, key1, key2, key3) = keys;
+    let key_a = pair(key0, key2);
+    let key_b = pair(key1, key3);
+    let mut a = pair(input[0], input[2]);
+    let mut b = pair(input[1], input[3]);
+
+    for chunk in into.chunks_exact_mut(8) {
+        a = _mm256_aesdec_epi128(a, key_a);
+        b = _mm256_aesenc_epi128(b, key_b);
+        store_chunk(a, b, chunk);
+    }
+    unpack(a, b)
+}
+
+/// Absorbs `input` into the hash `states` like `hash::hash_aes_1rx4`, without
+/// the final rounds.
+#[target_feature(enable = "avx2,vaes")]
+pub unsafe fn hash_aes_1rx4(states: [m128i; 4], input: &[u64]) -> [m128i; 4] {
+    let mut a = pair(states[0], states[2]);
+    let mut b = pair(states[1], states[3]);
+
+    for chunk in input.chunks_exact(8) {
+        let (in_a, in_b) = load_chunk(chunk);
+        a = _mm256_aesenc_epi128(a, in_a);
+        b = _mm256_aesdec_epi128(b, in_b);
+    }
+    unpack(a, b)
+}
+
+/// `hash::hash_and_fill_aes_1rx4` with VAES, without the final rounds of the hash.
+#[target_feature(enable = "avx2,vaes")]
+pub unsafe fn hash_and_fill_aes_1rx4(
+    hash_states: [m128i; 4],
+    fill_seed: &[m128i; 4],
+    keys: (m128i, m128i, m128i, m128i),
+    scratchpad: &mut [u64],
+) -> ([m128i; 4], [m128i; 4]) {
+    let (key0, key1, key2, key3) = keys;
+    let key_a = pair(key0, key2);
+    let key_b = pair(key1, key3);
+    let mut hash_a = pair(hash_states[0], hash_states[2]);
+    let mut hash_b = pair(hash_states[1], hash_states[3]);
+    let mut fill_a = pair(fill_seed[0], fill_seed[2]);
+    let mut fill_b = pair(fill_seed[1], fill_seed[3]);
+
+    for chunk in scratchpad.chunks_exact_mut(8) {
+        let (in_a, in_b) = load_chunk(chunk);
+        hash_a = _mm256_aesenc_epi128(hash_a, in_a);
+        hash_b = _mm256_aesdec_epi128(hash_b, in_b);
+
+        fill_a = _mm256_aesdec_epi128(fill_a, key_a);
+        fill_b = _mm256_aesenc_epi128(fill_b, key_b);
+        store_chunk(fill_a, fill_b, chunk);
+    }
+    (unpack(hash_a, hash_b), unpack(fill_a, fill_b))
+}
+
+/// `hash::gen_program_aes_4rx4` with VAES.
+#[target_feature(enable = "avx2,vaes")]
+pub unsafe fn gen_program_aes_4rx4(
+    input: &[m128i; 4],
+    keys: &[m128i; 8],
+    output_size: usize,
+) -> Vec<m128i> {
+    let mut result = Vec::with_capacity(output_size);
+    //states 0 and 1 use keys 0 to 3, states 2 and 3 use keys 4 to 7
+    let round_keys = [
+        pair(keys[0], keys[4]),
+        pair(keys[1], keys[5]),
+        pair(keys[2], keys[6]),
+        pair(keys[3], keys[7]),
+    ];
+    let mut a = pair(input[0], input[2]);
+    let mut b = pair(input[1], input[3]);
+
+    while result.len() < output_size {
+        for key in round_keys.iter() {
+            a = _mm256_aesdec_epi128(a, *key);
+            b = _mm256_aesenc_epi128(b, *key);
+        }
+        result.extend_from_slice(&unpack(a, b));
+    }
+    result
+}
+
+#[target_feature(enable = "avx2,vaes")]
+unsafe fn unpack(a: __m256i, b: __m256i) -> [m128i; 4] {
+    let (state0, state2) = unpair(a);
+    let (state1, state3) = unpair(b);
+    [state0, state1, state2, state3]
+}
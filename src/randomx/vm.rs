@@ -1,34 +1,28 @@
 extern crate blake2b_simd;
 
 use self::blake2b_simd::{blake2b, Hash, Params};
-use super::common::{mulh, randomx_reciprocal, smulh, u64_from_i32_imm};
-use super::hash::{fill_aes_1rx4_u64, gen_program_aes_4rx4, hash_aes_1rx4};
-use super::m128::{m128d, m128i};
+use super::common::{mulh, smulh};
+use super::hardware::PageBuffer;
+use super::hash::{fill_aes_1rx4_u64, gen_program_aes_4rx4, hash_aes_1rx4, hash_and_fill_aes_1rx4};
+use super::jit::{JitCompiler, JitConstants};
+use super::m128::{get_rounding_mode, m128d, m128i, set_rounding_mode, ROUND_TO_NEAREST};
 use super::memory::{VmMemory, CACHE_LINE_SIZE};
-use super::program::{Instr, Mode, Program, Store, MAX_FLOAT_REG, MAX_REG};
-use std::arch::x86_64::{_mm_getcsr, _mm_setcsr};
-use std::convert::TryInto;
+use super::params::{RandomXParams, RX_0};
+use super::program::{Decoder, Instr, Opcode, Program, MAX_FLOAT_REG, MAX_REG};
+use super::trace::{NoTracer, TracePos, Tracer};
 use std::sync::Arc;
+use strum::{Display, EnumString};
 
-pub const SCRATCHPAD_L1_MASK: u64 = 0x3ff8;
-pub const SCRATCHPAD_L2_MASK: u64 = 0x3fff8;
-pub const SCRATCHPAD_L3_MASK: u64 = 0x1ffff8;
-const SCRATCHPAD_L3_MASK_U32: u32 = 0x1fffc0;
-
-const SCRATCHPAD_SIZE: usize = 262144;
-const MXCSR_DEFAULT: u32 = 0x9FC0;
-const CONDITION_OFFSET: u64 = 8;
-const CONDITION_MASK: u64 = (1 << CONDITION_OFFSET) - 1;
-
-const RANDOMX_PROGRAM_COUNT: usize = 8;
-const RANDOMX_PROGRAM_SIZE: i32 = 256;
-const RANDOMX_PROGRAM_ITERATIONS: usize = 2048;
-const RANDOMX_DATASET_BASE_SIZE: usize = 2147483648;
-const RANDOMX_DATASET_ITEM_SIZE: usize = 64;
-const RANDOMX_DATASET_EXTRA_SIZE: usize = 33554368;
-const RANDOMX_HASH_SIZE: usize = 32;
+//scratchpad masks of rx/0, the masks of the running variant are in the decoded instructions
+pub const SCRATCHPAD_L1_MASK: u64 = RX_0.scratchpad_l1_mask();
+pub const SCRATCHPAD_L2_MASK: u64 = RX_0.scratchpad_l2_mask();
+pub const SCRATCHPAD_L3_MASK: u64 = RX_0.scratchpad_l3_mask();
+
+pub const CONDITION_OFFSET: u64 = 8;
+pub const CONDITION_MASK: u64 = (1 << CONDITION_OFFSET) - 1;
 
-const DATASET_EXTRA_ITEMS: usize = RANDOMX_DATASET_EXTRA_SIZE / RANDOMX_DATASET_ITEM_SIZE;
+const RANDOMX_DATASET_ITEM_SIZE: u64 = 64;
+const RANDOMX_HASH_SIZE: usize = 32;
 
 const MANTISSA_SIZE: u64 = 52;
 const MANTISSA_MASK: u64 = (1 << MANTISSA_SIZE) - 1;
@@ -40,14 +34,12 @@ const DYNAMIC_EXPONENT_BITS: u64 = 4;
 const STATIC_EXPONENT_BITS: u64 = 4;
 const DYNAMIC_MANTISSA_MASK: u64 = (1 << (MANTISSA_SIZE + DYNAMIC_EXPONENT_BITS)) - 1;
 
-const CACHE_LINE_ALIGN_MASK: u64 =
-    ((RANDOMX_DATASET_BASE_SIZE - 1) & !(RANDOMX_DATASET_ITEM_SIZE - 1)) as u64;
-
 pub struct MemoryRegister {
     pub mx: usize,
     pub ma: usize,
 }
 
+#[repr(C)]
 pub struct Register {
     pub r: [u64; MAX_REG as usize],
     pub f: [m128d; MAX_FLOAT_REG as usize],
@@ -108,6 +100,13 @@ impl Register {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum VmMode {
+    Interpreted,
+    Compiled,
+}
+
 pub struct VmConfig {
     pub e_mask: [u64; 2],
     pub read_reg: [usize; 4],
@@ -116,36 +115,51 @@ pub struct VmConfig {
 pub struct Vm {
     pub mem_reg: MemoryRegister,
     pub reg: Register,
-    pub scratchpad: Vec<u64>,
+    pub scratchpad: PageBuffer<u64>,
     pub pc: i32,
     pub config: VmConfig,
     pub mem: Arc<VmMemory>,
     pub dataset_offset: u64,
+    pub jit: Option<JitCompiler>,
+    /// the decoded program, reused for every run
+    pub prog: Box<Program>,
+    /// seed of the first program for the hash started with calculate_hash_first/next
+    pub tmp_hash: [m128i; 4],
+    /// the parameters of the variant, taken from the memory
+    pub params: RandomXParams,
+    pub decoder: Decoder,
+    //masks derived from the params, see the reference implementation
+    scratchpad_iter_mask: u32,
+    cache_line_align_mask: u64,
 }
 
 impl Vm {
     pub fn init_vm(&mut self, prog: &Program) {
+        self.init_vm_with_entropy(&prog.entropy);
+    }
+
+    fn init_vm_with_entropy(&mut self, entropy: &[u64; 16]) {
         self.reg.a[0] = m128d::from_u64(
-            small_positive_float_bit(prog.entropy[1]),
-            small_positive_float_bit(prog.entropy[0]),
+            small_positive_float_bit(entropy[1]),
+            small_positive_float_bit(entropy[0]),
         );
         self.reg.a[1] = m128d::from_u64(
-            small_positive_float_bit(prog.entropy[3]),
-            small_positive_float_bit(prog.entropy[2]),
+            small_positive_float_bit(entropy[3]),
+            small_positive_float_bit(entropy[2]),
         );
         self.reg.a[2] = m128d::from_u64(
-            small_positive_float_bit(prog.entropy[5]),
-            small_positive_float_bit(prog.entropy[4]),
+            small_positive_float_bit(entropy[5]),
+            small_positive_float_bit(entropy[4]),
         );
         self.reg.a[3] = m128d::from_u64(
-            small_positive_float_bit(prog.entropy[7]),
-            small_positive_float_bit(prog.entropy[6]),
+            small_positive_float_bit(entropy[7]),
+            small_positive_float_bit(entropy[6]),
         );
 
-        self.mem_reg.ma = ((prog.entropy[8] & CACHE_LINE_ALIGN_MASK) as u32) as usize;
-        self.mem_reg.mx = (prog.entropy[10] as u32) as usize;
+        self.mem_reg.ma = ((entropy[8] & self.cache_line_align_mask) as u32) as usize;
+        self.mem_reg.mx = (entropy[10] as u32) as usize;
 
-        let mut address_reg = prog.entropy[12] as usize;
+        let mut address_reg = entropy[12] as usize;
         self.config.read_reg[0] = address_reg & 1;
         address_reg >>= 1;
         self.config.read_reg[1] = 2 + (address_reg & 1);
@@ -155,10 +169,10 @@ impl Vm {
         self.config.read_reg[3] = 6 + (address_reg & 1);
 
         self.dataset_offset =
-            (prog.entropy[13] % (DATASET_EXTRA_ITEMS as u64 + 1)) * CACHE_LINE_SIZE;
+            (entropy[13] % (self.params.dataset_extra_items() + 1)) * CACHE_LINE_SIZE;
 
-        self.config.e_mask[0] = float_mask(prog.entropy[14]);
-        self.config.e_mask[1] = float_mask(prog.entropy[15]);
+        self.config.e_mask[0] = float_mask(entropy[14]);
+        self.config.e_mask[1] = float_mask(entropy[15]);
 
         for i in 0..MAX_REG {
             self.reg.r[i] = 0;
@@ -170,20 +184,57 @@ impl Vm {
     }
 
     pub fn calculate_hash(&mut self, input: &[u8]) -> Hash {
-        let hash = blake2b(input);
-        let seed = hash_to_m128i_array(&hash);
+        self.calculate_hash_first(input);
+        self.calculate_hash_last()
+    }
+
+    /// Like `calculate_hash`, but calls the hooks of `tracer` while running the programs.
+    pub fn calculate_hash_traced<T: Tracer>(&mut self, input: &[u8], tracer: &mut T) -> Hash {
+        self.calculate_hash_first(input);
+        self.run_programs(tracer);
+        let final_hash = hash_aes_1rx4(&self.scratchpad);
+        self.finish_hash(&final_hash)
+    }
+
+    /// Starts hashing `input`. The hash is returned by the following call
+    /// of `calculate_hash_next` or `calculate_hash_last`.
+    pub fn calculate_hash_first(&mut self, input: &[u8]) {
+        let seed = hash_to_m128i_array(&blake2b(input));
+        self.tmp_hash = self.init_scratchpad(&seed);
+    }
+
+    /// Finishes the hash of the previous input and starts hashing `next_input`.
+    /// The final scratchpad hash of the previous input and the scratchpad fill
+    /// for `next_input` are done in one pass.
+    pub fn calculate_hash_next(&mut self, next_input: &[u8]) -> Hash {
+        self.run_programs(&mut NoTracer);
+        let seed = hash_to_m128i_array(&blake2b(next_input));
+        let (final_hash, tmp_hash) = hash_and_fill_aes_1rx4(&seed, &mut self.scratchpad);
+        self.tmp_hash = tmp_hash;
+        self.finish_hash(&final_hash)
+    }
+
+    /// Finishes the hash of the previous input without starting a new one.
+    pub fn calculate_hash_last(&mut self) -> Hash {
+        self.run_programs(&mut NoTracer);
+        let final_hash = hash_aes_1rx4(&self.scratchpad);
+        self.finish_hash(&final_hash)
+    }
 
-        let mut tmp_hash = self.init_scratchpad(&seed);
+    fn run_programs<T: Tracer>(&mut self, tracer: &mut T) {
+        let mut tmp_hash = self.tmp_hash;
         self.reset_rounding_mode();
 
-        for _ in 0..(RANDOMX_PROGRAM_COUNT - 1) {
-            self.run(&tmp_hash);
+        for program in 0..(self.params.program_count - 1) {
+            self.run_traced(&tmp_hash, program, tracer);
             let blake_result = blake2b(&self.reg.to_bytes());
             tmp_hash = hash_to_m128i_array(&blake_result);
         }
 
-        self.run(&tmp_hash);
-        let final_hash = hash_aes_1rx4(&self.scratchpad);
+        self.run_traced(&tmp_hash, self.params.program_count - 1, tracer);
+    }
+
+    fn finish_hash(&mut self, final_hash: &[m128i; 4]) -> Hash {
         self.reg.a[0] = final_hash[0].as_m128d();
         self.reg.a[1] = final_hash[1].as_m128d();
         self.reg.a[2] = final_hash[2].as_m128d();
@@ -196,21 +247,35 @@ impl Vm {
 
     /// Runs one round
     pub fn run(&mut self, seed: &[m128i; 4]) {
-        let prog = Program::from_bytes(gen_program_aes_4rx4(seed, 136));
+        self.run_traced(seed, 0, &mut NoTracer);
+    }
+
+    /// Runs one round as the `program`th program of the hash and calls the hooks of `tracer`.
+    pub fn run_traced<T: Tracer>(&mut self, seed: &[m128i; 4], program: usize, tracer: &mut T) {
+        let bytes = gen_program_aes_4rx4(seed, self.params.program_blocks());
+        self.prog.decode(&bytes, &self.decoder);
+        let entropy = self.prog.entropy;
+        self.init_vm_with_entropy(&entropy);
 
-        self.init_vm(&prog);
+        let mut jit = self.jit.take();
+        let compiled = if tracer.traces_instructions() {
+            None
+        } else {
+            jit.as_mut().map(|jit| jit.compile(&self.prog))
+        };
+        let jit_consts = JitConstants::new(self.config.e_mask, DYNAMIC_MANTISSA_MASK);
 
         let mut sp_addr_0: u32 = self.mem_reg.mx as u32;
         let mut sp_addr_1: u32 = self.mem_reg.ma as u32;
 
-        for _ in 0..RANDOMX_PROGRAM_ITERATIONS {
+        for iteration in 0..self.params.program_iterations {
             let sp_mix = self.reg.r[self.config.read_reg[0]] ^ self.reg.r[self.config.read_reg[1]];
 
             sp_addr_0 ^= sp_mix as u32;
-            sp_addr_0 &= SCRATCHPAD_L3_MASK_U32;
+            sp_addr_0 &= self.scratchpad_iter_mask;
             sp_addr_0 /= 8;
             sp_addr_1 ^= (sp_mix >> 32) as u32;
-            sp_addr_1 &= SCRATCHPAD_L3_MASK_U32;
+            sp_addr_1 &= self.scratchpad_iter_mask;
             sp_addr_1 /= 8;
 
             for i in 0..MAX_REG {
@@ -227,18 +292,33 @@ impl Vm {
                 );
             }
 
-            self.pc = 0;
-            while self.pc < RANDOMX_PROGRAM_SIZE {
-                let instr = &prog.program[self.pc as usize];
-                instr.execute(self);
-                self.pc += 1;
+            if let Some(compiled) = &compiled {
+                compiled.execute(&mut self.reg, &mut self.scratchpad, &jit_consts);
+            } else {
+                self.pc = 0;
+                while self.pc < self.prog.size as i32 {
+                    let instr = self.prog.program[self.pc as usize];
+                    if tracer.traces_instructions() {
+                        let pos = TracePos {
+                            program,
+                            iteration,
+                            pc: Some(self.pc as usize),
+                        };
+                        let address = self.scratchpad_address(&instr);
+                        instr.execute(self);
+                        tracer.instruction(self, pos, &instr, address);
+                    } else {
+                        instr.execute(self);
+                    }
+                    self.pc += 1;
+                }
             }
 
             self.mem_reg.mx ^= (self.reg.r[self.config.read_reg[2]]
                 ^ self.reg.r[self.config.read_reg[3]]) as usize;
-            self.mem_reg.mx &= CACHE_LINE_ALIGN_MASK as usize;
+            self.mem_reg.mx &= self.cache_line_align_mask as usize;
             self.mem.dataset_prefetch(self.mem_reg.mx as u64);
-            self.mem.dataset_read(
+            let dataset_hit = self.mem.dataset_read(
                 self.dataset_offset + self.mem_reg.ma as u64,
                 &mut self.reg.r,
             );
@@ -258,313 +338,261 @@ impl Vm {
                 self.scratchpad[ix] = u0;
                 self.scratchpad[ix + 1] = u1;
             }
+
+            let pos = TracePos {
+                program,
+                iteration,
+                pc: None,
+            };
+            let dataset_addr = self.dataset_offset + self.mem_reg.mx as u64;
+            tracer.dataset_item(self, pos, dataset_hit);
+            tracer.iteration(
+                self,
+                pos,
+                [u64::from(sp_addr_0) * 8, u64::from(sp_addr_1) * 8],
+                dataset_addr,
+            );
             sp_addr_0 = 0;
             sp_addr_1 = 0;
         }
+
+        self.jit = jit;
     }
 
-    pub fn reset_rounding_mode(&mut self) {
-        unsafe {
-            _mm_setcsr(MXCSR_DEFAULT);
+    /// Interprets the instructions of `prog` once on the current registers and
+    /// scratchpad (e.g. for a program built with `asm::assemble`).
+    pub fn execute_program(&mut self, prog: &Program) {
+        self.pc = 0;
+        while self.pc < prog.size as i32 {
+            prog.program[self.pc as usize].execute(self);
+            self.pc += 1;
         }
     }
 
+    pub fn reset_rounding_mode(&mut self) {
+        set_rounding_mode(ROUND_TO_NEAREST);
+    }
+
     pub fn set_rounding_mode(&mut self, mode: u32) {
-        unsafe { _mm_setcsr(MXCSR_DEFAULT | (mode << 13)) }
+        set_rounding_mode(mode);
     }
 
     pub fn get_rounding_mode(&self) -> u32 {
-        unsafe { (_mm_getcsr() >> 13) & 3 }
+        get_rounding_mode()
     }
 
     //f...
 
     pub fn exec_fswap_r(&mut self, instr: &Instr) {
-        let v_dst = self.read_float_reg(&instr.dst);
-        self.write_float_reg(&instr.dst, v_dst.shuffle_1(&v_dst));
+        let dst = instr.dst as usize;
+        if dst < MAX_FLOAT_REG {
+            let v_dst = self.reg.f[dst];
+            self.reg.f[dst] = v_dst.shuffle_1(&v_dst);
+        } else {
+            let v_dst = self.reg.e[dst - MAX_FLOAT_REG];
+            self.reg.e[dst - MAX_FLOAT_REG] = v_dst.shuffle_1(&v_dst);
+        }
     }
 
     pub fn exec_fadd_r(&mut self, instr: &Instr) {
-        let v_src = self.read_a(&instr.src);
-        let v_dst = self.read_f(&instr.dst);
-        self.write_f(&instr.dst, v_src + v_dst);
+        let v_src = self.reg.a[instr.src as usize];
+        let v_dst = self.reg.f[instr.dst as usize];
+        self.reg.f[instr.dst as usize] = v_src + v_dst;
     }
 
     pub fn exec_fadd_m(&mut self, instr: &Instr) {
         let v = self.scratchpad[self.scratchpad_src_ix(instr)];
         let v_src = m128i::from_u64(0, v).lower_to_m128d();
-        let v_dst = self.read_f(&instr.dst);
-        self.write_f(&instr.dst, v_dst + v_src);
+        let v_dst = self.reg.f[instr.dst as usize];
+        self.reg.f[instr.dst as usize] = v_dst + v_src;
     }
 
     pub fn exec_fsub_r(&mut self, instr: &Instr) {
-        let v_src = self.read_a(&instr.src);
-        let v_dst = self.read_f(&instr.dst);
-        self.write_f(&instr.dst, v_dst - v_src);
+        let v_src = self.reg.a[instr.src as usize];
+        let v_dst = self.reg.f[instr.dst as usize];
+        self.reg.f[instr.dst as usize] = v_dst - v_src;
     }
 
     pub fn exec_fsub_m(&mut self, instr: &Instr) {
         let v = self.scratchpad[self.scratchpad_src_ix(instr)];
         let v_src = m128i::from_u64(0, v).lower_to_m128d();
-        let v_dst = self.read_f(&instr.dst);
-        self.write_f(&instr.dst, v_dst - v_src);
+        let v_dst = self.reg.f[instr.dst as usize];
+        self.reg.f[instr.dst as usize] = v_dst - v_src;
     }
 
     pub fn exec_fscal_r(&mut self, instr: &Instr) {
-        let v_dst = self.read_f(&instr.dst);
+        let v_dst = self.reg.f[instr.dst as usize];
         let mask = m128d::from_u64(0x80F0000000000000, 0x80F0000000000000);
-        self.write_f(&instr.dst, v_dst ^ mask);
+        self.reg.f[instr.dst as usize] = v_dst ^ mask;
     }
 
     pub fn exec_fmul_r(&mut self, instr: &Instr) {
-        let v_src = self.read_a(&instr.src);
-        let v_dst = self.read_e(&instr.dst);
-        self.write_e(&instr.dst, v_src * v_dst);
+        let v_src = self.reg.a[instr.src as usize];
+        let v_dst = self.reg.e[instr.dst as usize];
+        self.reg.e[instr.dst as usize] = v_src * v_dst;
     }
 
     pub fn exec_fsqrt_r(&mut self, instr: &Instr) {
-        let v_dst = self.read_e(&instr.dst);
-        self.write_e(&instr.dst, v_dst.sqrt());
+        let v_dst = self.reg.e[instr.dst as usize];
+        self.reg.e[instr.dst as usize] = v_dst.sqrt();
     }
 
     pub fn exec_fdiv_m(&mut self, instr: &Instr) {
         let v = self.scratchpad[self.scratchpad_src_ix(instr)];
         let v_src = self.mask_register_exponent_mantissa(m128i::from_u64(0, v).lower_to_m128d());
-        let v_dst = self.read_e(&instr.dst);
-        self.write_e(&instr.dst, v_dst / v_src);
+        let v_dst = self.reg.e[instr.dst as usize];
+        self.reg.e[instr.dst as usize] = v_dst / v_src;
     }
 
     //i...
 
     pub fn exec_iadd_m(&mut self, instr: &Instr) {
-        let ix = self.scratchpad_src_ix(instr);
-        self.write_r(
-            &instr.dst,
-            self.read_r(&instr.dst).wrapping_add(self.scratchpad[ix]),
-        );
+        let v_src = self.scratchpad[self.scratchpad_src_ix(instr)];
+        let dst = &mut self.reg.r[instr.dst as usize];
+        *dst = dst.wrapping_add(v_src);
     }
 
     pub fn exec_isub_m(&mut self, instr: &Instr) {
-        let ix = self.scratchpad_src_ix(instr);
-        self.write_r(
-            &instr.dst,
-            self.read_r(&instr.dst).wrapping_sub(self.scratchpad[ix]),
-        );
+        let v_src = self.scratchpad[self.scratchpad_src_ix(instr)];
+        let dst = &mut self.reg.r[instr.dst as usize];
+        *dst = dst.wrapping_sub(v_src);
     }
 
     pub fn exec_imul_m(&mut self, instr: &Instr) {
-        let ix = self.scratchpad_src_ix(instr);
-        self.write_r(
-            &instr.dst,
-            self.read_r(&instr.dst).wrapping_mul(self.scratchpad[ix]),
-        );
+        let v_src = self.scratchpad[self.scratchpad_src_ix(instr)];
+        let dst = &mut self.reg.r[instr.dst as usize];
+        *dst = dst.wrapping_mul(v_src);
     }
     pub fn exec_iadd_rs(&mut self, instr: &Instr) {
-        let mut v = self.read_r(&instr.src) << shift_mode(instr);
-        if let Some(imm) = instr.imm {
-            v = v.wrapping_add(u64_from_i32_imm(imm));
-        }
-        self.write_r(&instr.dst, self.read_r(&instr.dst).wrapping_add(v));
+        let v = (self.reg.r[instr.src as usize] << instr.modi).wrapping_add(instr.imm64);
+        let dst = &mut self.reg.r[instr.dst as usize];
+        *dst = dst.wrapping_add(v);
     }
     pub fn exec_isub_r(&mut self, instr: &Instr) {
         let v = self.imm_or_r(instr);
-        self.write_r(&instr.dst, self.read_r(&instr.dst).wrapping_sub(v));
+        let dst = &mut self.reg.r[instr.dst as usize];
+        *dst = dst.wrapping_sub(v);
     }
 
     pub fn exec_imul_r(&mut self, instr: &Instr) {
         let v = self.imm_or_r(instr);
-        self.write_r(&instr.dst, self.read_r(&instr.dst).wrapping_mul(v));
+        let dst = &mut self.reg.r[instr.dst as usize];
+        *dst = dst.wrapping_mul(v);
     }
 
     pub fn exec_imul_rcp(&mut self, instr: &Instr) {
-        if !is_zero_or_power_of_2(instr.imm.unwrap() as u64) {
-            let v = randomx_reciprocal((instr.imm.unwrap() as u64) & 0x00000000FFFFFFFF);
-            self.write_r(&instr.dst, self.read_r(&instr.dst).wrapping_mul(v));
+        if instr.imm64 != 0 {
+            let dst = &mut self.reg.r[instr.dst as usize];
+            *dst = dst.wrapping_mul(instr.imm64);
         } //else: nop
     }
 
     pub fn exec_imulh_r(&mut self, instr: &Instr) {
-        let v_src = self.read_r(&instr.src);
-        let v_dst = self.read_r(&instr.dst);
-        self.write_r(&instr.dst, mulh(v_src, v_dst));
+        let v_src = self.reg.r[instr.src as usize];
+        let v_dst = self.reg.r[instr.dst as usize];
+        self.reg.r[instr.dst as usize] = mulh(v_src, v_dst);
     }
 
     pub fn exec_imulh_m(&mut self, instr: &Instr) {
-        let v_dst = self.read_r(&instr.dst);
+        let v_dst = self.reg.r[instr.dst as usize];
         let v_src = self.scratchpad[self.scratchpad_src_ix(instr)];
-        self.write_r(&instr.dst, mulh(v_src, v_dst));
+        self.reg.r[instr.dst as usize] = mulh(v_src, v_dst);
     }
 
     pub fn exec_ismulh_r(&mut self, instr: &Instr) {
-        let v_src = self.read_r(&instr.src);
-        let v_dst = self.read_r(&instr.dst);
-        self.write_r(&instr.dst, smulh(v_src, v_dst));
+        let v_src = self.reg.r[instr.src as usize];
+        let v_dst = self.reg.r[instr.dst as usize];
+        self.reg.r[instr.dst as usize] = smulh(v_src, v_dst);
     }
 
     pub fn exec_ismulh_m(&mut self, instr: &Instr) {
         let v_src = self.scratchpad[self.scratchpad_src_ix(instr)];
-        let v_dst = self.read_r(&instr.dst);
-        self.write_r(&instr.dst, smulh(v_src, v_dst));
+        let v_dst = self.reg.r[instr.dst as usize];
+        self.reg.r[instr.dst as usize] = smulh(v_src, v_dst);
     }
 
     pub fn exec_ineg_r(&mut self, instr: &Instr) {
-        let v_dst = self.read_r(&instr.dst);
-        self.write_r(&instr.dst, (!v_dst).wrapping_add(1));
+        let v_dst = self.reg.r[instr.dst as usize];
+        self.reg.r[instr.dst as usize] = (!v_dst).wrapping_add(1);
     }
 
     pub fn exec_ixor_r(&mut self, instr: &Instr) {
         let v_src = self.imm_or_r(instr);
-        let v_dst = self.read_r(&instr.dst);
-        self.write_r(&instr.dst, v_dst ^ v_src);
+        self.reg.r[instr.dst as usize] ^= v_src;
     }
 
     pub fn exec_ixor_m(&mut self, instr: &Instr) {
         let v_src = self.scratchpad[self.scratchpad_src_ix(instr)];
-        let v_dst = self.read_r(&instr.dst);
-        self.write_r(&instr.dst, v_dst ^ v_src);
+        self.reg.r[instr.dst as usize] ^= v_src;
     }
 
     pub fn exec_iror_r(&mut self, instr: &Instr) {
         let v_src = (self.imm_or_r(instr) & 0xFFFFFF) as u32;
-        let v_dst = self.read_r(&instr.dst);
-        self.write_r(&instr.dst, v_dst.rotate_right(v_src));
+        let dst = &mut self.reg.r[instr.dst as usize];
+        *dst = dst.rotate_right(v_src);
     }
 
     pub fn exec_irol_r(&mut self, instr: &Instr) {
         let v_src = (self.imm_or_r(instr) & 0xFFFFFF) as u32;
-        let v_dst = self.read_r(&instr.dst);
-        self.write_r(&instr.dst, v_dst.rotate_left(v_src));
+        let dst = &mut self.reg.r[instr.dst as usize];
+        *dst = dst.rotate_left(v_src);
     }
 
     pub fn exec_iswap_r(&mut self, instr: &Instr) {
-        let v_src = self.read_r(&instr.src);
-        let v_dst = self.read_r(&instr.dst);
-        self.write_r(&instr.dst, v_src);
-        self.write_r(&instr.src, v_dst);
+        self.reg.r.swap(instr.dst as usize, instr.src as usize);
     }
 
     pub fn exec_istore(&mut self, instr: &Instr) {
-        let ix = self.scratchpad_dst_ix(instr);
-        self.scratchpad[ix] = self.read_r(&instr.src);
+        let addr = self.reg.r[instr.dst as usize].wrapping_add(instr.imm64) & instr.mem_mask as u64;
+        self.scratchpad[(addr / 8) as usize] = self.reg.r[instr.src as usize];
     }
 
     //c..
 
     pub fn exec_cfround(&mut self, instr: &Instr) {
-        let v_src = self.read_r(&instr.src);
-        let mode = (v_src.rotate_right(instr.imm.unwrap() as u32) % 4) as u32;
+        let v_src = self.reg.r[instr.src as usize];
+        let mode = (v_src.rotate_right(instr.imm as u32) % 4) as u32;
         self.set_rounding_mode(mode);
     }
 
     pub fn exec_cbranch(&mut self, instr: &Instr) {
-        let shift = cond_mode(instr) as u64 + CONDITION_OFFSET;
-        let mut imm = u64_from_i32_imm(instr.imm.unwrap()) | 1 << shift;
-        if CONDITION_OFFSET > 0 || shift > 0 {
-            imm &= !(1 << (shift - 1));
-        }
-        let v_dst = self.read_r(&instr.dst).wrapping_add(imm);
-        self.write_r(&instr.dst, v_dst);
+        let shift = instr.modi as u64 + CONDITION_OFFSET;
+        let v_dst = self.reg.r[instr.dst as usize].wrapping_add(instr.imm64);
+        self.reg.r[instr.dst as usize] = v_dst;
         if v_dst & (CONDITION_MASK << shift) == 0 {
-            self.pc = instr.target.unwrap();
+            self.pc = instr.target as i32;
         }
     }
 
     //helper
 
-    fn imm_or_r(&self, instr: &Instr) -> u64 {
-        if instr.src == Store::NONE {
-            return instr.imm.unwrap() as u64;
-        }
-        self.read_r(&instr.src)
-    }
-
-    fn read_float_reg(&self, store: &Store) -> m128d {
-        match store {
-            Store::A(i) => self.reg.a[*i],
-            Store::E(i) => self.reg.e[*i],
-            Store::F(i) => self.reg.f[*i],
-            _ => panic!("illegal read from float register"),
-        }
-    }
-
-    fn write_float_reg(&mut self, store: &Store, v: m128d) {
-        match store {
-            Store::A(i) => self.reg.a[*i] = v,
-            Store::E(i) => self.reg.e[*i] = v,
-            Store::F(i) => self.reg.f[*i] = v,
-            _ => panic!("illegal write to float register"),
-        }
-    }
-
-    fn read_r(&self, store: &Store) -> u64 {
-        match store {
-            Store::R(i) => self.reg.r[*i],
-            _ => panic!("illegal read from register r"),
-        }
-    }
-    fn write_r(&mut self, store: &Store, v: u64) {
-        match store {
-            Store::R(i) => self.reg.r[*i] = v,
-            _ => panic!("illegal store to register r"),
-        }
-    }
-    fn read_f(&self, store: &Store) -> m128d {
-        match store {
-            Store::F(i) => self.reg.f[*i],
-            _ => panic!("illegal read from register f"),
-        }
-    }
-    fn write_f(&mut self, store: &Store, v: m128d) {
-        match store {
-            Store::F(i) => self.reg.f[*i] = v,
-            _ => panic!("illegal store to register f"),
+    /// The scratchpad byte address `instr` reads or writes with the current registers.
+    pub fn scratchpad_address(&self, instr: &Instr) -> Option<u64> {
+        if instr.mem_mask == 0 {
+            return None;
         }
-    }
-
-    fn read_a(&self, store: &Store) -> m128d {
-        match store {
-            Store::A(i) => self.reg.a[*i],
-            _ => panic!("illegal read from register a"),
+        if instr.op == Opcode::ISTORE {
+            let addr = self.reg.r[instr.dst as usize].wrapping_add(instr.imm64);
+            return Some(addr & instr.mem_mask as u64);
         }
+        Some(self.scratchpad_src_ix(instr) as u64 * 8)
     }
 
-    fn read_e(&self, store: &Store) -> m128d {
-        match store {
-            Store::E(i) => self.reg.e[*i],
-            _ => panic!("illegal read from register e"),
+    fn imm_or_r(&self, instr: &Instr) -> u64 {
+        if instr.src_imm {
+            return instr.imm64;
         }
+        self.reg.r[instr.src as usize]
     }
 
-    fn write_e(&mut self, store: &Store, v: m128d) {
-        match store {
-            Store::E(i) => self.reg.e[*i] = v,
-            _ => panic!("illegal store to register e"),
-        }
-    }
     fn scratchpad_src_ix(&self, instr: &Instr) -> usize {
-        let imm = u64_from_i32_imm(instr.imm.unwrap());
-        let addr: usize = match &instr.src {
-            Store::L1(d) => (self.read_r(d).wrapping_add(imm)) & SCRATCHPAD_L1_MASK,
-            Store::L2(d) => (self.read_r(d).wrapping_add(imm)) & SCRATCHPAD_L2_MASK,
-            Store::L3(_) => imm & SCRATCHPAD_L3_MASK,
-            _ => panic!("illegal read from scratchpad"),
-        }
-        .try_into()
-        .unwrap();
-        addr / 8
-    }
-
-    fn scratchpad_dst_ix(&self, instr: &Instr) -> usize {
-        let imm = u64_from_i32_imm(instr.imm.unwrap());
-        let addr: usize = match &instr.dst {
-            Store::L1(d) => (self.read_r(d).wrapping_add(imm)) & SCRATCHPAD_L1_MASK,
-            Store::L2(d) => (self.read_r(d).wrapping_add(imm)) & SCRATCHPAD_L2_MASK,
-            Store::L3(d) => (self.read_r(d).wrapping_add(imm)) & SCRATCHPAD_L3_MASK,
-            _ => panic!("illegal read from scratchpad"),
-        }
-        .try_into()
-        .unwrap();
-        addr / 8
+        let base = if instr.src_imm {
+            0
+        } else {
+            self.reg.r[instr.src as usize]
+        };
+        let addr = base.wrapping_add(instr.imm64) & instr.mem_mask as u64;
+        (addr / 8) as usize
     }
 
     fn mask_register_exponent_mantissa(&self, v: m128d) -> m128d {
@@ -583,20 +611,6 @@ pub fn hash_to_m128i_array(hash: &Hash) -> [m128i; 4] {
     [i1, i2, i3, i4]
 }
 
-fn shift_mode(instr: &Instr) -> u8 {
-    match instr.mode {
-        Mode::Shft(x) => x,
-        _ => panic!("illegal shift mode {}", instr.mode),
-    }
-}
-
-fn cond_mode(instr: &Instr) -> u8 {
-    match instr.mode {
-        Mode::Cond(x) => x,
-        _ => panic!("illegal cond mode {}", instr.mode),
-    }
-}
-
 pub fn is_zero_or_power_of_2(imm: u64) -> bool {
     imm & imm.wrapping_sub(1) == 0
 }
@@ -622,10 +636,29 @@ fn static_exponent(entropy: u64) -> u64 {
 }
 
 pub fn new_vm(mem: Arc<VmMemory>) -> Vm {
+    new_vm_with_mode(mem, VmMode::Interpreted)
+}
+
+/// Creates a vm that runs programs in the given mode. Falls back to
+/// interpreted mode if compiled mode is not available on this platform.
+pub fn new_vm_with_mode(mem: Arc<VmMemory>, mode: VmMode) -> Vm {
+    let jit = match mode {
+        VmMode::Interpreted => None,
+        VmMode::Compiled => {
+            let jit = JitCompiler::new();
+            if jit.is_none() {
+                warn!("compiled mode not available, falling back to interpreted mode");
+            }
+            jit
+        }
+    };
+    let params = mem.seed_memory.params;
+    let scratchpad = PageBuffer::zeroed((params.scratchpad_l3 / 8) as usize, &mem.hardware_conf);
+    debug!("scratchpad allocated with {} pages", scratchpad.kind());
     Vm {
         mem_reg: MemoryRegister { mx: 0, ma: 0 },
         reg: new_register(),
-        scratchpad: vec![0; SCRATCHPAD_SIZE],
+        scratchpad,
         pc: 0,
         config: VmConfig {
             e_mask: [0; 2],
@@ -633,5 +666,12 @@ pub fn new_vm(mem: Arc<VmMemory>) -> Vm {
         },
         mem,
         dataset_offset: 0,
+        jit,
+        prog: Box::default(),
+        tmp_hash: [m128i::zero(); 4],
+        params,
+        decoder: Decoder::new(&params),
+        scratchpad_iter_mask: (params.scratchpad_l3 - RANDOMX_DATASET_ITEM_SIZE) as u32,
+        cache_line_align_mask: (params.dataset_base_size - 1) & !(RANDOMX_DATASET_ITEM_SIZE - 1),
     }
 }
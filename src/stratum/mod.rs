@@ -27,6 +27,7 @@ pub enum StratumAction {
     Job {
         miner_id: String,
         seed_hash: String,
+        next_seed_hash: Option<String>,
         blob: String,
         job_id: String,
         target: String,
@@ -404,6 +405,7 @@ pub fn parse_line_dispatch_result(
                                 job:
                                     stratum_data::Job {
                                         seed_hash,
+                                        next_seed_hash,
                                         blob,
                                         job_id,
                                         target,
@@ -416,6 +418,7 @@ pub fn parse_line_dispatch_result(
                             action = StratumAction::Job {
                                 miner_id: miner_id.clone(),
                                 seed_hash,
+                                next_seed_hash,
                                 blob,
                                 job_id,
                                 target,
@@ -458,6 +461,7 @@ fn parse_job(line: &str, miner_id_mutx: &Arc<Mutex<Option<String>>>) -> StratumA
             params:
                 stratum_data::Job {
                     seed_hash,
+                    next_seed_hash,
                     blob,
                     job_id,
                     target,
@@ -465,6 +469,7 @@ fn parse_job(line: &str, miner_id_mutx: &Arc<Mutex<Option<String>>>) -> StratumA
         }) => StratumAction::Job {
             miner_id,
             seed_hash,
+            next_seed_hash,
             blob,
             job_id,
             target,
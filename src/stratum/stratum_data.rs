@@ -21,6 +21,8 @@ pub struct ErrorResult {
 #[derive(Deserialize)]
 pub struct Job {
     pub seed_hash: String,
+    /// seed hash of the upcoming seed epoch, only sent by some pools
+    pub next_seed_hash: Option<String>,
     pub blob: String,
     pub job_id: String,
     pub target: String,
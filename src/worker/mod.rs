@@ -1 +1,2 @@
+pub mod share_verifier;
 pub mod worker_pool;
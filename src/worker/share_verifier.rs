@@ -0,0 +1,173 @@
+extern crate crossbeam_channel;
+
+//Hashes the shares found by the workers again before they are submitted. The
+//verifier has its own light mode memory, initialised from the seed hash, so a
+//corrupted dataset item (bad RAM, unstable overclocking) does not end up as a
+//rejected share at the pool. The memory is initialised when the workers start
+//on a new seed, not when the first share for it is found.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use self::crossbeam_channel::{unbounded, Receiver, Sender};
+use super::super::byte_string;
+use super::super::randomx::memory::VmMemory;
+use super::super::randomx::params::RandomXParams;
+use super::super::randomx::vm::{new_vm, Vm};
+use super::super::stratum;
+use super::super::stratum::stratum_data;
+
+/// A share found by a worker with everything needed to hash it again.
+pub struct ShareCandidate {
+    pub share: stratum_data::Share,
+    pub seed_hash: String,
+    /// the parameters of the memory the share was found with
+    pub params: RandomXParams,
+    /// the hashed blob (including the nonce)
+    pub input: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+pub struct ShareStats {
+    /// shares that were verified and submitted
+    pub verified: AtomicU64,
+    /// shares with a wrong hash that were dropped
+    pub dropped: AtomicU64,
+    /// light mode memories initialised
+    pub seeds: AtomicU64,
+}
+
+pub enum VerifierCmd {
+    /// the workers start on `seed_hash`, the light mode memory for it is initialised
+    NewSeed {
+        seed_hash: String,
+        params: RandomXParams,
+    },
+    Verify {
+        candidate: ShareCandidate,
+    },
+}
+
+pub struct ShareVerifier {
+    cmd_sndr: Sender<VerifierCmd>,
+    hnd: thread::JoinHandle<()>,
+    pub stats: Arc<ShareStats>,
+}
+
+/// Starts the verifier thread. Verified shares are submitted to `share_sndr`.
+pub fn start(share_sndr: &Sender<stratum::StratumCmd>) -> ShareVerifier {
+    let (cmd_sndr, cmd_rcvr) = unbounded();
+    let stats = Arc::new(ShareStats::default());
+    let share_sndr = share_sndr.clone();
+    let thread_stats = stats.clone();
+    let hnd = thread::Builder::new()
+        .name("share verifier".to_string())
+        .spawn(move || verify(&cmd_rcvr, &share_sndr, &thread_stats))
+        .expect("share verifier thread handle");
+    ShareVerifier {
+        cmd_sndr,
+        hnd,
+        stats,
+    }
+}
+
+impl ShareVerifier {
+    pub fn cmd_sender(&self) -> Sender<VerifierCmd> {
+        self.cmd_sndr.clone()
+    }
+
+    /// Initialises the light mode memory for `seed_hash` unless it is the seed of
+    /// the current memory.
+    pub fn seed_change(&self, seed_hash: &str, params: RandomXParams) {
+        let cmd = VerifierCmd::NewSeed {
+            seed_hash: seed_hash.to_string(),
+            params,
+        };
+        if self.cmd_sndr.send(cmd).is_err() {
+            error!("share verifier is not running");
+        }
+    }
+
+    /// Verifies the remaining candidates and waits for the verifier thread. The thread
+    /// stops once all senders returned by `cmd_sender` are dropped.
+    pub fn join(self) {
+        drop(self.cmd_sndr);
+        if let Err(err) = self.hnd.join() {
+            error!("share verifier join failed {:?}", err);
+        }
+    }
+}
+
+fn verify(
+    rcvr: &Receiver<VerifierCmd>,
+    share_sndr: &Sender<stratum::StratumCmd>,
+    stats: &ShareStats,
+) {
+    //the light vm of the last seed, only rebuilt if the seed changes
+    let mut light_vm: Option<(String, Vm)> = None;
+
+    for cmd in rcvr.iter() {
+        match cmd {
+            VerifierCmd::NewSeed { seed_hash, params } => {
+                light_vm_for(&mut light_vm, &seed_hash, &params, stats);
+            }
+            //a share of the previous seed found before the worker got the new job
+            //rebuilds the vm of that seed
+            VerifierCmd::Verify { candidate } => {
+                let vm = light_vm_for(
+                    &mut light_vm,
+                    &candidate.seed_hash,
+                    &candidate.params,
+                    stats,
+                );
+                verify_share(vm, candidate, share_sndr, stats);
+            }
+        }
+    }
+}
+
+fn light_vm_for<'a>(
+    light_vm: &'a mut Option<(String, Vm)>,
+    seed_hash: &str,
+    params: &RandomXParams,
+    stats: &ShareStats,
+) -> &'a mut Vm {
+    let outdated = match *light_vm {
+        Some((ref seed, ref vm)) => seed != seed_hash || vm.params != *params,
+        None => true,
+    };
+    if outdated {
+        //free the memory of the old seed before the new one is initialised
+        drop(light_vm.take());
+        let key = byte_string::string_to_u8_array(seed_hash);
+        let mem = VmMemory::light_with_params(&key, params);
+        *light_vm = Some((seed_hash.to_string(), new_vm(Arc::new(mem))));
+        stats.seeds.fetch_add(1, Ordering::Relaxed);
+    }
+    &mut light_vm.as_mut().expect("light vm").1
+}
+
+fn verify_share(
+    vm: &mut Vm,
+    candidate: ShareCandidate,
+    share_sndr: &Sender<stratum::StratumCmd>,
+    stats: &ShareStats,
+) {
+    let hash = vm.calculate_hash(&candidate.input).to_hex();
+    if hash.as_str() == candidate.share.hash {
+        stats.verified.fetch_add(1, Ordering::Relaxed);
+        if let Err(err) = stratum::submit_share(share_sndr, candidate.share) {
+            error!("submitting share failed: {:?}", err);
+        }
+    } else {
+        let dropped = stats.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+        warn!(
+            "share with nonce {} for job {} failed verification and was dropped \
+             (hash {}, light mode hash {}, {} shares dropped so far). The dataset or \
+             the cpu computed a wrong hash, this may be a hardware problem \
+             (bad RAM, unstable overclocking).",
+            candidate.share.nonce, candidate.share.job_id, candidate.share.hash, hash, dropped
+        );
+    }
+}
@@ -6,15 +6,23 @@ use std::thread;
 
 use self::crossbeam_channel::{unbounded, Receiver, Sender};
 use super::super::byte_string;
-use super::super::randomx::memory::{VmMemory, VmMemoryAllocator};
-use super::super::randomx::vm::new_vm;
+use super::super::randomx::memory::{BuiltMemory, VmMemory, VmMemoryAllocator};
+use super::super::randomx::numa;
+use super::super::randomx::params::RandomXVariant;
+use super::super::randomx::vm::{new_vm_with_mode, VmMode};
 use super::super::stratum;
 use super::super::stratum::stratum_data;
+use super::share_verifier::{self, ShareCandidate, ShareVerifier, VerifierCmd};
 
 pub struct WorkerPool {
     thread_chan: Vec<Sender<WorkerCmd>>,
     thread_hnd: Vec<thread::JoinHandle<()>>,
+    //the NUMA node of each worker, all 0 without NUMA
+    worker_nodes: Vec<usize>,
     pub vm_memory_allocator: VmMemoryAllocator,
+    //the latest job, if the memory for its seed is not ready yet
+    pending_job: Option<PendingJob>,
+    share_verifier: Option<ShareVerifier>,
 }
 
 #[derive(Clone)]
@@ -23,6 +31,12 @@ pub struct WorkerConfig {
     pub auto_tune: bool,
     pub auto_tune_interval_minutes: u64,
     pub auto_tune_log: String,
+    pub randomx_mode: VmMode,
+    pub randomx_variant: RandomXVariant,
+    /// check the RandomX implementation with known hashes before mining
+    pub self_test: bool,
+    /// hash each share again in light mode before it is submitted
+    pub verify_shares: bool,
 }
 
 pub struct JobData {
@@ -35,6 +49,14 @@ pub struct JobData {
     pub nonce: Arc<AtomicU32>,
 }
 
+struct PendingJob {
+    miner_id: String,
+    seed_hash: String,
+    blob: String,
+    job_id: String,
+    target: String,
+}
+
 pub enum WorkerCmd {
     NewJob { job_data: JobData },
     Stop,
@@ -52,22 +74,50 @@ pub fn start(
     metric_resolution: u64,
     metric_sndr: &Sender<u64>,
     vm_memory_allocator: VmMemoryAllocator,
+    vm_mode: VmMode,
+    verify_shares: bool,
 ) -> WorkerPool {
+    let share_verifier = if verify_shares {
+        Some(share_verifier::start(share_sndr))
+    } else {
+        None
+    };
     let mut thread_chan: Vec<Sender<WorkerCmd>> = Vec::with_capacity(num_threads as usize);
     let mut thread_hnd: Vec<thread::JoinHandle<()>> = Vec::with_capacity(num_threads as usize);
+    let mut worker_nodes = Vec::with_capacity(num_threads as usize);
+    let topology = &vm_memory_allocator.numa;
     for i in 0..num_threads {
+        //without NUMA the threads are left to the scheduler
+        let placement = if topology.is_numa() {
+            Some(topology.worker_placement(i as usize))
+        } else {
+            None
+        };
+        worker_nodes.push(placement.map_or(0, |(node, _)| node));
         let (sndr, rcvr) = unbounded();
-        let share_sndr_thread = share_sndr.clone();
+        let share_sndr_thread = match share_verifier {
+            Some(ref verifier) => ShareSender::Verifier(verifier.cmd_sender()),
+            None => ShareSender::Pool(share_sndr.clone()),
+        };
         let metric_sndr_thread = metric_sndr.clone();
 
         let hnd = thread::Builder::new()
             .name(format!("worker thread {}", i))
             .spawn(move || {
+                if let Some((node, cpu)) = placement {
+                    match numa::pin_current_thread(&[cpu]) {
+                        Ok(()) => {
+                            info!("worker thread {} pinned to cpu {} (node {})", i, cpu, node)
+                        }
+                        Err(e) => warn!("pinning worker thread {} failed: {}", i, e),
+                    }
+                }
                 work(
                     &rcvr,
                     &share_sndr_thread,
                     metric_resolution,
                     &metric_sndr_thread,
+                    vm_mode,
                 )
             })
             .expect("worker thread handle");
@@ -77,37 +127,84 @@ pub fn start(
     WorkerPool {
         thread_chan,
         thread_hnd,
+        worker_nodes,
         vm_memory_allocator,
+        pending_job: None,
+        share_verifier,
     }
 }
 
 impl WorkerPool {
+    /// Sends the new job to the workers. If the memory for the seed of the job is
+    /// not ready, the workers continue with the previous job and the new job is
+    /// sent once `memory_built` is called with the memory for its seed.
     pub fn job_change(
         &mut self,
         miner_id: &str,
         seed_hash: &str,
+        next_seed_hash: Option<&str>,
         blob: &str,
         job_id: &str,
         target: &str,
     ) {
         info!("job change, blob {}", blob);
-        self.vm_memory_allocator.reallocate(seed_hash.to_string());
+        self.pending_job = Some(PendingJob {
+            miner_id: miner_id.to_string(),
+            seed_hash: seed_hash.to_string(),
+            blob: blob.to_string(),
+            job_id: job_id.to_string(),
+            target: target.to_string(),
+        });
+        if !self.send_pending_job() {
+            info!(
+                "memory for seed_hash {} not ready, job starts after the memory init",
+                seed_hash
+            );
+        }
+        if let Some(next_seed_hash) = next_seed_hash {
+            self.vm_memory_allocator.prepare(next_seed_hash);
+        }
+    }
+
+    /// Passes memory built in the background to the allocator and starts the
+    /// pending job if it was waiting for this memory.
+    pub fn memory_built(&mut self, built: BuiltMemory) {
+        self.vm_memory_allocator.memory_built(built);
+        self.send_pending_job();
+    }
+
+    //returns false if the memory for the pending job is not ready
+    fn send_pending_job(&mut self) -> bool {
+        let memory = match self.pending_job {
+            Some(ref job) => match self.vm_memory_allocator.memory_for(&job.seed_hash) {
+                Some(memory) => memory,
+                None => return false,
+            },
+            None => return true,
+        };
+        let job = self.pending_job.take().expect("pending job");
         let nonce = Arc::new(AtomicU32::new(0));
 
-        for (_, tx) in self.thread_chan.iter().enumerate() {
+        //the verifier initialises its memory while the workers start on the job
+        if let Some(ref verifier) = self.share_verifier {
+            verifier.seed_change(&job.seed_hash, memory.seed_memory.params);
+        }
+
+        for (tx, node) in self.thread_chan.iter().zip(self.worker_nodes.iter()) {
             tx.send(WorkerCmd::NewJob {
                 job_data: JobData {
-                    miner_id: miner_id.to_string(),
-                    seed_hash: seed_hash.to_string(),
-                    memory: self.vm_memory_allocator.vm_memory.clone(),
-                    blob: blob.to_string(),
-                    job_id: job_id.to_string(),
-                    target: target.to_string(),
+                    miner_id: job.miner_id.clone(),
+                    seed_hash: job.seed_hash.clone(),
+                    memory: self.vm_memory_allocator.memory_for_node(*node),
+                    blob: job.blob.clone(),
+                    job_id: job.job_id.clone(),
+                    target: job.target.clone(),
                     nonce: nonce.clone(),
                 },
             })
             .expect("sending new job command");
         }
+        true
     }
 
     pub fn stop(&self) {
@@ -118,6 +215,13 @@ impl WorkerPool {
         }
     }
 
+    /// The number of shares dropped by the share verifier, 0 if shares are not verified.
+    pub fn dropped_shares(&self) -> u64 {
+        self.share_verifier
+            .as_ref()
+            .map_or(0, |v| v.stats.dropped.load(Ordering::Relaxed))
+    }
+
     //Waits for completing of all threads in the pool
     pub fn join(self) {
         for hnd in self.thread_hnd {
@@ -126,14 +230,24 @@ impl WorkerPool {
                 error!("thread join failed {:?}, waiting for next", join_result)
             }
         }
+        if let Some(verifier) = self.share_verifier {
+            verifier.join();
+        }
     }
 }
 
+//where the workers send the shares they found
+enum ShareSender {
+    Pool(Sender<stratum::StratumCmd>),
+    Verifier(Sender<VerifierCmd>),
+}
+
 fn work(
     rcv: &Receiver<WorkerCmd>,
-    share_tx: &Sender<stratum::StratumCmd>,
+    share_tx: &ShareSender,
     metric_resolution: u64,
     metric_tx: &Sender<u64>,
+    vm_mode: VmMode,
 ) {
     let first_job = rcv.recv();
     if first_job.is_err() {
@@ -149,7 +263,7 @@ fn work(
     };
 
     loop {
-        let exit_reason = work_job(&job, rcv, share_tx, metric_resolution, metric_tx);
+        let exit_reason = work_job(&job, rcv, share_tx, metric_resolution, metric_tx, vm_mode);
         //if work_job returns the nonce space was exhausted or a new job was received.
         //In case the nonce space was exhausted, we have to wait blocking for a new job and "idle".
         match exit_reason {
@@ -178,35 +292,62 @@ fn work(
 fn work_job<'a>(
     job: &'a JobData,
     rcv: &'a Receiver<WorkerCmd>,
-    share_tx: &Sender<stratum::StratumCmd>,
+    share_tx: &ShareSender,
     metric_resolution: u64,
     metric_tx: &Sender<u64>,
+    vm_mode: VmMode,
 ) -> WorkerExit {
     let num_target = job_target_value(&job.target);
-    let mut nonce = job.nonce.fetch_add(1, Ordering::SeqCst);
+    let nonce = job.nonce.fetch_add(1, Ordering::SeqCst);
+    if nonce > 65535 {
+        return WorkerExit::NonceSpaceExhausted;
+    }
 
     let mut hash_count: u64 = 0;
-    let mut vm = new_vm(job.memory.clone());
+    let mut vm = new_vm_with_mode(job.memory.clone(), vm_mode);
 
-    while nonce <= 65535 {
-        let nonce_hex = nonce_hex(nonce);
-        let hash_in = with_nonce(&job.blob, &nonce_hex);
-        let bytes_in = byte_string::string_to_u8_array(&hash_in);
+    //the hashing is pipelined, the hash for nonce N is finished while
+    //the hash for nonce N+1 is started
+    let mut current_nonce_hex = nonce_hex(nonce);
+    vm.calculate_hash_first(&blob_with_nonce(&job.blob, &current_nonce_hex));
 
-        let hash_result = vm.calculate_hash(&bytes_in).to_hex();
+    loop {
+        let next_nonce = job.nonce.fetch_add(1, Ordering::SeqCst);
+        let next_nonce_hex = nonce_hex(next_nonce);
+        let hash_result = if next_nonce <= 65535 {
+            vm.calculate_hash_next(&blob_with_nonce(&job.blob, &next_nonce_hex))
+        } else {
+            vm.calculate_hash_last()
+        }
+        .to_hex();
         let hash_val = hash_target_value(&hash_result);
 
         if hash_val < num_target {
             let share = stratum_data::Share {
                 miner_id: job.miner_id.clone(),
                 job_id: job.job_id.clone(),
-                nonce: nonce_hex,
+                nonce: current_nonce_hex,
                 hash: hash_result.to_string(),
             };
 
-            let submit_result = stratum::submit_share(share_tx, share);
-            if submit_result.is_err() {
-                error!("submitting share failed: {:?}", submit_result);
+            match share_tx {
+                ShareSender::Pool(tx) => {
+                    let submit_result = stratum::submit_share(tx, share);
+                    if submit_result.is_err() {
+                        error!("submitting share failed: {:?}", submit_result);
+                    }
+                }
+                ShareSender::Verifier(tx) => {
+                    let candidate = ShareCandidate {
+                        input: blob_with_nonce(&job.blob, &share.nonce),
+                        share,
+                        seed_hash: job.seed_hash.clone(),
+                        params: job.memory.seed_memory.params,
+                    };
+                    if tx.send(VerifierCmd::Verify { candidate }).is_err() {
+                        error!("share verifier is not running");
+                    }
+                }
             }
         }
 
@@ -234,15 +375,21 @@ fn work_job<'a>(
             }
         }
 
-        nonce = job.nonce.fetch_add(1, Ordering::SeqCst);
+        if next_nonce > 65535 {
+            return WorkerExit::NonceSpaceExhausted;
+        }
+        current_nonce_hex = next_nonce_hex;
     }
-    WorkerExit::NonceSpaceExhausted
 }
 
 pub fn nonce_hex(nonce: u32) -> String {
     format!("{:08x}", nonce)
 }
 
+fn blob_with_nonce(blob: &str, nonce: &str) -> Vec<u8> {
+    byte_string::string_to_u8_array(&with_nonce(blob, nonce))
+}
+
 pub fn with_nonce(blob: &str, nonce: &str) -> String {
     let (a, _) = blob.split_at(78);
     let (_, b) = blob.split_at(86);
@@ -1,6 +1,9 @@
 extern crate mithril;
 
 use mithril::mithril_config;
+use mithril::randomx::hardware::{AesMode, DatasetInit, MemoryMode};
+use mithril::randomx::params::RandomXVariant;
+use mithril::randomx::vm::VmMode;
 
 use std::path::Path;
 use std::time::{Duration, Instant};
@@ -17,6 +20,10 @@ fn test_read_default_config() {
     assert_eq!(config.worker_conf.auto_tune, true);
     assert_eq!(config.worker_conf.auto_tune_interval_minutes, 15);
     assert_eq!(config.worker_conf.auto_tune_log, "./bandit.log");
+    assert_eq!(config.worker_conf.randomx_mode, VmMode::Compiled);
+    assert_eq!(config.worker_conf.randomx_variant, RandomXVariant::Rx0);
+    assert!(config.worker_conf.self_test);
+    assert!(!config.worker_conf.verify_shares);
 
     assert_eq!(config.metric_conf.enabled, false);
     assert_eq!(config.metric_conf.resolution, std::u32::MAX as u64);
@@ -27,6 +34,28 @@ fn test_read_default_config() {
     assert_eq!(config.metric_conf.report_file, "/dev/null");
 
     assert_eq!(config.donation_conf.percentage, 2.5);
+
+    assert_eq!(config.hardware_conf.huge_pages, true);
+    assert_eq!(config.hardware_conf.huge_pages_1g, false);
+    assert_eq!(config.hardware_conf.aes, AesMode::Auto);
+    assert_eq!(config.hardware_conf.memory_mode, MemoryMode::Auto);
+    assert_eq!(config.hardware_conf.hybrid_cache_mib, 512);
+    assert!(config.hardware_conf.numa);
+    assert_eq!(config.hardware_conf.dataset_init, DatasetInit::Auto);
+
+    assert_eq!(config.dataset_cache_conf.enabled, false);
+    assert_eq!(config.dataset_cache_conf.max_seeds, 2);
+    assert!(config
+        .dataset_cache_conf
+        .dir
+        .ends_with(".mithril/dataset_cache"));
+
+    assert!(!config.shared_dataset_conf.enabled);
+    assert_eq!(config.shared_dataset_conf.max_seeds, 2);
+    assert_eq!(
+        config.shared_dataset_conf.dir,
+        Path::new("/dev/shm/mithril")
+    );
 }
 
 #[test] //Bugfix test, there should be some "room" so that this value can be added to a time instant
@@ -0,0 +1,91 @@
+extern crate argon2;
+extern crate mithril;
+
+use mithril::randomx::argon2d::{fill_memory_blocks, BlockFiller};
+use mithril::randomx::params::{RandomXParams, RX_0, RX_LOKI};
+
+const KEY: &[u8] = b"test key 000";
+
+//small memories, the full size is covered by the block vectors in randomx_memory.rs
+const ONE_LANE: RandomXParams = RandomXParams {
+    argon_memory: 256,
+    ..RX_0
+};
+const TWO_LANES: RandomXParams = RandomXParams {
+    argon_memory: 512,
+    ..RX_LOKI
+};
+
+#[test]
+fn test_scalar_equals_argon2_crate() {
+    for params in &[ONE_LANE, TWO_LANES] {
+        assert_blocks_eq(&fill(params, BlockFiller::Scalar), &fill_reference(params));
+    }
+}
+
+#[test]
+fn test_avx2_equals_argon2_crate() {
+    if !BlockFiller::avx2_available() {
+        return;
+    }
+    for params in &[ONE_LANE, TWO_LANES] {
+        assert_blocks_eq(&fill(params, BlockFiller::Avx2), &fill_reference(params));
+    }
+}
+
+#[test]
+fn test_detect() {
+    let filler = BlockFiller::detect();
+    assert_eq!(filler == BlockFiller::Avx2, BlockFiller::avx2_available());
+}
+
+//helper
+
+fn assert_blocks_eq(blocks: &argon2::memory::Memory, expected: &argon2::memory::Memory) {
+    assert_eq!(blocks.blocks.len(), expected.blocks.len());
+    for (i, (block, expected_block)) in blocks.blocks.iter().zip(expected.blocks.iter()).enumerate()
+    {
+        assert!(block == expected_block, "block {} differs", i);
+    }
+}
+
+fn fill(params: &RandomXParams, filler: BlockFiller) -> argon2::memory::Memory {
+    let mut mem = memory(params);
+    argon2::core::initialize(&context(params), &mut mem);
+    fill_memory_blocks(&mut mem.blocks, params, filler);
+    mem
+}
+
+fn fill_reference(params: &RandomXParams) -> argon2::memory::Memory {
+    let mut mem = memory(params);
+    let context = context(params);
+    argon2::core::initialize(&context, &mut mem);
+    argon2::core::fill_memory_blocks(&context, &mut mem);
+    mem
+}
+
+fn memory(params: &RandomXParams) -> argon2::memory::Memory {
+    argon2::memory::Memory::new(params.argon_lanes, params.argon_memory / params.argon_lanes)
+}
+
+fn context(params: &RandomXParams) -> argon2::context::Context<'static> {
+    let lane_length = params.argon_memory / params.argon_lanes;
+    argon2::context::Context {
+        config: argon2::config::Config {
+            ad: &[],
+            hash_length: 0,
+            lanes: params.argon_lanes,
+            mem_cost: params.argon_memory,
+            secret: &[],
+            thread_mode: argon2::ThreadMode::Sequential,
+            time_cost: params.argon_iterations,
+            variant: argon2::Variant::Argon2d,
+            version: argon2::Version::Version13,
+        },
+        memory_blocks: params.argon_memory,
+        pwd: KEY,
+        salt: params.argon_salt,
+        lane_length,
+        segment_length: lane_length / 4,
+    }
+}
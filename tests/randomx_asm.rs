@@ -0,0 +1,116 @@
+extern crate mithril;
+
+use mithril::randomx::asm::{assemble, disassemble, generate_programs};
+use mithril::randomx::memory::VmMemory;
+use mithril::randomx::params::{RX_0, RX_ARQ};
+use mithril::randomx::program::{Decoder, Opcode, Program};
+use mithril::randomx::vm::new_vm;
+use std::sync::Arc;
+
+#[test]
+fn test_assemble() {
+    let program: Program = "; comment\n\
+                            IADD_RS r5, r1, -100, SHFT 3\n\
+                            \n\
+                            IMUL_M r2, L3[2097144] ; max L3 address\n\
+                            FSWAP_R e1\n\
+                            ISTORE L1[r0-8], r7\n\
+                            NOP"
+    .parse()
+    .unwrap();
+
+    let instrs = program.instrs();
+    assert_eq!(instrs.len(), 5);
+    assert_eq!(instrs[0].op, Opcode::IADD_RS);
+    assert_eq!((instrs[0].dst, instrs[0].src, instrs[0].modi), (5, 1, 3));
+    assert_eq!(instrs[0].imm, -100);
+    assert!(instrs[1].src_imm);
+    assert_eq!(u64::from(instrs[1].mem_mask), RX_0.scratchpad_l3_mask());
+    assert_eq!(instrs[2].dst, 5);
+    assert_eq!(u64::from(instrs[3].mem_mask), RX_0.scratchpad_l1_mask());
+    assert_eq!(instrs[3].imm64, (-8i64) as u64);
+    assert_eq!(instrs[4].op, Opcode::NOP);
+    assert_eq!(program.register_usage[5], 0);
+    assert_eq!(program.register_usage[2], 1);
+}
+
+#[test]
+fn test_assemble_with_variant_masks() {
+    let program = assemble("ISUB_M r0, L2[r1+16]", &Decoder::new(&RX_ARQ)).unwrap();
+    assert_eq!(
+        u64::from(program.instrs()[0].mem_mask),
+        RX_ARQ.scratchpad_l2_mask()
+    );
+    assert!(assemble("ISUB_M r0, L3[262144]", &Decoder::new(&RX_ARQ)).is_err());
+}
+
+#[test]
+fn test_assemble_errors() {
+    let err = |text: &str| text.parse::<Program>().err().unwrap();
+    assert_eq!(
+        err("NOP\nIADD_X r0, r1"),
+        "line 2: unknown instruction (IADD_X r0, r1)"
+    );
+    assert!(err("ISUB_R r8, r1").contains("expected register r0-r7"));
+    assert!(err("FADD_R f0, f1").contains("expected register a0-a3"));
+    assert!(err("IROR_R r0, 64").contains("immediate has to be 0-63"));
+    assert!(err("CBRANCH r0, 1").contains("expected 3 operands"));
+    assert!(err("CBRANCH r0, 1, COND 16").contains("expected COND 0-15"));
+    assert!(err("IMUL_M r0, L2[100]").contains("L3[imm]"));
+    assert!(err("IMUL_M r0, L3[1]").contains("outside of the scratchpad"));
+    assert!(err("ISTORE L4[r0+1], r1").contains("invalid memory operand"));
+}
+
+#[test]
+fn test_cbranch_target() {
+    let program: Program = "ISUB_R r1, -1\n\
+                            CBRANCH r0, 0, COND 0\n\
+                            IXOR_R r0, r2\n\
+                            CBRANCH r0, 0, COND 0"
+        .parse()
+        .unwrap();
+    //no instruction modified r0 before the first CBRANCH, it restarts the program
+    assert_eq!(program.instrs()[1].target, -1);
+    assert_eq!(program.instrs()[3].target, 2);
+}
+
+#[test]
+fn test_execute_cbranch() {
+    let program: Program = "ISUB_R r1, -1\n\
+                            CBRANCH r0, 0, COND 0"
+        .parse()
+        .unwrap();
+    let mut vm = new_vm(Arc::new(VmMemory::no_memory()));
+    //the CBRANCH adds 256 and jumps if bits 8-15 are zero
+    vm.reg.r[0] = 0xff00;
+    vm.execute_program(&program);
+    assert_eq!(vm.reg.r[1], 2);
+    assert_eq!(vm.reg.r[0], 0x10100);
+}
+
+#[test]
+fn test_execute_cfround() {
+    let program: Program = "CFROUND r3, 2".parse().unwrap();
+    let mut vm = new_vm(Arc::new(VmMemory::no_memory()));
+    vm.reg.r[3] = 0b1100;
+    vm.execute_program(&program);
+    assert_eq!(vm.get_rounding_mode(), 3);
+    vm.reset_rounding_mode();
+}
+
+#[test]
+fn test_generate_and_disassemble_programs() {
+    let mem = Arc::new(VmMemory::light(b"test key 000"));
+    let programs = generate_programs(mem, b"This is a test");
+    assert_eq!(programs.len(), RX_0.program_count);
+
+    let text = disassemble(&programs);
+    assert!(text.starts_with("; program 0\n"));
+    assert!(text.contains("\n; program 7\n"));
+    assert_eq!(text.lines().count(), 8 * (RX_0.program_size + 1));
+
+    //the entropy differs, the instructions of all programs as well
+    assert_ne!(programs[0].instrs(), programs[1].instrs());
+    let reassembled: Program = programs[7].to_string().parse().unwrap();
+    assert_eq!(reassembled.instrs(), programs[7].instrs());
+}
@@ -0,0 +1,95 @@
+extern crate mithril;
+
+use mithril::randomx::conformance::{parse_vectors, reference_vectors, run, self_test, Vectors};
+use mithril::randomx::hardware::{use_hardware_aes, with_aes_mode, AesMode};
+use mithril::randomx::vm::VmMode;
+use mithril::randomx::RandomXMode;
+
+#[test]
+fn test_reference_vectors() {
+    let vectors = reference_vectors();
+    assert_eq!(vectors.version, 1);
+    assert_eq!(vectors.variant, "rx/0");
+    assert_eq!(vectors.hashes.len(), 5);
+}
+
+#[test]
+fn test_reference_vectors_with_light_memory() {
+    for vm_mode in &[VmMode::Interpreted, VmMode::Compiled] {
+        let report = run(&reference_vectors(), RandomXMode::Light, *vm_mode);
+        assert!(report.is_ok(), "{}", report);
+        //7 reciprocals, 17 aes outputs, 3 cache words, 2*4 dataset items, 5 hashes
+        assert_eq!(report.checks, 40);
+    }
+}
+
+#[test]
+fn test_reference_vectors_with_full_memory() {
+    let report = run(&reference_vectors(), RandomXMode::Full, VmMode::Compiled);
+    assert!(report.is_ok(), "{}", report);
+    assert_eq!(report.checks, 36);
+}
+
+#[test]
+fn test_self_test() {
+    for vm_mode in &[VmMode::Interpreted, VmMode::Compiled] {
+        let report = self_test(*vm_mode);
+        assert!(report.is_ok(), "{}", report);
+        //7 reciprocals, 17 aes outputs, 3 hashes of test key 000
+        assert_eq!(report.checks, 27);
+    }
+}
+
+#[test]
+fn test_aes_vectors_with_software_aes() {
+    let vectors = Vectors {
+        cache: Vec::new(),
+        dataset: Vec::new(),
+        hashes: Vec::new(),
+        ..reference_vectors()
+    };
+    let report = with_aes_mode(AesMode::Software, || {
+        assert!(!use_hardware_aes());
+        run(&vectors, RandomXMode::Light, VmMode::Interpreted)
+    });
+    assert!(report.is_ok(), "{}", report);
+    assert_eq!(report.checks, 24);
+}
+
+#[test]
+fn test_mismatches_are_reported() {
+    let mut vectors = Vectors {
+        cache: Vec::new(),
+        dataset: Vec::new(),
+        hashes: Vec::new(),
+        ..reference_vectors()
+    };
+    vectors.reciprocals[1].reciprocal += 1;
+    vectors.aes[0].outputs[0].bytes = "00".repeat(32);
+
+    let report = run(&vectors, RandomXMode::Light, VmMode::Interpreted);
+    assert_eq!(report.mismatches.len(), 2);
+    assert_eq!(
+        report.mismatches[0].to_string(),
+        "randomx_reciprocal: divisor 13: expected 11351842506898185610, got 11351842506898185609"
+    );
+    assert_eq!(report.mismatches[1].vector, "AesGenerator1R");
+    assert_eq!(report.mismatches[1].check, "bytes at offset 0");
+    assert_eq!(
+        report.mismatches[1].actual,
+        "fa89397dd6ca422513aeadba3f124b5540324c4ad4b6db434394307a17c833ab"
+    );
+    assert!(report.to_string().starts_with("2 of 24 checks failed\n"));
+}
+
+#[test]
+fn test_unknown_variant() {
+    let vectors = parse_vectors(
+        r#"{"version": 1, "variant": "rx/xyz", "reciprocals": [], "aes": [],
+            "cache": [], "dataset": [], "hashes": []}"#,
+    )
+    .unwrap();
+    let report = run(&vectors, RandomXMode::Light, VmMode::Interpreted);
+    assert!(!report.is_ok());
+    assert_eq!(report.mismatches[0].actual, "rx/xyz");
+}
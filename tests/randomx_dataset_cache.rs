@@ -0,0 +1,209 @@
+extern crate lazy_static;
+extern crate mithril;
+
+use lazy_static::lazy_static;
+use mithril::randomx::dataset_cache::{self, DatasetCacheConfig};
+use mithril::randomx::hardware::{HardwareConfig, PageBuffer, PageKind};
+use mithril::randomx::memory::{init_dataset_item, DatasetItem, SeedMemory, VmMemory};
+use mithril::randomx::params::{RandomXParams, RX_0, RX_WOW};
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Seek, SeekFrom, Write};
+use std::sync::Arc;
+
+const TEST_ITEM_COUNT: usize = 128;
+//the parameters of rx/0 with a dataset of TEST_ITEM_COUNT items, a full dataset
+//is too big for the tests
+const TEST_PARAMS: RandomXParams = RandomXParams {
+    dataset_base_size: TEST_ITEM_COUNT as u64 * 64,
+    dataset_extra_size: 0,
+    ..RX_0
+};
+
+lazy_static! {
+    static ref TEST_MEM: VmMemory = test_memory(b"test key 000");
+}
+
+#[test]
+fn test_store_and_load() {
+    let conf = test_conf("store_and_load", 2);
+    dataset_cache::store(&conf, b"test key 000", &TEST_MEM).unwrap();
+
+    let mem = dataset_cache::load(
+        &conf,
+        b"test key 000",
+        &TEST_PARAMS,
+        &HardwareConfig::default(),
+    )
+    .unwrap()
+    .unwrap();
+    assert!(mem.cache);
+    assert_eq!(mem.dataset_memory.kind(), PageKind::File);
+    assert_eq!(mem.dataset_memory.len(), TEST_ITEM_COUNT);
+    for i in 0..TEST_ITEM_COUNT {
+        assert_eq!(mem.dataset_memory[i].0, TEST_MEM.dataset_memory[i].0);
+    }
+    assert_eq!(mem.seed_memory.blocks[12253][29], 0xf1b62fe6210bf8b1);
+    assert_eq!(
+        init_dataset_item(&mem.seed_memory, 10000000)[0],
+        0x7943a1f6186ffb72
+    );
+
+    fs::remove_dir_all(&conf.dir).unwrap();
+}
+
+#[test]
+fn test_load_unknown_seed() {
+    let conf = test_conf("unknown_seed", 2);
+    let mem =
+        dataset_cache::load(&conf, b"unknown", &TEST_PARAMS, &HardwareConfig::default()).unwrap();
+    assert!(mem.is_none());
+}
+
+#[test]
+fn test_load_other_variant() {
+    let conf = test_conf("other_variant", 2);
+    dataset_cache::store(&conf, b"test key 000", &TEST_MEM).unwrap();
+
+    let mem = dataset_cache::load(&conf, b"test key 000", &RX_WOW, &HardwareConfig::default());
+    assert!(mem.unwrap().is_none());
+
+    fs::remove_dir_all(&conf.dir).unwrap();
+}
+
+#[test]
+fn test_load_corrupted_file() {
+    let conf = test_conf("corrupted", 2);
+    dataset_cache::store(&conf, b"test key 000", &TEST_MEM).unwrap();
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .open(dataset_cache::cache_file(
+            &conf,
+            b"test key 000",
+            &TEST_PARAMS,
+        ))
+        .unwrap();
+    file.seek(SeekFrom::End(-10)).unwrap();
+    file.write_all(&[0xff]).unwrap();
+    drop(file);
+
+    let result = dataset_cache::load(
+        &conf,
+        b"test key 000",
+        &TEST_PARAMS,
+        &HardwareConfig::default(),
+    );
+    assert_eq!(result.err().unwrap().kind(), ErrorKind::InvalidData);
+
+    fs::remove_dir_all(&conf.dir).unwrap();
+}
+
+#[test]
+fn test_load_file_of_other_seed() {
+    let conf = test_conf("other_seed", 2);
+    dataset_cache::store(&conf, b"test key 000", &TEST_MEM).unwrap();
+    fs::rename(
+        dataset_cache::cache_file(&conf, b"test key 000", &TEST_PARAMS),
+        dataset_cache::cache_file(&conf, b"test key 001", &TEST_PARAMS),
+    )
+    .unwrap();
+
+    let result = dataset_cache::load(
+        &conf,
+        b"test key 001",
+        &TEST_PARAMS,
+        &HardwareConfig::default(),
+    );
+    assert_eq!(result.err().unwrap().kind(), ErrorKind::InvalidData);
+
+    fs::remove_dir_all(&conf.dir).unwrap();
+}
+
+#[test]
+fn test_store_evicts_oldest_seeds() {
+    let conf = test_conf("evict", 2);
+    for key in &[b"seed 1", b"seed 2", b"seed 3"] {
+        dataset_cache::store(&conf, *key, &TEST_MEM).unwrap();
+    }
+
+    assert!(!dataset_cache::cache_file(&conf, b"seed 1", &TEST_PARAMS).exists());
+    assert!(dataset_cache::cache_file(&conf, b"seed 2", &TEST_PARAMS).exists());
+    assert!(dataset_cache::cache_file(&conf, b"seed 3", &TEST_PARAMS).exists());
+
+    fs::remove_dir_all(&conf.dir).unwrap();
+}
+
+#[test]
+fn test_load_file_with_other_item_count() {
+    let conf = test_conf("item_count", 2);
+    dataset_cache::store(&conf, b"test key 000", &TEST_MEM).unwrap();
+
+    //a consistent file with fewer items than the dataset of the variant
+    let result = dataset_cache::load(&conf, b"test key 000", &RX_0, &HardwareConfig::default());
+    assert_eq!(result.err().unwrap().kind(), ErrorKind::InvalidData);
+
+    fs::remove_dir_all(&conf.dir).unwrap();
+}
+
+#[test]
+fn test_load_file_with_huge_item_count() {
+    let conf = test_conf("huge_item_count", 2);
+    dataset_cache::store(&conf, b"test key 000", &TEST_MEM).unwrap();
+
+    //the size of the file computed from this item count overflows
+    let huge = RandomXParams {
+        dataset_base_size: 1 << 63,
+        dataset_extra_size: (1 << 63) - 64,
+        ..TEST_PARAMS
+    };
+    let mut file = OpenOptions::new()
+        .write(true)
+        .open(dataset_cache::cache_file(
+            &conf,
+            b"test key 000",
+            &TEST_PARAMS,
+        ))
+        .unwrap();
+    //the item count in the header
+    file.seek(SeekFrom::Start(16 + 64)).unwrap();
+    file.write_all(&(huge.dataset_item_count() as u64).to_le_bytes())
+        .unwrap();
+    drop(file);
+
+    let result = dataset_cache::load(&conf, b"test key 000", &huge, &HardwareConfig::default());
+    assert_eq!(result.err().unwrap().kind(), ErrorKind::InvalidData);
+
+    fs::remove_dir_all(&conf.dir).unwrap();
+}
+
+//helper
+
+fn test_conf(name: &str, max_seeds: usize) -> DatasetCacheConfig {
+    let mut dir = std::env::temp_dir();
+    dir.push(format!(
+        "mithril_dataset_cache_{}_{}",
+        name,
+        std::process::id()
+    ));
+    DatasetCacheConfig {
+        enabled: true,
+        max_seeds,
+        dir,
+    }
+}
+
+//a memory with only the first few dataset items, a full dataset is too big for the tests
+fn test_memory(key: &[u8]) -> VmMemory {
+    let seed_memory = SeedMemory::new_initialised(key);
+    let mut dataset = PageBuffer::zeroed(TEST_ITEM_COUNT, &HardwareConfig::default());
+    for (i, item) in dataset.iter_mut().enumerate() {
+        *item = DatasetItem(init_dataset_item(&seed_memory, i as u64));
+    }
+    VmMemory {
+        seed_memory: Arc::new(seed_memory),
+        dataset_memory: dataset,
+        cache: true,
+        item_cache: None,
+        hardware_conf: HardwareConfig::default(),
+    }
+}
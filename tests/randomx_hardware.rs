@@ -0,0 +1,78 @@
+extern crate mithril;
+
+use mithril::randomx::hardware::{
+    use_hardware_aes, with_aes_mode, AesMode, DatasetInit, HardwareConfig, MemoryMode, PageBuffer,
+    PageKind,
+};
+use std::panic;
+
+#[test]
+fn test_page_buffer_normal_pages() {
+    let conf = HardwareConfig {
+        huge_pages: false,
+        huge_pages_1g: false,
+        aes: AesMode::Auto,
+        memory_mode: MemoryMode::Full,
+        hybrid_cache_mib: 512,
+        numa: false,
+        dataset_init: DatasetInit::Auto,
+    };
+    let mut buf: PageBuffer<u64> = PageBuffer::zeroed(1000, &conf);
+    assert_eq!(buf.kind(), PageKind::Normal);
+    assert_eq!(buf.len(), 1000);
+    assert!(buf.iter().all(|v| *v == 0));
+
+    buf[999] = 0xffff;
+    assert_eq!(buf[999], 0xffff);
+}
+
+#[test]
+fn test_page_buffer_allocation_failure() {
+    //more than the address space
+    let buf = PageBuffer::<u64>::try_zeroed(usize::MAX / 16, &HardwareConfig::default());
+    assert!(buf.is_err());
+}
+
+#[test]
+fn test_page_buffer_huge_pages_fallback() {
+    let mut buf: PageBuffer<u64> = PageBuffer::zeroed(262144, &HardwareConfig::default());
+    assert_eq!(buf.len(), 262144);
+    assert!(buf.iter().all(|v| *v == 0));
+
+    buf[262143] = 0xffff;
+    assert_eq!(buf[262143], 0xffff);
+}
+
+#[test]
+fn test_page_buffer_empty() {
+    let buf: PageBuffer<u64> = PageBuffer::zeroed(0, &HardwareConfig::default());
+    assert_eq!(buf.len(), 0);
+}
+
+#[test]
+fn test_page_kind_display() {
+    assert_eq!(PageKind::Huge1G.to_string(), "1GiB huge");
+    assert_eq!(PageKind::Huge2M.to_string(), "2MiB huge");
+    assert_eq!(PageKind::Transparent.to_string(), "transparent huge");
+    assert_eq!(PageKind::Normal.to_string(), "normal");
+}
+
+#[test]
+fn test_aes_mode_from_str() {
+    assert_eq!("auto".parse::<AesMode>().unwrap(), AesMode::Auto);
+    assert_eq!("hardware".parse::<AesMode>().unwrap(), AesMode::Hardware);
+    assert_eq!("software".parse::<AesMode>().unwrap(), AesMode::Software);
+    assert_eq!("aesni".parse::<AesMode>().unwrap(), AesMode::Aesni);
+    assert!("has_aes".parse::<AesMode>().is_err());
+}
+
+#[test]
+fn test_with_aes_mode_restores_mode() {
+    let hardware_aes = use_hardware_aes();
+    assert!(!with_aes_mode(AesMode::Software, use_hardware_aes));
+    assert_eq!(use_hardware_aes(), hardware_aes);
+
+    let result = panic::catch_unwind(|| with_aes_mode(AesMode::Software, || panic!("aes")));
+    assert!(result.is_err());
+    assert_eq!(use_hardware_aes(), hardware_aes);
+}
@@ -1,6 +1,9 @@
 extern crate mithril;
 
-use mithril::randomx::hash::{gen_program_aes_1rx4, gen_program_aes_4rx4};
+use mithril::randomx::hash::{
+    fill_aes_1rx4_u64, gen_program_aes_1rx4, gen_program_aes_4rx4, hash_aes_1rx4,
+    hash_and_fill_aes_1rx4,
+};
 use mithril::randomx::m128::m128i;
 
 #[test]
@@ -110,3 +113,22 @@ fn test_gen_program_aes_4rx4() {
         m128i::from_i32(0x3f7fdb2f, 0x565cd0c7, 0xbe72f8e3, 0x5da409a1)
     );
 }
+
+#[test]
+#[allow(overflowing_literals)]
+fn test_hash_and_fill_aes_1rx4() {
+    let seed0 = m128i::from_i32(0x31903876, 0xbb7a2914, 0xb370f616, 0xd6f7e4f3);
+    let seed1 = m128i::from_i32(0xb5a8ef67, 0x749809c8, 0xf349884a, 0x05c9f5ef);
+    let mut scratchpad = vec![0; 1024];
+    fill_aes_1rx4_u64(&[seed0, seed1, seed0, seed1], &mut scratchpad);
+
+    let next_seed = [seed1, seed0, seed1, seed0];
+    let expected_hash = hash_aes_1rx4(&scratchpad);
+    let mut expected_scratchpad = vec![0; 1024];
+    let expected_state = fill_aes_1rx4_u64(&next_seed, &mut expected_scratchpad);
+
+    let (hash, state) = hash_and_fill_aes_1rx4(&next_seed, &mut scratchpad);
+    assert_eq!(hash, expected_hash);
+    assert_eq!(state, expected_state);
+    assert_eq!(scratchpad, expected_scratchpad);
+}
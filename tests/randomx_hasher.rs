@@ -0,0 +1,82 @@
+extern crate mithril;
+
+use mithril::byte_string::{string_to_u8_array, u8_array_to_string};
+use mithril::randomx::memory::VmMemory;
+use mithril::randomx::vm::VmMode;
+use mithril::randomx::{RandomX, RandomXMode};
+use std::sync::Arc;
+use std::thread;
+
+fn assert_send_sync<T: Send + Sync>() {}
+
+#[test]
+fn test_randomx_is_send_sync() {
+    assert_send_sync::<RandomX>();
+    assert_send_sync::<Arc<VmMemory>>();
+}
+
+#[test]
+fn test_hash_light() {
+    let mut randomx = RandomX::new(b"test key 000", RandomXMode::Light);
+    assert_eq!(randomx.mode(), RandomXMode::Light);
+
+    let result = randomx.hash(b"This is a test");
+    assert_eq!(
+        "639183aae1bf4c9a35884cb46b09cad9175f04efd7684e7262a0ac1c2f0b4e3f",
+        u8_array_to_string(&result)
+    );
+}
+
+#[test]
+fn test_verify() {
+    let mut randomx = RandomX::new(b"test key 001", RandomXMode::Light);
+    let input = b"sed do eiusmod tempor incididunt ut labore et dolore magna aliqua";
+    let mut expected = [0; 32];
+    expected.copy_from_slice(&string_to_u8_array(
+        "e9ff4503201c0c2cca26d285c93ae883f9b1d30c9eb240b820756f2d5a7905fc",
+    ));
+
+    assert!(randomx.verify(input, &expected));
+    expected[31] ^= 1;
+    assert!(!randomx.verify(input, &expected));
+}
+
+#[test]
+fn test_clone_shares_memory_between_threads() {
+    let randomx = RandomX::new_with_vm_mode(b"test key 000", RandomXMode::Light, VmMode::Compiled);
+
+    let handles: Vec<_> = (0..2)
+        .map(|_| {
+            let mut hasher = randomx.clone();
+            assert!(Arc::ptr_eq(hasher.memory(), randomx.memory()));
+            thread::spawn(move || u8_array_to_string(&hasher.hash(b"Lorem ipsum dolor sit amet")))
+        })
+        .collect();
+
+    for handle in handles {
+        assert_eq!(
+            "300a0adb47603dedb42228ccb2b211104f4da45af709cd7547cd049e9489c969",
+            handle.join().unwrap()
+        );
+    }
+}
+
+#[test]
+fn test_from_memory() {
+    let mem = Arc::new(VmMemory::light(b"test key 000"));
+    let mut randomx = RandomX::from_memory(mem.clone(), VmMode::Interpreted);
+    assert_eq!(randomx.mode(), RandomXMode::Light);
+
+    let result = randomx.hash(b"sed do eiusmod tempor incididunt ut labore et dolore magna aliqua");
+    assert_eq!(
+        "c36d4ed4191e617309867ed66a443be4075014e2b061bcdaf9ce7b721d2b77a8",
+        u8_array_to_string(&result)
+    );
+}
+
+#[test]
+fn test_randomx_mode_from_str() {
+    assert_eq!("light".parse::<RandomXMode>().unwrap(), RandomXMode::Light);
+    assert_eq!("full".parse::<RandomXMode>().unwrap(), RandomXMode::Full);
+    assert!("fast".parse::<RandomXMode>().is_err());
+}
@@ -0,0 +1,138 @@
+extern crate mithril;
+
+use mithril::randomx::hardware::{parse_mem_available, HardwareConfig, MemoryMode};
+use mithril::randomx::item_cache::ItemCache;
+use mithril::randomx::memory::{admit_worker_threads, select_memory_mode, VmMemory};
+use mithril::randomx::params::RX_0;
+use mithril::randomx::vm::new_vm;
+use std::sync::Arc;
+
+const MIB: u64 = 1024 * 1024;
+
+#[test]
+fn test_item_cache_get_insert() {
+    let cache = ItemCache::new(16);
+    assert_eq!(cache.get(3), None);
+
+    cache.insert(3, &[1, 2, 3, 4, 5, 6, 7, 8]);
+    assert_eq!(cache.get(3), Some([1, 2, 3, 4, 5, 6, 7, 8]));
+    //a different item of the same slot
+    assert_eq!(cache.get(19), None);
+
+    assert_eq!(cache.hits(), 1);
+    assert_eq!(cache.misses(), 2);
+}
+
+#[test]
+fn test_item_cache_evicts_item_of_same_slot() {
+    let cache = ItemCache::new(16);
+    cache.insert(3, &[1; 8]);
+    cache.insert(4, &[2; 8]);
+    cache.insert(19, &[3; 8]);
+
+    assert_eq!(cache.get(3), None);
+    assert_eq!(cache.get(4), Some([2; 8]));
+    assert_eq!(cache.get(19), Some([3; 8]));
+}
+
+#[test]
+fn test_item_cache_with_size() {
+    assert_eq!(ItemCache::with_size(72 * 1000).slots(), 1000);
+    assert_eq!(ItemCache::with_size(0).slots(), 1);
+}
+
+#[test]
+fn test_item_cache_allocation_failure() {
+    assert!(ItemCache::try_with_size(usize::MAX / 2).is_err());
+}
+
+#[test]
+fn test_hybrid_memory_hash() {
+    let mem = VmMemory::hybrid_with_config(
+        b"test key 000",
+        &RX_0,
+        MIB as usize,
+        &HardwareConfig::default(),
+    );
+    let mut vm = new_vm(Arc::new(mem));
+
+    //the second hash reads the same dataset items, now (partly) from the cache
+    for _ in 0..2 {
+        let hash = vm.calculate_hash(b"This is a test").to_hex();
+        assert_eq!(
+            hash.as_str(),
+            "639183aae1bf4c9a35884cb46b09cad9175f04efd7684e7262a0ac1c2f0b4e3f"
+        );
+    }
+    let item_cache = vm.mem.item_cache.as_ref().unwrap();
+    assert!(item_cache.hits() > 0);
+}
+
+#[test]
+fn test_select_memory_mode() {
+    let conf = HardwareConfig {
+        memory_mode: MemoryMode::Auto,
+        hybrid_cache_mib: 512,
+        ..HardwareConfig::default()
+    };
+    //256MiB seed memory, 2080MiB dataset and 256MiB headroom
+    assert_eq!(
+        select_memory_mode(&conf, &RX_0, Some(2592 * MIB)),
+        MemoryMode::Full
+    );
+    assert_eq!(
+        select_memory_mode(&conf, &RX_0, Some(2591 * MIB)),
+        MemoryMode::Hybrid
+    );
+    assert_eq!(
+        select_memory_mode(&conf, &RX_0, Some(1024 * MIB)),
+        MemoryMode::Hybrid
+    );
+    assert_eq!(
+        select_memory_mode(&conf, &RX_0, Some(1023 * MIB)),
+        MemoryMode::Light
+    );
+    assert_eq!(select_memory_mode(&conf, &RX_0, None), MemoryMode::Full);
+
+    let small_cache = HardwareConfig {
+        hybrid_cache_mib: 64,
+        ..conf
+    };
+    assert_eq!(
+        select_memory_mode(&small_cache, &RX_0, Some(576 * MIB)),
+        MemoryMode::Hybrid
+    );
+    assert_eq!(
+        select_memory_mode(&small_cache, &RX_0, Some(575 * MIB)),
+        MemoryMode::Light
+    );
+}
+
+#[test]
+fn test_select_memory_mode_configured() {
+    let conf = HardwareConfig {
+        memory_mode: MemoryMode::Light,
+        ..HardwareConfig::default()
+    };
+    assert_eq!(
+        select_memory_mode(&conf, &RX_0, Some(64 * 1024 * MIB)),
+        MemoryMode::Light
+    );
+}
+
+#[test]
+fn test_admit_worker_threads() {
+    //2MiB scratchpads and 256MiB headroom
+    assert_eq!(admit_worker_threads(8, &RX_0, Some(1024 * MIB)), 8);
+    assert_eq!(admit_worker_threads(8, &RX_0, Some(263 * MIB)), 3);
+    assert_eq!(admit_worker_threads(8, &RX_0, Some(100 * MIB)), 1);
+    assert_eq!(admit_worker_threads(8, &RX_0, None), 8);
+}
+
+#[test]
+fn test_parse_mem_available() {
+    let meminfo = "MemTotal:       16316412 kB\nMemFree:         1217716 kB\n\
+                   MemAvailable:    9512348 kB\nBuffers:          436260 kB\n";
+    assert_eq!(parse_mem_available(meminfo), Some(9512348 * 1024));
+    assert_eq!(parse_mem_available("MemTotal: 16316412 kB\n"), None);
+}
@@ -0,0 +1,120 @@
+extern crate blake2b_simd;
+extern crate lazy_static;
+extern crate mithril;
+
+use self::blake2b_simd::blake2b;
+use lazy_static::lazy_static;
+use mithril::byte_string::{string_to_u8_array, u8_array_to_string};
+use mithril::randomx::jit::JitCompiler;
+use mithril::randomx::memory::VmMemory;
+use mithril::randomx::vm::{hash_to_m128i_array, new_vm, new_vm_with_mode, VmMode};
+use std::sync::Arc;
+
+lazy_static! {
+    static ref TEST_MEM_000: Arc<VmMemory> = Arc::new(VmMemory::light(b"test key 000"));
+}
+
+#[test]
+#[cfg(all(target_arch = "x86_64", unix))]
+fn test_new_vm_with_mode_compiled_has_jit() {
+    let vm = new_vm_with_mode(TEST_MEM_000.clone(), VmMode::Compiled);
+    assert!(vm.jit.is_some());
+
+    let vm = new_vm_with_mode(TEST_MEM_000.clone(), VmMode::Interpreted);
+    assert!(vm.jit.is_none());
+}
+
+//the vm falls back to the interpreter
+#[test]
+#[cfg(not(all(target_arch = "x86_64", unix)))]
+fn test_new_vm_with_mode_compiled_has_no_jit() {
+    let vm = new_vm_with_mode(TEST_MEM_000.clone(), VmMode::Compiled);
+    assert!(vm.jit.is_none());
+}
+
+#[test]
+#[cfg(all(target_arch = "x86_64", unix))]
+fn test_jit_compiler_new() {
+    assert!(JitCompiler::new().is_some());
+}
+
+#[test]
+#[cfg(not(all(target_arch = "x86_64", unix)))]
+fn test_jit_compiler_new_unsupported() {
+    assert!(JitCompiler::new().is_none());
+}
+
+#[test]
+fn test_run_compiled_equals_interpreted() {
+    let mut vm_interpreted = new_vm(TEST_MEM_000.clone());
+    let mut vm_compiled = new_vm_with_mode(TEST_MEM_000.clone(), VmMode::Compiled);
+
+    for input in &["This is a test", "Lorem ipsum dolor sit amet", "jit"] {
+        let seed = hash_to_m128i_array(&blake2b(input.as_bytes()));
+        vm_interpreted.init_scratchpad(&seed);
+        vm_compiled.init_scratchpad(&seed);
+
+        vm_interpreted.reset_rounding_mode();
+        vm_interpreted.run(&seed);
+        vm_compiled.reset_rounding_mode();
+        vm_compiled.run(&seed);
+
+        assert_eq!(
+            &vm_interpreted.reg.to_bytes()[..],
+            &vm_compiled.reg.to_bytes()[..]
+        );
+        assert!(vm_interpreted.scratchpad[..] == vm_compiled.scratchpad[..]);
+    }
+}
+
+#[test]
+fn test_calculate_hash_1_compiled_with_light_memory() {
+    let mut vm = new_vm_with_mode(TEST_MEM_000.clone(), VmMode::Compiled);
+    let result = vm.calculate_hash(b"This is a test");
+    assert_eq!(
+        "639183aae1bf4c9a35884cb46b09cad9175f04efd7684e7262a0ac1c2f0b4e3f",
+        u8_array_to_string(result.as_bytes())
+    );
+
+    let result = vm.calculate_hash(b"Lorem ipsum dolor sit amet");
+    assert_eq!(
+        "300a0adb47603dedb42228ccb2b211104f4da45af709cd7547cd049e9489c969",
+        u8_array_to_string(result.as_bytes())
+    );
+
+    let result =
+        vm.calculate_hash(b"sed do eiusmod tempor incididunt ut labore et dolore magna aliqua");
+    assert_eq!(
+        "c36d4ed4191e617309867ed66a443be4075014e2b061bcdaf9ce7b721d2b77a8",
+        u8_array_to_string(result.as_bytes())
+    );
+}
+
+#[test]
+fn test_calculate_hash_2_compiled_with_light_memory() {
+    let mut vm = new_vm_with_mode(Arc::new(VmMemory::light(b"test key 001")), VmMode::Compiled);
+
+    let result =
+        vm.calculate_hash(b"sed do eiusmod tempor incididunt ut labore et dolore magna aliqua");
+    assert_eq!(
+        "e9ff4503201c0c2cca26d285c93ae883f9b1d30c9eb240b820756f2d5a7905fc",
+        u8_array_to_string(result.as_bytes())
+    );
+
+    let seed = string_to_u8_array("0b0b98bea7e805e0010a2126d287a2a0cc833d312cb786385a7c2f9de69d25537f584a9bc9977b00000000666fd8753bf61a8631f12984e3fd44f4014eca629276817b56f32e9b68bd82f416");
+    let result = vm.calculate_hash(&seed);
+    assert_eq!(
+        "c56414121acda1713c2f2a819d8ae38aed7c80c35c2a769298d34f03833cd5f1",
+        u8_array_to_string(result.as_bytes())
+    );
+}
+
+#[test]
+fn test_vm_mode_from_str() {
+    assert_eq!("compiled".parse::<VmMode>().unwrap(), VmMode::Compiled);
+    assert_eq!(
+        "interpreted".parse::<VmMode>().unwrap(),
+        VmMode::Interpreted
+    );
+    assert!("jit".parse::<VmMode>().is_err());
+}
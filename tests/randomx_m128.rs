@@ -1,6 +1,8 @@
 extern crate mithril;
 
-use mithril::randomx::m128::{m128d, m128i};
+use mithril::randomx::m128::{
+    m128d, m128i, set_rounding_mode, ROUND_DOWN, ROUND_TO_NEAREST, ROUND_TO_ZERO, ROUND_UP,
+};
 
 #[test]
 #[allow(overflowing_literals)]
@@ -168,3 +170,58 @@ fn test_m128_sqrt() {
         m128d::from_u64(0x40d30e573fa3ba8d, 0x40212a610b301fe8)
     );
 }
+
+#[test]
+fn test_m128d_add_rounding_modes() {
+    let one = m128d::from_f64(-1.0, 1.0);
+    let tiny = m128d::from_f64(-1e-20, 1e-20);
+
+    set_rounding_mode(ROUND_TO_NEAREST);
+    assert_eq!(one + tiny, m128d::from_f64(-1.0, 1.0));
+    set_rounding_mode(ROUND_UP);
+    assert_eq!(
+        one + tiny,
+        m128d::from_u64(0xbff0000000000000, 0x3ff0000000000001)
+    );
+    set_rounding_mode(ROUND_DOWN);
+    assert_eq!(
+        one + tiny,
+        m128d::from_u64(0xbff0000000000001, 0x3ff0000000000000)
+    );
+    set_rounding_mode(ROUND_TO_ZERO);
+    assert_eq!(one + tiny, m128d::from_f64(-1.0, 1.0));
+    set_rounding_mode(ROUND_TO_NEAREST);
+}
+
+#[test]
+fn test_m128d_div_rounding_modes() {
+    let one = m128d::from_f64(-1.0, 1.0);
+    let three = m128d::from_f64(3.0, 3.0);
+
+    set_rounding_mode(ROUND_TO_NEAREST);
+    assert_eq!(
+        one / three,
+        m128d::from_u64(0xbfd5555555555555, 0x3fd5555555555555)
+    );
+    set_rounding_mode(ROUND_UP);
+    assert_eq!(
+        one / three,
+        m128d::from_u64(0xbfd5555555555555, 0x3fd5555555555556)
+    );
+    set_rounding_mode(ROUND_DOWN);
+    assert_eq!(
+        one / three,
+        m128d::from_u64(0xbfd5555555555556, 0x3fd5555555555555)
+    );
+    set_rounding_mode(ROUND_TO_NEAREST);
+}
+
+#[test]
+fn test_m128d_overflow_rounding_modes() {
+    let max = m128d::from_f64(f64::MAX, -f64::MAX);
+
+    set_rounding_mode(ROUND_TO_ZERO);
+    assert_eq!(max + max, max);
+    set_rounding_mode(ROUND_TO_NEAREST);
+    assert_eq!(max + max, m128d::from_f64(f64::INFINITY, f64::NEG_INFINITY));
+}
@@ -2,7 +2,25 @@ extern crate lazy_static;
 extern crate mithril;
 
 use lazy_static::lazy_static;
-use mithril::randomx::memory::{init_dataset_item, SeedMemory};
+use mithril::byte_string::u8_array_to_string;
+use mithril::randomx::dataset_cache::{self, DatasetCacheConfig};
+use mithril::randomx::hardware::{has_avx2, DatasetInit, HardwareConfig, PageBuffer};
+use mithril::randomx::memory::{
+    dataset_init_executor, init_dataset, init_dataset_item, init_dataset_item_interpreted,
+    init_dataset_items_batched, DatasetItem, SeedMemory, VmMemory, VmMemoryAllocator,
+};
+use mithril::randomx::numa::{NumaNode, NumaTopology};
+use mithril::randomx::params::{RandomXParams, RX_0};
+use std::fs;
+use std::sync::Arc;
+
+//the parameters of rx/0 with a dataset of 128 items, so the allocator tests do not
+//need a full dataset
+const SMALL_DATASET_PARAMS: RandomXParams = RandomXParams {
+    dataset_base_size: 128 * 64,
+    dataset_extra_size: 0,
+    ..RX_0
+};
 
 lazy_static! {
     static ref TEST_SEED_MEM: SeedMemory = SeedMemory::new_initialised(b"test key 000");
@@ -29,3 +47,220 @@ fn test_init_dataset_item() {
     let item = init_dataset_item(&TEST_SEED_MEM, 30000000);
     assert_eq!(item[0], 0x145a5091f7853099);
 }
+
+#[test]
+#[cfg(all(target_arch = "x86_64", not(feature = "portable")))]
+fn test_init_dataset_items_batched() {
+    //otherwise the items are computed by the interpreter
+    if !has_avx2() {
+        eprintln!("skipping, the cpu has no AVX2");
+        return;
+    }
+
+    let mut items = [DatasetItem([0; 8]); 4];
+    init_dataset_items_batched(&TEST_SEED_MEM, 10000000, &mut items);
+    assert_eq!(items[0].0[0], 0x7943a1f6186ffb72);
+
+    //the batches of four items and a remainder
+    let last_item = RX_0.dataset_item_count() as u64 - 1;
+    for start_item in &[0, 10000000, 20000001, last_item - 6] {
+        let mut items = [DatasetItem([0; 8]); 7];
+        init_dataset_items_batched(&TEST_SEED_MEM, *start_item, &mut items);
+        for (i, item) in items.iter().enumerate() {
+            assert_eq!(
+                item.0,
+                init_dataset_item(&TEST_SEED_MEM, start_item + i as u64)
+            );
+        }
+    }
+}
+
+#[test]
+fn test_dataset_init_executor() {
+    let compiled = TEST_SEED_MEM.compiled.is_some();
+    let fallback = if has_avx2() {
+        DatasetInit::Avx2
+    } else {
+        DatasetInit::Interpreted
+    };
+    let auto = if compiled {
+        DatasetInit::Compiled
+    } else {
+        fallback
+    };
+    assert_eq!(
+        dataset_init_executor(&TEST_SEED_MEM, DatasetInit::Auto),
+        auto
+    );
+    assert_eq!(
+        dataset_init_executor(&TEST_SEED_MEM, DatasetInit::Compiled),
+        auto
+    );
+    assert_eq!(
+        dataset_init_executor(&TEST_SEED_MEM, DatasetInit::Avx2),
+        fallback
+    );
+    assert_eq!(
+        dataset_init_executor(&SeedMemory::no_memory(), DatasetInit::Auto),
+        fallback
+    );
+    assert_eq!(
+        dataset_init_executor(&TEST_SEED_MEM, DatasetInit::Interpreted),
+        DatasetInit::Interpreted
+    );
+}
+
+#[test]
+fn test_init_dataset_executors_equal() {
+    let expected: Vec<[u64; 8]> = (0..70)
+        .map(|i| init_dataset_item_interpreted(&TEST_SEED_MEM, i))
+        .collect();
+    for executor in &[
+        DatasetInit::Auto,
+        DatasetInit::Compiled,
+        DatasetInit::Avx2,
+        DatasetInit::Interpreted,
+    ] {
+        let mut dataset = [DatasetItem([0; 8]); 70];
+        init_dataset(&TEST_SEED_MEM, &mut dataset, 3, *executor);
+        for (item, expected) in dataset.iter().zip(expected.iter()) {
+            assert_eq!(item.0, *expected, "{}", executor);
+        }
+    }
+}
+
+#[test]
+fn test_dataset_item_is_cache_line_aligned() {
+    assert_eq!(std::mem::size_of::<DatasetItem>(), 64);
+    assert_eq!(std::mem::align_of::<DatasetItem>(), 64);
+}
+
+#[test]
+fn test_allocator_builds_memory_in_background() {
+    let conf = cached_test_conf("background", &[b"test key 000"]);
+    let seed = u8_array_to_string(b"test key 000");
+    let mut allocator = VmMemoryAllocator::initial(
+        HardwareConfig::default(),
+        conf.clone(),
+        SMALL_DATASET_PARAMS,
+    );
+
+    assert!(allocator.memory_for(&seed).is_none());
+    assert!(allocator.memory_for(&seed).is_none());
+
+    let built = allocator.built_memory().recv().unwrap();
+    assert_eq!(built.seed, seed);
+    allocator.memory_built(built);
+    assert!(allocator.built_memory().is_empty());
+
+    let mem = allocator.memory_for(&seed).unwrap();
+    assert_eq!(mem.dataset_memory.len(), 128);
+    assert_eq!(allocator.vm_memory_seed, seed);
+
+    fs::remove_dir_all(&conf.dir).unwrap();
+}
+
+#[test]
+fn test_allocator_prepares_next_seed() {
+    let conf = cached_test_conf("next_seed", &[b"test key 000", b"test key 001"]);
+    let seed = u8_array_to_string(b"test key 000");
+    let next_seed = u8_array_to_string(b"test key 001");
+    let mut allocator = VmMemoryAllocator::initial(
+        HardwareConfig::default(),
+        conf.clone(),
+        SMALL_DATASET_PARAMS,
+    );
+    allocator.reallocate(seed.clone());
+    let current = allocator.vm_memory.clone();
+
+    allocator.prepare(&next_seed);
+    let built = allocator.built_memory().recv().unwrap();
+    allocator.memory_built(built);
+
+    //the old memory keeps serving jobs of the old seed until a job needs the new one
+    assert!(Arc::ptr_eq(&allocator.memory_for(&seed).unwrap(), &current));
+    assert_eq!(allocator.vm_memory_seed, seed);
+
+    let next = allocator.memory_for(&next_seed).unwrap();
+    assert!(!Arc::ptr_eq(&next, &current));
+    assert_eq!(allocator.vm_memory_seed, next_seed);
+    assert!(allocator.next_vm_memory.is_none());
+
+    fs::remove_dir_all(&conf.dir).unwrap();
+}
+
+#[test]
+fn test_allocator_builds_replica_per_numa_node() {
+    let conf = cached_test_conf("numa", &[b"test key 000"]);
+    let seed = u8_array_to_string(b"test key 000");
+    let mut allocator = VmMemoryAllocator::initial(
+        HardwareConfig::default(),
+        conf.clone(),
+        SMALL_DATASET_PARAMS,
+    );
+    //two nodes on cpu 0, so the pinning works on every machine
+    allocator.numa = NumaTopology {
+        nodes: vec![
+            NumaNode {
+                id: 0,
+                cpus: vec![0],
+            },
+            NumaNode {
+                id: 1,
+                cpus: vec![0],
+            },
+        ],
+    };
+    allocator.reallocate(seed);
+
+    let node0 = allocator.memory_for_node(0);
+    let node1 = allocator.memory_for_node(1);
+    assert!(Arc::ptr_eq(&node0, &allocator.vm_memory));
+    assert!(!Arc::ptr_eq(&node0, &node1));
+    assert_eq!(node1.dataset_memory.len(), 128);
+    for i in 0..128 {
+        assert_eq!(node1.dataset_memory[i].0, node0.dataset_memory[i].0);
+    }
+    assert_eq!(node1.seed_memory.blocks[12253][29], 0xf1b62fe6210bf8b1);
+    //only the dataset is copied
+    assert!(Arc::ptr_eq(&node1.seed_memory, &node0.seed_memory));
+    //nodes without a replica use the memory of the first node
+    assert!(Arc::ptr_eq(&allocator.memory_for_node(2), &node0));
+
+    fs::remove_dir_all(&conf.dir).unwrap();
+}
+
+//helper
+
+//a dataset cache with a small memory stored for each key, so the allocator
+//loads it instead of initialising a full dataset
+fn cached_test_conf(name: &str, keys: &[&[u8]]) -> DatasetCacheConfig {
+    let mut dir = std::env::temp_dir();
+    dir.push(format!("mithril_allocator_{}_{}", name, std::process::id()));
+    let conf = DatasetCacheConfig {
+        enabled: true,
+        max_seeds: keys.len(),
+        dir,
+    };
+
+    let mut dataset = PageBuffer::zeroed(128, &HardwareConfig::default());
+    for (i, item) in dataset.iter_mut().enumerate() {
+        *item = DatasetItem(init_dataset_item(&TEST_SEED_MEM, i as u64));
+    }
+    let mem = VmMemory {
+        seed_memory: Arc::new(SeedMemory::from_blocks(
+            b"test key 000",
+            TEST_SEED_MEM.blocks.clone(),
+            &SMALL_DATASET_PARAMS,
+        )),
+        dataset_memory: dataset,
+        cache: true,
+        item_cache: None,
+        hardware_conf: HardwareConfig::default(),
+    };
+    //the content does not matter for the allocator, all keys get the same memory
+    for key in keys {
+        dataset_cache::store(&conf, key, &mem).unwrap();
+    }
+    conf
+}
@@ -0,0 +1,92 @@
+extern crate mithril;
+
+use mithril::randomx::numa::{self, parse_cpu_list, NumaNode, NumaTopology};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[test]
+fn test_parse_cpu_list() {
+    assert_eq!(parse_cpu_list("0\n"), Some(vec![0]));
+    assert_eq!(parse_cpu_list("0-3"), Some(vec![0, 1, 2, 3]));
+    assert_eq!(parse_cpu_list("0-1,8-9,12"), Some(vec![0, 1, 8, 9, 12]));
+    assert_eq!(parse_cpu_list("\n"), Some(vec![]));
+    assert_eq!(parse_cpu_list("3-1"), None);
+    assert_eq!(parse_cpu_list("a-b"), None);
+}
+
+#[test]
+fn test_detect_nodes() {
+    let root = mock_sysfs(
+        "two_nodes",
+        &[
+            ("node1", "4-7,12-15\n"),
+            ("node0", "0-3,8-11\n"),
+            ("node2", "\n"),
+        ],
+    );
+
+    let topology = numa::detect(&root);
+    assert_eq!(
+        topology,
+        NumaTopology {
+            nodes: vec![
+                NumaNode {
+                    id: 0,
+                    cpus: vec![0, 1, 2, 3, 8, 9, 10, 11],
+                },
+                //node 2 has only memory
+                NumaNode {
+                    id: 1,
+                    cpus: vec![4, 5, 6, 7, 12, 13, 14, 15],
+                },
+            ],
+        }
+    );
+    assert!(topology.is_numa());
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn test_detect_without_numa() {
+    let topology = numa::detect(Path::new("/nonexistent/sysfs"));
+    assert_eq!(topology, NumaTopology::single_node());
+    assert!(!topology.is_numa());
+}
+
+#[test]
+fn test_worker_placement() {
+    let topology = NumaTopology {
+        nodes: vec![
+            NumaNode {
+                id: 0,
+                cpus: vec![0, 1],
+            },
+            NumaNode {
+                id: 1,
+                cpus: vec![2, 3],
+            },
+        ],
+    };
+    let placements: Vec<(usize, usize)> = (0..6).map(|w| topology.worker_placement(w)).collect();
+    assert_eq!(
+        placements,
+        vec![(0, 0), (1, 2), (0, 1), (1, 3), (0, 0), (1, 2)]
+    );
+}
+
+//helper
+
+//a sysfs tree with a cpulist for each node
+fn mock_sysfs(name: &str, nodes: &[(&str, &str)]) -> PathBuf {
+    let mut root = std::env::temp_dir();
+    root.push(format!("mithril_sysfs_{}_{}", name, std::process::id()));
+    let node_dir = root.join("devices/system/node");
+    fs::create_dir_all(&node_dir).unwrap();
+    fs::write(node_dir.join("possible"), "0-2\n").unwrap();
+    for (node, cpulist) in nodes {
+        fs::create_dir_all(node_dir.join(node)).unwrap();
+        fs::write(node_dir.join(node).join("cpulist"), cpulist).unwrap();
+    }
+    root
+}
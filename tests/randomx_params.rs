@@ -0,0 +1,212 @@
+extern crate mithril;
+
+use mithril::byte_string::u8_array_to_string;
+use mithril::randomx::memory::VmMemory;
+use mithril::randomx::params::{
+    InstrFrequencies, RandomXParams, RandomXVariant, RX_0, RX_ARQ, RX_KEVA, RX_LOKI, RX_SFX, RX_WOW,
+};
+use mithril::randomx::program::{Decoder, Opcode, MAX_REG};
+use mithril::randomx::vm::VmMode;
+use mithril::randomx::{RandomX, RandomXMode};
+use std::sync::Arc;
+
+#[test]
+fn test_presets_are_valid() {
+    for params in &[RX_0, RX_WOW, RX_ARQ, RX_LOKI, RX_SFX, RX_KEVA] {
+        assert_eq!(params.validate(), Ok(()), "{:?}", params);
+    }
+}
+
+#[test]
+fn test_invalid_frequencies() {
+    let mut params = RX_0;
+    params.frequencies.nop = 1;
+    assert!(params.validate().is_err());
+}
+
+#[test]
+fn test_invalid_scratchpad() {
+    let params = RandomXParams {
+        scratchpad_l2: RX_0.scratchpad_l3 * 2,
+        ..RX_0
+    };
+    assert!(params.validate().is_err());
+}
+
+#[test]
+fn test_rx_0_derived_values() {
+    assert_eq!(RX_0.dataset_item_count(), 34078719);
+    assert_eq!(RX_0.dataset_extra_items(), 524287);
+    assert_eq!(RX_0.cache_line_mask(), 4194303);
+    assert_eq!(RX_0.scratchpad_l1_mask(), 0x3ff8);
+    assert_eq!(RX_0.scratchpad_l2_mask(), 0x3fff8);
+    assert_eq!(RX_0.scratchpad_l3_mask(), 0x1ffff8);
+    assert_eq!(RX_0.program_blocks(), 136);
+    assert_eq!(RandomXParams::default(), RX_0);
+}
+
+#[test]
+fn test_variant_from_str() {
+    assert_eq!(
+        "rx/0".parse::<RandomXVariant>().unwrap(),
+        RandomXVariant::Rx0
+    );
+    assert_eq!(
+        "rx/wow".parse::<RandomXVariant>().unwrap(),
+        RandomXVariant::Wow
+    );
+    assert_eq!(
+        "rx/loki".parse::<RandomXVariant>().unwrap(),
+        RandomXVariant::Loki
+    );
+    assert!("rx/1".parse::<RandomXVariant>().is_err());
+
+    assert_eq!(RandomXVariant::Arq.to_string(), "rx/arq");
+    assert_eq!(RandomXVariant::Keva.params(), RX_KEVA);
+}
+
+#[test]
+fn test_decode_with_variant_frequencies() {
+    //0x10 is the first IADD_M opcode of rx/0, RandomWOW has 25 IADD_RS opcodes
+    let bytes = 0x10;
+    let mut register_usage = [-1; MAX_REG];
+    let instr = Decoder::new(&RX_0).decode_instruction(bytes, 0, &mut register_usage);
+    assert_eq!(instr.op, Opcode::IADD_M);
+    let instr = Decoder::new(&RX_WOW).decode_instruction(bytes, 0, &mut register_usage);
+    assert_eq!(instr.op, Opcode::IADD_RS);
+}
+
+#[test]
+fn test_decode_with_variant_scratchpad() {
+    //ISUB_M r0, L3[imm] (src == dst)
+    let bytes = 0x2a | (0x7fffffffi64 << 32);
+    let mut register_usage = [-1; MAX_REG];
+    let instr = Decoder::new(&RX_0).decode_instruction(bytes, 0, &mut register_usage);
+    assert_eq!(instr.op, Opcode::ISUB_M);
+    assert_eq!(u64::from(instr.mem_mask), RX_0.scratchpad_l3_mask());
+    assert_eq!(instr.to_string(), "ISUB_M r0, L3[2097144]");
+
+    let instr = Decoder::new(&RX_ARQ).decode_instruction(bytes, 0, &mut register_usage);
+    assert_eq!(u64::from(instr.mem_mask), RX_ARQ.scratchpad_l3_mask());
+    assert_eq!(instr.to_string(), "ISUB_M r0, L3[262136]");
+}
+
+#[test]
+#[should_panic]
+fn test_decoder_rejects_invalid_frequencies() {
+    let mut params = RX_0;
+    params.frequencies.cbranch = 0;
+    Decoder::new(&params);
+}
+
+#[test]
+fn test_loki_frequencies() {
+    //RandomXL swaps the frequencies of IADD_RS and CBRANCH
+    assert_eq!(RX_LOKI.frequencies.iadd_rs, 25);
+    assert_eq!(RX_LOKI.frequencies.cbranch, 16);
+}
+
+#[test]
+fn test_presets_match_fork_configurations() {
+    //the differences to rx/0 as configured for the forks in xmrig (RandomX_ConfigurationWownero,
+    //..Arqma, ..Loki, ..Safex and ..Keva), the hashing itself is checked by the rx/0 vectors
+    let expected = [
+        (
+            RX_WOW,
+            RandomXParams {
+                argon_salt: b"RandomWOW\x01",
+                program_iterations: 1024,
+                program_count: 16,
+                scratchpad_l2: 131072,
+                scratchpad_l3: 1048576,
+                frequencies: InstrFrequencies {
+                    iadd_rs: 25,
+                    iror_r: 10,
+                    irol_r: 0,
+                    fswap_r: 8,
+                    fadd_r: 20,
+                    fsub_r: 20,
+                    fmul_r: 20,
+                    cbranch: 16,
+                    ..RX_0.frequencies
+                },
+                ..RX_0
+            },
+        ),
+        (
+            RX_ARQ,
+            RandomXParams {
+                argon_iterations: 1,
+                argon_salt: b"RandomARQ\x01",
+                program_iterations: 1024,
+                program_count: 4,
+                scratchpad_l2: 131072,
+                scratchpad_l3: 262144,
+                ..RX_0
+            },
+        ),
+        (
+            RX_LOKI,
+            RandomXParams {
+                argon_iterations: 4,
+                argon_lanes: 2,
+                argon_salt: b"RandomXL\x12",
+                program_size: 320,
+                program_count: 7,
+                frequencies: InstrFrequencies {
+                    iadd_rs: 25,
+                    cbranch: 16,
+                    ..RX_0.frequencies
+                },
+                ..RX_0
+            },
+        ),
+        (
+            RX_SFX,
+            RandomXParams {
+                argon_salt: b"RandomSFX\x01",
+                ..RX_0
+            },
+        ),
+        (
+            RX_KEVA,
+            RandomXParams {
+                argon_salt: b"RandomKV\x01",
+                scratchpad_l2: 131072,
+                scratchpad_l3: 1048576,
+                ..RX_0
+            },
+        ),
+    ];
+    for (params, fork) in expected.iter() {
+        assert_eq!(params, fork);
+    }
+}
+
+#[test]
+fn test_variants_hash_differently() {
+    let mem_0 = Arc::new(VmMemory::light(b"test key 000"));
+    let mut rx_0 = RandomX::from_memory(mem_0, VmMode::Interpreted);
+    let mut wow = RandomX::new_with_params(
+        b"test key 000",
+        RandomXMode::Light,
+        VmMode::Interpreted,
+        &RX_WOW,
+    );
+    assert_ne!(rx_0.hash(b"This is a test"), wow.hash(b"This is a test"));
+}
+
+#[test]
+fn test_variant_compiled_equals_interpreted() {
+    //RandomXL covers two argon lanes and a program size other than 256
+    for params in &[RX_LOKI, RX_ARQ] {
+        let mem = Arc::new(VmMemory::light_with_params(b"test key 001", params));
+        let mut interpreted = RandomX::from_memory(mem.clone(), VmMode::Interpreted);
+        let mut compiled = RandomX::from_memory(mem, VmMode::Compiled);
+        let input = b"Lorem ipsum dolor sit amet";
+        assert_eq!(
+            u8_array_to_string(&interpreted.hash(input)),
+            u8_array_to_string(&compiled.hash(input))
+        );
+    }
+}
@@ -4,13 +4,13 @@ extern crate difference;
 
 use mithril::randomx::hash::{gen_program_aes_1rx4, gen_program_aes_4rx4};
 use mithril::randomx::m128::m128i;
-use mithril::randomx::program::{decode_instruction, Opcode, Program, MAX_REG};
+use mithril::randomx::program::{Decoder, Opcode, Program, MAX_REG};
 
 #[test]
 fn test_decode_instruction_imul_rcp() {
-    let bytes = (Opcode::IMUL_RCP as i64) - 1; //imm = 0
+    let bytes = 0x53; //last IMUL_RCP opcode of rx/0, imm = 0
     let mut register_usage = [-1; MAX_REG];
-    let instr = decode_instruction(bytes, 6, &mut register_usage);
+    let instr = Decoder::default().decode_instruction(bytes, 6, &mut register_usage);
 
     assert_eq!(instr.op, Opcode::IMUL_RCP);
     assert_eq!(register_usage[0], -1);
@@ -128,6 +128,36 @@ fn test_decode_program_2_4rx4_and_register_usage() {
     assert_eq!(program.register_usage[7], 254);
 }
 
+#[test]
+fn test_decode_program_reuse() {
+    let mut program = Program::from_bytes(gen_test_program_1_4rx4_with_register_usage());
+    program.decode(
+        &gen_test_program_2_4rx4_with_register_usage(),
+        &Decoder::default(),
+    );
+
+    let expected = Program::from_bytes(gen_test_program_2_4rx4_with_register_usage());
+    assert_eq!(program.instrs(), expected.instrs());
+    assert_eq!(program.entropy, expected.entropy);
+    assert_eq!(program.register_usage, expected.register_usage);
+}
+
+#[test]
+fn test_assemble_disassembled_programs() {
+    for bytes in [
+        gen_test_program_nonce_1000(),
+        gen_test_program_nonce_1002(),
+        gen_test_program_nonce_666(),
+        gen_test_program_1_4rx4_with_register_usage(),
+        gen_test_program_2_4rx4_with_register_usage(),
+    ] {
+        let program = Program::from_bytes(bytes);
+        let assembled: Program = program.to_string().parse().unwrap();
+        assert_eq!(assembled.instrs(), program.instrs());
+        assert_eq!(assembled.register_usage, program.register_usage);
+    }
+}
+
 //helper
 
 #[allow(overflowing_literals)]
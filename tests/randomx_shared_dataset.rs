@@ -0,0 +1,140 @@
+extern crate lazy_static;
+extern crate mithril;
+
+use lazy_static::lazy_static;
+use mithril::randomx::hardware::{HardwareConfig, PageBuffer, PageKind};
+use mithril::randomx::memory::{init_dataset_item, DatasetItem, SeedMemory, VmMemory};
+use mithril::randomx::params::{RandomXParams, RX_0};
+use mithril::randomx::shared_dataset::{self, SharedDatasetConfig};
+use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+const TEST_ITEM_COUNT: usize = 128;
+//the parameters of rx/0 with a dataset of TEST_ITEM_COUNT items
+const TEST_PARAMS: RandomXParams = RandomXParams {
+    dataset_base_size: TEST_ITEM_COUNT as u64 * 64,
+    dataset_extra_size: 0,
+    ..RX_0
+};
+const TEST_KEY: &[u8] = b"test key 000";
+
+lazy_static! {
+    static ref TEST_SEED_MEM: SeedMemory = SeedMemory::new_initialised(TEST_KEY);
+}
+
+#[test]
+fn test_first_process_publishes_others_map() {
+    let conf = test_conf("publish");
+    let builds = AtomicUsize::new(0);
+
+    let mem = load_or_build(&conf, &builds);
+    assert_eq!(builds.load(Ordering::SeqCst), 1);
+    assert!(mem.cache);
+    assert_eq!(mem.dataset_memory.kind(), PageKind::SharedFile);
+
+    //a second process finds the published dataset and does not build it again
+    let shared = load_or_build(&conf, &builds);
+    assert_eq!(builds.load(Ordering::SeqCst), 1);
+    assert_eq!(shared.dataset_memory.kind(), PageKind::SharedFile);
+    assert_eq!(shared.dataset_memory.len(), TEST_ITEM_COUNT);
+    for i in 0..TEST_ITEM_COUNT {
+        assert_eq!(
+            shared.dataset_memory[i].0,
+            init_dataset_item(&TEST_SEED_MEM, i as u64)
+        );
+    }
+    assert_eq!(shared.seed_memory.blocks[12253][29], 0xf1b62fe6210bf8b1);
+
+    fs::remove_dir_all(&conf.dir).unwrap();
+}
+
+#[test]
+fn test_concurrent_processes_build_once() {
+    let conf = test_conf("concurrent");
+    let builds = AtomicUsize::new(0);
+
+    thread::scope(|s| {
+        for _ in 0..3 {
+            s.spawn(|| {
+                let mem = load_or_build(&conf, &builds);
+                assert_eq!(mem.dataset_memory.kind(), PageKind::SharedFile);
+            });
+        }
+    });
+    assert_eq!(builds.load(Ordering::SeqCst), 1);
+
+    fs::remove_dir_all(&conf.dir).unwrap();
+}
+
+#[test]
+fn test_memory_without_dataset_is_not_published() {
+    let conf = test_conf("no_dataset");
+
+    let mem = shared_dataset::load_or_build(
+        &conf,
+        TEST_KEY,
+        &RX_0,
+        &HardwareConfig::default(),
+        VmMemory::no_memory,
+    )
+    .unwrap();
+    assert!(!mem.cache);
+    let published = fs::read_dir(&conf.dir)
+        .unwrap()
+        .filter(|e| e.as_ref().unwrap().path().extension().unwrap() == "dataset")
+        .count();
+    assert_eq!(published, 0);
+
+    fs::remove_dir_all(&conf.dir).unwrap();
+}
+
+//helper
+
+fn load_or_build(conf: &SharedDatasetConfig, builds: &AtomicUsize) -> VmMemory {
+    shared_dataset::load_or_build(
+        conf,
+        TEST_KEY,
+        &TEST_PARAMS,
+        &HardwareConfig::default(),
+        || {
+            builds.fetch_add(1, Ordering::SeqCst);
+            test_memory()
+        },
+    )
+    .unwrap()
+}
+
+fn test_conf(name: &str) -> SharedDatasetConfig {
+    let mut dir = std::env::temp_dir();
+    dir.push(format!(
+        "mithril_shared_dataset_{}_{}",
+        name,
+        std::process::id()
+    ));
+    SharedDatasetConfig {
+        enabled: true,
+        max_seeds: 2,
+        dir,
+    }
+}
+
+//a memory with only the first few dataset items, a full dataset is too big for the tests
+fn test_memory() -> VmMemory {
+    let mut dataset = PageBuffer::zeroed(TEST_ITEM_COUNT, &HardwareConfig::default());
+    for (i, item) in dataset.iter_mut().enumerate() {
+        *item = DatasetItem(init_dataset_item(&TEST_SEED_MEM, i as u64));
+    }
+    VmMemory {
+        seed_memory: Arc::new(SeedMemory::from_blocks(
+            TEST_KEY,
+            TEST_SEED_MEM.blocks.clone(),
+            &TEST_PARAMS,
+        )),
+        dataset_memory: dataset,
+        cache: true,
+        item_cache: None,
+        hardware_conf: HardwareConfig::default(),
+    }
+}
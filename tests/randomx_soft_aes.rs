@@ -0,0 +1,93 @@
+extern crate mithril;
+
+use mithril::byte_string::u8_array_to_string;
+use mithril::randomx::hardware::{has_hardware_aes, use_hardware_aes, with_aes_mode, AesMode};
+use mithril::randomx::hash::{fill_aes_1rx4_u64, gen_program_aes_4rx4, hash_aes_1rx4};
+use mithril::randomx::m128::m128i;
+use mithril::randomx::memory::VmMemory;
+use mithril::randomx::soft_aes;
+use mithril::randomx::vm::new_vm;
+use std::sync::Arc;
+
+#[allow(overflowing_literals)]
+fn test_states() -> Vec<m128i> {
+    vec![
+        m128i::zero(),
+        m128i::from_i32(0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff),
+        m128i::from_i32(0x31903876, 0xbb7a2914, 0xb370f616, 0xd6f7e4f3),
+        m128i::from_i32(0xb5a8ef67, 0x749809c8, 0xf349884a, 0x05c9f5ef),
+        m128i::from_i32(0xa9a93ab0, 0x22e46d0a, 0x1a1fe305, 0xb42708c0),
+        m128i::from_i32(0x68247034, 0xed99ee84, 0x438f563a, 0x138612ff),
+    ]
+}
+
+#[test]
+fn test_soft_aesenc_equals_hardware() {
+    if !has_hardware_aes() {
+        eprintln!("skipping, the cpu has no AES-NI");
+        return;
+    }
+    for state in test_states() {
+        for key in test_states() {
+            assert_eq!(soft_aes::aesenc(state, key), state.aesenc(key));
+        }
+    }
+}
+
+#[test]
+fn test_soft_aesdec_equals_hardware() {
+    if !has_hardware_aes() {
+        eprintln!("skipping, the cpu has no AES-NI");
+        return;
+    }
+    for state in test_states() {
+        for key in test_states() {
+            assert_eq!(soft_aes::aesdec(state, key), state.aesdec(key));
+        }
+    }
+}
+
+#[test]
+fn test_hash_functions_software_equals_hardware() {
+    if !has_hardware_aes() {
+        eprintln!("skipping, the cpu has no AES-NI");
+        return;
+    }
+    let states = test_states();
+    let seed = [states[2], states[3], states[4], states[5]];
+
+    let (scratchpad_hw, seed_hw, hash_hw, prog_hw) = with_aes_mode(AesMode::Hardware, || {
+        assert!(use_hardware_aes());
+        let mut scratchpad = vec![0; 4096];
+        let new_seed = fill_aes_1rx4_u64(&seed, &mut scratchpad);
+        let hash = hash_aes_1rx4(&scratchpad);
+        (scratchpad, new_seed, hash, gen_program_aes_4rx4(&seed, 136))
+    });
+
+    let (scratchpad_sw, seed_sw, hash_sw, prog_sw) = with_aes_mode(AesMode::Software, || {
+        assert!(!use_hardware_aes());
+        let mut scratchpad = vec![0; 4096];
+        let new_seed = fill_aes_1rx4_u64(&seed, &mut scratchpad);
+        let hash = hash_aes_1rx4(&scratchpad);
+        (scratchpad, new_seed, hash, gen_program_aes_4rx4(&seed, 136))
+    });
+
+    assert_eq!(scratchpad_hw, scratchpad_sw);
+    assert_eq!(seed_hw, seed_sw);
+    assert_eq!(hash_hw, hash_sw);
+    assert_eq!(prog_hw, prog_sw);
+}
+
+#[test]
+fn test_calculate_hash_with_software_aes() {
+    let result = with_aes_mode(AesMode::Software, || {
+        assert!(!use_hardware_aes());
+        let mut vm = new_vm(Arc::new(VmMemory::light(b"test key 000")));
+        vm.calculate_hash(b"This is a test")
+    });
+
+    assert_eq!(
+        "639183aae1bf4c9a35884cb46b09cad9175f04efd7684e7262a0ac1c2f0b4e3f",
+        u8_array_to_string(result.as_bytes())
+    );
+}
@@ -0,0 +1,107 @@
+extern crate lazy_static;
+extern crate mithril;
+extern crate serde_json;
+
+use lazy_static::lazy_static;
+use mithril::byte_string::u8_array_to_string;
+use mithril::randomx::hardware::HardwareConfig;
+use mithril::randomx::memory::VmMemory;
+use mithril::randomx::params::RX_0;
+use mithril::randomx::program::Opcode;
+use mithril::randomx::stats::VmStats;
+use mithril::randomx::vm::{new_vm, new_vm_with_mode, VmMode};
+use std::sync::Arc;
+
+lazy_static! {
+    static ref MEM: Arc<VmMemory> = Arc::new(VmMemory::light(b"test key 000"));
+}
+
+#[test]
+fn test_collect_stats() {
+    let mut vm = new_vm_with_mode(MEM.clone(), VmMode::Compiled);
+    let mut stats = VmStats::default();
+    let result = vm.calculate_hash_traced(b"This is a test", &mut stats);
+    assert_eq!(
+        "639183aae1bf4c9a35884cb46b09cad9175f04efd7684e7262a0ac1c2f0b4e3f",
+        u8_array_to_string(result.as_bytes())
+    );
+
+    assert_eq!(stats.programs, 8);
+    assert_eq!(stats.iterations, 8 * 2048);
+    assert_eq!(stats.dataset_misses, stats.iterations);
+    assert_eq!(stats.dataset_hits, 0);
+    //taken branches execute instructions again
+    assert!(stats.instructions > stats.iterations * 256);
+    assert_eq!(stats.opcodes.values().sum::<u64>(), stats.instructions);
+
+    let cbranches = stats.opcode_count(Opcode::CBRANCH);
+    assert!(stats.cbranch_taken > 0 && stats.cbranch_taken < cbranches);
+    assert_eq!(
+        stats.cbranch_taken_ratio,
+        stats.cbranch_taken as f64 / cbranches as f64
+    );
+    assert!(stats.cfround_mode_changes > 0);
+    assert!(stats.cfround_mode_changes <= stats.opcode_count(Opcode::CFROUND));
+    assert!(stats.scratchpad_l1 > stats.scratchpad_l3);
+    assert!(stats.scratchpad_l2 > 0);
+}
+
+#[test]
+fn test_hybrid_dataset_hits() {
+    let mem = VmMemory::hybrid_with_config(
+        b"test key 000",
+        &RX_0,
+        64 * 1024 * 1024,
+        &HardwareConfig::default(),
+    );
+    let mut vm = new_vm(Arc::new(mem));
+    let mut first = VmStats::default();
+    vm.calculate_hash_traced(b"This is a test", &mut first);
+    //the second hash reads the items the first hash put into the item cache
+    let mut second = VmStats::default();
+    vm.calculate_hash_traced(b"This is a test", &mut second);
+
+    let item_cache = vm.mem.item_cache.as_ref().unwrap();
+    assert_eq!(first.dataset_hits + second.dataset_hits, item_cache.hits());
+    assert_eq!(
+        first.dataset_misses + second.dataset_misses,
+        item_cache.misses()
+    );
+    assert_eq!(first.dataset_hits + first.dataset_misses, first.iterations);
+    assert!(first.dataset_misses > first.dataset_hits);
+    assert!(second.dataset_hits > second.dataset_misses);
+}
+
+#[test]
+fn test_frequencies_match_decoder() {
+    let mut vm = new_vm_with_mode(MEM.clone(), VmMode::Interpreted);
+    let mut stats = VmStats::default();
+    vm.calculate_hash_traced(b"Lorem ipsum dolor sit amet", &mut stats);
+
+    let frequencies = stats.frequencies();
+    let expected = [
+        ("IADD_RS", RX_0.frequencies.iadd_rs),
+        ("ISTORE", RX_0.frequencies.istore),
+        ("CBRANCH", RX_0.frequencies.cbranch),
+        ("FSCAL_R", RX_0.frequencies.fscal_r),
+    ];
+    for (op, freq) in expected.iter() {
+        let diff = frequencies[*op] - f64::from(*freq) / 256.0;
+        assert!(diff.abs() < 0.03, "{} {}", op, frequencies[*op]);
+    }
+}
+
+#[test]
+fn test_stats_json() {
+    let mut vm = new_vm_with_mode(MEM.clone(), VmMode::Interpreted);
+    let mut stats = VmStats::default();
+    vm.calculate_hash_traced(b"This is a test", &mut stats);
+
+    let json = stats.to_json();
+    let parsed: VmStats = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed.to_json(), json);
+    let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(value["programs"], 8);
+    assert!(value["opcodes"]["IMUL_R"].as_u64().unwrap() > 0);
+    assert!(value.get("rounding_mode").is_none());
+}
@@ -1,9 +1,20 @@
+extern crate lazy_static;
 extern crate mithril;
 #[macro_use(assert_diff)]
 extern crate difference;
 
+use lazy_static::lazy_static;
+#[cfg(all(target_arch = "x86_64", unix))]
+use mithril::randomx::memory::{init_dataset_item_interpreted, DatasetItem};
+use mithril::randomx::memory::SeedMemory;
+#[cfg(all(target_arch = "x86_64", unix))]
+use mithril::randomx::params::RX_0;
 use mithril::randomx::superscalar::{Blake2Generator, ScProgram};
 
+lazy_static! {
+	static ref TEST_SEED_MEM: SeedMemory = SeedMemory::new_initialised(b"test key 000");
+}
+
 #[test]
 fn test_generate_1() {
 	let key_str = b"test key 000";
@@ -29,6 +40,34 @@ fn test_generate_rage() {
 	assert_diff!(EXPECTED_SUPERSCALAR_PROG_RAGE, &prog.to_string(), "\n", 0);
 }
 
+#[test]
+#[cfg(all(target_arch = "x86_64", unix))]
+fn test_compiled_equals_interpreted() {
+	let compiled = TEST_SEED_MEM.compiled.as_ref().unwrap();
+	let last_batch = (RX_0.dataset_item_count() - 64) as u64;
+	for start_item in &[0, 1, 10000000, 20000000, last_batch] {
+		let mut items = [DatasetItem([0; 8]); 64];
+		compiled.init_dataset_items(&TEST_SEED_MEM, *start_item, &mut items);
+
+		for (i, item) in items.iter().enumerate() {
+			let expected = init_dataset_item_interpreted(&TEST_SEED_MEM, start_item + i as u64);
+			assert_eq!(item.0, expected, "item {}", start_item + i as u64);
+		}
+	}
+}
+
+#[test]
+#[cfg(all(target_arch = "x86_64", unix))]
+fn test_compiled_empty_batch() {
+	let compiled = TEST_SEED_MEM.compiled.as_ref().unwrap();
+	compiled.init_dataset_items(&TEST_SEED_MEM, 0, &mut []);
+}
+
+#[test]
+fn test_no_memory_is_not_compiled() {
+	assert!(SeedMemory::no_memory().compiled.is_none());
+}
+
 //helper + testdata
 
 const EXPECTED_SUPERSCALAR_PROG_1: &str = r#"op: IMUL_R, src: 0, dst: 3
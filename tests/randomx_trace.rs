@@ -0,0 +1,164 @@
+extern crate lazy_static;
+extern crate mithril;
+extern crate serde_json;
+
+use lazy_static::lazy_static;
+use mithril::byte_string::u8_array_to_string;
+use mithril::randomx::memory::VmMemory;
+use mithril::randomx::trace::{JsonTracer, TraceFilter, TraceLevel, TracePos, TraceRecord};
+use mithril::randomx::vm::{new_vm, new_vm_with_mode, VmMode};
+use std::io::{self, Write};
+use std::sync::Arc;
+
+lazy_static! {
+    static ref MEM: Arc<VmMemory> = Arc::new(VmMemory::light(b"test key 000"));
+}
+
+const HASH: &str = "639183aae1bf4c9a35884cb46b09cad9175f04efd7684e7262a0ac1c2f0b4e3f";
+
+#[test]
+fn test_traced_hash_equals_hash() {
+    for mode in &[VmMode::Interpreted, VmMode::Compiled] {
+        let mut vm = new_vm_with_mode(MEM.clone(), *mode);
+        let filter = TraceFilter {
+            programs: Some(7..=7),
+            ..TraceFilter::default()
+        };
+        let mut tracer = JsonTracer::new(Vec::new(), TraceLevel::Instruction, filter);
+        let result = vm.calculate_hash_traced(b"This is a test", &mut tracer);
+        assert_eq!(HASH, u8_array_to_string(result.as_bytes()));
+        assert!(!tracer.finish().unwrap().is_empty());
+
+        //the untraced hash still uses the vm mode
+        let result = vm.calculate_hash(b"This is a test");
+        assert_eq!(HASH, u8_array_to_string(result.as_bytes()));
+    }
+}
+
+#[test]
+fn test_trace_iterations() {
+    let mut vm = new_vm_with_mode(MEM.clone(), VmMode::Compiled);
+    let filter = TraceFilter {
+        programs: Some(7..=7),
+        ..TraceFilter::default()
+    };
+    let mut tracer = JsonTracer::new(Vec::new(), TraceLevel::Iteration, filter);
+    vm.calculate_hash_traced(b"This is a test", &mut tracer);
+    let records = parse(&tracer.finish().unwrap());
+
+    assert_eq!(records.len(), 2048);
+    for (i, record) in records.iter().enumerate() {
+        assert_eq!(record.program, 7);
+        assert_eq!(record.iteration, i);
+        assert_eq!(record.pc, None);
+        assert_eq!(record.instr, None);
+        assert_eq!(record.scratchpad.len(), 2);
+        assert!(record.dataset.is_some());
+    }
+    //the integer registers are not changed after the last iteration
+    assert_eq!(records[2047].r, vm.reg.r);
+}
+
+#[test]
+fn test_trace_instructions_with_filter() {
+    let mut vm = new_vm(MEM.clone());
+    let filter = TraceFilter {
+        programs: Some(0..=0),
+        iterations: Some(1..=2),
+        pcs: Some(0..=9),
+    };
+    let mut tracer = JsonTracer::new(Vec::new(), TraceLevel::Instruction, filter);
+    vm.calculate_hash_traced(b"This is a test", &mut tracer);
+    let records = parse(&tracer.finish().unwrap());
+
+    let iterations: Vec<&TraceRecord> = records.iter().filter(|r| r.pc.is_none()).collect();
+    assert_eq!(iterations.len(), 2);
+    let instructions: Vec<&TraceRecord> = records.iter().filter(|r| r.pc.is_some()).collect();
+    assert!(instructions.len() >= 20);
+    for record in &records {
+        assert_eq!(record.program, 0);
+        assert!(record.iteration == 1 || record.iteration == 2);
+        assert!(record.pc.unwrap_or(0) <= 9);
+        assert!(record.rounding_mode < 4);
+    }
+    for record in instructions {
+        let instr = record.instr.as_ref().unwrap();
+        assert_eq!(
+            record.scratchpad.len(),
+            instr.contains('[') as usize,
+            "{}",
+            instr
+        );
+        for addr in &record.scratchpad {
+            assert!(*addr < vm.params.scratchpad_l3);
+            assert_eq!(addr % 8, 0);
+        }
+    }
+}
+
+#[test]
+fn test_trace_record_json() {
+    let vm = new_vm(MEM.clone());
+    let pos = TracePos {
+        program: 1,
+        iteration: 2,
+        pc: Some(3),
+    };
+    let json = serde_json::to_string(&TraceRecord::new(&vm, pos)).unwrap();
+    assert!(json.starts_with(r#"{"program":1,"iteration":2,"pc":3,"r":[0,0,0,0,0,0,0,0],"#));
+    assert!(!json.contains("instr"));
+    assert!(!json.contains("dataset"));
+}
+
+#[test]
+fn test_filter_matches() {
+    let filter = TraceFilter {
+        programs: None,
+        iterations: Some(10..=20),
+        pcs: Some(5..=5),
+    };
+    let pos = |iteration, pc| TracePos {
+        program: 3,
+        iteration,
+        pc,
+    };
+    assert!(filter.matches(&pos(10, Some(5))));
+    assert!(filter.matches(&pos(20, None)));
+    assert!(!filter.matches(&pos(21, Some(5))));
+    assert!(!filter.matches(&pos(15, Some(6))));
+    assert!(TraceFilter::default().matches(&pos(0, Some(0))));
+}
+
+#[test]
+fn test_finish_returns_write_error() {
+    let mut vm = new_vm(MEM.clone());
+    let filter = TraceFilter {
+        programs: Some(0..=0),
+        iterations: Some(0..=0),
+        ..TraceFilter::default()
+    };
+    let mut tracer = JsonTracer::new(FailingSink, TraceLevel::Iteration, filter);
+    vm.calculate_hash_traced(b"This is a test", &mut tracer);
+    assert!(tracer.finish().is_err());
+}
+
+//helper
+
+fn parse(out: &[u8]) -> Vec<TraceRecord> {
+    std::str::from_utf8(out)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+struct FailingSink;
+
+impl Write for FailingSink {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        Err(io::Error::other("sink closed"))
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,82 @@
+extern crate mithril;
+
+use mithril::randomx::hardware::{has_vaes, use_vaes, with_aes_mode, AesMode};
+use mithril::randomx::hash::{
+    fill_aes_1rx4_u64, gen_program_aes_4rx4, hash_aes_1rx4, hash_and_fill_aes_1rx4,
+};
+use mithril::randomx::m128::m128i;
+
+#[allow(overflowing_literals)]
+fn test_seed() -> [m128i; 4] {
+    [
+        m128i::from_i32(0x31903876, 0xbb7a2914, 0xb370f616, 0xd6f7e4f3),
+        m128i::from_i32(0xb5a8ef67, 0x749809c8, 0xf349884a, 0x05c9f5ef),
+        m128i::from_i32(0xa9a93ab0, 0x22e46d0a, 0x1a1fe305, 0xb42708c0),
+        m128i::from_i32(0x68247034, 0xed99ee84, 0x438f563a, 0x138612ff),
+    ]
+}
+
+struct AesOutputs {
+    scratchpad: Vec<u64>,
+    fill_seed: [m128i; 4],
+    hash: [m128i; 4],
+    hash_and_fill: ([m128i; 4], [m128i; 4]),
+    refilled: Vec<u64>,
+    program: Vec<m128i>,
+}
+
+fn aes_outputs(mode: AesMode) -> AesOutputs {
+    with_aes_mode(mode, || {
+        //the outputs are computed with the AES implementation of the mode
+        assert_eq!(use_vaes(), mode == AesMode::Hardware && has_vaes());
+        compute_aes_outputs()
+    })
+}
+
+fn compute_aes_outputs() -> AesOutputs {
+    let seed = test_seed();
+    let mut scratchpad = vec![0; 4096];
+    let fill_seed = fill_aes_1rx4_u64(&seed, &mut scratchpad);
+    let hash = hash_aes_1rx4(&scratchpad);
+    let mut refilled = scratchpad.clone();
+    let hash_and_fill = hash_and_fill_aes_1rx4(&fill_seed, &mut refilled);
+    let program = gen_program_aes_4rx4(&seed, 136);
+
+    AesOutputs {
+        scratchpad,
+        fill_seed,
+        hash,
+        hash_and_fill,
+        refilled,
+        program,
+    }
+}
+
+fn assert_outputs_eq(a: &AesOutputs, b: &AesOutputs) {
+    assert_eq!(a.scratchpad, b.scratchpad);
+    assert_eq!(a.fill_seed, b.fill_seed);
+    assert_eq!(a.hash, b.hash);
+    assert_eq!(a.hash_and_fill, b.hash_and_fill);
+    assert_eq!(a.refilled, b.refilled);
+    assert_eq!(a.program, b.program);
+}
+
+#[test]
+fn test_vaes_equals_aesni_and_software() {
+    if !has_vaes() {
+        eprintln!("cpu without VAES, comparing AES-NI and software AES only");
+    }
+    let hardware = aes_outputs(AesMode::Hardware);
+    let aesni = aes_outputs(AesMode::Aesni);
+    let software = aes_outputs(AesMode::Software);
+
+    assert_outputs_eq(&hardware, &aesni);
+    assert_outputs_eq(&hardware, &software);
+}
+
+#[test]
+fn test_use_vaes() {
+    assert!(!with_aes_mode(AesMode::Aesni, use_vaes));
+    assert!(!with_aes_mode(AesMode::Software, use_vaes));
+    assert_eq!(with_aes_mode(AesMode::Hardware, use_vaes), has_vaes());
+}
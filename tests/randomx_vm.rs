@@ -7,11 +7,11 @@ use mithril::randomx::common::randomx_reciprocal;
 use mithril::randomx::hash::gen_program_aes_4rx4;
 use mithril::randomx::m128::m128d;
 use mithril::randomx::memory::VmMemory;
-use mithril::randomx::program::{
-    a_reg, e_reg, f_reg, r_reg, Instr, Mode, Opcode, Program, Store, REG_NEEDS_DISPLACEMENT,
-    REG_NEEDS_DISPLACEMENT_IX,
+use mithril::randomx::program::{Instr, Opcode, Program, REG_NEEDS_DISPLACEMENT_IX};
+use mithril::randomx::vm::{
+    hash_to_m128i_array, new_register, new_vm, Vm, SCRATCHPAD_L1_MASK, SCRATCHPAD_L2_MASK,
+    SCRATCHPAD_L3_MASK,
 };
-use mithril::randomx::vm::{hash_to_m128i_array, new_register, new_vm, Vm};
 use std::sync::Arc;
 
 #[allow(overflowing_literals)]
@@ -45,6 +45,31 @@ fn test_calculate_hash_1_with_light_memory() {
     );
 }
 
+#[test]
+fn test_calculate_hash_pipelined_with_light_memory() {
+    let mut vm = new_vm(Arc::new(VmMemory::light(b"test key 000")));
+    vm.calculate_hash_first(b"This is a test");
+
+    let result = vm.calculate_hash_next(b"Lorem ipsum dolor sit amet");
+    assert_eq!(
+        "639183aae1bf4c9a35884cb46b09cad9175f04efd7684e7262a0ac1c2f0b4e3f",
+        u8_array_to_string(result.as_bytes())
+    );
+
+    let result = vm
+        .calculate_hash_next(b"sed do eiusmod tempor incididunt ut labore et dolore magna aliqua");
+    assert_eq!(
+        "300a0adb47603dedb42228ccb2b211104f4da45af709cd7547cd049e9489c969",
+        u8_array_to_string(result.as_bytes())
+    );
+
+    let result = vm.calculate_hash_last();
+    assert_eq!(
+        "c36d4ed4191e617309867ed66a443be4075014e2b061bcdaf9ce7b721d2b77a8",
+        u8_array_to_string(result.as_bytes())
+    );
+}
+
 #[test]
 fn test_calculate_hash_1_with_full_memory() {
     let mut vm = new_vm(Arc::new(VmMemory::full(b"test key 000")));
@@ -469,16 +494,7 @@ fn test_register_to_bytes() {
 
 #[test]
 fn test_exec_iadd_rs() {
-    let instr = Instr {
-        op: Opcode::IADD_RS,
-        dst: r_reg(0),
-        src: r_reg(1),
-        imm: None,
-        unsigned_imm: false,
-        mode: Mode::Shft(3),
-        target: None,
-        effect: Vm::exec_iadd_rs,
-    };
+    let instr = Instr::new(Opcode::IADD_RS, 0, 1).with_mode(3);
     let mut vm = new_test_vm();
     vm.reg.r[0] = 0x8000000000000000;
     vm.reg.r[1] = 0x1000000000000000;
@@ -488,16 +504,9 @@ fn test_exec_iadd_rs() {
 
 #[test]
 fn test_exec_iadd_rs_with_immediate() {
-    let instr = Instr {
-        op: Opcode::IADD_RS,
-        dst: REG_NEEDS_DISPLACEMENT,
-        src: r_reg(1),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::Shft(2),
-        target: None,
-        effect: Vm::exec_iadd_rs,
-    };
+    let instr = Instr::new(Opcode::IADD_RS, REG_NEEDS_DISPLACEMENT_IX, 1)
+        .with_mode(2)
+        .with_imm(IMM32);
     let mut vm = new_test_vm();
     vm.reg.r[REG_NEEDS_DISPLACEMENT_IX] = 0x8000000000000000;
     vm.reg.r[1] = 0x2000000000000000;
@@ -508,16 +517,7 @@ fn test_exec_iadd_rs_with_immediate() {
 #[test]
 #[allow(overflowing_literals)]
 fn test_exec_isub_r() {
-    let instr = Instr {
-        op: Opcode::ISUB_R,
-        dst: r_reg(0),
-        src: r_reg(1),
-        imm: None,
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_isub_r,
-    };
+    let instr = Instr::new(Opcode::ISUB_R, 0, 1);
     let mut vm = new_test_vm();
     vm.reg.r[0] = 1;
     vm.reg.r[1] = 0xFFFFFFFF;
@@ -527,16 +527,7 @@ fn test_exec_isub_r() {
 
 #[test]
 fn test_exec_isub_r_with_immediate() {
-    let instr = Instr {
-        op: Opcode::ISUB_R,
-        dst: r_reg(0),
-        src: Store::NONE,
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_isub_r,
-    };
+    let instr = Instr::new(Opcode::ISUB_R, 0, 0).with_src_imm(IMM32);
     let mut vm = new_test_vm();
     vm.reg.r[0] = 0;
     instr.execute(&mut vm);
@@ -545,16 +536,7 @@ fn test_exec_isub_r_with_immediate() {
 
 #[test]
 fn test_exec_imul_r() {
-    let instr = Instr {
-        op: Opcode::IMUL_R,
-        dst: r_reg(0),
-        src: r_reg(1),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_imul_r,
-    };
+    let instr = Instr::new(Opcode::IMUL_R, 0, 1);
     let mut vm = new_test_vm();
     vm.reg.r[0] = 0xBC550E96BA88A72B;
     vm.reg.r[1] = 0xF5391FA9F18D6273;
@@ -565,16 +547,7 @@ fn test_exec_imul_r() {
 
 #[test]
 fn test_exec_imul_r_with_immediate() {
-    let instr = Instr {
-        op: Opcode::IMUL_R,
-        dst: r_reg(0),
-        src: Store::NONE,
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_imul_r,
-    };
+    let instr = Instr::new(Opcode::IMUL_R, 0, 0).with_src_imm(IMM32);
     let mut vm = new_test_vm();
     vm.reg.r[0] = 1;
 
@@ -584,16 +557,7 @@ fn test_exec_imul_r_with_immediate() {
 
 #[test]
 fn test_exec_imulh_r() {
-    let instr = Instr {
-        op: Opcode::IMULH_R,
-        dst: r_reg(0),
-        src: r_reg(1),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_imulh_r,
-    };
+    let instr = Instr::new(Opcode::IMULH_R, 0, 1);
     let mut vm = new_test_vm();
     vm.reg.r[0] = 0xBC550E96BA88A72B;
     vm.reg.r[1] = 0xF5391FA9F18D6273;
@@ -604,16 +568,7 @@ fn test_exec_imulh_r() {
 
 #[test]
 fn test_exec_ismulh_r() {
-    let instr = Instr {
-        op: Opcode::ISMULH_R,
-        dst: r_reg(0),
-        src: r_reg(1),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_ismulh_r,
-    };
+    let instr = Instr::new(Opcode::ISMULH_R, 0, 1);
     let mut vm = new_test_vm();
     vm.reg.r[0] = 0xBC550E96BA88A72B;
     vm.reg.r[1] = 0xF5391FA9F18D6273;
@@ -624,16 +579,7 @@ fn test_exec_ismulh_r() {
 
 #[test]
 fn test_exec_ineg_r() {
-    let instr = Instr {
-        op: Opcode::INEG_R,
-        dst: r_reg(0),
-        src: Store::NONE,
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_ineg_r,
-    };
+    let instr = Instr::new(Opcode::INEG_R, 0, 0);
     let mut vm = new_test_vm();
     vm.reg.r[0] = 0xFFFFFFFFFFFFFFFF;
 
@@ -644,16 +590,7 @@ fn test_exec_ineg_r() {
 
 #[test]
 fn test_exec_ineg_r_overflow() {
-    let instr = Instr {
-        op: Opcode::INEG_R,
-        dst: r_reg(0),
-        src: Store::NONE,
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_ineg_r,
-    };
+    let instr = Instr::new(Opcode::INEG_R, 0, 0);
     let mut vm = new_test_vm();
     vm.reg.r[0] = 0x0;
 
@@ -664,16 +601,7 @@ fn test_exec_ineg_r_overflow() {
 
 #[test]
 fn test_exec_ixor_r() {
-    let instr = Instr {
-        op: Opcode::IXOR_R,
-        dst: r_reg(0),
-        src: r_reg(1),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_ixor_r,
-    };
+    let instr = Instr::new(Opcode::IXOR_R, 0, 1);
     let mut vm = new_test_vm();
     vm.reg.r[0] = 0x8888888888888888;
     vm.reg.r[1] = 0xAAAAAAAAAAAAAAAA;
@@ -685,16 +613,7 @@ fn test_exec_ixor_r() {
 
 #[test]
 fn test_exec_ixor_r_with_immediate() {
-    let instr = Instr {
-        op: Opcode::IXOR_R,
-        dst: r_reg(0),
-        src: Store::NONE,
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_ixor_r,
-    };
+    let instr = Instr::new(Opcode::IXOR_R, 0, 0).with_src_imm(IMM32);
     let mut vm = new_test_vm();
     vm.reg.r[0] = 0xFFFFFFFFFFFFFFFF;
 
@@ -705,16 +624,7 @@ fn test_exec_ixor_r_with_immediate() {
 
 #[test]
 fn test_exec_iror_r() {
-    let instr = Instr {
-        op: Opcode::IROR_R,
-        dst: r_reg(0),
-        src: r_reg(1),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_iror_r,
-    };
+    let instr = Instr::new(Opcode::IROR_R, 0, 1);
     let mut vm = new_test_vm();
     vm.reg.r[0] = 953360005391419562;
     vm.reg.r[1] = 4569451684712230561;
@@ -727,16 +637,7 @@ fn test_exec_iror_r() {
 #[test]
 #[allow(overflowing_literals)]
 fn test_exec_iror_r_with_immediate() {
-    let instr = Instr {
-        op: Opcode::IROR_R,
-        dst: r_reg(0),
-        src: Store::NONE,
-        imm: Some(4569451684712230561 as i32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_iror_r,
-    };
+    let instr = Instr::new(Opcode::IROR_R, 0, 0).with_src_imm(4569451684712230561 as i32);
     let mut vm = new_test_vm();
     vm.reg.r[0] = 953360005391419562;
 
@@ -747,16 +648,7 @@ fn test_exec_iror_r_with_immediate() {
 
 #[test]
 fn test_exec_irol_r() {
-    let instr = Instr {
-        op: Opcode::IROL_R,
-        dst: r_reg(0),
-        src: r_reg(1),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_irol_r,
-    };
+    let instr = Instr::new(Opcode::IROL_R, 0, 1);
     let mut vm = new_test_vm();
     vm.reg.r[0] = 953360005391419562;
     vm.reg.r[1] = 4569451684712230561;
@@ -769,16 +661,7 @@ fn test_exec_irol_r() {
 #[test]
 #[allow(overflowing_literals)]
 fn test_exec_irol_r_with_immediate() {
-    let instr = Instr {
-        op: Opcode::IROL_R,
-        dst: r_reg(0),
-        src: Store::NONE,
-        imm: Some(4569451684712230561 as i32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_irol_r,
-    };
+    let instr = Instr::new(Opcode::IROL_R, 0, 0).with_src_imm(4569451684712230561 as i32);
     let mut vm = new_test_vm();
     vm.reg.r[0] = 953360005391419562;
 
@@ -789,16 +672,7 @@ fn test_exec_irol_r_with_immediate() {
 
 #[test]
 fn test_exec_iswap_r() {
-    let instr = Instr {
-        op: Opcode::ISWAP_R,
-        dst: r_reg(0),
-        src: r_reg(1),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_iswap_r,
-    };
+    let instr = Instr::new(Opcode::ISWAP_R, 0, 1);
     let mut vm = new_test_vm();
     vm.reg.r[0] = 953360005391419562;
     vm.reg.r[1] = 4569451684712230561;
@@ -810,16 +684,7 @@ fn test_exec_iswap_r() {
 
 #[test]
 fn test_exec_fswap_r_from_f_reg() {
-    let instr = Instr {
-        op: Opcode::FSWAP_R,
-        dst: f_reg(0),
-        src: Store::NONE,
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_fswap_r,
-    };
+    let instr = Instr::new(Opcode::FSWAP_R, 0, 0);
     let mut vm = new_test_vm();
     vm.reg.f[0] = m128d::from_u64(953360005391419562, 4569451684712230561);
 
@@ -833,16 +698,7 @@ fn test_exec_fswap_r_from_f_reg() {
 
 #[test]
 fn test_exec_fswap_r_from_e_reg() {
-    let instr = Instr {
-        op: Opcode::FSWAP_R,
-        dst: e_reg(3),
-        src: Store::NONE,
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_fswap_r,
-    };
+    let instr = Instr::new(Opcode::FSWAP_R, 7, 0);
     let mut vm = new_test_vm();
     vm.reg.e[3] = m128d::from_u64(953360005391419562, 4569451684712230561);
 
@@ -856,16 +712,7 @@ fn test_exec_fswap_r_from_e_reg() {
 
 #[test]
 fn test_exec_fadd_r_round_to_nearest() {
-    let instr = Instr {
-        op: Opcode::FADD_R,
-        dst: f_reg(0),
-        src: a_reg(1),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_fadd_r,
-    };
+    let instr = Instr::new(Opcode::FADD_R, 0, 1);
     let mut vm = new_test_vm();
     vm.set_rounding_mode(ROUND_TO_NEAREST);
 
@@ -882,16 +729,7 @@ fn test_exec_fadd_r_round_to_nearest() {
 
 #[test]
 fn test_exec_fadd_r_round_down() {
-    let instr = Instr {
-        op: Opcode::FADD_R,
-        dst: f_reg(0),
-        src: a_reg(1),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_fadd_r,
-    };
+    let instr = Instr::new(Opcode::FADD_R, 0, 1);
     let mut vm = new_test_vm();
     vm.set_rounding_mode(ROUND_DOWN);
 
@@ -908,16 +746,7 @@ fn test_exec_fadd_r_round_down() {
 
 #[test]
 fn test_exec_fadd_r_round_up() {
-    let instr = Instr {
-        op: Opcode::FADD_R,
-        dst: f_reg(0),
-        src: a_reg(1),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_fadd_r,
-    };
+    let instr = Instr::new(Opcode::FADD_R, 0, 1);
     let mut vm = new_test_vm();
     vm.set_rounding_mode(ROUND_UP);
 
@@ -934,16 +763,7 @@ fn test_exec_fadd_r_round_up() {
 
 #[test]
 fn test_exec_fadd_r_round_to_zero() {
-    let instr = Instr {
-        op: Opcode::FADD_R,
-        dst: f_reg(0),
-        src: a_reg(1),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_fadd_r,
-    };
+    let instr = Instr::new(Opcode::FADD_R, 0, 1);
     let mut vm = new_test_vm();
     vm.set_rounding_mode(ROUND_TO_ZERO);
 
@@ -960,16 +780,7 @@ fn test_exec_fadd_r_round_to_zero() {
 
 #[test]
 fn test_exec_fsub_r_round_to_nearest() {
-    let instr = Instr {
-        op: Opcode::FSUB_R,
-        dst: f_reg(0),
-        src: a_reg(1),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_fsub_r,
-    };
+    let instr = Instr::new(Opcode::FSUB_R, 0, 1);
     let mut vm = new_test_vm();
     vm.set_rounding_mode(ROUND_TO_NEAREST);
 
@@ -986,16 +797,7 @@ fn test_exec_fsub_r_round_to_nearest() {
 
 #[test]
 fn test_exec_fsub_r_round_down() {
-    let instr = Instr {
-        op: Opcode::FSUB_R,
-        dst: f_reg(0),
-        src: a_reg(1),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_fsub_r,
-    };
+    let instr = Instr::new(Opcode::FSUB_R, 0, 1);
     let mut vm = new_test_vm();
     vm.set_rounding_mode(ROUND_DOWN);
 
@@ -1012,16 +814,7 @@ fn test_exec_fsub_r_round_down() {
 
 #[test]
 fn test_exec_fsub_r_round_up() {
-    let instr = Instr {
-        op: Opcode::FSUB_R,
-        dst: f_reg(0),
-        src: a_reg(1),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_fsub_r,
-    };
+    let instr = Instr::new(Opcode::FSUB_R, 0, 1);
     let mut vm = new_test_vm();
     vm.set_rounding_mode(ROUND_UP);
 
@@ -1038,16 +831,7 @@ fn test_exec_fsub_r_round_up() {
 
 #[test]
 fn test_exec_fsub_r_round_to_zero() {
-    let instr = Instr {
-        op: Opcode::FSUB_R,
-        dst: f_reg(0),
-        src: a_reg(1),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_fsub_r,
-    };
+    let instr = Instr::new(Opcode::FSUB_R, 0, 1);
     let mut vm = new_test_vm();
     vm.set_rounding_mode(ROUND_TO_ZERO);
 
@@ -1064,16 +848,7 @@ fn test_exec_fsub_r_round_to_zero() {
 
 #[test]
 fn test_exec_fscal_r() {
-    let instr = Instr {
-        op: Opcode::FSCAL_R,
-        dst: f_reg(0),
-        src: Store::L1(Box::new(Store::R(1))),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_fscal_r,
-    };
+    let instr = Instr::new(Opcode::FSCAL_R, 0, 0);
     let mut vm = new_test_vm();
     vm.reg.f[0] = m128d::from_u64(0x41dbc35cef248783, 0x40fdfdabb6173d07);
     instr.execute(&mut vm);
@@ -1086,16 +861,7 @@ fn test_exec_fscal_r() {
 
 #[test]
 fn test_exec_fmul_r_round_to_nearest() {
-    let instr = Instr {
-        op: Opcode::FMUL_R,
-        dst: e_reg(0),
-        src: a_reg(1),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_fmul_r,
-    };
+    let instr = Instr::new(Opcode::FMUL_R, 0, 1);
     let mut vm = new_test_vm();
     vm.set_rounding_mode(ROUND_TO_NEAREST);
 
@@ -1112,16 +878,7 @@ fn test_exec_fmul_r_round_to_nearest() {
 
 #[test]
 fn test_exec_fmul_r_round_round_down() {
-    let instr = Instr {
-        op: Opcode::FMUL_R,
-        dst: e_reg(0),
-        src: a_reg(1),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_fmul_r,
-    };
+    let instr = Instr::new(Opcode::FMUL_R, 0, 1);
     let mut vm = new_test_vm();
     vm.set_rounding_mode(ROUND_DOWN);
 
@@ -1138,16 +895,7 @@ fn test_exec_fmul_r_round_round_down() {
 
 #[test]
 fn test_exec_fmul_r_round_up() {
-    let instr = Instr {
-        op: Opcode::FMUL_R,
-        dst: e_reg(0),
-        src: a_reg(1),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_fmul_r,
-    };
+    let instr = Instr::new(Opcode::FMUL_R, 0, 1);
     let mut vm = new_test_vm();
     vm.set_rounding_mode(ROUND_UP);
 
@@ -1164,16 +912,7 @@ fn test_exec_fmul_r_round_up() {
 
 #[test]
 fn test_exec_fmul_r_round_to_zero() {
-    let instr = Instr {
-        op: Opcode::FMUL_R,
-        dst: e_reg(0),
-        src: a_reg(1),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_fmul_r,
-    };
+    let instr = Instr::new(Opcode::FMUL_R, 0, 1);
     let mut vm = new_test_vm();
     vm.set_rounding_mode(ROUND_TO_ZERO);
 
@@ -1190,16 +929,7 @@ fn test_exec_fmul_r_round_to_zero() {
 
 #[test]
 fn test_exec_fsqrt_r_round_to_nearest() {
-    let instr = Instr {
-        op: Opcode::FSQRT_R,
-        dst: e_reg(0),
-        src: Store::NONE,
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_fsqrt_r,
-    };
+    let instr = Instr::new(Opcode::FSQRT_R, 0, 0);
     let mut vm = new_test_vm();
     vm.set_rounding_mode(ROUND_TO_NEAREST);
 
@@ -1215,16 +945,7 @@ fn test_exec_fsqrt_r_round_to_nearest() {
 
 #[test]
 fn test_exec_fsqrt_r_round_up() {
-    let instr = Instr {
-        op: Opcode::FSQRT_R,
-        dst: e_reg(0),
-        src: Store::NONE,
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_fsqrt_r,
-    };
+    let instr = Instr::new(Opcode::FSQRT_R, 0, 0);
     let mut vm = new_test_vm();
     vm.set_rounding_mode(ROUND_UP);
 
@@ -1240,16 +961,7 @@ fn test_exec_fsqrt_r_round_up() {
 
 #[test]
 fn test_exec_fsqrt_r_round_down() {
-    let instr = Instr {
-        op: Opcode::FSQRT_R,
-        dst: e_reg(0),
-        src: Store::NONE,
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_fsqrt_r,
-    };
+    let instr = Instr::new(Opcode::FSQRT_R, 0, 0);
     let mut vm = new_test_vm();
     vm.set_rounding_mode(ROUND_DOWN);
 
@@ -1265,16 +977,7 @@ fn test_exec_fsqrt_r_round_down() {
 
 #[test]
 fn test_exec_fsqrt_r_round_to_zero() {
-    let instr = Instr {
-        op: Opcode::FSQRT_R,
-        dst: e_reg(0),
-        src: Store::NONE,
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_fsqrt_r,
-    };
+    let instr = Instr::new(Opcode::FSQRT_R, 0, 0);
     let mut vm = new_test_vm();
     vm.set_rounding_mode(ROUND_TO_ZERO);
 
@@ -1291,16 +994,9 @@ fn test_exec_fsqrt_r_round_to_zero() {
 #[test]
 #[allow(overflowing_literals)]
 fn test_exec_fadd_m() {
-    let instr = Instr {
-        op: Opcode::FADD_M,
-        dst: f_reg(0),
-        src: Store::L1(Box::new(Store::R(1))),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_fadd_m,
-    };
+    let instr = Instr::new(Opcode::FADD_M, 0, 1)
+        .with_imm(IMM32)
+        .with_mem_mask(SCRATCHPAD_L1_MASK);
     let mut vm = new_test_vm();
     vm.scratchpad[0] = 0x1234567890abcdef;
     vm.reg.r[1] = 0xFFFFFFFFFFFFE930;
@@ -1315,16 +1011,9 @@ fn test_exec_fadd_m() {
 
 #[test]
 fn test_exec_fsub_m() {
-    let instr = Instr {
-        op: Opcode::FSUB_M,
-        dst: f_reg(0),
-        src: Store::L1(Box::new(r_reg(1))),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_fsub_m,
-    };
+    let instr = Instr::new(Opcode::FSUB_M, 0, 1)
+        .with_imm(IMM32)
+        .with_mem_mask(SCRATCHPAD_L1_MASK);
     let mut vm = new_test_vm();
     vm.reg.r[1] = 0xFFFFFFFFFFFFE930;
     vm.reg.f[0] = m128d::from_u64(0x3ffd2c97cc4ef015, 0xc1ce30b3c4223576);
@@ -1339,16 +1028,7 @@ fn test_exec_fsub_m() {
 
 #[test]
 fn test_exec_cfround() {
-    let instr = Instr {
-        op: Opcode::CFROUND,
-        dst: Store::NONE,
-        src: r_reg(0),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_cfround,
-    };
+    let instr = Instr::new(Opcode::CFROUND, 0, 0).with_imm(IMM32);
     let mut vm = new_test_vm();
     vm.reg.r[0] = 0xFFFFFFFFFFFC6800;
 
@@ -1362,16 +1042,10 @@ fn test_exec_cfround() {
 #[test]
 #[allow(overflowing_literals)]
 fn test_exec_cbranch_taken() {
-    let instr = Instr {
-        op: Opcode::CBRANCH,
-        dst: r_reg(0),
-        src: Store::NONE,
-        imm: Some(0xFFFFFFFFC0CB9AD2),
-        unsigned_imm: false,
-        mode: Mode::Cond(3),
-        target: Some(100),
-        effect: Vm::exec_cbranch,
-    };
+    let instr = Instr::new(Opcode::CBRANCH, 0, 0)
+        .with_mode(3)
+        .with_imm(0xFFFFFFFFC0CB9AD2)
+        .with_target(100);
     let mut vm = new_test_vm();
     vm.pc = 200;
     vm.reg.r[0] = 0xFFFFFFFFFFFC6800;
@@ -1384,16 +1058,9 @@ fn test_exec_cbranch_taken() {
 #[test]
 #[allow(overflowing_literals)]
 fn test_exec_cbranch_not_taken() {
-    let instr = Instr {
-        op: Opcode::CBRANCH,
-        dst: r_reg(0),
-        src: Store::NONE,
-        imm: Some(0xFFFFFFFFC0CB9AD2),
-        unsigned_imm: false,
-        mode: Mode::Cond(3),
-        target: None,
-        effect: Vm::exec_cbranch,
-    };
+    let instr = Instr::new(Opcode::CBRANCH, 0, 0)
+        .with_mode(3)
+        .with_imm(0xFFFFFFFFC0CB9AD2);
     let mut vm = new_test_vm();
     vm.pc = 200;
     vm.reg.r[0] = 0;
@@ -1406,16 +1073,9 @@ fn test_exec_cbranch_not_taken() {
 #[test]
 #[allow(overflowing_literals)]
 fn test_exec_istore_l1() {
-    let instr = Instr {
-        op: Opcode::ISTORE,
-        dst: Store::L1(Box::new(r_reg(0))),
-        src: r_reg(1),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_istore,
-    };
+    let instr = Instr::new(Opcode::ISTORE, 0, 1)
+        .with_imm(IMM32)
+        .with_mem_mask(SCRATCHPAD_L1_MASK);
     let mut vm = new_test_vm();
     vm.reg.r[1] = 0xFFFFFFFFFFFC6800;
     vm.reg.r[0] = 0xFFFFFFFFC0C802D2;
@@ -1428,16 +1088,9 @@ fn test_exec_istore_l1() {
 #[test]
 #[allow(overflowing_literals)]
 fn test_exec_istore_l2() {
-    let instr = Instr {
-        op: Opcode::ISTORE,
-        dst: Store::L2(Box::new(r_reg(0))),
-        src: r_reg(1),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_istore,
-    };
+    let instr = Instr::new(Opcode::ISTORE, 0, 1)
+        .with_imm(IMM32)
+        .with_mem_mask(SCRATCHPAD_L2_MASK);
     let mut vm = new_test_vm();
     vm.reg.r[1] = 0xFFFFFFFFFFFC6800;
     vm.reg.r[0] = 0xFFFFFFFFC0C802D2;
@@ -1450,16 +1103,9 @@ fn test_exec_istore_l2() {
 #[test]
 #[allow(overflowing_literals)]
 fn test_exec_istore_l3() {
-    let instr = Instr {
-        op: Opcode::ISTORE,
-        dst: Store::L3(Box::new(r_reg(0))),
-        src: r_reg(1),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_istore,
-    };
+    let instr = Instr::new(Opcode::ISTORE, 0, 1)
+        .with_imm(IMM32)
+        .with_mem_mask(SCRATCHPAD_L3_MASK);
     let mut vm = new_test_vm();
     vm.reg.r[1] = 0xFFFFFFFFFFFC6800;
     vm.reg.r[0] = 0xFFFFFFFFC0C802D2;
@@ -1471,16 +1117,9 @@ fn test_exec_istore_l3() {
 
 #[test]
 fn test_exec_iadd_m_l1() {
-    let instr = Instr {
-        op: Opcode::IADD_M,
-        dst: r_reg(0),
-        src: Store::L1(Box::new(r_reg(1))),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_iadd_m,
-    };
+    let instr = Instr::new(Opcode::IADD_M, 0, 1)
+        .with_imm(IMM32)
+        .with_mem_mask(SCRATCHPAD_L1_MASK);
     let mut vm = new_test_vm();
     vm.reg.r[1] = 0xFFFFFFFFFFFFE930;
     vm.reg.r[0] = 0x666;
@@ -1492,16 +1131,9 @@ fn test_exec_iadd_m_l1() {
 
 #[test]
 fn test_exec_iadd_m_l2() {
-    let instr = Instr {
-        op: Opcode::IADD_M,
-        dst: r_reg(0),
-        src: Store::L2(Box::new(r_reg(1))),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_iadd_m,
-    };
+    let instr = Instr::new(Opcode::IADD_M, 0, 1)
+        .with_imm(IMM32)
+        .with_mem_mask(SCRATCHPAD_L2_MASK);
     let mut vm = new_test_vm();
     vm.reg.r[1] = 0xFFFFFFFFFFFFE930;
     vm.reg.r[0] = 0x666;
@@ -1513,16 +1145,9 @@ fn test_exec_iadd_m_l2() {
 
 #[test]
 fn test_exec_iadd_m_l3() {
-    let instr = Instr {
-        op: Opcode::IADD_M,
-        dst: r_reg(0),
-        src: Store::L3(Box::new(r_reg(1))),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_iadd_m,
-    };
+    let instr = Instr::new(Opcode::IADD_M, 0, 0)
+        .with_src_imm(IMM32)
+        .with_mem_mask(SCRATCHPAD_L3_MASK);
     let mut vm = new_test_vm();
     vm.reg.r[1] = 0xFFFFFFFFFFFFE930;
     vm.reg.r[0] = 0x666;
@@ -1534,16 +1159,9 @@ fn test_exec_iadd_m_l3() {
 
 #[test]
 fn test_exec_isub_m_l1() {
-    let instr = Instr {
-        op: Opcode::ISUB_M,
-        dst: r_reg(0),
-        src: Store::L1(Box::new(r_reg(1))),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_isub_m,
-    };
+    let instr = Instr::new(Opcode::ISUB_M, 0, 1)
+        .with_imm(IMM32)
+        .with_mem_mask(SCRATCHPAD_L1_MASK);
     let mut vm = new_test_vm();
     vm.reg.r[1] = 0xFFFFFFFFFFFFE930;
     vm.reg.r[0] = 0x666;
@@ -1555,16 +1173,9 @@ fn test_exec_isub_m_l1() {
 
 #[test]
 fn test_exec_isub_m_l2() {
-    let instr = Instr {
-        op: Opcode::ISUB_M,
-        dst: r_reg(0),
-        src: Store::L2(Box::new(r_reg(1))),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_isub_m,
-    };
+    let instr = Instr::new(Opcode::ISUB_M, 0, 1)
+        .with_imm(IMM32)
+        .with_mem_mask(SCRATCHPAD_L2_MASK);
     let mut vm = new_test_vm();
     vm.reg.r[1] = 0xFFFFFFFFFFFFE930;
     vm.reg.r[0] = 0x666;
@@ -1576,16 +1187,9 @@ fn test_exec_isub_m_l2() {
 
 #[test]
 fn test_exec_isub_m_l3() {
-    let instr = Instr {
-        op: Opcode::ISUB_M,
-        dst: r_reg(0),
-        src: Store::L3(Box::new(r_reg(1))),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_isub_m,
-    };
+    let instr = Instr::new(Opcode::ISUB_M, 0, 0)
+        .with_src_imm(IMM32)
+        .with_mem_mask(SCRATCHPAD_L3_MASK);
     let mut vm = new_test_vm();
     vm.reg.r[1] = 0xFFFFFFFFFFFFE930;
     vm.reg.r[0] = 0x666;
@@ -1597,16 +1201,9 @@ fn test_exec_isub_m_l3() {
 
 #[test]
 fn test_exec_imul_m_l1() {
-    let instr = Instr {
-        op: Opcode::IMUL_M,
-        dst: r_reg(0),
-        src: Store::L1(Box::new(r_reg(1))),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_imul_m,
-    };
+    let instr = Instr::new(Opcode::IMUL_M, 0, 1)
+        .with_imm(IMM32)
+        .with_mem_mask(SCRATCHPAD_L1_MASK);
     let mut vm = new_test_vm();
     vm.reg.r[1] = 0xFFFFFFFFFFFFE930;
     vm.reg.r[0] = 0x666;
@@ -1618,16 +1215,9 @@ fn test_exec_imul_m_l1() {
 
 #[test]
 fn test_exec_imul_m_l2() {
-    let instr = Instr {
-        op: Opcode::IMUL_M,
-        dst: r_reg(0),
-        src: Store::L2(Box::new(r_reg(1))),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_imul_m,
-    };
+    let instr = Instr::new(Opcode::IMUL_M, 0, 1)
+        .with_imm(IMM32)
+        .with_mem_mask(SCRATCHPAD_L2_MASK);
     let mut vm = new_test_vm();
     vm.reg.r[1] = 0xFFFFFFFFFFFFE930;
     vm.reg.r[0] = 0x666;
@@ -1639,16 +1229,9 @@ fn test_exec_imul_m_l2() {
 
 #[test]
 fn test_exec_imul_m_l3() {
-    let instr = Instr {
-        op: Opcode::IMUL_M,
-        dst: r_reg(0),
-        src: Store::L3(Box::new(r_reg(1))),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_imul_m,
-    };
+    let instr = Instr::new(Opcode::IMUL_M, 0, 0)
+        .with_src_imm(IMM32)
+        .with_mem_mask(SCRATCHPAD_L3_MASK);
     let mut vm = new_test_vm();
     vm.reg.r[1] = 0xFFFFFFFFFFFFE930;
     vm.reg.r[0] = 0x666;
@@ -1660,16 +1243,9 @@ fn test_exec_imul_m_l3() {
 
 #[test]
 fn test_exec_imulh_m_l1() {
-    let instr = Instr {
-        op: Opcode::IMULH_M,
-        dst: r_reg(0),
-        src: Store::L1(Box::new(r_reg(1))),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_imulh_m,
-    };
+    let instr = Instr::new(Opcode::IMULH_M, 0, 1)
+        .with_imm(IMM32)
+        .with_mem_mask(SCRATCHPAD_L1_MASK);
     let mut vm = new_test_vm();
     vm.reg.r[0] = 0xBC550E96BA88A72B;
     vm.reg.r[1] = 0xFFFFFFFFFFFFE930;
@@ -1682,16 +1258,9 @@ fn test_exec_imulh_m_l1() {
 
 #[test]
 fn test_exec_imulh_m_l2() {
-    let instr = Instr {
-        op: Opcode::IMULH_M,
-        dst: r_reg(0),
-        src: Store::L2(Box::new(r_reg(1))),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_imulh_m,
-    };
+    let instr = Instr::new(Opcode::IMULH_M, 0, 1)
+        .with_imm(IMM32)
+        .with_mem_mask(SCRATCHPAD_L2_MASK);
     let mut vm = new_test_vm();
     vm.reg.r[0] = 0xBC550E96BA88A72B;
     vm.reg.r[1] = 0xFFFFFFFFFFFFE930;
@@ -1704,16 +1273,9 @@ fn test_exec_imulh_m_l2() {
 
 #[test]
 fn test_exec_imulh_m_l3() {
-    let instr = Instr {
-        op: Opcode::IMULH_M,
-        dst: r_reg(0),
-        src: Store::L3(Box::new(r_reg(1))),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_imulh_m,
-    };
+    let instr = Instr::new(Opcode::IMULH_M, 0, 0)
+        .with_src_imm(IMM32)
+        .with_mem_mask(SCRATCHPAD_L3_MASK);
     let mut vm = new_test_vm();
     vm.reg.r[0] = 0xBC550E96BA88A72B;
     vm.reg.r[1] = 0xFFFFFFFFFFFFE930;
@@ -1726,16 +1288,9 @@ fn test_exec_imulh_m_l3() {
 
 #[test]
 fn test_exec_ismulh_m_l1() {
-    let instr = Instr {
-        op: Opcode::ISMULH_M,
-        dst: r_reg(0),
-        src: Store::L1(Box::new(r_reg(1))),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_ismulh_m,
-    };
+    let instr = Instr::new(Opcode::ISMULH_M, 0, 1)
+        .with_imm(IMM32)
+        .with_mem_mask(SCRATCHPAD_L1_MASK);
     let mut vm = new_test_vm();
     vm.reg.r[0] = 0xBC550E96BA88A72B;
     vm.reg.r[1] = 0xFFFFFFFFFFFFE930;
@@ -1748,16 +1303,9 @@ fn test_exec_ismulh_m_l1() {
 
 #[test]
 fn test_exec_ismulh_m_l2() {
-    let instr = Instr {
-        op: Opcode::ISMULH_M,
-        dst: r_reg(0),
-        src: Store::L2(Box::new(r_reg(1))),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_ismulh_m,
-    };
+    let instr = Instr::new(Opcode::ISMULH_M, 0, 1)
+        .with_imm(IMM32)
+        .with_mem_mask(SCRATCHPAD_L2_MASK);
     let mut vm = new_test_vm();
     vm.reg.r[0] = 0xBC550E96BA88A72B;
     vm.reg.r[1] = 0xFFFFFFFFFFFFE930;
@@ -1770,16 +1318,9 @@ fn test_exec_ismulh_m_l2() {
 
 #[test]
 fn test_exec_ismulh_m_l3() {
-    let instr = Instr {
-        op: Opcode::ISMULH_M,
-        dst: r_reg(0),
-        src: Store::L3(Box::new(r_reg(1))),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_ismulh_m,
-    };
+    let instr = Instr::new(Opcode::ISMULH_M, 0, 0)
+        .with_src_imm(IMM32)
+        .with_mem_mask(SCRATCHPAD_L3_MASK);
     let mut vm = new_test_vm();
     vm.reg.r[0] = 0xBC550E96BA88A72B;
     vm.reg.r[1] = 0xFFFFFFFFFFFFE930;
@@ -1792,16 +1333,7 @@ fn test_exec_ismulh_m_l3() {
 
 #[test]
 fn test_exec_imul_rcp_non_zero_imm_from_reg() {
-    let instr = Instr {
-        op: Opcode::IMUL_RCP,
-        dst: r_reg(0),
-        src: Store::NONE,
-        imm: Some(IMM32),
-        unsigned_imm: true,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_imul_rcp,
-    };
+    let instr = Instr::new(Opcode::IMUL_RCP, 0, 0).with_imm(IMM32);
     let mut vm = new_test_vm();
     vm.reg.r[0] = 666;
 
@@ -1812,16 +1344,7 @@ fn test_exec_imul_rcp_non_zero_imm_from_reg() {
 
 #[test]
 fn test_exec_imul_rcp_zero_imm() {
-    let instr = Instr {
-        op: Opcode::IMUL_RCP,
-        dst: r_reg(0),
-        src: r_reg(1),
-        imm: Some(0),
-        unsigned_imm: true,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_imul_rcp,
-    };
+    let instr = Instr::new(Opcode::IMUL_RCP, 0, 1).with_imm(0);
     let mut vm = new_test_vm();
     vm.reg.r[0] = 0x666;
 
@@ -1832,16 +1355,9 @@ fn test_exec_imul_rcp_zero_imm() {
 
 #[test]
 fn test_exec_ixor_m_l1() {
-    let instr = Instr {
-        op: Opcode::IXOR_M,
-        dst: r_reg(0),
-        src: Store::L1(Box::new(r_reg(1))),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_ixor_m,
-    };
+    let instr = Instr::new(Opcode::IXOR_M, 0, 1)
+        .with_imm(IMM32)
+        .with_mem_mask(SCRATCHPAD_L1_MASK);
     let mut vm = new_test_vm();
     vm.reg.r[1] = 0xFFFFFFFFFFFFE930;
     vm.reg.r[0] = 0x666;
@@ -1853,16 +1369,9 @@ fn test_exec_ixor_m_l1() {
 
 #[test]
 fn test_exec_ixor_m_l2() {
-    let instr = Instr {
-        op: Opcode::IXOR_M,
-        dst: r_reg(0),
-        src: Store::L2(Box::new(r_reg(1))),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_ixor_m,
-    };
+    let instr = Instr::new(Opcode::IXOR_M, 0, 1)
+        .with_imm(IMM32)
+        .with_mem_mask(SCRATCHPAD_L2_MASK);
     let mut vm = new_test_vm();
     vm.reg.r[1] = 0xFFFFFFFFFFFFE930;
     vm.reg.r[0] = 0x666;
@@ -1874,16 +1383,9 @@ fn test_exec_ixor_m_l2() {
 
 #[test]
 fn test_exec_ixor_m_l3() {
-    let instr = Instr {
-        op: Opcode::IXOR_M,
-        dst: r_reg(0),
-        src: Store::L3(Box::new(r_reg(1))),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_ixor_m,
-    };
+    let instr = Instr::new(Opcode::IXOR_M, 0, 0)
+        .with_src_imm(IMM32)
+        .with_mem_mask(SCRATCHPAD_L3_MASK);
     let mut vm = new_test_vm();
     vm.reg.r[1] = 0xFFFFFFFFFFFFE930;
     vm.reg.r[0] = 0x666;
@@ -1895,16 +1397,9 @@ fn test_exec_ixor_m_l3() {
 
 #[test]
 fn test_exec_fdiv_m_round_to_nearest() {
-    let instr = Instr {
-        op: Opcode::FDIV_M,
-        dst: e_reg(0),
-        src: Store::L1(Box::new(r_reg(1))),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_fdiv_m,
-    };
+    let instr = Instr::new(Opcode::FDIV_M, 0, 1)
+        .with_imm(IMM32)
+        .with_mem_mask(SCRATCHPAD_L1_MASK);
     let mut vm = new_test_vm();
     vm.reg.r[1] = 0xFFFFFFFFFFFFE930;
     vm.reg.e[0] = m128d::from_u64(0x41937f76fede16ee, 0x411b414296ce93b6);
@@ -1923,16 +1418,9 @@ fn test_exec_fdiv_m_round_to_nearest() {
 
 #[test]
 fn test_exec_fdiv_m_round_down_and_to_zero() {
-    let instr = Instr {
-        op: Opcode::FDIV_M,
-        dst: e_reg(0),
-        src: Store::L1(Box::new(r_reg(1))),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_fdiv_m,
-    };
+    let instr = Instr::new(Opcode::FDIV_M, 0, 1)
+        .with_imm(IMM32)
+        .with_mem_mask(SCRATCHPAD_L1_MASK);
     let mut vm = new_test_vm();
     vm.reg.r[1] = 0xFFFFFFFFFFFFE930;
     vm.reg.e[0] = m128d::from_u64(0x41937f76fede16ee, 0x411b414296ce93b6);
@@ -1951,16 +1439,9 @@ fn test_exec_fdiv_m_round_down_and_to_zero() {
 
 #[test]
 fn test_exec_fdiv_m_round_to_zero() {
-    let instr = Instr {
-        op: Opcode::FDIV_M,
-        dst: e_reg(0),
-        src: Store::L1(Box::new(r_reg(1))),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_fdiv_m,
-    };
+    let instr = Instr::new(Opcode::FDIV_M, 0, 1)
+        .with_imm(IMM32)
+        .with_mem_mask(SCRATCHPAD_L1_MASK);
     let mut vm = new_test_vm();
     vm.reg.r[1] = 0xFFFFFFFFFFFFE930;
     vm.reg.e[0] = m128d::from_u64(0x41937f76fede16ee, 0x411b414296ce93b6);
@@ -1979,16 +1460,9 @@ fn test_exec_fdiv_m_round_to_zero() {
 
 #[test]
 fn test_exec_fdiv_m_round_up() {
-    let instr = Instr {
-        op: Opcode::FDIV_M,
-        dst: e_reg(0),
-        src: Store::L1(Box::new(r_reg(1))),
-        imm: Some(IMM32),
-        unsigned_imm: false,
-        mode: Mode::None,
-        target: None,
-        effect: Vm::exec_fdiv_m,
-    };
+    let instr = Instr::new(Opcode::FDIV_M, 0, 1)
+        .with_imm(IMM32)
+        .with_mem_mask(SCRATCHPAD_L1_MASK);
     let mut vm = new_test_vm();
     vm.reg.r[1] = 0xFFFFFFFFFFFFE930;
     vm.reg.e[0] = m128d::from_u64(0x41937f76fede16ee, 0x411b414296ce93b6);
@@ -0,0 +1,88 @@
+extern crate crossbeam_channel;
+extern crate mithril;
+
+use crossbeam_channel::unbounded;
+use mithril::byte_string::u8_array_to_string;
+use mithril::randomx::params::RX_0;
+use mithril::stratum::stratum_data::Share;
+use mithril::stratum::StratumCmd;
+use mithril::worker::share_verifier::{self, ShareCandidate, VerifierCmd};
+use std::sync::atomic::Ordering;
+
+#[test]
+fn test_verifier_drops_wrong_shares() {
+    let (share_sndr, share_rcvr) = unbounded();
+    let verifier = share_verifier::start(&share_sndr);
+    let cmd_sndr = verifier.cmd_sender();
+
+    cmd_sndr
+        .send(verify(candidate(
+            "00000001",
+            "639183aae1bf4c9a35884cb46b09cad9175f04efd7684e7262a0ac1c2f0b4e3f",
+        )))
+        .unwrap();
+    //a corrupted dataset item changes the hash completely
+    cmd_sndr
+        .send(verify(candidate(
+            "00000002",
+            "639183aae1bf4c9a35884cb46b09cad9175f04efd7684e7262a0ac1c2f0b4e40",
+        )))
+        .unwrap();
+    drop(cmd_sndr);
+
+    let stats = verifier.stats.clone();
+    verifier.join();
+    assert_eq!(stats.verified.load(Ordering::Relaxed), 1);
+    assert_eq!(stats.dropped.load(Ordering::Relaxed), 1);
+
+    let submitted: Vec<StratumCmd> = share_rcvr.try_iter().collect();
+    assert_eq!(submitted.len(), 1);
+    match submitted[0] {
+        StratumCmd::SubmitShare { ref share } => assert_eq!(share.nonce, "00000001"),
+        _ => panic!("share expected"),
+    }
+}
+
+#[test]
+fn test_verifier_initialises_memory_on_seed_change() {
+    let (share_sndr, share_rcvr) = unbounded();
+    let verifier = share_verifier::start(&share_sndr);
+    let cmd_sndr = verifier.cmd_sender();
+
+    verifier.seed_change(&u8_array_to_string(b"test key 000"), RX_0);
+    //the seed of the current memory is not initialised again
+    verifier.seed_change(&u8_array_to_string(b"test key 000"), RX_0);
+    cmd_sndr
+        .send(verify(candidate(
+            "00000001",
+            "639183aae1bf4c9a35884cb46b09cad9175f04efd7684e7262a0ac1c2f0b4e3f",
+        )))
+        .unwrap();
+    drop(cmd_sndr);
+
+    let stats = verifier.stats.clone();
+    verifier.join();
+    assert_eq!(stats.seeds.load(Ordering::Relaxed), 1);
+    assert_eq!(stats.verified.load(Ordering::Relaxed), 1);
+    assert_eq!(share_rcvr.try_iter().count(), 1);
+}
+
+//helper
+
+fn verify(candidate: ShareCandidate) -> VerifierCmd {
+    VerifierCmd::Verify { candidate }
+}
+
+fn candidate(nonce: &str, hash: &str) -> ShareCandidate {
+    ShareCandidate {
+        share: Share {
+            miner_id: "miner".to_string(),
+            job_id: "job".to_string(),
+            nonce: nonce.to_string(),
+            hash: hash.to_string(),
+        },
+        seed_hash: u8_array_to_string(b"test key 000"),
+        params: RX_0,
+        input: b"This is a test".to_vec(),
+    }
+}
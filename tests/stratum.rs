@@ -112,6 +112,7 @@ fn test_parse_line_dispatch_result_initial_job() {
         stratum::StratumAction::Job {
             miner_id,
             seed_hash,
+            next_seed_hash,
             blob,
             job_id,
             target,
@@ -124,6 +125,7 @@ fn test_parse_line_dispatch_result_initial_job() {
             assert_eq!(blob, "0606fdb09bcf056875870cb2750c2db9d179d1e8cf22a2c89e4e43bc4aaaabda227e2fd1ad14f2000000007e6fe370e8ec9594b111fe7fa47d9a0f2efc52454d24fc610f59acbb399d098806");
             assert_eq!(job_id, "738478949642740");
             assert_eq!(target, "169f0200");
+            assert_eq!(next_seed_hash, None);
         }
         _ => assert!(false, "Wrong result returned: {:?}", result),
     }
@@ -208,6 +210,7 @@ fn test_parse_line_dispatch_job_method() {
         stratum::StratumAction::Job {
             miner_id,
             seed_hash,
+            next_seed_hash,
             blob,
             job_id,
             target,
@@ -219,12 +222,43 @@ fn test_parse_line_dispatch_job_method() {
             assert_eq!(
                 seed_hash,
                 "ae2b3c3b6e013f9c3512a94a4e9f2cf0552f28a3dd0383ba7bac3f54ec06b56f"
-            )
+            );
+            assert_eq!(next_seed_hash, None);
         }
         _ => assert!(false, "Wrong result returned: {:?}", result),
     }
 }
 
+#[test]
+fn test_parse_line_dispatch_job_method_with_next_seed_hash() {
+    let (tx, rx) = unbounded();
+    let miner_id_mutex = Arc::new(Mutex::new(Option::Some("test_miner_id".to_string())));
+
+    let line = r#"{
+        "jsonrpc":"2.0",
+        "method":"job",
+        "params":{
+            "blob":"0606fcb29bcf051b9c7bfc60c98885de404ef48f721f09b8f51d37faf280470880bd120d4e9e0500000000577192c076fed53a24372bc43a3bed1d448a061ad06a262ac5e7f6803a28ccc705",
+            "job_id":"878440772206522",
+            "target":"169f0200",
+            "seed_hash":"ae2b3c3b6e013f9c3512a94a4e9f2cf0552f28a3dd0383ba7bac3f54ec06b56f",
+            "next_seed_hash":"4f0a6e1bc1b8d8e1f1bd9a1b8c3a4d1e5e1b1c4d1e1f1a1b1c1d1e1f1a1b1c1d"
+        }}"#;
+
+    thread::spawn(move || {
+        stratum::parse_line_dispatch_result(line, &tx, &miner_id_mutex);
+    });
+
+    let result = rx.recv().unwrap();
+    match result {
+        stratum::StratumAction::Job { next_seed_hash, .. } => assert_eq!(
+            next_seed_hash.unwrap(),
+            "4f0a6e1bc1b8d8e1f1bd9a1b8c3a4d1e5e1b1c4d1e1f1a1b1c1d1e1f1a1b1c1d"
+        ),
+        _ => assert!(false, "Wrong result returned: {:?}", result),
+    }
+}
+
 #[test]
 fn test_parse_line_dispatch_job_method_missing_miner_id() {
     let (tx, rx) = unbounded();
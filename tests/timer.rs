@@ -1,6 +1,8 @@
 extern crate mithril;
 
 use mithril::mithril_config::DonationConfig;
+use mithril::randomx::params::RandomXVariant;
+use mithril::randomx::vm::VmMode;
 use mithril::timer;
 use mithril::worker::worker_pool::WorkerConfig;
 
@@ -11,6 +13,10 @@ fn test_interval_mod_setup_donation_disabled_auto_tune_enabled() {
         auto_tune_interval_minutes: 15,
         auto_tune_log: "/log/file".to_string(),
         num_threads: 8,
+        randomx_mode: VmMode::Interpreted,
+        randomx_variant: RandomXVariant::Rx0,
+        self_test: true,
+        verify_shares: false,
     };
     let donation_conf = DonationConfig { percentage: 0.0 };
 
@@ -26,6 +32,10 @@ fn test_interval_mod_setup_donation_below_threshold_auto_tune_enabled() {
         auto_tune_interval_minutes: 15,
         auto_tune_log: "/log/file".to_string(),
         num_threads: 8,
+        randomx_mode: VmMode::Interpreted,
+        randomx_variant: RandomXVariant::Rx0,
+        self_test: true,
+        verify_shares: false,
     };
     let donation_conf = DonationConfig {
         percentage: 1.0 / 10.0 - std::f64::EPSILON,
@@ -43,6 +53,10 @@ fn test_interval_mod_setup_donation_disabled_auto_tune_disabled() {
         auto_tune_interval_minutes: 15,
         auto_tune_log: "/log/file".to_string(),
         num_threads: 8,
+        randomx_mode: VmMode::Interpreted,
+        randomx_variant: RandomXVariant::Rx0,
+        self_test: true,
+        verify_shares: false,
     };
     let donation_conf = DonationConfig { percentage: 0.0 };
 
@@ -58,6 +72,10 @@ fn test_interval_mod_setup_donation_enabled_auto_tune_disabled() {
         auto_tune_interval_minutes: 15,
         auto_tune_log: "/log/file".to_string(),
         num_threads: 8,
+        randomx_mode: VmMode::Interpreted,
+        randomx_variant: RandomXVariant::Rx0,
+        self_test: true,
+        verify_shares: false,
     };
     let donation_conf = DonationConfig { percentage: 2.5 };
 
@@ -73,6 +91,10 @@ fn test_interval_mod_setup_donation_enabled_auto_tune_enabled() {
         auto_tune_interval_minutes: 15,
         auto_tune_log: "/log/file".to_string(),
         num_threads: 8,
+        randomx_mode: VmMode::Interpreted,
+        randomx_variant: RandomXVariant::Rx0,
+        self_test: true,
+        verify_shares: false,
     };
     let donation_conf = DonationConfig { percentage: 2.5 };
 
@@ -88,6 +110,10 @@ fn test_interval_mod_setup_donation_100_percent_enabled_auto_tune_enabled() {
         auto_tune_interval_minutes: 15,
         auto_tune_log: "/log/file".to_string(),
         num_threads: 8,
+        randomx_mode: VmMode::Interpreted,
+        randomx_variant: RandomXVariant::Rx0,
+        self_test: true,
+        verify_shares: false,
     };
     let donation_conf = DonationConfig { percentage: 100.0 };
 